@@ -13,6 +13,7 @@ use mas_data_model::SiteConfig;
 use mas_handlers::{
     ActivityTracker, BoundActivityTracker, CookieManager, ErrorWrapper, GraphQLSchema, Limiter,
     MetadataCache, RequesterFingerprint, passwords::PasswordManager,
+    synapse_migration::SynapseMigrations,
 };
 use mas_i18n::Translator;
 use mas_keystore::{Encrypter, Keystore};
@@ -44,6 +45,7 @@ pub struct AppState {
     pub graphql_schema: GraphQLSchema,
     pub http_client: reqwest::Client,
     pub password_manager: PasswordManager,
+    pub synapse_migrations: SynapseMigrations,
     pub metadata_cache: MetadataCache,
     pub site_config: SiteConfig,
     pub activity_tracker: ActivityTracker,
@@ -180,6 +182,12 @@ impl FromRef<AppState> for PasswordManager {
     }
 }
 
+impl FromRef<AppState> for SynapseMigrations {
+    fn from_ref(input: &AppState) -> Self {
+        input.synapse_migrations.clone()
+    }
+}
+
 impl FromRef<AppState> for CookieManager {
     fn from_ref(input: &AppState) -> Self {
         input.cookie_manager.clone()
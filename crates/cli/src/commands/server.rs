@@ -7,14 +7,18 @@
 use std::{collections::BTreeSet, process::ExitCode, sync::Arc, time::Duration};
 
 use anyhow::Context;
+use camino::Utf8PathBuf;
 use clap::Parser;
 use figment::Figment;
 use itertools::Itertools;
 use mas_config::{
-    AppConfig, ClientsConfig, ConfigurationSection, ConfigurationSectionExt, UpstreamOAuth2Config,
+    AppConfig, ClientsConfig, ConfigurationSection, ConfigurationSectionExt, HttpResource,
+    UpstreamOAuth2Config,
 };
 use mas_context::LogContext;
-use mas_handlers::{ActivityTracker, CookieManager, Limiter, MetadataCache};
+use mas_handlers::{
+    ActivityTracker, CookieManager, Limiter, MetadataCache, synapse_migration::SynapseMigrations,
+};
 use mas_listener::server::Server;
 use mas_router::UrlBuilder;
 use mas_storage::SystemClock;
@@ -24,6 +28,7 @@ use tracing::{Instrument, info, info_span, warn};
 
 use crate::{
     app_state::AppState,
+    commands::syn2mas::AdminApiMigrationRunner,
     lifecycle::LifecycleManager,
     util::{
         database_pool_from_config, homeserver_connection_from_config,
@@ -52,6 +57,22 @@ pub(super) struct Options {
     /// Do not sync the configuration with the database
     #[arg(long)]
     no_sync: bool,
+
+    /// Path to the Synapse configuration (in YAML format), to allow starting
+    /// migrations from Synapse through the admin API. Requires
+    /// `--synapse-migration-only`.
+    /// May be specified multiple times if multiple Synapse configuration files
+    /// are in use.
+    #[arg(long = "synapse-config", requires = "synapse_migration_only")]
+    synapse_configuration_files: Vec<Utf8PathBuf>,
+
+    /// Only run the server to migrate from Synapse through the admin API.
+    ///
+    /// The migration takes the tables of the users and their sessions over
+    /// until it finishes, so the task worker doesn't run, and the listeners
+    /// only serve the admin API, the health check and the metrics.
+    #[arg(long, requires = "synapse_configuration_files")]
+    synapse_migration_only: bool,
 }
 
 impl Options {
@@ -169,7 +190,9 @@ impl Options {
         let homeserver_connection =
             homeserver_connection_from_config(&config.matrix, http_client.clone()).await?;
 
-        if !self.no_worker {
+        if self.synapse_migration_only {
+            info!("Not starting the task worker, as the server only runs to migrate from Synapse");
+        } else if !self.no_worker {
             let mailer = mailer_from_config(&config.email, &templates)?;
             test_mailer_in_background(&mailer, Duration::from_secs(30));
 
@@ -187,10 +210,41 @@ impl Options {
             .await?;
         }
 
-        let listeners_config = config.http.listeners.clone();
+        let mut listeners_config = config.http.listeners.clone();
+        if self.synapse_migration_only {
+            for listener in &mut listeners_config {
+                listener.resources.retain(|resource| {
+                    matches!(
+                        resource,
+                        HttpResource::Health | HttpResource::Prometheus | HttpResource::AdminApi
+                    )
+                });
+            }
+
+            let serves_admin_api = listeners_config.iter().any(|listener| {
+                listener
+                    .resources
+                    .iter()
+                    .any(|resource| matches!(resource, HttpResource::AdminApi))
+            });
+            if !serves_admin_api {
+                anyhow::bail!(
+                    "The server runs with `--synapse-migration-only`, but none of the listeners serves the admin API"
+                );
+            }
+        }
 
         let password_manager = password_manager_from_config(&config.passwords).await?;
 
+        let synapse_migrations = if self.synapse_configuration_files.is_empty() {
+            SynapseMigrations::disabled()
+        } else {
+            SynapseMigrations::new(
+                AdminApiMigrationRunner::new(figment.clone(), self.synapse_configuration_files),
+                shutdown.task_tracker(),
+            )
+        };
+
         // The upstream OIDC metadata cache
         let metadata_cache = MetadataCache::new();
 
@@ -241,6 +295,7 @@ impl Options {
                 graphql_schema,
                 http_client,
                 password_manager,
+                synapse_migrations,
                 metadata_cache,
                 site_config,
                 activity_tracker,
@@ -3,24 +3,44 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::{collections::HashMap, process::ExitCode, time::Duration};
+use std::{
+    collections::HashMap,
+    num::{NonZeroU32, NonZeroUsize},
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::Context;
+use async_trait::async_trait;
 use camino::Utf8PathBuf;
+use chrono::{DateTime, Utc};
 use clap::Parser;
 use figment::Figment;
+use itertools::Itertools;
 use mas_config::{
     ConfigurationSection, ConfigurationSectionExt, DatabaseConfig, MatrixConfig, SyncConfig,
     UpstreamOAuth2Config,
 };
-use mas_storage::SystemClock;
+use mas_handlers::synapse_migration::SynapseMigrationRunner;
+use mas_storage::{Clock, SystemClock};
 use mas_storage_pg::MIGRATOR;
-use rand::thread_rng;
-use sqlx::{Connection, Either, PgConnection, postgres::PgConnectOptions, types::Uuid};
+use rand::{SeedableRng, thread_rng};
+use regex::RegexSet;
+use sqlx::{
+    Connection, Either, PgConnection, SqliteConnection,
+    postgres::{PgConnectOptions, PgSslMode},
+    types::Uuid,
+};
 use syn2mas::{
-    LockedMasDatabase, MasWriter, Progress, ProgressStage, SynapseReader, synapse_config,
+    CopyFormat, CountMode, DuplicateEmailPolicy, FallbackTimestampPolicy, InvalidIpPolicy,
+    InvalidUsernamePolicy, IpAnonymization, LockedMasDatabase, MasWriter, MigrationOptions,
+    MigrationReport, MissingUserPolicy, Phase, PhaseTimeout, Progress, ProgressStage,
+    PuppetTokenPolicy, ShadowBannedPolicy, SynapseConnection, SynapseReader,
+    UnknownPasswordSchemePolicy, UnknownProviderPolicy, UserAuthFilter, UserMapBacking, WriteMode,
+    synapse_config,
 };
-use tracing::{Instrument, error, info, info_span};
+use tracing::{Instrument, error, info, info_span, warn};
 
 use crate::util::{DatabaseConnectOptions, database_connection_from_config_with_options};
 
@@ -60,11 +80,42 @@ pub(super) struct Options {
     /// environment variables `PGHOST`, `PGPORT`, `PGUSER`, `PGDATABASE`,
     /// `PGPASSWORD`, etc. It is valid to specify the URL `postgresql:` and
     /// configure all values through those environment variables.
+    ///
+    /// This may point at a read replica of the Synapse database, to avoid
+    /// loading the primary. The replica must have caught up with every write
+    /// made before Synapse was stopped.
+    ///
+    /// This always points at a Postgres database, even if the Synapse
+    /// configuration uses SQLite.
     #[clap(long = "synapse-database-uri", global = true)]
     synapse_database_uri: Option<PgConnectOptions>,
+
+    /// Override the SSL mode used to connect to the Synapse database.
+    ///
+    /// One of `disable`, `allow`, `prefer`, `require`, `verify-ca` or
+    /// `verify-full`, like the `ssl_mode` option of the MAS database
+    /// configuration.
+    #[clap(long = "synapse-database-ssl-mode", global = true)]
+    synapse_database_ssl_mode: Option<PgSslMode>,
+
+    /// Path to the CA certificate used to verify the Synapse database
+    /// server certificate.
+    #[clap(long = "synapse-database-ssl-ca-file", global = true)]
+    synapse_database_ssl_ca_file: Option<Utf8PathBuf>,
+
+    /// Path to the client certificate used to authenticate against the
+    /// Synapse database, for servers requiring mutual TLS.
+    #[clap(long = "synapse-database-ssl-certificate-file", global = true)]
+    synapse_database_ssl_certificate_file: Option<Utf8PathBuf>,
+
+    /// Path to the private key of the client certificate used to
+    /// authenticate against the Synapse database.
+    #[clap(long = "synapse-database-ssl-key-file", global = true)]
+    synapse_database_ssl_key_file: Option<Utf8PathBuf>,
 }
 
 #[derive(Parser, Debug)]
+#[allow(clippy::large_enum_variant)]
 enum Subcommand {
     /// Check the setup for potential problems before running a migration.
     ///
@@ -80,12 +131,281 @@ enum Subcommand {
         /// realistic compared to the final migration.
         #[clap(long)]
         dry_run: bool,
+
+        /// Only count the rows that would be migrated, without writing any of
+        /// them to the MAS database.
+        ///
+        /// Like `--dry-run`, this is safe to run with Synapse running.
+        #[clap(long)]
+        count_only: bool,
+
+        /// How to count the Synapse rows up front, for the progress reports
+        /// and to size the lookup tables.
+        ///
+        /// One of `estimate`, which uses the table statistics of Postgres and
+        /// only counts the tables with stale statistics, or `exact`, which
+        /// scans every table.
+        #[clap(long, default_value = "estimate")]
+        count_mode: CountMode,
+
+        /// Which timestamp to use for the sessions and tokens which have no
+        /// creation time in the Synapse database.
+        ///
+        /// One of `now`, `user-creation` (the creation time of the owning
+        /// user) or a fixed RFC 3339 timestamp.
+        #[clap(long, default_value = "now")]
+        fallback_timestamp: FallbackTimestampPolicy,
+
+        /// Only migrate the user with this localpart, along with their
+        /// data. Can be repeated to migrate several users.
+        ///
+        /// This is meant for testing the migration on a handful of users.
+        #[clap(long = "only-user", value_name = "LOCALPART")]
+        only_users: Vec<String>,
+
+        /// Only let the user with this localpart request admin privileges
+        /// in MAS. Can be repeated to allow several users.
+        ///
+        /// When given, the users who were admins in Synapse can't request
+        /// admin privileges anymore, unless they are allowed here too.
+        #[clap(long = "admin-allowlist", value_name = "LOCALPART")]
+        admin_allowlist: Vec<String>,
+
+        /// What to do with the upstream links of Synapse auth providers
+        /// which aren't configured in MAS.
+        ///
+        /// One of `fail` or `skip-with-warning`. Skipped links are logged and
+        /// counted in the migration report.
+        #[clap(long, default_value = "fail")]
+        unknown_provider_policy: UnknownProviderPolicy,
+
+        /// What to do with the email addresses of a user which are the same
+        /// once trimmed and lowercased.
+        ///
+        /// One of `fail` or `skip-with-warning`. Skipped addresses are logged
+        /// and counted in the migration report.
+        #[clap(long, default_value = "fail")]
+        duplicate_email_policy: DuplicateEmailPolicy,
+
+        /// What to do with the rows of users missing from the Synapse
+        /// `users` table, as left behind by old Synapse bugs.
+        ///
+        /// One of `abort` or `skip-and-report`. Skipped rows are counted in
+        /// the migration report, which lists the tables and users involved.
+        #[clap(long, default_value = "abort")]
+        missing_user_policy: MissingUserPolicy,
+
+        /// Also migrate the devices Synapse flags as hidden, which are only
+        /// used to store cross-signing keys.
+        #[clap(long)]
+        include_hidden_devices: bool,
+
+        /// Tell apart the devices of a user which share the same display
+        /// name, by appending the day they were last seen to their name.
+        ///
+        /// This only changes the name shown in the list of sessions, not the
+        /// device IDs.
+        #[clap(long)]
+        dedupe_device_names: bool,
+
+        /// What to do with session IP addresses which fail to parse.
+        ///
+        /// One of `drop` or `preserve-raw`, which keeps the raw value as a
+        /// migration note in the MAS database.
+        #[clap(long, default_value = "drop")]
+        invalid_ip_policy: InvalidIpPolicy,
+
+        /// How to anonymize the IP addresses the sessions were last active
+        /// from.
+        ///
+        /// One of `none`, `v4:<prefix length>` or `v6:<prefix length>`, which
+        /// zero the bits of the IPv4 or IPv6 addresses beyond the prefix, for
+        /// example `v4:24`. The raw values kept with `--invalid-ip-policy
+        /// preserve-raw` are not anonymized.
+        #[clap(long, default_value = "none")]
+        ip_anonymization: IpAnonymization,
+
+        /// What to do with the access tokens Synapse admins created to act
+        /// as another user.
+        ///
+        /// One of `skip` or `record-note`, which migrates them as tokens of
+        /// the user they act as, and keeps the admin who created them as a
+        /// migration note in the MAS database.
+        #[clap(long, default_value = "skip")]
+        puppet_token_policy: PuppetTokenPolicy,
+
+        /// What to do with the users Synapse shadow-banned.
+        ///
+        /// One of `lock`, so that they can't use their account anymore, or
+        /// `ignore`, which migrates them as regular users.
+        #[clap(long, default_value = "lock")]
+        shadow_banned_policy: ShadowBannedPolicy,
+
+        /// What to do with the password hashes which aren't in the bcrypt
+        /// format Synapse uses, and which MAS can't verify.
+        ///
+        /// One of `fail`, or `skip-password`, which migrates the users without
+        /// their password.
+        #[clap(long, default_value = "fail")]
+        unknown_password_scheme_policy: UnknownPasswordSchemePolicy,
+
+        /// What to do with the users whose localpart MAS wouldn't accept as a
+        /// new username, for example because it has uppercase letters.
+        ///
+        /// One of `warn`, which migrates them as they are, or `fail`.
+        #[clap(long, default_value = "warn")]
+        invalid_username_policy: InvalidUsernamePolicy,
+
+        /// Only migrate the users with a password (`password-only`), or the
+        /// users without one, who log in through SSO (`sso-only`).
+        ///
+        /// Users with both a password and an upstream link are migrated with
+        /// `password-only`. Running the other filter afterwards migrates the
+        /// rest of the users into the same MAS database.
+        #[clap(long, default_value = "all")]
+        user_auth_filter: UserAuthFilter,
+
+        /// Also migrate the global account data of the users, which MAS
+        /// doesn't use itself, and which Synapse keeps serving.
+        #[clap(long)]
+        migrate_account_data: bool,
+
+        /// Also migrate the sync filters of the users, which MAS doesn't use
+        /// itself, and which Synapse keeps serving.
+        #[clap(long)]
+        migrate_user_filters: bool,
+
+        /// Which creation time to use for the users which have none in the
+        /// Synapse database, as an RFC 3339 timestamp.
+        ///
+        /// Defaults to the UNIX epoch.
+        #[clap(long, value_name = "TIMESTAMP")]
+        fallback_user_creation_time: Option<DateTime<Utc>>,
+
+        /// How many rows to write to the MAS database at once.
+        ///
+        /// Larger batches mean fewer round-trips to the database, but a
+        /// higher peak memory usage. Defaults to 4096.
+        #[clap(long)]
+        batch_size: Option<NonZeroUsize>,
+
+        /// How many batches can be written to the MAS database or waiting
+        /// for a connection at once.
+        ///
+        /// More batches in flight let the migration keep reading from
+        /// Synapse while the MAS database is slow to respond, but mean a
+        /// higher peak memory usage. Defaults to the number of writer
+        /// connections.
+        #[clap(long)]
+        max_in_flight_batches: Option<NonZeroU32>,
+
+        /// How many times a batch is retried after a serialization failure
+        /// or a deadlock in the MAS database, before failing the migration.
+        ///
+        /// Defaults to 3.
+        #[clap(long)]
+        batch_retries: Option<u32>,
+
+        /// A regular expression matching the user IDs in the namespace of an
+        /// application service. Can be repeated for several namespaces.
+        ///
+        /// Like in Synapse, the expression must match from the start of the
+        /// user ID. The matching users are migrated without their password.
+        #[clap(long = "appservice-namespace", value_name = "REGEX")]
+        appservice_namespaces: Vec<String>,
+
+        /// Don't migrate the users again, and use the users already in the
+        /// MAS database from a prior migration instead.
+        ///
+        /// This can't be combined with `--dry-run` or `--count-only`, which
+        /// would remove the existing users at the end.
+        #[clap(long)]
+        reuse_existing_users: bool,
+
+        /// Before migrating, abandon a previous migration which didn't
+        /// finish, keeping the rows it wrote instead of starting over.
+        ///
+        /// This is the way to migrate again after a migration which started
+        /// with existing data in the MAS database didn't finish, short of
+        /// restoring the MAS database from a backup.
+        #[clap(long)]
+        abandon_unfinished_migration: bool,
+
+        /// Only run this phase. Can be repeated to run several phases.
+        ///
+        /// One of `users`, `threepids`, `external-ids`, `account-data`,
+        /// `user-filters`, `unrefreshable-tokens`, `refreshable-tokens` or
+        /// `devices`. The `users` phase must be included, unless the users
+        /// are loaded with `--reuse-existing-users`.
+        #[clap(long = "only-phase", value_name = "PHASE")]
+        only_phases: Vec<Phase>,
+
+        /// Fail the migration if a phase runs for longer than the given
+        /// number of seconds, given as `<phase>=<seconds>`. Can be repeated
+        /// to limit several phases.
+        ///
+        /// This stops a phase made pathologically slow by the database, for
+        /// example because of a missing index, from hanging forever.
+        #[clap(long = "phase-timeout", value_name = "PHASE=SECONDS")]
+        phase_timeouts: Vec<PhaseTimeout>,
+
+        /// Don't check that every row of the Synapse tables was either
+        /// migrated or skipped on purpose once the migration is done.
+        ///
+        /// The rows are counted exactly for the check, even when they were
+        /// only estimated for the progress.
+        #[clap(long)]
+        skip_count_check: bool,
+
+        /// Derive the IDs of the migrated rows from the Synapse rows, so that
+        /// migrating the same Synapse database twice gives the same MAS IDs.
+        ///
+        /// This is meant for verifying the migration. Combine it with a
+        /// `--fallback-timestamp` which doesn't depend on the current time.
+        #[clap(long)]
+        deterministic_ids: bool,
+
+        /// How to write the compatibility sessions, their tokens and their
+        /// migration notes.
+        ///
+        /// One of `insert-only` or `upsert`, which skips the rows already in
+        /// the MAS database, so that re-running the `devices` phase with
+        /// `--deterministic-ids` doesn't fail on the rows of the prior run.
+        #[clap(long, default_value = "insert-only")]
+        write_mode: WriteMode,
+
+        /// Write the compatibility sessions and their access tokens with a
+        /// `COPY` statement in this format, instead of `INSERT` statements.
+        ///
+        /// One of `text` or `binary`. This only applies with the
+        /// `insert-only` write mode.
+        #[clap(long)]
+        copy_format: Option<CopyFormat>,
+
+        /// Also write the migrated rows to this file, one JSON object per
+        /// line, to inspect what the migration does.
+        ///
+        /// Password hashes and tokens are left out of the file.
+        #[clap(long, value_name = "FILE")]
+        export_path: Option<Utf8PathBuf>,
+
+        /// Keep the lookup table of the users in files created in this
+        /// directory instead of in memory, to cap the memory usage when
+        /// migrating tens of millions of users.
+        ///
+        /// This makes the phases after the users one noticeably slower.
+        #[clap(long, value_name = "DIRECTORY")]
+        user_map_directory: Option<Utf8PathBuf>,
     },
 }
 
 /// The number of parallel writing transactions active against the MAS database.
 const NUM_WRITER_CONNECTIONS: usize = 8;
 
+/// How recent the activity recorded by Synapse has to be for Synapse to be
+/// considered still running, in minutes.
+const SYNAPSE_ACTIVITY_THRESHOLD_MINUTES: i64 = 5;
+
 impl Options {
     #[tracing::instrument("cli.syn2mas.run", skip_all)]
     pub async fn run(self, figment: &Figment) -> anyhow::Result<ExitCode> {
@@ -98,18 +418,38 @@ impl Options {
             .map_err(anyhow::Error::from_boxed)
             .context("Failed to load Synapse configuration")?;
 
-        // Establish a connection to Synapse's Postgres database
-        let syn_connection_options = if let Some(db_override) = self.synapse_database_uri {
-            db_override
+        // Establish a connection to Synapse's database. The URI override always points
+        // at a Postgres database.
+        let mut syn_conn = if self.synapse_database_uri.is_none()
+            && synapse_config.database.name == synapse_config::SYNAPSE_DATABASE_DRIVER_NAME_SQLITE3
+        {
+            synapse_sqlite_connection(&synapse_config).await?
         } else {
-            synapse_config
-                .database
-                .to_sqlx_postgres()
-                .context("Synapse database configuration is invalid, cannot migrate.")?
+            let mut syn_connection_options = if let Some(db_override) = self.synapse_database_uri {
+                db_override
+            } else {
+                synapse_config
+                    .database
+                    .to_sqlx_postgres()
+                    .context("Synapse database configuration is invalid, cannot migrate.")?
+            };
+            if let Some(ssl_mode) = self.synapse_database_ssl_mode {
+                syn_connection_options = syn_connection_options.ssl_mode(ssl_mode);
+            }
+            if let Some(path) = &self.synapse_database_ssl_ca_file {
+                syn_connection_options = syn_connection_options.ssl_root_cert(path);
+            }
+            if let Some(path) = &self.synapse_database_ssl_certificate_file {
+                syn_connection_options = syn_connection_options.ssl_client_cert(path);
+            }
+            if let Some(path) = &self.synapse_database_ssl_key_file {
+                syn_connection_options = syn_connection_options.ssl_client_key(path);
+            }
+            let conn = PgConnection::connect_with(&syn_connection_options)
+                .await
+                .context("could not connect to Synapse Postgres database")?;
+            SynapseConnection::Postgres(conn)
         };
-        let mut syn_conn = PgConnection::connect_with(&syn_connection_options)
-            .await
-            .context("could not connect to Synapse Postgres database")?;
 
         let config =
             DatabaseConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
@@ -160,6 +500,31 @@ impl Options {
             return Ok(ExitCode::FAILURE);
         };
 
+        if let Subcommand::Migrate {
+            abandon_unfinished_migration: true,
+            dry_run,
+            count_only,
+            ..
+        } = &self.subcommand
+        {
+            if *dry_run || *count_only {
+                error!(
+                    "--abandon-unfinished-migration can't be used with --dry-run or --count-only, as it writes to the MAS database"
+                );
+                return Ok(ExitCode::FAILURE);
+            }
+
+            let abandoned =
+                MasWriter::abandon_unfinished_migration(&mut mas_connection, &Progress::default())
+                    .await
+                    .context("could not abandon the unfinished migration")?;
+            if abandoned {
+                info!("Abandoned the unfinished migration, keeping the rows it wrote");
+            } else {
+                info!("No unfinished migration to abandon");
+            }
+        }
+
         // Check configuration
         let (mut check_warnings, mut check_errors) = syn2mas::synapse_config_check(&synapse_config);
         {
@@ -170,10 +535,36 @@ impl Options {
         }
 
         // Check databases
-        syn2mas::mas_pre_migration_checks(&mut mas_connection).await?;
+        let allow_existing_data = match &self.subcommand {
+            Subcommand::Check => false,
+            // A dry-run would remove the existing data at the end
+            Subcommand::Migrate {
+                dry_run,
+                count_only,
+                reuse_existing_users,
+                user_auth_filter,
+                ..
+            } => {
+                !(*dry_run || *count_only)
+                    && (*reuse_existing_users || *user_auth_filter != UserAuthFilter::All)
+            }
+        };
+        syn2mas::mas_pre_migration_checks(&mut mas_connection, allow_existing_data).await?;
         {
-            let (extra_warnings, extra_errors) =
-                syn2mas::synapse_database_check(&mut syn_conn, &synapse_config, figment).await?;
+            let unknown_provider_policy = match &self.subcommand {
+                Subcommand::Check => UnknownProviderPolicy::default(),
+                Subcommand::Migrate {
+                    unknown_provider_policy,
+                    ..
+                } => *unknown_provider_policy,
+            };
+            let (extra_warnings, extra_errors) = syn2mas::synapse_database_check(
+                &mut syn_conn,
+                &synapse_config,
+                figment,
+                unknown_provider_policy,
+            )
+            .await?;
             check_warnings.extend(extra_warnings);
             check_errors.extend(extra_errors);
         }
@@ -212,39 +603,101 @@ impl Options {
                 Ok(ExitCode::SUCCESS)
             }
 
-            Subcommand::Migrate { dry_run } => {
-                let provider_id_mappings: HashMap<String, Uuid> = {
-                    let mas_oauth2 = UpstreamOAuth2Config::extract_or_default(figment)
-                        .map_err(anyhow::Error::from_boxed)?;
+            Subcommand::Migrate {
+                dry_run,
+                count_only,
+                count_mode,
+                fallback_timestamp,
+                only_users,
+                admin_allowlist,
+                unknown_provider_policy,
+                duplicate_email_policy,
+                missing_user_policy,
+                include_hidden_devices,
+                dedupe_device_names,
+                invalid_ip_policy,
+                ip_anonymization,
+                puppet_token_policy,
+                shadow_banned_policy,
+                unknown_password_scheme_policy,
+                invalid_username_policy,
+                user_auth_filter,
+                migrate_account_data,
+                migrate_user_filters,
+                fallback_user_creation_time,
+                batch_size,
+                max_in_flight_batches,
+                batch_retries,
+                appservice_namespaces,
+                reuse_existing_users,
+                abandon_unfinished_migration: _,
+                only_phases,
+                phase_timeouts,
+                skip_count_check,
+                deterministic_ids,
+                write_mode,
+                copy_format,
+                export_path,
+                user_map_directory,
+            } => {
+                // Counting rows doesn't write anything, so it is as safe as a dry-run
+                let dry_run = dry_run || count_only;
+
+                if dry_run && reuse_existing_users {
+                    error!(
+                        "--reuse-existing-users can't be used with --dry-run or --count-only, as they would remove the existing users"
+                    );
+                    return Ok(ExitCode::FAILURE);
+                }
 
-                    mas_oauth2
-                        .providers
-                        .iter()
-                        .filter_map(|provider| {
-                            let synapse_idp_id = provider.synapse_idp_id.clone()?;
-                            Some((synapse_idp_id, Uuid::from(provider.id)))
-                        })
-                        .collect()
+                if !only_phases.is_empty()
+                    && only_phases.contains(&Phase::Users) == reuse_existing_users
+                {
+                    error!(
+                        "--only-phase must include the `users` phase, unless the users are loaded with --reuse-existing-users, in which case it can't include it"
+                    );
+                    return Ok(ExitCode::FAILURE);
+                }
+
+                let provider_id_mappings = provider_id_mappings(figment)?;
+
+                // Synapse matches the namespaces from the start of the user ID
+                let appservice_namespaces = if appservice_namespaces.is_empty() {
+                    None
+                } else {
+                    Some(
+                        RegexSet::new(
+                            appservice_namespaces
+                                .iter()
+                                .map(|namespace| format!("^(?:{namespace})")),
+                        )
+                        .context("Invalid appservice namespace")?,
+                    )
                 };
 
                 // TODO how should we handle warnings at this stage?
 
-                let reader = SynapseReader::new(&mut syn_conn, dry_run).await?;
-                let writer_mas_connections =
-                    futures_util::future::try_join_all((0..NUM_WRITER_CONNECTIONS).map(|_| {
-                        database_connection_from_config_with_options(
-                            &config,
-                            &DatabaseConnectOptions {
-                                log_slow_statements: false,
-                            },
+                let clock = SystemClock::default();
+
+                let mut reader = SynapseReader::from_connection(&mut syn_conn, dry_run).await?;
+                if !dry_run {
+                    reader
+                        .assert_synapse_stopped(
+                            clock.now(),
+                            chrono::Duration::minutes(SYNAPSE_ACTIVITY_THRESHOLD_MINUTES),
                         )
-                    }))
-                    .instrument(tracing::info_span!("syn2mas.mas_writer_connections"))
-                    .await?;
-                let writer =
+                        .await?;
+                }
+                let writer_mas_connections = writer_mas_connections(&config).await?;
+                let mut writer =
                     MasWriter::new(mas_connection, writer_mas_connections, dry_run).await?;
 
-                let clock = SystemClock::default();
+                let existing_users = if reuse_existing_users {
+                    Some(writer.load_user_localpart_map().await?)
+                } else {
+                    None
+                };
+
                 // TODO is this rng ok?
                 #[allow(clippy::disallowed_methods)]
                 let mut rng = thread_rng();
@@ -256,7 +709,7 @@ impl Options {
 
                 let mas_matrix =
                     MatrixConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
-                syn2mas::migrate(
+                let report = syn2mas::migrate(
                     reader,
                     writer,
                     mas_matrix.homeserver,
@@ -264,17 +717,265 @@ impl Options {
                     &mut rng,
                     provider_id_mappings,
                     &progress,
+                    &MigrationOptions {
+                        count_only,
+                        count_mode,
+                        fallback_timestamp_policy: fallback_timestamp,
+                        unknown_provider_policy,
+                        duplicate_email_policy,
+                        missing_user_policy,
+                        include_hidden_devices,
+                        dedupe_device_names,
+                        invalid_ip_policy,
+                        ip_anonymization,
+                        puppet_token_policy,
+                        shadow_banned_policy,
+                        unknown_password_scheme_policy,
+                        invalid_username_policy,
+                        fallback_user_creation_time: fallback_user_creation_time
+                            .unwrap_or_default(),
+                        batch_size,
+                        max_in_flight_batches,
+                        batch_retries,
+                        user_filter: (!only_users.is_empty())
+                            .then(|| only_users.into_iter().collect()),
+                        admin_allowlist: (!admin_allowlist.is_empty())
+                            .then(|| admin_allowlist.into_iter().map(Into::into).collect()),
+                        appservice_namespaces,
+                        user_auth_filter,
+                        // The MAS database is expected to contain the users of the prior run
+                        allow_existing_data: reuse_existing_users
+                            || user_auth_filter != UserAuthFilter::All,
+                        existing_users,
+                        only_phases: (!only_phases.is_empty())
+                            .then(|| only_phases.into_iter().collect()),
+                        phase_timeouts: phase_timeouts
+                            .into_iter()
+                            .map(|PhaseTimeout { phase, timeout }| (phase, timeout))
+                            .collect(),
+                        skip_count_check,
+                        migrate_account_data,
+                        migrate_user_filters,
+                        deterministic_ids,
+                        write_mode,
+                        copy_format,
+                        export_path,
+                        user_map_backing: user_map_directory
+                            .map_or(UserMapBacking::InMemory, UserMapBacking::OnDisk),
+                        synapse_server_name: Some(synapse_config.server_name.clone()),
+                        ..MigrationOptions::default()
+                    },
                 )
                 .await?;
 
                 occasional_progress_logger_task.abort();
 
+                if count_only {
+                    info!("Would migrate {report}");
+                } else {
+                    info!("Migrated {report}");
+                }
+
+                // List the missing users, so that their rows can be cleaned up in Synapse
+                for (table, users) in &report.missing_users {
+                    let users = users
+                        .iter()
+                        .map(|user| user.0.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    warn!(
+                        "Skipped the rows of users missing from the users table in {table}: {users}"
+                    );
+                }
+
                 Ok(ExitCode::SUCCESS)
             }
         }
     }
 }
 
+/// Map the IDs of the Synapse identity providers to the IDs of the MAS upstream
+/// OAuth 2.0 providers
+fn provider_id_mappings(figment: &Figment) -> anyhow::Result<HashMap<String, Uuid>> {
+    let mas_oauth2 =
+        UpstreamOAuth2Config::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+
+    Ok(mas_oauth2
+        .providers
+        .iter()
+        .filter_map(|provider| {
+            let synapse_idp_id = provider.synapse_idp_id.clone()?;
+            Some((synapse_idp_id, Uuid::from(provider.id)))
+        })
+        .collect())
+}
+
+/// Open a connection to the SQLite database of Synapse, using the path from the
+/// Synapse configuration
+async fn synapse_sqlite_connection(
+    synapse_config: &synapse_config::Config,
+) -> anyhow::Result<SynapseConnection> {
+    let options = synapse_config
+        .database
+        .to_sqlx_sqlite()
+        .context("Synapse database configuration is invalid, cannot migrate.")?;
+    let conn = SqliteConnection::connect_with(&options)
+        .await
+        .context("could not connect to Synapse SQLite database")?;
+
+    Ok(SynapseConnection::Sqlite(conn))
+}
+
+/// Open the connections used by the [`MasWriter`] to write in parallel
+async fn writer_mas_connections(config: &DatabaseConfig) -> anyhow::Result<Vec<PgConnection>> {
+    let connections = futures_util::future::try_join_all((0..NUM_WRITER_CONNECTIONS).map(|_| {
+        database_connection_from_config_with_options(
+            config,
+            &DatabaseConnectOptions {
+                log_slow_statements: false,
+            },
+        )
+    }))
+    .instrument(tracing::info_span!("syn2mas.mas_writer_connections"))
+    .await?;
+
+    Ok(connections)
+}
+
+/// Runs the migrations started through the admin API, with the default
+/// migration options
+///
+/// Unlike `syn2mas migrate`, this doesn't sync the configuration with the
+/// database first, as the server already does it on startup.
+pub(crate) struct AdminApiMigrationRunner {
+    figment: Figment,
+    synapse_configuration_files: Vec<Utf8PathBuf>,
+}
+
+impl AdminApiMigrationRunner {
+    pub(crate) fn new(figment: Figment, synapse_configuration_files: Vec<Utf8PathBuf>) -> Self {
+        Self {
+            figment,
+            synapse_configuration_files,
+        }
+    }
+}
+
+#[async_trait]
+impl SynapseMigrationRunner for AdminApiMigrationRunner {
+    async fn run(
+        &self,
+        progress: Progress,
+        warnings: Arc<Mutex<Vec<String>>>,
+    ) -> anyhow::Result<MigrationReport> {
+        let synapse_config = synapse_config::Config::load(&self.synapse_configuration_files)
+            .map_err(anyhow::Error::from_boxed)
+            .context("Failed to load Synapse configuration")?;
+
+        let mut syn_conn = if synapse_config.database.name
+            == synapse_config::SYNAPSE_DATABASE_DRIVER_NAME_SQLITE3
+        {
+            synapse_sqlite_connection(&synapse_config).await?
+        } else {
+            let syn_connection_options = synapse_config
+                .database
+                .to_sqlx_postgres()
+                .context("Synapse database configuration is invalid, cannot migrate.")?;
+            let conn = PgConnection::connect_with(&syn_connection_options)
+                .await
+                .context("could not connect to Synapse Postgres database")?;
+            SynapseConnection::Postgres(conn)
+        };
+
+        let config =
+            DatabaseConfig::extract_or_default(&self.figment).map_err(anyhow::Error::from_boxed)?;
+        let mas_connection = database_connection_from_config_with_options(
+            &config,
+            &DatabaseConnectOptions {
+                log_slow_statements: false,
+            },
+        )
+        .await?;
+
+        let Either::Left(mut mas_connection) = LockedMasDatabase::try_new(mas_connection)
+            .await
+            .context("failed to issue query to lock database")?
+        else {
+            anyhow::bail!("Failed to acquire syn2mas lock on the database");
+        };
+
+        // Warnings can't be reviewed before migrating here, so only the errors prevent
+        // migrating, and the warnings are reported with the status of the migration
+        let (mut check_warnings, mut check_errors) = syn2mas::synapse_config_check(&synapse_config);
+        let (extra_warnings, extra_errors) =
+            syn2mas::synapse_config_check_against_mas_config(&synapse_config, &self.figment)
+                .await?;
+        check_warnings.extend(extra_warnings);
+        check_errors.extend(extra_errors);
+        syn2mas::mas_pre_migration_checks(&mut mas_connection, false).await?;
+        let (extra_warnings, extra_errors) = syn2mas::synapse_database_check(
+            &mut syn_conn,
+            &synapse_config,
+            &self.figment,
+            UnknownProviderPolicy::default(),
+        )
+        .await?;
+        check_warnings.extend(extra_warnings);
+        check_errors.extend(extra_errors);
+
+        for warning in &check_warnings {
+            warn!("{warning}");
+        }
+        warnings
+            .lock()
+            .unwrap()
+            .extend(check_warnings.iter().map(ToString::to_string));
+
+        if !check_errors.is_empty() {
+            anyhow::bail!(
+                "Cannot migrate from Synapse right now: {}",
+                check_errors.iter().join("; ")
+            );
+        }
+
+        let provider_id_mappings = provider_id_mappings(&self.figment)?;
+        let clock = SystemClock::default();
+
+        let mut reader = SynapseReader::from_connection(&mut syn_conn, false).await?;
+        reader
+            .assert_synapse_stopped(
+                clock.now(),
+                chrono::Duration::minutes(SYNAPSE_ACTIVITY_THRESHOLD_MINUTES),
+            )
+            .await?;
+        let writer_mas_connections = writer_mas_connections(&config).await?;
+        let writer = MasWriter::new(mas_connection, writer_mas_connections, false).await?;
+
+        // XXX: we should disallow SeedableRng::from_entropy
+        let mut rng = rand_chacha::ChaChaRng::from_entropy();
+
+        let mas_matrix = MatrixConfig::extract(&self.figment).map_err(anyhow::Error::from_boxed)?;
+        let report = syn2mas::migrate(
+            reader,
+            writer,
+            mas_matrix.homeserver,
+            &clock,
+            &mut rng,
+            provider_id_mappings,
+            &progress,
+            &MigrationOptions {
+                synapse_server_name: Some(synapse_config.server_name.clone()),
+                ..MigrationOptions::default()
+            },
+        )
+        .await?;
+
+        info!("Migrated {report}");
+
+        Ok(report)
+    }
+}
+
 /// Logs progress every 5 seconds, as a lightweight alternative to a progress
 /// bar. For most deployments, the migration will not take 5 seconds so this
 /// will not be relevant. In other cases, this will give the operator an idea of
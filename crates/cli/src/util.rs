@@ -150,9 +150,13 @@ pub async fn policy_factory_from_config(
     let data =
         mas_policy::Data::new(matrix_config.homeserver.clone()).with_rest(config.data.clone());
 
-    PolicyFactory::load(policy_file, data, entrypoints)
+    let policy_factory = PolicyFactory::load(policy_file, data, entrypoints)
         .await
-        .context("failed to load the policy")
+        .context("failed to load the policy")?;
+
+    policy_factory
+        .with_data_schema(&config.data_schema)
+        .context("invalid policy data schema")
 }
 
 pub fn captcha_config_from_config(
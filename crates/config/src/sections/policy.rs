@@ -128,6 +128,15 @@ pub struct PolicyConfig {
     /// Arbitrary data to pass to the policy
     #[serde(default = "default_data", skip_serializing_if = "is_default_data")]
     pub data: serde_json::Value,
+
+    /// JSON Schema which the policy data set through the admin API must match
+    ///
+    /// Policy data which doesn't match it is rejected. It is also served by the
+    /// admin API, so that clients can validate the policy data before setting
+    /// it. Defaults to an empty schema, which allows anything, so leave it
+    /// unset to store free-form policy data.
+    #[serde(default = "default_data", skip_serializing_if = "is_default_data")]
+    pub data_schema: serde_json::Value,
 }
 
 impl Default for PolicyConfig {
@@ -140,6 +149,7 @@ impl Default for PolicyConfig {
             password_entrypoint: default_password_entrypoint(),
             email_entrypoint: default_email_entrypoint(),
             data: default_data(),
+            data_schema: default_data(),
         }
     }
 }
@@ -154,6 +164,7 @@ impl PolicyConfig {
             && is_default_password_entrypoint(&self.password_entrypoint)
             && is_default_email_entrypoint(&self.email_entrypoint)
             && is_default_data(&self.data)
+            && is_default_data(&self.data_schema)
     }
 }
 
@@ -31,6 +31,15 @@ pub struct RateLimitingConfig {
     /// Email authentication-specific rate limits
     #[serde(default)]
     pub email_authentication: EmailauthenticationRateLimitingConfig,
+
+    /// Controls how many times the policy data can be set or reset through the
+    /// admin API, based on the client making the requests.
+    ///
+    /// This is enforced from the policy data versions saved in the database,
+    /// so at most `burst` versions can be set by a client over
+    /// `burst / per_second` seconds, across all the instances.
+    #[serde(default = "default_admin_policy_data_set")]
+    pub admin_policy_data_set: RateLimiterConfiguration,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
@@ -174,6 +183,10 @@ impl ConfigurationSection for RateLimitingConfig {
             return Err(error_on_nested_field(error, "login", "per_account").into());
         }
 
+        if let Some(error) = error_on_limiter(&self.admin_policy_data_set) {
+            return Err(error_on_field(error, "admin_policy_data_set").into());
+        }
+
         Ok(())
     }
 }
@@ -192,6 +205,14 @@ impl RateLimiterConfiguration {
         }
         Some(Quota::with_period(Duration::from_secs_f64(reciprocal))?.allow_burst(self.burst))
     }
+
+    /// The window over which the whole burst replenishes, for the rate limits
+    /// which count past actions instead of using a [`Quota`]
+    #[must_use]
+    pub fn to_window(self) -> Duration {
+        Duration::try_from_secs_f64(f64::from(self.burst.get()) / self.per_second)
+            .unwrap_or(Duration::MAX)
+    }
 }
 
 fn default_login_per_ip() -> RateLimiterConfiguration {
@@ -250,6 +271,13 @@ fn default_email_authentication_emails_per_session() -> RateLimiterConfiguration
     }
 }
 
+fn default_admin_policy_data_set() -> RateLimiterConfiguration {
+    RateLimiterConfiguration {
+        burst: NonZeroU32::new(10).unwrap(),
+        per_second: 10.0 / 60.0,
+    }
+}
+
 fn default_email_authentication_attempt_per_session() -> RateLimiterConfiguration {
     RateLimiterConfiguration {
         burst: NonZeroU32::new(10).unwrap(),
@@ -264,6 +292,7 @@ impl Default for RateLimitingConfig {
             registration: default_registration(),
             account_recovery: AccountRecoveryRateLimitingConfig::default(),
             email_authentication: EmailauthenticationRateLimitingConfig::default(),
+            admin_policy_data_set: default_admin_policy_data_set(),
         }
     }
 }
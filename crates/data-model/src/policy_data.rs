@@ -11,5 +11,7 @@ use ulid::Ulid;
 pub struct PolicyData {
     pub id: Ulid,
     pub created_at: DateTime<Utc>,
+    pub created_by_user_id: Option<Ulid>,
+    pub created_by_client_id: Option<Ulid>,
     pub data: serde_json::Value,
 }
@@ -565,6 +565,9 @@ pub struct PolicyData {
     /// The creation date of the policy data
     created_at: DateTime<Utc>,
 
+    /// Who set the policy data. If null, it was not set through the admin API.
+    created_by: Option<PolicyDataAuthor>,
+
     /// The policy data content
     data: serde_json::Value,
 }
@@ -574,11 +577,30 @@ impl From<mas_data_model::PolicyData> for PolicyData {
         Self {
             id: policy_data.id,
             created_at: policy_data.created_at,
+            created_by: policy_data
+                .created_by_client_id
+                .map(|client_id| PolicyDataAuthor {
+                    user_id: policy_data.created_by_user_id,
+                    client_id,
+                }),
             data: policy_data.data,
         }
     }
 }
 
+/// The user and client which set some policy data
+#[derive(Serialize, JsonSchema)]
+pub struct PolicyDataAuthor {
+    /// The ID of the user who set the policy data, if it was set on behalf of
+    /// a user
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    user_id: Option<Ulid>,
+
+    /// The ID of the client which set the policy data
+    #[schemars(with = "super::schema::Ulid")]
+    client_id: Ulid,
+}
+
 impl Resource for PolicyData {
     const KIND: &'static str = "policy-data";
     const PATH: &'static str = "/api/admin/v1/policy-data";
@@ -589,17 +611,54 @@ impl Resource for PolicyData {
 }
 
 impl PolicyData {
-    /// Samples of policy data
-    pub fn samples() -> [Self; 1] {
-        [Self {
-            id: Ulid::from_bytes([0x01; 16]),
-            created_at: DateTime::default(),
-            data: serde_json::json!({
-                "hello": "world",
-                "foo": 42,
-                "bar": true
-            }),
-        }]
+    /// Samples of policy data, with different shapes of data
+    pub fn samples() -> [Self; 3] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                created_at: DateTime::default(),
+                created_by: None,
+                data: serde_json::json!({
+                    "hello": "world",
+                    "foo": 42,
+                    "bar": true
+                }),
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                created_at: DateTime::default(),
+                created_by: Some(PolicyDataAuthor {
+                    user_id: Some(Ulid::from_bytes([0x04; 16])),
+                    client_id: Ulid::from_bytes([0x05; 16]),
+                }),
+                data: serde_json::json!({
+                    "emails": {
+                        "banned_addresses": {
+                            "literals": ["alice@example.com"],
+                            "regexes": ["@spam\\.example\\.com$"]
+                        }
+                    }
+                }),
+            },
+            Self {
+                id: Ulid::from_bytes([0x03; 16]),
+                created_at: DateTime::default(),
+                created_by: Some(PolicyDataAuthor {
+                    user_id: None,
+                    client_id: Ulid::from_bytes([0x05; 16]),
+                }),
+                data: serde_json::json!({
+                    "registration": {
+                        "banned_usernames": {
+                            "literals": ["admin", "root"]
+                        }
+                    },
+                    "requester": {
+                        "banned_ips": ["192.0.2.0/24"]
+                    }
+                }),
+            },
+        ]
     }
 }
 
@@ -688,3 +747,186 @@ impl UserRegistrationToken {
         ]
     }
 }
+
+/// The status of a Synapse migration
+#[derive(Serialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SynapseMigrationStatus {
+    /// The migration is still running
+    Running,
+
+    /// The migration finished successfully
+    Succeeded,
+
+    /// The migration failed
+    Failed,
+}
+
+/// The progress of a phase of a Synapse migration
+#[derive(Serialize, JsonSchema)]
+pub struct SynapseMigrationPhase {
+    /// The type of rows migrated in this phase
+    phase: &'static str,
+
+    /// The number of rows processed so far, migrated or skipped
+    processed: u64,
+
+    /// The approximate total number of rows to process
+    total: Option<u64>,
+}
+
+impl From<syn2mas::MigrationProgress> for SynapseMigrationPhase {
+    fn from(progress: syn2mas::MigrationProgress) -> Self {
+        Self {
+            phase: progress.phase.name(),
+            processed: progress.processed,
+            total: progress.total,
+        }
+    }
+}
+
+/// A migration from Synapse, started through the admin API
+#[derive(Serialize, JsonSchema)]
+pub struct SynapseMigration {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The status of the migration
+    status: SynapseMigrationStatus,
+
+    /// When the migration was started
+    started_at: DateTime<Utc>,
+
+    /// When the migration finished. If null, the migration is still running.
+    finished_at: Option<DateTime<Utc>>,
+
+    /// What the migration is currently doing. If null, the migration is
+    /// finished.
+    stage: Option<String>,
+
+    /// The progress of the phases started so far. It is updated every 1000
+    /// rows.
+    phases: Vec<SynapseMigrationPhase>,
+
+    /// The warnings of the pre-migration checks, which didn't prevent the
+    /// migration from running
+    warnings: Vec<String>,
+
+    /// A summary of what was migrated, if the migration succeeded
+    report: Option<String>,
+
+    /// The total number of rows migrated, if the migration succeeded
+    migrated_rows: Option<u64>,
+
+    /// Why the migration failed, if it did
+    error: Option<String>,
+}
+
+impl From<&crate::synapse_migration::SynapseMigrationJob> for SynapseMigration {
+    fn from(job: &crate::synapse_migration::SynapseMigrationJob) -> Self {
+        let outcome = job.outcome();
+        let (status, report, migrated_rows, error) =
+            match outcome.as_ref().map(|outcome| &outcome.result) {
+                None => (SynapseMigrationStatus::Running, None, None, None),
+                Some(Ok(report)) => (
+                    SynapseMigrationStatus::Succeeded,
+                    Some(report.to_string()),
+                    Some(report.total_rows()),
+                    None,
+                ),
+                Some(Err(error)) => (
+                    SynapseMigrationStatus::Failed,
+                    None,
+                    None,
+                    Some(error.clone()),
+                ),
+            };
+
+        Self {
+            id: job.id(),
+            status,
+            started_at: job.started_at(),
+            finished_at: outcome.as_ref().map(|outcome| outcome.finished_at),
+            stage: job.current_stage(),
+            phases: job.phases().into_iter().map(Into::into).collect(),
+            warnings: job.warnings(),
+            report,
+            migrated_rows,
+            error,
+        }
+    }
+}
+
+impl Resource for SynapseMigration {
+    const KIND: &'static str = "synapse-migration";
+    const PATH: &'static str = "/api/admin/v1/migrations/synapse";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+impl SynapseMigration {
+    /// Samples of Synapse migrations, running, succeeded and failed
+    pub fn samples() -> [Self; 3] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                status: SynapseMigrationStatus::Running,
+                started_at: DateTime::default(),
+                finished_at: None,
+                stage: Some("migrating devices".to_owned()),
+                phases: vec![
+                    SynapseMigrationPhase {
+                        phase: "users",
+                        processed: 2000,
+                        total: Some(2042),
+                    },
+                    SynapseMigrationPhase {
+                        phase: "devices",
+                        processed: 1000,
+                        total: Some(5120),
+                    },
+                ],
+                warnings: vec![
+                    "Synapse config has registration enabled. This must be disabled after \
+                    migration before bringing Synapse back online."
+                        .to_owned(),
+                ],
+                report: None,
+                migrated_rows: None,
+                error: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                status: SynapseMigrationStatus::Succeeded,
+                started_at: DateTime::default(),
+                finished_at: Some(DateTime::default() + chrono::Duration::minutes(5)),
+                stage: None,
+                phases: vec![SynapseMigrationPhase {
+                    phase: "users",
+                    processed: 2000,
+                    total: Some(2042),
+                }],
+                warnings: Vec::new(),
+                report: Some(
+                    "2042 users (1 Synapse admins, 0 in appservice namespaces), ...".to_owned(),
+                ),
+                migrated_rows: Some(2042),
+                error: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x03; 16]),
+                status: SynapseMigrationStatus::Failed,
+                started_at: DateTime::default(),
+                finished_at: Some(DateTime::default() + chrono::Duration::seconds(3)),
+                stage: None,
+                phases: Vec::new(),
+                warnings: Vec::new(),
+                report: None,
+                migrated_rows: None,
+                error: Some("The MAS database is not empty".to_owned()),
+            },
+        ]
+    }
+}
@@ -7,7 +7,7 @@
 // Generated code from schemars violates this rule
 #![allow(clippy::str_to_string)]
 
-use std::num::NonZeroUsize;
+use std::{convert::Infallible, num::NonZeroUsize};
 
 use aide::OperationIo;
 use axum::{
@@ -19,7 +19,7 @@ use axum::{
     response::IntoResponse,
 };
 use axum_macros::FromRequestParts;
-use hyper::StatusCode;
+use hyper::{StatusCode, header::ACCEPT};
 use mas_storage::pagination::PaginationDirection;
 use schemars::JsonSchema;
 use serde::Deserialize;
@@ -138,3 +138,37 @@ impl<S: Send + Sync> FromRequestParts<S> for Pagination {
         }))
     }
 }
+
+/// The media type of newline-delimited JSON
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// An extractor which tells whether the client asked, through the `Accept`
+/// header, for the resources to be streamed as newline-delimited JSON
+#[derive(Debug, Clone, Copy)]
+pub struct AcceptNdjson(pub bool);
+
+impl<S: Send + Sync> FromRequestParts<S> for AcceptNdjson {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let accepted = parts
+            .headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|media_type| {
+                media_type
+                    .split(';')
+                    .next()
+                    .is_some_and(|essence| essence.trim().eq_ignore_ascii_case(NDJSON))
+            });
+
+        Ok(Self(accepted))
+    }
+}
+
+impl aide::OperationInput for AcceptNdjson {}
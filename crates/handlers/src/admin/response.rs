@@ -6,12 +6,20 @@
 
 #![allow(clippy::module_name_repetitions)]
 
-use mas_storage::Pagination;
+use aide::{OperationOutput, generate::GenContext, openapi::Operation};
+use axum::{
+    Json,
+    body::Body,
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream::BoxStream};
+use hyper::header::CONTENT_TYPE;
+use mas_storage::{Pagination, RepositoryError};
 use schemars::JsonSchema;
 use serde::Serialize;
 use ulid::Ulid;
 
-use super::model::Resource;
+use super::{model::Resource, params::NDJSON};
 
 /// Related links
 #[derive(Serialize, JsonSchema)]
@@ -127,6 +135,59 @@ impl<T: Resource> PaginatedResponse<T> {
     }
 }
 
+/// A response to a list request, either as a page of resources, or as all the
+/// resources streamed as newline-delimited JSON
+pub enum ListResponse<T> {
+    /// A page of resources
+    Page(PaginatedResponse<T>),
+
+    /// All the resources, streamed one per line, without pagination links
+    Stream(BoxStream<'static, Result<T, RepositoryError>>),
+}
+
+impl<T: Resource + Serialize + 'static> IntoResponse for ListResponse<T> {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Page(page) => Json(page).into_response(),
+            Self::Stream(stream) => {
+                let lines = stream.map(|resource| {
+                    let resource = resource.inspect_err(|error| {
+                        tracing::error!(
+                            error = error as &dyn std::error::Error,
+                            "Failed to fetch the resources to stream"
+                        );
+                    })?;
+                    let mut line = serde_json::to_vec(&SingleResource::new(resource))?;
+                    line.push(b'\n');
+                    Ok::<_, Box<dyn std::error::Error + Send + Sync>>(line)
+                });
+
+                ([(CONTENT_TYPE, NDJSON)], Body::from_stream(lines)).into_response()
+            }
+        }
+    }
+}
+
+// The streaming variant is documented in the description of the operations,
+// so this only documents the paginated response
+impl<T: JsonSchema> OperationOutput for ListResponse<T> {
+    type Inner = PaginatedResponse<T>;
+
+    fn operation_response(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Option<aide::openapi::Response> {
+        Json::<PaginatedResponse<T>>::operation_response(ctx, operation)
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, aide::openapi::Response)> {
+        Json::<PaginatedResponse<T>>::inferred_responses(ctx, operation)
+    }
+}
+
 /// A single resource, with its type, ID, attributes and related links
 #[derive(Serialize, JsonSchema)]
 struct SingleResource<T> {
@@ -188,6 +249,22 @@ impl<T: Resource> SingleResponse<T> {
     }
 }
 
+/// A top-level response with a single resource, without top-level links, as
+/// the canonical link of the resource is already in the resource itself
+#[derive(Serialize, JsonSchema)]
+pub struct EmbeddedResponse<T> {
+    data: SingleResource<T>,
+}
+
+impl<T: Resource> EmbeddedResponse<T> {
+    /// Create a new embedded response with the given resource
+    pub fn new(resource: T) -> Self {
+        Self {
+            data: SingleResource::new(resource),
+        }
+    }
+}
+
 /// A single error
 #[derive(Serialize, JsonSchema)]
 struct Error {
@@ -221,4 +298,12 @@ impl ErrorResponse {
         }
         Self { errors }
     }
+
+    /// Add more errors after the existing ones
+    #[must_use]
+    pub fn with_errors(mut self, titles: impl IntoIterator<Item = String>) -> Self {
+        self.errors
+            .extend(titles.into_iter().map(|title| Error { title }));
+        self
+    }
 }
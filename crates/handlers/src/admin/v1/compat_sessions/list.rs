@@ -10,9 +10,11 @@ use axum::{
     response::IntoResponse,
 };
 use axum_macros::FromRequestParts;
+use futures_util::{StreamExt, TryStreamExt, stream::BoxStream};
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
-use mas_storage::{Page, compat::CompatSessionFilter};
+use mas_data_model::{BrowserSession, User};
+use mas_storage::{BoxRepository, Page, RepositoryError, compat::CompatSessionFilter};
 use schemars::JsonSchema;
 use serde::Deserialize;
 use ulid::Ulid;
@@ -21,12 +23,16 @@ use crate::{
     admin::{
         call_context::CallContext,
         model::{CompatSession, Resource},
-        params::Pagination,
-        response::{ErrorResponse, PaginatedResponse},
+        params::{AcceptNdjson, Pagination},
+        response::{ErrorResponse, ListResponse, PaginatedResponse},
     },
     impl_from_error_for_route,
 };
 
+/// How many sessions are fetched from the database at once when streaming
+/// them
+const STREAM_BATCH_SIZE: usize = 1000;
+
 #[derive(Deserialize, JsonSchema, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 enum CompatSessionStatus {
@@ -131,7 +137,9 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .summary("List compatibility sessions")
         .description("Retrieve a list of compatibility sessions.
 Note that by default, all sessions, including finished ones are returned, with the oldest first.
-Use the `filter[status]` parameter to filter the sessions by their status and `page[last]` parameter to retrieve the last N sessions.")
+Use the `filter[status]` parameter to filter the sessions by their status and `page[last]` parameter to retrieve the last N sessions.
+
+To export all the sessions at once, send an `Accept: application/x-ndjson` header: every session matching the filters is then streamed as one resource per line, and the pagination parameters are ignored.")
         .tag("compat-session")
         .response_with::<200, Json<PaginatedResponse<CompatSession>>, _>(|t| {
             let sessions = CompatSession::samples();
@@ -156,14 +164,81 @@ Use the `filter[status]` parameter to filter the sessions by their status and `p
         })
 }
 
+fn session_filter<'a>(
+    user: Option<&'a User>,
+    user_session: Option<&'a BrowserSession>,
+    status: Option<CompatSessionStatus>,
+) -> CompatSessionFilter<'a> {
+    let filter = CompatSessionFilter::default();
+
+    let filter = match user {
+        Some(user) => filter.for_user(user),
+        None => filter,
+    };
+
+    let filter = match user_session {
+        Some(user_session) => filter.for_browser_session(user_session),
+        None => filter,
+    };
+
+    match status {
+        Some(CompatSessionStatus::Active) => filter.active_only(),
+        Some(CompatSessionStatus::Finished) => filter.finished_only(),
+        None => filter,
+    }
+}
+
+/// What is needed to fetch the next batch of sessions when streaming them
+struct StreamState {
+    repo: BoxRepository,
+    user: Option<User>,
+    user_session: Option<BrowserSession>,
+    status: Option<CompatSessionStatus>,
+    after: Option<Ulid>,
+}
+
+/// Stream all the sessions matching the filter, fetching them in batches of
+/// [`STREAM_BATCH_SIZE`] so that they are never all loaded in memory
+fn stream_sessions(
+    state: StreamState,
+) -> BoxStream<'static, Result<CompatSession, RepositoryError>> {
+    futures_util::stream::try_unfold(Some(state), |state| async move {
+        let Some(mut state) = state else {
+            return Ok(None);
+        };
+
+        let filter = session_filter(
+            state.user.as_ref(),
+            state.user_session.as_ref(),
+            state.status,
+        );
+        let pagination = match state.after {
+            Some(after) => mas_storage::Pagination::first(STREAM_BATCH_SIZE).after(after),
+            None => mas_storage::Pagination::first(STREAM_BATCH_SIZE),
+        };
+        let page = state.repo.compat_session().list(filter, pagination).await?;
+
+        state.after = page.edges.last().map(|(session, _)| session.id);
+        let next = page.has_next_page.then_some(state);
+        let sessions = page
+            .edges
+            .into_iter()
+            .map(|edge| Ok(CompatSession::from(edge)));
+
+        Ok(Some((futures_util::stream::iter(sessions), next)))
+    })
+    .try_flatten()
+    .boxed()
+}
+
 #[tracing::instrument(name = "handler.admin.v1.compat_sessions.list", skip_all)]
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     Pagination(pagination): Pagination,
+    AcceptNdjson(stream): AcceptNdjson,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<CompatSession>>, RouteError> {
+) -> Result<ListResponse<CompatSession>, RouteError> {
     let base = format!("{path}{params}", path = CompatSession::PATH);
-    let filter = CompatSessionFilter::default();
 
     // Load the user from the filter
     let user = if let Some(user_id) = params.user {
@@ -178,11 +253,6 @@ pub async fn handler(
         None
     };
 
-    let filter = match &user {
-        Some(user) => filter.for_user(user),
-        None => filter,
-    };
-
     let user_session = if let Some(user_session_id) = params.user_session {
         let user_session = repo
             .browser_session()
@@ -195,21 +265,22 @@ pub async fn handler(
         None
     };
 
-    let filter = match &user_session {
-        Some(user_session) => filter.for_browser_session(user_session),
-        None => filter,
-    };
+    if stream {
+        return Ok(ListResponse::Stream(stream_sessions(StreamState {
+            repo,
+            user,
+            user_session,
+            status: params.status,
+            after: None,
+        })));
+    }
 
-    let filter = match params.status {
-        Some(CompatSessionStatus::Active) => filter.active_only(),
-        Some(CompatSessionStatus::Finished) => filter.finished_only(),
-        None => filter,
-    };
+    let filter = session_filter(user.as_ref(), user_session.as_ref(), params.status);
 
     let page = repo.compat_session().list(filter, pagination).await?;
     let count = repo.compat_session().count(filter).await?;
 
-    Ok(Json(PaginatedResponse::new(
+    Ok(ListResponse::Page(PaginatedResponse::new(
         page.map(CompatSession::from),
         pagination,
         count,
@@ -220,7 +291,10 @@ pub async fn handler(
 #[cfg(test)]
 mod tests {
     use chrono::Duration;
-    use hyper::{Request, StatusCode};
+    use hyper::{
+        Request, StatusCode,
+        header::{ACCEPT, CONTENT_TYPE},
+    };
     use insta::assert_json_snapshot;
     use mas_data_model::Device;
     use sqlx::PgPool;
@@ -455,4 +529,59 @@ mod tests {
         }
         "#);
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_compat_session_list_stream(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Provision a user with three compat sessions
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let mut session_ids = Vec::new();
+        for _ in 0..3 {
+            state.clock.advance(Duration::minutes(1));
+            let device = Device::generate(&mut rng);
+            let session = repo
+                .compat_session()
+                .add(&mut rng, &state.clock, &alice, device, None, false, None)
+                .await
+                .unwrap();
+            session_ids.push(session.id.to_string());
+        }
+        repo.save().await.unwrap();
+
+        // Pagination parameters are ignored when streaming
+        let request = Request::get("/api/admin/v1/compat-sessions?page[first]=1")
+            .bearer(&token)
+            .header(ACCEPT, "application/x-ndjson")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(CONTENT_TYPE, "application/x-ndjson");
+
+        let lines: Vec<serde_json::Value> = response
+            .body()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let ids: Vec<_> = lines
+            .iter()
+            .map(|line| line["id"].as_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(ids, session_ids);
+        assert_eq!(lines[0]["type"], "compat-session");
+        assert_eq!(
+            lines[0]["attributes"]["user_id"],
+            alice.id.to_string().as_str()
+        );
+        assert!(lines[0].get("meta").is_none());
+    }
 }
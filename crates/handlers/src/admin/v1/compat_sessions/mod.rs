@@ -3,10 +3,14 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+mod count;
 mod get;
 mod list;
+mod revoke;
 
 pub use self::{
+    count::{doc as count_doc, handler as count},
     get::{doc as get_doc, handler as get},
     list::{doc as list_doc, handler as list},
+    revoke::{doc as revoke_doc, handler as revoke},
 };
@@ -0,0 +1,212 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{
+    Json,
+    extract::{Query, rejection::QueryRejection},
+    response::IntoResponse,
+};
+use axum_macros::FromRequestParts;
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::compat::CompatSessionFilter;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admin::{call_context::CallContext, response::ErrorResponse},
+    impl_from_error_for_route,
+};
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "GcOrphansParams")]
+#[aide(input_with = "Query<Params>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct Params {
+    /// Only count the orphaned rows, without deleting them
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Invalid parameters")]
+    InvalidParams(#[from] QueryRejection),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidParams(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # Orphaned rows found in the database
+#[derive(Serialize, JsonSchema, Debug, PartialEq, Eq)]
+pub struct GcOrphansReport {
+    /// Whether the orphaned rows were only counted, and kept in the database
+    dry_run: bool,
+
+    /// The number of compatibility sessions without a device nor an access
+    /// token, which can't be used
+    compat_sessions: usize,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("gcOrphans")
+        .summary("Delete the compatibility sessions which can't be used anymore")
+        .description(
+            "Access tokens imported from Synapse which weren't tied to a device get a \
+            compatibility session without a device. If their access token is then removed \
+            from the database by other means than MAS, those sessions are left without a \
+            device nor an access token, and can't be used anymore. \
+            This deletes them, or only counts them if the `dry_run` parameter is set.",
+        )
+        .tag("compat-session")
+        .response_with::<200, Json<GcOrphansReport>, _>(|t| {
+            t.description("The orphaned rows were deleted, or counted")
+                .example(GcOrphansReport {
+                    dry_run: false,
+                    compat_sessions: 42,
+                })
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.maintenance.gc_orphans", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    Params { dry_run }: Params,
+) -> Result<Json<GcOrphansReport>, RouteError> {
+    let compat_sessions = if dry_run {
+        repo.compat_session()
+            .count(CompatSessionFilter::new().orphaned_only())
+            .await?
+    } else {
+        let count = repo.compat_session().remove_orphaned().await?;
+        repo.save().await?;
+        tracing::info!(compat_sessions = count, "Deleted orphaned rows");
+        count
+    };
+
+    Ok(Json(GcOrphansReport {
+        dry_run,
+        compat_sessions,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use insta::assert_json_snapshot;
+    use mas_data_model::Device;
+    use mas_storage::Clock;
+    use sqlx::{PgPool, types::Uuid};
+    use ulid::Ulid;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_gc_orphans(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Only syn2mas creates deviceless sessions
+        let orphaned_id = Ulid::from_datetime_with_source(state.clock.now().into(), &mut rng);
+        sqlx::query(
+            "INSERT INTO compat_sessions (compat_session_id, user_id, created_at)
+             VALUES ($1, $2, $3)",
+        )
+        .bind(Uuid::from(orphaned_id))
+        .bind(Uuid::from(user.id))
+        .bind(state.clock.now())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // A dry run only counts the orphaned sessions
+        let request = Request::post("/api/admin/v1/maintenance/gc-orphans?dry_run=true")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_json_snapshot!(body, @r###"
+        {
+          "dry_run": true,
+          "compat_sessions": 1
+        }
+        "###);
+
+        let mut repo = state.repository().await.unwrap();
+        assert!(
+            repo.compat_session()
+                .lookup(orphaned_id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        repo.save().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/maintenance/gc-orphans")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_json_snapshot!(body, @r###"
+        {
+          "dry_run": false,
+          "compat_sessions": 1
+        }
+        "###);
+
+        // The orphaned session is gone, but not the one with a device
+        let mut repo = state.repository().await.unwrap();
+        assert!(
+            repo.compat_session()
+                .lookup(orphaned_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.compat_session()
+                .lookup(session.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        repo.save().await.unwrap();
+    }
+}
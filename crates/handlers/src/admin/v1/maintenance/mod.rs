@@ -0,0 +1,8 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+mod gc_orphans;
+
+pub use self::gc_orphans::{doc as gc_orphans_doc, handler as gc_orphans};
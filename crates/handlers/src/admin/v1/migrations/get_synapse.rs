@@ -0,0 +1,238 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::SynapseMigration,
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    synapse_migration::SynapseMigrations,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error("Synapse migration ID {0} not found")]
+    NotFound(Ulid),
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, !);
+        let status = match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("getSynapseMigration")
+        .summary("Get the status of a migration from Synapse")
+        .description(
+            "Migrations are only tracked in memory by the server which started them, so they \
+            are forgotten when it restarts. Only the running migration and the latest finished \
+            one are kept.",
+        )
+        .tag("migration")
+        .response_with::<200, Json<SingleResponse<SynapseMigration>>, _>(|t| {
+            let [_, sample, ..] = SynapseMigration::samples();
+            let response = SingleResponse::new_canonical(sample);
+            t.description("The migration was found").example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("The migration was not found")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.migrations.get_synapse", skip_all)]
+pub async fn handler(
+    _: CallContext,
+    State(migrations): State<SynapseMigrations>,
+    id: UlidPathParam,
+) -> Result<Json<SingleResponse<SynapseMigration>>, RouteError> {
+    let job = migrations.get(*id).ok_or(RouteError::NotFound(*id))?;
+
+    Ok(Json(SingleResponse::new_canonical(SynapseMigration::from(
+        &*job,
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+    use syn2mas::{MigrationReport, Progress};
+    use tokio_util::task::TaskTracker;
+
+    use crate::{
+        synapse_migration::{SynapseMigrationRunner, SynapseMigrations},
+        test_utils::{RequestBuilderExt, ResponseExt, TestState, setup},
+    };
+
+    /// A runner whose migrations migrate a single user, with a warning
+    struct SingleUserRunner;
+
+    #[async_trait::async_trait]
+    impl SynapseMigrationRunner for SingleUserRunner {
+        async fn run(
+            &self,
+            _progress: Progress,
+            warnings: Arc<Mutex<Vec<String>>>,
+        ) -> Result<MigrationReport, anyhow::Error> {
+            warnings
+                .lock()
+                .unwrap()
+                .push("Synapse config has registration enabled.".to_owned());
+            Ok(MigrationReport {
+                users: 1,
+                ..MigrationReport::default()
+            })
+        }
+    }
+
+    /// A runner whose migrations panic
+    struct PanickingRunner;
+
+    #[async_trait::async_trait]
+    impl SynapseMigrationRunner for PanickingRunner {
+        async fn run(
+            &self,
+            _progress: Progress,
+            _warnings: Arc<Mutex<Vec<String>>>,
+        ) -> Result<MigrationReport, anyhow::Error> {
+            panic!("the migration panicked");
+        }
+    }
+
+    /// Start a migration and poll it until it finished, returning its ID and
+    /// attributes
+    async fn run_migration(state: &mut TestState, token: &str) -> (String, serde_json::Value) {
+        let request = Request::post("/api/admin/v1/migrations/synapse")
+            .bearer(token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::ACCEPTED);
+        let body: serde_json::Value = response.json();
+        let id = body["data"]["id"].as_str().unwrap().to_owned();
+
+        // The migration runs in the background, so poll it until it finished
+        let mut attributes = serde_json::Value::Null;
+        for _ in 0..100 {
+            let request = Request::get(format!("/api/admin/v1/migrations/synapse/{id}"))
+                .bearer(token)
+                .empty();
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::OK);
+            let body: serde_json::Value = response.json();
+            attributes = body["data"]["attributes"].clone();
+            if attributes["status"] != "running" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        (id, attributes)
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.synapse_migrations = SynapseMigrations::new(SingleUserRunner, &TaskTracker::new());
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let (_, attributes) = run_migration(&mut state, &token).await;
+
+        assert_eq!(attributes["status"], "succeeded");
+        assert_eq!(attributes["migrated_rows"], 1);
+        assert_eq!(attributes["stage"], serde_json::Value::Null);
+        assert_eq!(attributes["error"], serde_json::Value::Null);
+        assert_eq!(
+            attributes["warnings"],
+            serde_json::json!(["Synapse config has registration enabled."])
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get_only_latest(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.synapse_migrations = SynapseMigrations::new(SingleUserRunner, &TaskTracker::new());
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let (first_id, _) = run_migration(&mut state, &token).await;
+        let (second_id, _) = run_migration(&mut state, &token).await;
+
+        // Only the latest finished migration is kept
+        let request = Request::get(format!("/api/admin/v1/migrations/synapse/{first_id}"))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+
+        let request = Request::get(format!("/api/admin/v1/migrations/synapse/{second_id}"))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get_panicked(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.synapse_migrations = SynapseMigrations::new(PanickingRunner, &TaskTracker::new());
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        // A panic is reported as a failure, and doesn't block the next migration
+        let (_, attributes) = run_migration(&mut state, &token).await;
+        assert_eq!(attributes["status"], "failed");
+        assert!(
+            attributes["error"]
+                .as_str()
+                .unwrap()
+                .contains("the migration panicked")
+        );
+
+        let (_, attributes) = run_migration(&mut state, &token).await;
+        assert_eq!(attributes["status"], "failed");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::get("/api/admin/v1/migrations/synapse/01040G2081040G2081040G2081")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Synapse migration ID 01040G2081040G2081040G2081 not found"
+        );
+    }
+}
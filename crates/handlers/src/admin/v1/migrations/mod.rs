@@ -0,0 +1,12 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+mod get_synapse;
+mod start_synapse;
+
+pub use self::{
+    get_synapse::{doc as get_synapse_doc, handler as get_synapse},
+    start_synapse::{doc as start_synapse_doc, handler as start_synapse},
+};
@@ -0,0 +1,165 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::BoxRng;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::SynapseMigration,
+        response::{ErrorResponse, SingleResponse},
+    },
+    synapse_migration::{StartMigrationError, SynapseMigrations},
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Start(#[from] StartMigrationError),
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, !);
+        let status = match self {
+            Self::Start(StartMigrationError::Disabled) => StatusCode::FORBIDDEN,
+            Self::Start(StartMigrationError::AlreadyRunning(_)) => StatusCode::CONFLICT,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("startSynapseMigration")
+        .summary("Start a migration from Synapse")
+        .description(
+            "Starts migrating the users, devices and tokens from the Synapse database in a \
+            background task. The migration can then be followed with the returned ID. \
+            Only one migration can run at a time. This is only checked by the server \
+            handling the request: with several replicas, a migration started while another \
+            one is running on a different replica fails as it can't lock the database.",
+        )
+        .tag("migration")
+        .response_with::<202, Json<SingleResponse<SynapseMigration>>, _>(|t| {
+            let [sample, ..] = SynapseMigration::samples();
+            let response = SingleResponse::new_canonical(sample);
+            t.description("The migration was started").example(response)
+        })
+        .response_with::<403, RouteError, _>(|t| {
+            let response =
+                ErrorResponse::from_error(&RouteError::Start(StartMigrationError::Disabled));
+            t.description("Migrations from Synapse are not enabled on this server")
+                .example(response)
+        })
+        .response_with::<409, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::Start(
+                StartMigrationError::AlreadyRunning(Ulid::nil()),
+            ));
+            t.description("Another migration is still running")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.migrations.start_synapse", skip_all)]
+pub async fn handler(
+    CallContext { clock, .. }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    State(migrations): State<SynapseMigrations>,
+) -> Result<(StatusCode, Json<SingleResponse<SynapseMigration>>), RouteError> {
+    let job = migrations.start(clock, &mut rng)?;
+
+    tracing::info!(migration.id = %job.id(), "Started a migration from Synapse");
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(SingleResponse::new_canonical(SynapseMigration::from(&*job))),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+    use syn2mas::{MigrationReport, Progress};
+    use tokio_util::task::TaskTracker;
+
+    use crate::{
+        synapse_migration::{SynapseMigrationRunner, SynapseMigrations},
+        test_utils::{RequestBuilderExt, ResponseExt, TestState, setup},
+    };
+
+    /// A runner whose migrations never finish
+    struct PendingRunner;
+
+    #[async_trait::async_trait]
+    impl SynapseMigrationRunner for PendingRunner {
+        async fn run(
+            &self,
+            _progress: Progress,
+            _warnings: Arc<Mutex<Vec<String>>>,
+        ) -> Result<MigrationReport, anyhow::Error> {
+            std::future::pending().await
+        }
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_start(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.synapse_migrations = SynapseMigrations::new(PendingRunner, &TaskTracker::new());
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/migrations/synapse")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::ACCEPTED);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["type"], "synapse-migration");
+        assert_eq!(body["data"]["attributes"]["status"], "running");
+        assert_eq!(body["data"]["attributes"]["stage"], "setting up");
+        let id = body["data"]["id"].as_str().unwrap().to_owned();
+
+        // A second migration can't start while the first one is running
+        let request = Request::post("/api/admin/v1/migrations/synapse")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            format!("Synapse migration {id} is still running")
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_disabled(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/migrations/synapse")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Synapse migrations are disabled"
+        );
+    }
+}
@@ -16,12 +16,15 @@ use mas_policy::PolicyFactory;
 use mas_storage::BoxRng;
 
 use super::call_context::CallContext;
-use crate::passwords::PasswordManager;
+use crate::{Limiter, passwords::PasswordManager, synapse_migration::SynapseMigrations};
 
 mod compat_sessions;
+mod maintenance;
+mod migrations;
 mod oauth2_sessions;
 mod policy_data;
 mod upstream_oauth_links;
+mod upstream_oauth_providers;
 mod user_emails;
 mod user_registration_tokens;
 mod user_sessions;
@@ -32,7 +35,9 @@ where
     S: Clone + Send + Sync + 'static,
     Arc<dyn HomeserverConnection>: FromRef<S>,
     PasswordManager: FromRef<S>,
+    SynapseMigrations: FromRef<S>,
     Arc<PolicyFactory>: FromRef<S>,
+    Limiter: FromRef<S>,
     BoxRng: FromRequestParts<S>,
     CallContext: FromRequestParts<S>,
 {
@@ -45,6 +50,34 @@ where
             "/compat-sessions/{id}",
             get_with(self::compat_sessions::get, self::compat_sessions::get_doc),
         )
+        .api_route(
+            "/compat-sessions/{id}/revoke",
+            post_with(
+                self::compat_sessions::revoke,
+                self::compat_sessions::revoke_doc,
+            ),
+        )
+        .api_route(
+            "/maintenance/gc-orphans",
+            post_with(
+                self::maintenance::gc_orphans,
+                self::maintenance::gc_orphans_doc,
+            ),
+        )
+        .api_route(
+            "/migrations/synapse",
+            post_with(
+                self::migrations::start_synapse,
+                self::migrations::start_synapse_doc,
+            ),
+        )
+        .api_route(
+            "/migrations/synapse/{id}",
+            get_with(
+                self::migrations::get_synapse,
+                self::migrations::get_synapse_doc,
+            ),
+        )
         .api_route(
             "/oauth2-sessions",
             get_with(self::oauth2_sessions::list, self::oauth2_sessions::list_doc),
@@ -55,7 +88,13 @@ where
         )
         .api_route(
             "/policy-data",
-            post_with(self::policy_data::set, self::policy_data::set_doc),
+            get_with(self::policy_data::list, self::policy_data::list_doc)
+                .post_with(self::policy_data::set, self::policy_data::set_doc)
+                .delete_with(self::policy_data::reset, self::policy_data::reset_doc),
+        )
+        .api_route(
+            "/policy-data/diff",
+            post_with(self::policy_data::diff, self::policy_data::diff_doc),
         )
         .api_route(
             "/policy-data/latest",
@@ -64,6 +103,13 @@ where
                 self::policy_data::get_latest_doc,
             ),
         )
+        .api_route(
+            "/policy-data/schema",
+            get_with(
+                self::policy_data::get_schema,
+                self::policy_data::get_schema_doc,
+            ),
+        )
         .api_route(
             "/policy-data/{id}",
             get_with(self::policy_data::get, self::policy_data::get_doc),
@@ -73,6 +119,10 @@ where
             get_with(self::users::list, self::users::list_doc)
                 .post_with(self::users::add, self::users::add_doc),
         )
+        .api_route(
+            "/users:bulk-lock",
+            post_with(self::users::bulk_lock, self::users::bulk_lock_doc),
+        )
         .api_route(
             "/users/{id}",
             get_with(self::users::get, self::users::get_doc),
@@ -81,6 +131,13 @@ where
             "/users/{id}/set-password",
             post_with(self::users::set_password, self::users::set_password_doc),
         )
+        .api_route(
+            "/users/{id}/compat-sessions/count",
+            get_with(
+                self::compat_sessions::count,
+                self::compat_sessions::count_doc,
+            ),
+        )
         .api_route(
             "/users/by-username/{username}",
             get_with(self::users::by_username, self::users::by_username_doc),
@@ -181,4 +238,11 @@ where
                 self::upstream_oauth_links::delete_doc,
             ),
         )
+        .api_route(
+            "/upstream-oauth-providers/{id}/remap/{target_id}",
+            post_with(
+                self::upstream_oauth_providers::remap,
+                self::upstream_oauth_providers::remap_doc,
+            ),
+        )
 }
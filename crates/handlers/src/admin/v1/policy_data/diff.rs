@@ -0,0 +1,340 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::set::SetPolicyDataRequest;
+use crate::{
+    admin::{call_context::CallContext, response::ErrorResponse},
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// A value which was added or removed
+#[derive(Serialize, JsonSchema, Debug, PartialEq)]
+pub struct ValueEntry {
+    /// The JSON pointer to the value
+    path: String,
+
+    /// The value
+    value: Value,
+}
+
+/// A value which was changed
+#[derive(Serialize, JsonSchema, Debug, PartialEq)]
+pub struct ChangedEntry {
+    /// The JSON pointer to the value
+    path: String,
+
+    /// The value in the current policy data
+    old_value: Value,
+
+    /// The value in the proposed policy data
+    new_value: Value,
+}
+
+/// # Differences between the current and the proposed policy data
+///
+/// Objects are compared key by key, any other value is compared as a whole.
+#[derive(Serialize, JsonSchema, Default, Debug, PartialEq)]
+pub struct PolicyDataDiff {
+    /// Values which are only in the proposed policy data
+    added: Vec<ValueEntry>,
+
+    /// Values which are only in the current policy data
+    removed: Vec<ValueEntry>,
+
+    /// Values which are different between the current and the proposed policy
+    /// data
+    changed: Vec<ChangedEntry>,
+}
+
+impl PolicyDataDiff {
+    /// Compute the differences between the current and the proposed policy
+    /// data
+    fn compute(old: &Value, new: &Value) -> Self {
+        let mut diff = Self::default();
+        diff.visit(String::new(), old, new);
+        diff
+    }
+
+    fn visit(&mut self, path: String, old: &Value, new: &Value) {
+        let (Value::Object(old), Value::Object(new)) = (old, new) else {
+            if old != new {
+                self.changed.push(ChangedEntry {
+                    path,
+                    old_value: old.clone(),
+                    new_value: new.clone(),
+                });
+            }
+            return;
+        };
+
+        for (key, old_value) in old {
+            let path = format!("{path}/{}", escape_pointer_token(key));
+            match new.get(key) {
+                Some(new_value) => self.visit(path, old_value, new_value),
+                None => self.removed.push(ValueEntry {
+                    path,
+                    value: old_value.clone(),
+                }),
+            }
+        }
+
+        for (key, new_value) in new {
+            if !old.contains_key(key) {
+                self.added.push(ValueEntry {
+                    path: format!("{path}/{}", escape_pointer_token(key)),
+                    value: new_value.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Escape a key to be used as a JSON pointer reference token, as per RFC 6901
+fn escape_pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("diffPolicyData")
+        .summary("Compare policy data with the current one")
+        .description(
+            "Compare the provided policy data with the current one, without saving it. \
+            If there is no current policy data, it is compared with an empty object.",
+        )
+        .tag("policy-data")
+        .response_with::<200, Json<PolicyDataDiff>, _>(|t| {
+            let diff = PolicyDataDiff::compute(
+                &serde_json::json!({"hello": "world", "foo": 42}),
+                &serde_json::json!({"hello": "there", "bar": true}),
+            );
+            t.description("Differences with the current policy data")
+                .example(diff)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.policy_data.diff", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    Json(request): Json<SetPolicyDataRequest>,
+) -> Result<Json<PolicyDataDiff>, RouteError> {
+    let current = repo
+        .policy_data()
+        .get()
+        .await?
+        .map_or_else(|| Value::Object(serde_json::Map::new()), |data| data.data);
+
+    Ok(Json(PolicyDataDiff::compute(&current, &request.data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use insta::assert_json_snapshot;
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use super::PolicyDataDiff;
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[test]
+    fn test_compute() {
+        let diff = PolicyDataDiff::compute(
+            &json!({
+                "unchanged": 1,
+                "removed": "value",
+                "nested": {"a": 1, "b": [1, 2], "c/~": true},
+                "type": {"a": 1},
+            }),
+            &json!({
+                "unchanged": 1,
+                "nested": {"a": 2, "b": [1, 2, 3], "c/~": true, "d": null},
+                "type": [1],
+                "added": {"x": "y"},
+            }),
+        );
+
+        assert_json_snapshot!(diff, @r###"
+        {
+          "added": [
+            {
+              "path": "/nested/d",
+              "value": null
+            },
+            {
+              "path": "/added",
+              "value": {
+                "x": "y"
+              }
+            }
+          ],
+          "removed": [
+            {
+              "path": "/removed",
+              "value": "value"
+            }
+          ],
+          "changed": [
+            {
+              "path": "/nested/a",
+              "old_value": 1,
+              "new_value": 2
+            },
+            {
+              "path": "/nested/b",
+              "old_value": [
+                1,
+                2
+              ],
+              "new_value": [
+                1,
+                2,
+                3
+              ]
+            },
+            {
+              "path": "/type",
+              "old_value": {
+                "a": 1
+              },
+              "new_value": [
+                1
+              ]
+            }
+          ]
+        }
+        "###);
+
+        // Keys are escaped in the paths
+        let diff = PolicyDataDiff::compute(&json!({}), &json!({"a/b~c": 1}));
+        assert_eq!(diff.added[0].path, "/a~1b~0c");
+
+        // Identical data has no differences
+        let diff = PolicyDataDiff::compute(&json!({"a": [1]}), &json!({"a": [1]}));
+        assert_eq!(diff, PolicyDataDiff::default());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_diff(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        repo.policy_data()
+            .set(
+                &mut rng,
+                &state.clock,
+                None,
+                json!({"hello": "world", "foo": 42}),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/policy-data/diff")
+            .bearer(&token)
+            .json(json!({
+                "data": {
+                    "hello": "there",
+                    "bar": true
+                }
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_json_snapshot!(body, @r###"
+        {
+          "added": [
+            {
+              "path": "/bar",
+              "value": true
+            }
+          ],
+          "removed": [
+            {
+              "path": "/foo",
+              "value": 42
+            }
+          ],
+          "changed": [
+            {
+              "path": "/hello",
+              "old_value": "world",
+              "new_value": "there"
+            }
+          ]
+        }
+        "###);
+
+        // Nothing was saved
+        let mut repo = state.repository().await.unwrap();
+        let current = repo.policy_data().get().await.unwrap().unwrap();
+        assert_eq!(current.data, json!({"hello": "world", "foo": 42}));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_diff_without_current(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/policy-data/diff")
+            .bearer(&token)
+            .json(json!({
+                "data": {
+                    "hello": "world"
+                }
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_json_snapshot!(body, @r###"
+        {
+          "added": [
+            {
+              "path": "/hello",
+              "value": "world"
+            }
+          ],
+          "removed": [],
+          "changed": []
+        }
+        "###);
+
+        // Nothing was saved
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.policy_data().get().await.unwrap().is_none());
+    }
+}
@@ -5,6 +5,8 @@
 
 use aide::{OperationIo, transform::TransformOperation};
 use axum::{Json, response::IntoResponse};
+use axum_extra::TypedHeader;
+use headers::ETag;
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
 use ulid::Ulid;
@@ -49,9 +51,10 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .summary("Get policy data by ID")
         .tag("policy-data")
         .response_with::<200, Json<SingleResponse<PolicyData>>, _>(|t| {
-            let [sample, ..] = PolicyData::samples();
-            let response = SingleResponse::new_canonical(sample);
-            t.description("Policy data was found").example(response)
+            super::with_sample_examples(
+                t.description("Policy data was found"),
+                SingleResponse::new_canonical,
+            )
         })
         .response_with::<404, RouteError, _>(|t| {
             let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
@@ -63,14 +66,19 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     id: UlidPathParam,
-) -> Result<Json<SingleResponse<PolicyData>>, RouteError> {
+) -> Result<(TypedHeader<ETag>, Json<SingleResponse<PolicyData>>), RouteError> {
     let policy_data = repo
         .policy_data()
-        .get()
+        .lookup(*id)
         .await?
         .ok_or(RouteError::NotFound(*id))?;
 
-    Ok(Json(SingleResponse::new_canonical(policy_data.into())))
+    let etag = super::etag(&policy_data);
+
+    Ok((
+        TypedHeader(etag),
+        Json(SingleResponse::new_canonical(policy_data.into())),
+    ))
 }
 
 #[cfg(test)]
@@ -96,6 +104,7 @@ mod tests {
             .set(
                 &mut rng,
                 &state.clock,
+                None,
                 serde_json::json!({"hello": "world"}),
             )
             .await
@@ -116,6 +125,7 @@ mod tests {
             "id": "01FSHN9AG0MZAA6S4AF7CTV32E",
             "attributes": {
               "created_at": "2022-01-16T14:40:00Z",
+              "created_by": null,
               "data": {
                 "hello": "world"
               }
@@ -131,6 +141,59 @@ mod tests {
         "###);
     }
 
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get_older_version(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let old_policy_data = repo
+            .policy_data()
+            .set(
+                &mut rng,
+                &state.clock,
+                None,
+                serde_json::json!({"version": 1}),
+            )
+            .await
+            .unwrap();
+
+        state
+            .clock
+            .advance(chrono::Duration::try_minutes(1).unwrap());
+
+        repo.policy_data()
+            .set(
+                &mut rng,
+                &state.clock,
+                None,
+                serde_json::json!({"version": 2}),
+            )
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        // Fetching an older version by its ID should not return the latest one
+        let request = Request::get(format!("/api/admin/v1/policy-data/{}", old_policy_data.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["id"],
+            serde_json::json!(old_policy_data.id.to_string())
+        );
+        assert_eq!(
+            body["data"]["attributes"]["data"],
+            serde_json::json!({"version": 1})
+        );
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_get_not_found(pool: PgPool) {
         setup();
@@ -5,6 +5,8 @@
 
 use aide::{OperationIo, transform::TransformOperation};
 use axum::{Json, response::IntoResponse};
+use axum_extra::TypedHeader;
+use headers::ETag;
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
 
@@ -47,10 +49,10 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .summary("Get the latest policy data")
         .tag("policy-data")
         .response_with::<200, Json<SingleResponse<PolicyData>>, _>(|t| {
-            let [sample, ..] = PolicyData::samples();
-            let response = SingleResponse::new_canonical(sample);
-            t.description("Latest policy data was found")
-                .example(response)
+            super::with_sample_examples(
+                t.description("Latest policy data was found"),
+                SingleResponse::new_canonical,
+            )
         })
         .response_with::<404, RouteError, _>(|t| {
             let response = ErrorResponse::from_error(&RouteError::NotFound);
@@ -61,19 +63,24 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 #[tracing::instrument(name = "handler.admin.v1.policy_data.get_latest", skip_all)]
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
-) -> Result<Json<SingleResponse<PolicyData>>, RouteError> {
+) -> Result<(TypedHeader<ETag>, Json<SingleResponse<PolicyData>>), RouteError> {
     let policy_data = repo
         .policy_data()
         .get()
         .await?
         .ok_or(RouteError::NotFound)?;
 
-    Ok(Json(SingleResponse::new_canonical(policy_data.into())))
+    let etag = super::etag(&policy_data);
+
+    Ok((
+        TypedHeader(etag),
+        Json(SingleResponse::new_canonical(policy_data.into())),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
-    use hyper::{Request, StatusCode};
+    use hyper::{Request, StatusCode, header::ETAG};
     use insta::assert_json_snapshot;
     use sqlx::PgPool;
 
@@ -92,6 +99,7 @@ mod tests {
             .set(
                 &mut rng,
                 &state.clock,
+                None,
                 serde_json::json!({"hello": "world"}),
             )
             .await
@@ -104,6 +112,7 @@ mod tests {
             .empty();
         let response = state.request(request).await;
         response.assert_status(StatusCode::OK);
+        response.assert_header_value(ETAG, "\"01FSHN9AG0MZAA6S4AF7CTV32E\"");
         let body: serde_json::Value = response.json();
         assert_json_snapshot!(body, @r###"
         {
@@ -112,6 +121,7 @@ mod tests {
             "id": "01FSHN9AG0MZAA6S4AF7CTV32E",
             "attributes": {
               "created_at": "2022-01-16T14:40:00Z",
+              "created_by": null,
               "data": {
                 "hello": "world"
               }
@@ -0,0 +1,102 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::sync::Arc;
+
+use aide::transform::TransformOperation;
+use axum::{Json, extract::State};
+use mas_policy::PolicyFactory;
+
+use crate::admin::call_context::CallContext;
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("getPolicyDataSchema")
+        .summary("Get the JSON Schema of the policy data")
+        .description(
+            "Returns the JSON Schema document which the policy data must match when it is set, \
+            so that clients can validate it beforehand. \
+            When no schema is configured, this is an empty schema, which allows anything.",
+        )
+        .tag("policy-data")
+        .response_with::<200, Json<serde_json::Value>, _>(|t| {
+            t.description("The JSON Schema of the policy data")
+                .example(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "emails": {
+                            "type": "object",
+                            "properties": {
+                                "banned_addresses": {
+                                    "type": "object",
+                                },
+                            },
+                        },
+                    },
+                }))
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.policy_data.get_schema", skip_all)]
+pub async fn handler(
+    _: CallContext,
+    State(policy_factory): State<Arc<PolicyFactory>>,
+) -> Json<serde_json::Value> {
+    Json(policy_factory.data_schema().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, policy_factory, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get_schema(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        // No schema is configured in the tests, so this is the empty schema
+        let request = Request::get("/api/admin/v1/policy-data/schema")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body, serde_json::json!({}));
+
+        // Once configured, the enforced schema is served as-is
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "emails": {
+                    "type": "object",
+                },
+            },
+        });
+        let policy_factory = policy_factory(&state.site_config.server_name, serde_json::json!({}))
+            .await
+            .unwrap();
+        let Some(policy_factory) = Arc::into_inner(policy_factory) else {
+            panic!("the policy factory should not be shared yet");
+        };
+        let Ok(policy_factory) = policy_factory.with_data_schema(&schema) else {
+            panic!("the schema should be valid");
+        };
+        state.policy_factory = Arc::new(policy_factory);
+
+        let request = Request::get("/api/admin/v1/policy-data/schema")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body, schema);
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{
+    Json,
+    extract::{Query, rejection::QueryRejection},
+    response::IntoResponse,
+};
+use axum_macros::FromRequestParts;
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::{Page, policy_data::PolicyDataFilter};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{PolicyData, Resource},
+        params::Pagination,
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "PolicyDataFilter")]
+#[aide(input_with = "Query<FilterParams>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct FilterParams {
+    /// Retrieve only the latest version of the policy data
+    #[serde(rename = "filter[latest]")]
+    latest: Option<bool>,
+}
+
+impl std::fmt::Display for FilterParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sep = '?';
+
+        if let Some(latest) = self.latest {
+            write!(f, "{sep}filter[latest]={latest}")?;
+            sep = '&';
+        }
+
+        let _ = sep;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Invalid filter parameters")]
+    InvalidFilter(#[from] QueryRejection),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+        };
+
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listPolicyData")
+        .summary("List the versions of the policy data")
+        .description("The versions are listed from the oldest to the newest.")
+        .tag("policy-data")
+        .response_with::<200, Json<PaginatedResponse<PolicyData>>, _>(|t| {
+            let policy_data = PolicyData::samples();
+            let pagination = mas_storage::Pagination::first(policy_data.len());
+            let page = Page {
+                edges: policy_data.into(),
+                has_next_page: true,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of policy data")
+                .example(PaginatedResponse::new(
+                    page,
+                    pagination,
+                    42,
+                    PolicyData::PATH,
+                ))
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.policy_data.list", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    Pagination(pagination): Pagination,
+    params: FilterParams,
+) -> Result<Json<PaginatedResponse<PolicyData>>, RouteError> {
+    let base = format!("{path}{params}", path = PolicyData::PATH);
+    let filter = if params.latest == Some(true) {
+        PolicyDataFilter::new().latest_only()
+    } else {
+        PolicyDataFilter::new()
+    };
+
+    let page = repo.policy_data().list(filter, pagination).await?;
+    let count = repo.policy_data().count(filter).await?;
+
+    Ok(Json(PaginatedResponse::new(
+        page.map(PolicyData::from),
+        pagination,
+        count,
+        &base,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    async fn create_test_policy_data(state: &mut TestState) {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        for version in 1..=3 {
+            repo.policy_data()
+                .set(
+                    &mut rng,
+                    &state.clock,
+                    None,
+                    serde_json::json!({"version": version}),
+                )
+                .await
+                .unwrap();
+
+            state.clock.advance(Duration::try_minutes(1).unwrap());
+        }
+
+        repo.save().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        create_test_policy_data(&mut state).await;
+
+        let request = Request::get("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+
+        assert_eq!(body["meta"]["count"], 3);
+        let versions: Vec<_> = body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["attributes"]["data"]["version"].clone())
+            .collect();
+        assert_eq!(versions, [1, 2, 3]);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list_latest(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        create_test_policy_data(&mut state).await;
+
+        let request = Request::get("/api/admin/v1/policy-data?filter[latest]=true")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["attributes"]["data"]["version"], 3);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list_empty(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::get("/api/admin/v1/policy-data?filter[latest]=true")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+
+        assert_eq!(body["meta"]["count"], 0);
+        assert_eq!(body["data"].as_array().unwrap().len(), 0);
+    }
+}
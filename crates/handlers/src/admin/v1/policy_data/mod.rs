@@ -3,12 +3,66 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use aide::{
+    openapi::{Example, ReferenceOr},
+    transform::TransformResponse,
+};
+use headers::ETag;
+use serde::Serialize;
+
+use crate::admin::model::PolicyData;
+
+mod diff;
 mod get;
 mod get_latest;
+mod get_schema;
+mod list;
+mod reset;
 mod set;
 
 pub use self::{
+    diff::{doc as diff_doc, handler as diff},
     get::{doc as get_doc, handler as get},
     get_latest::{doc as get_latest_doc, handler as get_latest},
+    get_schema::{doc as get_schema_doc, handler as get_schema},
+    list::{doc as list_doc, handler as list},
+    reset::{doc as reset_doc, handler as reset},
     set::{doc as set_doc, handler as set},
 };
+
+/// Compute the `ETag` of a version of the policy data, derived from its ID
+fn etag(policy_data: &mas_data_model::PolicyData) -> ETag {
+    format!("\"{}\"", policy_data.id)
+        .parse()
+        .expect("ULIDs should be valid entity tags")
+}
+
+/// Names of the [`PolicyData::samples`], used as the keys of the examples in
+/// the API documentation
+const SAMPLE_NAMES: [&str; 3] = ["simple", "banned-emails", "banned-usernames-and-ips"];
+
+/// Add every [`PolicyData`] sample as a separate named example of a response,
+/// using `example` to build the response out of each sample
+fn with_sample_examples<T: Serialize>(
+    mut t: TransformResponse<'_, T>,
+    example: impl Fn(PolicyData) -> T,
+) -> TransformResponse<'_, T> {
+    for (name, sample) in SAMPLE_NAMES.into_iter().zip(PolicyData::samples()) {
+        let value =
+            serde_json::to_value(example(sample)).expect("examples should serialize to JSON");
+
+        for content in t.inner().content.values_mut() {
+            // `example` and `examples` are mutually exclusive
+            content.example = None;
+            content.examples.insert(
+                name.to_owned(),
+                ReferenceOr::Item(Example {
+                    value: Some(value.clone()),
+                    ..Example::default()
+                }),
+            );
+        }
+    }
+
+    t
+}
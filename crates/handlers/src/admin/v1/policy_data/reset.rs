@@ -0,0 +1,339 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::{sync::Arc, time::Duration};
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
+use axum_extra::TypedHeader;
+use headers::{IfMatch, RetryAfter};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_policy::PolicyFactory;
+use mas_storage::BoxRng;
+
+use crate::{
+    admin::{call_context::CallContext, response::ErrorResponse},
+    impl_from_error_for_route,
+    rate_limit::{Limiter, PolicyDataSetLimitedError},
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error("Policy data doesn't match the configured schema")]
+    InvalidPolicyData { errors: Vec<String> },
+
+    #[error("Policy data was changed since it was last fetched")]
+    StaleVersion,
+
+    #[error("Policy data was set too many times by this client, try again later")]
+    RateLimited { retry_after: Duration },
+
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(mas_policy::LoadError);
+
+impl From<PolicyDataSetLimitedError> for RouteError {
+    fn from(e: PolicyDataSetLimitedError) -> Self {
+        match e {
+            PolicyDataSetLimitedError::Client { retry_after, .. } => {
+                Self::RateLimited { retry_after }
+            }
+            PolicyDataSetLimitedError::Repository(e) => Self::from(e),
+        }
+    }
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let mut error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let (status, retry_after) = match self {
+            // List each schema violation as its own error
+            RouteError::InvalidPolicyData { errors } => {
+                error = error.with_errors(errors);
+                (StatusCode::BAD_REQUEST, None)
+            }
+            RouteError::StaleVersion => (StatusCode::PRECONDITION_FAILED, None),
+            RouteError::RateLimited { retry_after } => {
+                // Retry-After only has a precision of a second, so round up
+                let retry_after = Duration::from_secs(
+                    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0),
+                );
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Some(TypedHeader(RetryAfter::delay(retry_after))),
+                )
+            }
+            RouteError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, None),
+        };
+        (status, retry_after, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("resetPolicyData")
+        .summary("Reset the policy data")
+        .description(
+            "This sets the current policy data to an empty object, which disables all the \
+            custom policy data without removing the previous versions. If an `If-Match` header \
+            is provided, the policy data is only reset if the current policy data matches the \
+            given `ETag`, as returned when fetching it. \
+            The reset is rejected if an empty object doesn't match the schema returned by \
+            `GET /api/admin/v1/policy-data/schema`. \
+            Resetting the policy data counts towards the \
+            `rate_limiting.admin_policy_data_set` limit, like setting it.",
+        )
+        .tag("policy-data")
+        .response_with::<204, (), _>(|t| t.description("Policy data was reset"))
+        .response_with::<400, RouteError, _>(|t| {
+            let error =
+                ErrorResponse::from_error(&RouteError::InvalidPolicyData { errors: Vec::new() })
+                    .with_errors(["\"emails\" is a required property".to_owned()]);
+            t.description("The empty policy data doesn't match the configured schema")
+                .example(error)
+        })
+        .response_with::<412, RouteError, _>(|t| {
+            let error = ErrorResponse::from_error(&RouteError::StaleVersion);
+            t.description("Policy data was changed since it was last fetched")
+                .example(error)
+        })
+        .response_with::<429, RouteError, _>(|t| {
+            let error = ErrorResponse::from_error(&RouteError::RateLimited {
+                retry_after: Duration::from_secs(60),
+            });
+            t.description(
+                "Policy data was set too many times by this client. \
+                The `Retry-After` header tells how long to wait before trying again.",
+            )
+            .example(error)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.policy_data.reset", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        session,
+        ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    State(policy_factory): State<Arc<PolicyFactory>>,
+    State(limiter): State<Limiter>,
+    if_match: Option<TypedHeader<IfMatch>>,
+) -> Result<StatusCode, RouteError> {
+    limiter
+        .check_admin_policy_data_set(&mut repo, &clock, session.client_id)
+        .await?;
+
+    let data = serde_json::json!({});
+    policy_factory
+        .validate_data(&data)
+        .map_err(|errors| RouteError::InvalidPolicyData { errors })?;
+
+    if let Some(TypedHeader(if_match)) = if_match {
+        // Hold a lock until the new policy data is saved, so that two concurrent
+        // requests with the same `If-Match` can't both pass the check
+        repo.policy_data().acquire_lock().await?;
+
+        let current = repo.policy_data().get().await?;
+        let passes =
+            current.is_some_and(|current| if_match.precondition_passes(&super::etag(&current)));
+
+        if !passes {
+            return Err(RouteError::StaleVersion);
+        }
+    }
+
+    let policy_data = repo
+        .policy_data()
+        .set(&mut rng, &clock, Some(&session), data)
+        .await?;
+
+    policy_factory.set_dynamic_data(policy_data).await?;
+
+    repo.save().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hyper::{
+        Request, StatusCode,
+        header::{IF_MATCH, RETRY_AFTER},
+    };
+    use insta::assert_json_snapshot;
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, policy_factory, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_reset(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        repo.policy_data()
+            .set(
+                &mut rng,
+                &state.clock,
+                None,
+                serde_json::json!({"hello": "world"}),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::delete("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        // The latest policy data is now empty
+        let request = Request::get("/api/admin/v1/policy-data/latest")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["data"], serde_json::json!({}));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_reset_invalid_schema(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let policy_factory = policy_factory(&state.site_config.server_name, serde_json::json!({}))
+            .await
+            .unwrap();
+        let Some(policy_factory) = Arc::into_inner(policy_factory) else {
+            panic!("the policy factory should not be shared yet");
+        };
+        let Ok(policy_factory) = policy_factory.with_data_schema(&serde_json::json!({
+            "type": "object",
+            "required": ["emails"],
+        })) else {
+            panic!("the schema should be valid");
+        };
+        state.policy_factory = Arc::new(policy_factory);
+
+        let request = Request::delete("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_json_snapshot!(body, @r###"
+        {
+          "errors": [
+            {
+              "title": "Policy data doesn't match the configured schema"
+            },
+            {
+              "title": "\"emails\" is a required property"
+            }
+          ]
+        }
+        "###);
+
+        // Nothing was stored
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.policy_data().get().await.unwrap().is_none());
+        repo.cancel().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_reset_if_match(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let current = repo
+            .policy_data()
+            .set(
+                &mut rng,
+                &state.clock,
+                None,
+                serde_json::json!({"hello": "world"}),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Resetting the data with a stale version should fail
+        let request = Request::delete("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .header(IF_MATCH, "\"01FSHNB5300000000000000000\"")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::PRECONDITION_FAILED);
+
+        // Resetting the data with the current version should work
+        let request = Request::delete("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .header(IF_MATCH, format!("\"{}\"", current.id))
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_reset_rate_limited(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let reset = || {
+            Request::delete("/api/admin/v1/policy-data")
+                .bearer(&token)
+                .empty()
+        };
+
+        // Resetting shares the limit of 10 versions per minute with setting
+        for version in 0..5 {
+            let request = Request::post("/api/admin/v1/policy-data")
+                .bearer(&token)
+                .json(serde_json::json!({
+                    "data": {
+                        "version": version
+                    }
+                }));
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::CREATED);
+        }
+
+        for _ in 0..5 {
+            let response = state.request(reset()).await;
+            response.assert_status(StatusCode::NO_CONTENT);
+        }
+
+        let response = state.request(reset()).await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        response.assert_header_value(RETRY_AFTER, "60");
+
+        // The limit is lifted once the first versions fall out of the window
+        state
+            .clock
+            .advance(chrono::Duration::try_minutes(1).unwrap());
+        let response = state.request(reset()).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+    }
+}
@@ -3,10 +3,12 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use aide::{NoApi, OperationIo, transform::TransformOperation};
 use axum::{Json, extract::State, response::IntoResponse};
+use axum_extra::TypedHeader;
+use headers::{ETag, IfMatch, RetryAfter};
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
 use mas_policy::PolicyFactory;
@@ -18,16 +20,26 @@ use crate::{
     admin::{
         call_context::CallContext,
         model::PolicyData,
-        response::{ErrorResponse, SingleResponse},
+        response::{EmbeddedResponse, ErrorResponse},
     },
     impl_from_error_for_route,
+    rate_limit::{Limiter, PolicyDataSetLimitedError},
 };
 
 #[derive(Debug, thiserror::Error, OperationIo)]
 #[aide(output_with = "Json<ErrorResponse>")]
 pub enum RouteError {
+    #[error("Policy data doesn't match the configured schema")]
+    InvalidPolicyData { errors: Vec<String> },
+
     #[error("Failed to instanciate policy with the provided data")]
-    InvalidPolicyData(#[from] mas_policy::LoadError),
+    Instantiate(#[from] mas_policy::LoadError),
+
+    #[error("Policy data was changed since it was last fetched")]
+    StaleVersion,
+
+    #[error("Policy data was set too many times by this client, try again later")]
+    RateLimited { retry_after: Duration },
 
     #[error(transparent)]
     Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
@@ -35,15 +47,42 @@ pub enum RouteError {
 
 impl_from_error_for_route!(mas_storage::RepositoryError);
 
+impl From<PolicyDataSetLimitedError> for RouteError {
+    fn from(e: PolicyDataSetLimitedError) -> Self {
+        match e {
+            PolicyDataSetLimitedError::Client { retry_after, .. } => {
+                Self::RateLimited { retry_after }
+            }
+            PolicyDataSetLimitedError::Repository(e) => Self::from(e),
+        }
+    }
+}
+
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
-        let error = ErrorResponse::from_error(&self);
+        let mut error = ErrorResponse::from_error(&self);
         let sentry_event_id = record_error!(self, Self::Internal(_));
-        let status = match self {
-            RouteError::InvalidPolicyData(_) => StatusCode::BAD_REQUEST,
-            RouteError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        let (status, retry_after) = match self {
+            // List each schema violation as its own error
+            RouteError::InvalidPolicyData { errors } => {
+                error = error.with_errors(errors);
+                (StatusCode::BAD_REQUEST, None)
+            }
+            RouteError::Instantiate(_) => (StatusCode::BAD_REQUEST, None),
+            RouteError::StaleVersion => (StatusCode::PRECONDITION_FAILED, None),
+            RouteError::RateLimited { retry_after } => {
+                // Retry-After only has a precision of a second, so round up
+                let retry_after = Duration::from_secs(
+                    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0),
+                );
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Some(TypedHeader(RetryAfter::delay(retry_after))),
+                )
+            }
+            RouteError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, None),
         };
-        (status, sentry_event_id, Json(error)).into_response()
+        (status, retry_after, sentry_event_id, Json(error)).into_response()
     }
 }
 
@@ -67,33 +106,92 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
     operation
         .id("setPolicyData")
         .summary("Set the current policy data")
+        .description(
+            "If an `If-Match` header is provided, the policy data is only set if the current \
+            policy data matches the given `ETag`, as returned when fetching it. \
+            The data is rejected if it doesn't match the schema returned by \
+            `GET /api/admin/v1/policy-data/schema`, or if the policy fails to load with it. \
+            The number of times a client can set the policy data is limited by the \
+            `rate_limiting.admin_policy_data_set` configuration option. \
+            The link to the new policy data is only given in the returned resource.",
+        )
         .tag("policy-data")
-        .response_with::<201, Json<SingleResponse<PolicyData>>, _>(|t| {
-            let [sample, ..] = PolicyData::samples();
-            let response = SingleResponse::new_canonical(sample);
-            t.description("Policy data was successfully set")
-                .example(response)
+        .response_with::<201, Json<EmbeddedResponse<PolicyData>>, _>(|t| {
+            super::with_sample_examples(
+                t.description("Policy data was successfully set"),
+                EmbeddedResponse::new,
+            )
         })
         .response_with::<400, Json<ErrorResponse>, _>(|t| {
-            let error = ErrorResponse::from_error(&RouteError::InvalidPolicyData(
-                mas_policy::LoadError::invalid_data_example(),
-            ));
+            let error =
+                ErrorResponse::from_error(&RouteError::InvalidPolicyData { errors: Vec::new() })
+                    .with_errors(["/emails: 42 is not of type \"object\"".to_owned()]);
             t.description("Invalid policy data").example(error)
         })
+        .response_with::<412, RouteError, _>(|t| {
+            let error = ErrorResponse::from_error(&RouteError::StaleVersion);
+            t.description("Policy data was changed since it was last fetched")
+                .example(error)
+        })
+        .response_with::<429, RouteError, _>(|t| {
+            let error = ErrorResponse::from_error(&RouteError::RateLimited {
+                retry_after: Duration::from_secs(60),
+            });
+            t.description(
+                "Policy data was set too many times by this client. \
+                The `Retry-After` header tells how long to wait before trying again.",
+            )
+            .example(error)
+        })
 }
 
 #[tracing::instrument(name = "handler.admin.v1.policy_data.set", skip_all)]
+#[allow(clippy::type_complexity)]
 pub async fn handler(
     CallContext {
-        mut repo, clock, ..
+        mut repo,
+        clock,
+        session,
+        ..
     }: CallContext,
     NoApi(mut rng): NoApi<BoxRng>,
     State(policy_factory): State<Arc<PolicyFactory>>,
+    State(limiter): State<Limiter>,
+    if_match: Option<TypedHeader<IfMatch>>,
     Json(request): Json<SetPolicyDataRequest>,
-) -> Result<(StatusCode, Json<SingleResponse<PolicyData>>), RouteError> {
+) -> Result<
+    (
+        StatusCode,
+        TypedHeader<ETag>,
+        Json<EmbeddedResponse<PolicyData>>,
+    ),
+    RouteError,
+> {
+    limiter
+        .check_admin_policy_data_set(&mut repo, &clock, session.client_id)
+        .await?;
+
+    policy_factory
+        .validate_data(&request.data)
+        .map_err(|errors| RouteError::InvalidPolicyData { errors })?;
+
+    if let Some(TypedHeader(if_match)) = if_match {
+        // Hold a lock until the new policy data is saved, so that two concurrent
+        // requests with the same `If-Match` can't both pass the check
+        repo.policy_data().acquire_lock().await?;
+
+        let current = repo.policy_data().get().await?;
+        let passes =
+            current.is_some_and(|current| if_match.precondition_passes(&super::etag(&current)));
+
+        if !passes {
+            return Err(RouteError::StaleVersion);
+        }
+    }
+
     let policy_data = repo
         .policy_data()
-        .set(&mut rng, &clock, request.data)
+        .set(&mut rng, &clock, Some(&session), request.data)
         .await?;
 
     // Swap the policy data. This will fail if the policy data is invalid
@@ -101,19 +199,27 @@ pub async fn handler(
 
     repo.save().await?;
 
+    let etag = super::etag(&policy_data);
+
     Ok((
         StatusCode::CREATED,
-        Json(SingleResponse::new_canonical(policy_data.into())),
+        TypedHeader(etag),
+        Json(EmbeddedResponse::new(policy_data.into())),
     ))
 }
 
 #[cfg(test)]
 mod tests {
-    use hyper::{Request, StatusCode};
+    use std::sync::Arc;
+
+    use hyper::{
+        Request, StatusCode,
+        header::{ETAG, IF_MATCH, RETRY_AFTER},
+    };
     use insta::assert_json_snapshot;
     use sqlx::PgPool;
 
-    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, policy_factory, setup};
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_create(pool: PgPool) {
@@ -138,6 +244,10 @@ mod tests {
             "id": "01FSHN9AG0MZAA6S4AF7CTV32E",
             "attributes": {
               "created_at": "2022-01-16T14:40:00Z",
+              "created_by": {
+                "user_id": null,
+                "client_id": "01FSHN9AG0FAQ50MT1E9FFRPZR"
+              },
               "data": {
                 "hello": "world"
               }
@@ -145,11 +255,175 @@ mod tests {
             "links": {
               "self": "/api/admin/v1/policy-data/01FSHN9AG0MZAA6S4AF7CTV32E"
             }
-          },
-          "links": {
-            "self": "/api/admin/v1/policy-data/01FSHN9AG0MZAA6S4AF7CTV32E"
           }
         }
         "###);
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_create_invalid_schema(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let policy_factory = policy_factory(&state.site_config.server_name, serde_json::json!({}))
+            .await
+            .unwrap();
+        let Some(policy_factory) = Arc::into_inner(policy_factory) else {
+            panic!("the policy factory should not be shared yet");
+        };
+        let Ok(policy_factory) = policy_factory.with_data_schema(&serde_json::json!({
+            "type": "object",
+            "properties": {
+                "emails": {
+                    "type": "object",
+                },
+            },
+        })) else {
+            panic!("the schema should be valid");
+        };
+        state.policy_factory = Arc::new(policy_factory);
+
+        let request = Request::post("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "data": {
+                    "emails": 42
+                }
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_json_snapshot!(body, @r###"
+        {
+          "errors": [
+            {
+              "title": "Policy data doesn't match the configured schema"
+            },
+            {
+              "title": "/emails: 42 is not of type \"object\""
+            }
+          ]
+        }
+        "###);
+
+        // Nothing was stored
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.policy_data().get().await.unwrap().is_none());
+        repo.cancel().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "data": {
+                    "emails": {}
+                }
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_create_if_match(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let current = repo
+            .policy_data()
+            .set(
+                &mut rng,
+                &state.clock,
+                None,
+                serde_json::json!({"hello": "world"}),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Setting the data with a stale version should fail
+        let request = Request::post("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .header(IF_MATCH, "\"01FSHNB5300000000000000000\"")
+            .json(serde_json::json!({
+                "data": {
+                    "hello": "there"
+                }
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::PRECONDITION_FAILED);
+        let body: serde_json::Value = response.json();
+        assert_json_snapshot!(body, @r###"
+        {
+          "errors": [
+            {
+              "title": "Policy data was changed since it was last fetched"
+            }
+          ]
+        }
+        "###);
+
+        // Setting the data with the current version should work
+        let request = Request::post("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .header(IF_MATCH, format!("\"{}\"", current.id))
+            .json(serde_json::json!({
+                "data": {
+                    "hello": "there"
+                }
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let body: serde_json::Value = response.json();
+        let id = body["data"]["id"].as_str().unwrap();
+        response.assert_header_value(ETAG, &format!("\"{id}\""));
+
+        // The previous version is now stale
+        let request = Request::post("/api/admin/v1/policy-data")
+            .bearer(&token)
+            .header(IF_MATCH, format!("\"{}\"", current.id))
+            .json(serde_json::json!({
+                "data": {
+                    "hello": "again"
+                }
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_create_rate_limited(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let set = |version: usize| {
+            Request::post("/api/admin/v1/policy-data")
+                .bearer(&token)
+                .json(serde_json::json!({
+                    "data": {
+                        "version": version
+                    }
+                }))
+        };
+
+        // The default limit allows 10 versions per minute
+        for version in 0..10 {
+            let response = state.request(set(version)).await;
+            response.assert_status(StatusCode::CREATED);
+        }
+
+        let response = state.request(set(10)).await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        response.assert_header_value(RETRY_AFTER, "60");
+
+        // The limit is lifted once the first versions fall out of the window
+        state
+            .clock
+            .advance(chrono::Duration::try_minutes(1).unwrap());
+        let response = state.request(set(10)).await;
+        response.assert_status(StatusCode::CREATED);
+    }
 }
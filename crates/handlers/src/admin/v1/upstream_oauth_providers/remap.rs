@@ -0,0 +1,273 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, extract::Path, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::upstream_oauth2::UpstreamOAuthLinkFilter;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    admin::{call_context::CallContext, response::ErrorResponse},
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Upstream OAuth 2.0 Provider ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("Cannot remap the links of an upstream OAuth 2.0 provider onto itself")]
+    SameProvider,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::SameProvider => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct RemapPathParams {
+    /// The ID of the provider to move the links from
+    #[schemars(with = "crate::admin::schema::Ulid")]
+    id: Ulid,
+
+    /// The ID of the provider to move the links to
+    #[schemars(with = "crate::admin::schema::Ulid")]
+    target_id: Ulid,
+}
+
+/// # Number of links moved by a provider remap
+#[derive(Serialize, JsonSchema, Debug, PartialEq, Eq)]
+pub struct UpstreamOAuthProviderRemapResponse {
+    /// Number of links which were moved to the target provider
+    remapped: usize,
+
+    /// Number of links left on the source provider, because the target
+    /// provider already has a link with the same subject
+    conflicting: usize,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("remapUpstreamOAuthProvider")
+        .summary("Move the links of an upstream OAuth 2.0 provider to another provider")
+        .description(
+            "Moves all the upstream OAuth 2.0 links of a provider to another provider in a \
+            single transaction. \
+            This is typically useful when a provider was re-created with a new ID in the \
+            configuration. \
+            Links whose subject already exists on the target provider are left untouched and \
+            reported as conflicting.",
+        )
+        .tag("upstream-oauth-link")
+        .response_with::<200, Json<UpstreamOAuthProviderRemapResponse>, _>(|t| {
+            t.description("Links were moved")
+                .example(UpstreamOAuthProviderRemapResponse {
+                    remapped: 42,
+                    conflicting: 0,
+                })
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::SameProvider);
+            t.description("The source and target providers are the same")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("Upstream OAuth 2.0 provider was not found")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.upstream_oauth_providers.remap", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    Path(RemapPathParams { id, target_id }): Path<RemapPathParams>,
+) -> Result<Json<UpstreamOAuthProviderRemapResponse>, RouteError> {
+    if id == target_id {
+        return Err(RouteError::SameProvider);
+    }
+
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    let target = repo
+        .upstream_oauth_provider()
+        .lookup(target_id)
+        .await?
+        .ok_or(RouteError::NotFound(target_id))?;
+
+    let remapped = repo
+        .upstream_oauth_link()
+        .remap_provider(&provider, &target)
+        .await?;
+
+    let conflicting = repo
+        .upstream_oauth_link()
+        .count(UpstreamOAuthLinkFilter::new().for_provider(&provider))
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(UpstreamOAuthProviderRemapResponse {
+        remapped,
+        conflicting,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::{
+        admin::v1::upstream_oauth_links::test_utils,
+        test_utils::{RequestBuilderExt, ResponseExt, TestState, setup},
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_remap(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let old = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_utils::oidc_provider_params("provider1"),
+            )
+            .await
+            .unwrap();
+        let new = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_utils::oidc_provider_params("provider2"),
+            )
+            .await
+            .unwrap();
+
+        for subject in ["subject1", "subject2"] {
+            repo.upstream_oauth_link()
+                .add(&mut rng, &state.clock, &old, subject.to_owned(), None)
+                .await
+                .unwrap();
+        }
+        // subject2 already exists on the new provider
+        repo.upstream_oauth_link()
+            .add(&mut rng, &state.clock, &new, "subject2".to_owned(), None)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!(
+            "/api/admin/v1/upstream-oauth-providers/{}/remap/{}",
+            old.id, new.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["remapped"], 1);
+        assert_eq!(body["conflicting"], 1);
+
+        let mut repo = state.repository().await.unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .find_by_subject(&new, "subject1")
+            .await
+            .unwrap()
+            .expect("link to be moved to the new provider");
+        assert_eq!(link.provider_id, new.id);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_same_provider(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_utils::oidc_provider_params("provider1"),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!(
+            "/api/admin/v1/upstream-oauth-providers/{}/remap/{}",
+            provider.id, provider.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_utils::oidc_provider_params("provider1"),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!(
+            "/api/admin/v1/upstream-oauth-providers/{}/remap/{}",
+            provider.id,
+            Ulid::nil()
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::user::UserFilter;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admin::{call_context::CallContext, response::ErrorResponse},
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("At least one criterion must be given to select the users to lock")]
+    NoCriteria,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NoCriteria => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users:bulk-lock` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "UserBulkLockRequest")]
+pub struct Request {
+    /// Only lock the users created before this time, which had no session
+    /// activity since then.
+    ///
+    /// Sessions which never recorded any activity count as active when they
+    /// were created.
+    #[serde(default)]
+    last_active_before: Option<DateTime<Utc>>,
+
+    /// Only lock the users which have no active session left.
+    #[serde(default)]
+    no_sessions: bool,
+}
+
+/// # Number of users locked by a bulk lock
+#[derive(Serialize, JsonSchema, Debug, PartialEq, Eq)]
+pub struct UserBulkLockResponse {
+    /// Number of users which were locked by this call
+    locked: usize,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("bulkLockUsers")
+        .summary("Lock users in bulk")
+        .description(
+            "Locks all the active users matching the given criteria in a single transaction. \
+            At least one criterion must be given. \
+            This is typically useful after a migration from Synapse, to lock the accounts \
+            which were not used in a long time. \
+            Like locking a single user, this DOES NOT invalidate any existing session.",
+        )
+        .tag("user")
+        .response_with::<200, Json<UserBulkLockResponse>, _>(|t| {
+            t.description("Users were locked")
+                .example(UserBulkLockResponse { locked: 42 })
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NoCriteria);
+            t.description("No criterion was given").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.bulk_lock", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    Json(params): Json<Request>,
+) -> Result<Json<UserBulkLockResponse>, RouteError> {
+    if params.last_active_before.is_none() && !params.no_sessions {
+        return Err(RouteError::NoCriteria);
+    }
+
+    let mut filter = UserFilter::new().active_only();
+    if let Some(last_active_before) = params.last_active_before {
+        filter = filter.with_last_active_before(last_active_before);
+    }
+    if params.no_sessions {
+        filter = filter.without_active_sessions_only();
+    }
+
+    let locked = repo.user().lock_bulk(&clock, filter).await?;
+
+    repo.save().await?;
+
+    Ok(Json(UserBulkLockResponse { locked }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_storage::{Clock, RepositoryAccess, user::UserRepository};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_bulk_lock(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Alice never had a session, Bob has a session created after the cutoff
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        state.clock.advance(Duration::try_days(1).unwrap());
+        let cutoff = state.clock.now();
+        state.clock.advance(Duration::try_days(1).unwrap());
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.browser_session()
+            .add(&mut rng, &state.clock, &bob, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/users:bulk-lock")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "last_active_before": cutoff,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["locked"], 1);
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+        assert!(!alice.is_valid());
+        let bob = repo.user().lookup(bob.id).await.unwrap().unwrap();
+        assert!(bob.is_valid());
+        repo.save().await.unwrap();
+
+        // Bob still has an active session
+        let request = Request::post("/api/admin/v1/users:bulk-lock")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "no_sessions": true,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["locked"], 0);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_bulk_lock_without_criteria(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/users:bulk-lock")
+            .bearer(&token)
+            .json(serde_json::json!({}));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
@@ -16,6 +16,7 @@ use mas_axum_utils::record_error;
 use mas_storage::{Page, user::UserFilter};
 use schemars::JsonSchema;
 use serde::Deserialize;
+use ulid::Ulid;
 
 use crate::{
     admin::{
@@ -65,6 +66,19 @@ pub struct FilterParams {
     /// * `deactivated`: Only retrieve deactivated users
     #[serde(rename = "filter[status]")]
     status: Option<UserStatus>,
+
+    /// Retrieve the users linked to the given upstream OAuth 2.0 provider
+    #[serde(rename = "filter[upstream-provider]")]
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    upstream_provider: Option<Ulid>,
+
+    /// Retrieve the users linked to an upstream account with the given
+    /// subject
+    ///
+    /// Combined with `filter[upstream-provider]`, both have to match the same
+    /// link.
+    #[serde(rename = "filter[upstream-subject]")]
+    upstream_subject: Option<String>,
 }
 
 impl std::fmt::Display for FilterParams {
@@ -79,6 +93,14 @@ impl std::fmt::Display for FilterParams {
             write!(f, "{sep}filter[status]={status}")?;
             sep = '&';
         }
+        if let Some(upstream_provider) = self.upstream_provider {
+            write!(f, "{sep}filter[upstream-provider]={upstream_provider}")?;
+            sep = '&';
+        }
+        if let Some(upstream_subject) = &self.upstream_subject {
+            write!(f, "{sep}filter[upstream-subject]={upstream_subject}")?;
+            sep = '&';
+        }
 
         let _ = sep;
         Ok(())
@@ -91,6 +113,9 @@ pub enum RouteError {
     #[error(transparent)]
     Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
 
+    #[error("Provider ID {0} not found")]
+    ProviderNotFound(Ulid),
+
     #[error("Invalid filter parameters")]
     InvalidFilter(#[from] QueryRejection),
 }
@@ -103,6 +128,7 @@ impl IntoResponse for RouteError {
         let sentry_event_id = record_error!(self, Self::Internal(_));
         let status = match self {
             Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ProviderNotFound(_) => StatusCode::NOT_FOUND,
             Self::InvalidFilter(_) => StatusCode::BAD_REQUEST,
         };
         (status, sentry_event_id, Json(error)).into_response()
@@ -126,6 +152,10 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
             t.description("Paginated response of users")
                 .example(PaginatedResponse::new(page, pagination, 42, User::PATH))
         })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::ProviderNotFound(Ulid::nil()));
+            t.description("Provider was not found").example(response)
+        })
 }
 
 #[tracing::instrument(name = "handler.admin.v1.users.list", skip_all)]
@@ -150,6 +180,30 @@ pub async fn handler(
         None => filter,
     };
 
+    // Load the provider from the filter
+    let maybe_provider = if let Some(provider_id) = params.upstream_provider {
+        let provider = repo
+            .upstream_oauth_provider()
+            .lookup(provider_id)
+            .await?
+            .ok_or(RouteError::ProviderNotFound(provider_id))?;
+        Some(provider)
+    } else {
+        None
+    };
+
+    let filter = if let Some(provider) = &maybe_provider {
+        filter.with_upstream_oauth_provider(provider)
+    } else {
+        filter
+    };
+
+    let filter = if let Some(subject) = &params.upstream_subject {
+        filter.with_upstream_oauth_subject(subject)
+    } else {
+        filter
+    };
+
     let page = repo.user().list(filter, pagination).await?;
     let count = repo.user().count(filter).await?;
 
@@ -160,3 +214,86 @@ pub async fn handler(
         &base,
     )))
 }
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use super::super::super::upstream_oauth_links::test_utils;
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_filter_by_upstream_link(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_utils::oidc_provider_params("acme"),
+            )
+            .await
+            .unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .add(
+                &mut rng,
+                &state.clock,
+                &provider,
+                "subject1".to_owned(),
+                None,
+            )
+            .await
+            .unwrap();
+        repo.upstream_oauth_link()
+            .associate_to_user(&link, &alice)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(format!(
+            "/api/admin/v1/users?filter[upstream-provider]={}&filter[upstream-subject]=subject1",
+            provider.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["id"], alice.id.to_string());
+
+        let request = Request::get("/api/admin/v1/users?filter[upstream-subject]=subject2")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 0);
+
+        let request = Request::get(format!(
+            "/api/admin/v1/users?filter[upstream-provider]={}",
+            Ulid::nil()
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
@@ -5,6 +5,7 @@
 // Please see LICENSE files in the repository root for full details.
 
 mod add;
+mod bulk_lock;
 mod by_username;
 mod deactivate;
 mod get;
@@ -17,6 +18,7 @@ mod unlock;
 
 pub use self::{
     add::{doc as add_doc, handler as add},
+    bulk_lock::{doc as bulk_lock_doc, handler as bulk_lock},
     by_username::{doc as by_username_doc, handler as by_username},
     deactivate::{doc as deactivate_doc, handler as deactivate},
     get::{doc as get_doc, handler as get},
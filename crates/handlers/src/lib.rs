@@ -57,6 +57,7 @@ mod graphql;
 mod health;
 mod oauth2;
 pub mod passwords;
+pub mod synapse_migration;
 pub mod upstream_oauth2;
 mod views;
 
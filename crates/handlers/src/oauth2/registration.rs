@@ -366,6 +366,9 @@ pub(crate) async fn post(
         // XXX: we should have a `created_at` field on the clients
         client_id_issued_at: Some(client.id.datetime().into()),
         client_secret_expires_at: None,
+        // We don't support the client configuration endpoint
+        registration_access_token: None,
+        registration_client_uri: None,
     };
 
     // We round-trip back to the metadata to output it in the response
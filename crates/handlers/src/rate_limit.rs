@@ -9,6 +9,9 @@ use std::{net::IpAddr, sync::Arc, time::Duration};
 use governor::{RateLimiter, clock::QuantaClock, state::keyed::DashMapStateStore};
 use mas_config::RateLimitingConfig;
 use mas_data_model::{User, UserEmailAuthentication};
+use mas_storage::{
+    BoxRepository, Clock, Pagination, RepositoryError, policy_data::PolicyDataFilter,
+};
 use ulid::Ulid;
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -47,6 +50,19 @@ pub enum EmailAuthenticationLimitedError {
     Email(String),
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyDataSetLimitedError {
+    #[error("Too many policy data updates for client {client_id}")]
+    Client {
+        client_id: Ulid,
+        /// How long until the client can set the policy data again
+        retry_after: Duration,
+    },
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
 /// Key used to rate limit requests per requester
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct RequesterFingerprint {
@@ -94,6 +110,8 @@ struct LimiterInner {
     email_authentication_per_email: KeyedRateLimiter<String>,
     email_authentication_emails_per_session: KeyedRateLimiter<Ulid>,
     email_authentication_attempt_per_session: KeyedRateLimiter<Ulid>,
+    admin_policy_data_set_burst: u32,
+    admin_policy_data_set_window: Duration,
 }
 
 impl LimiterInner {
@@ -120,6 +138,8 @@ impl LimiterInner {
             email_authentication_attempt_per_session: RateLimiter::keyed(
                 config.email_authentication.attempt_per_session.to_quota()?,
             ),
+            admin_policy_data_set_burst: config.admin_policy_data_set.burst.get(),
+            admin_policy_data_set_window: config.admin_policy_data_set.to_window(),
         })
     }
 }
@@ -297,6 +317,57 @@ impl Limiter {
             .check_key(&authentication.id)
             .map_err(|_| EmailAuthenticationLimitedError::Authentication(authentication.id))
     }
+
+    /// Check if the policy data can be set by a client through the admin API
+    ///
+    /// Unlike the other limits, this one is enforced by counting the policy
+    /// data versions recently set by the client in the database, so that it
+    /// applies across all the instances.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation is rate limited, or if the repository
+    /// fails.
+    pub async fn check_admin_policy_data_set(
+        &self,
+        repo: &mut BoxRepository,
+        clock: &dyn Clock,
+        client_id: Ulid,
+    ) -> Result<(), PolicyDataSetLimitedError> {
+        let now = clock.now();
+        let window = chrono::Duration::from_std(self.inner.admin_policy_data_set_window)
+            .unwrap_or(chrono::Duration::MAX);
+        let window_start = now
+            .checked_sub_signed(window)
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+
+        let filter = PolicyDataFilter::new()
+            .for_client_id(client_id)
+            .with_created_after(window_start);
+
+        let count = repo.policy_data().count(filter).await?;
+        if count < self.inner.admin_policy_data_set_burst as usize {
+            return Ok(());
+        }
+
+        // The client can set the policy data again once the oldest version in
+        // the window falls out of it
+        let oldest = repo
+            .policy_data()
+            .list(filter, Pagination::first(1))
+            .await?
+            .edges
+            .into_iter()
+            .next();
+        let retry_after = oldest
+            .and_then(|oldest| (oldest.created_at - window_start).to_std().ok())
+            .unwrap_or(self.inner.admin_policy_data_set_window);
+
+        Err(PolicyDataSetLimitedError::Client {
+            client_id,
+            retry_after,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,268 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Runs Synapse-to-MAS migrations in the background, on behalf of the admin
+//! API.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_storage::{BoxClock, BoxRng};
+use syn2mas::{MigrationProgress, MigrationReport, Progress, ProgressStage};
+use thiserror::Error;
+use tokio_util::task::TaskTracker;
+use ulid::Ulid;
+
+/// Runs a Synapse-to-MAS migration, with everything it needs to connect to
+/// both databases.
+#[async_trait]
+pub trait SynapseMigrationRunner: Send + Sync {
+    /// Run the migration, reporting its progress to `progress`, and the
+    /// warnings of the pre-migration checks to `warnings`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pre-migration checks or the migration itself
+    /// failed.
+    async fn run(
+        &self,
+        progress: Progress,
+        warnings: Arc<Mutex<Vec<String>>>,
+    ) -> Result<MigrationReport, anyhow::Error>;
+}
+
+#[derive(Debug, Error)]
+pub enum StartMigrationError {
+    #[error("Synapse migrations are disabled")]
+    Disabled,
+
+    #[error("Synapse migration {0} is still running")]
+    AlreadyRunning(Ulid),
+}
+
+/// The outcome of a finished migration
+pub struct SynapseMigrationOutcome {
+    /// When the migration finished
+    pub finished_at: DateTime<Utc>,
+
+    /// The report of the migration, or why it failed
+    pub result: Result<MigrationReport, String>,
+}
+
+/// A migration started through [`SynapseMigrations::start`]
+pub struct SynapseMigrationJob {
+    id: Ulid,
+    started_at: DateTime<Utc>,
+    progress: Progress,
+
+    /// The last progress reported for each phase, in the order they started
+    phases: Arc<Mutex<Vec<MigrationProgress>>>,
+
+    /// The warnings of the pre-migration checks
+    warnings: Arc<Mutex<Vec<String>>>,
+
+    outcome: Mutex<Option<Arc<SynapseMigrationOutcome>>>,
+}
+
+impl SynapseMigrationJob {
+    /// The ID of the migration
+    #[must_use]
+    pub fn id(&self) -> Ulid {
+        self.id
+    }
+
+    /// When the migration was started
+    #[must_use]
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    /// The stage the migration is at, if it is still running
+    #[must_use]
+    pub fn current_stage(&self) -> Option<String> {
+        if self.outcome().is_some() {
+            return None;
+        }
+
+        let stage = match &**self.progress.get_current_stage() {
+            ProgressStage::SettingUp => "setting up".to_owned(),
+            ProgressStage::MigratingData { entity, .. } => format!("migrating {entity}"),
+            ProgressStage::RebuildIndex { index_name } => format!("rebuilding index {index_name}"),
+            ProgressStage::RebuildConstraint { constraint_name } => {
+                format!("rebuilding constraint {constraint_name}")
+            }
+        };
+        Some(stage)
+    }
+
+    /// The last progress reported by each phase which started
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    #[must_use]
+    pub fn phases(&self) -> Vec<MigrationProgress> {
+        self.phases.lock().unwrap().clone()
+    }
+
+    /// The warnings of the pre-migration checks, which didn't prevent the
+    /// migration from running
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    #[must_use]
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.lock().unwrap().clone()
+    }
+
+    /// The outcome of the migration, once it finished
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    #[must_use]
+    pub fn outcome(&self) -> Option<Arc<SynapseMigrationOutcome>> {
+        self.outcome.lock().unwrap().clone()
+    }
+}
+
+/// Keeps track of the migrations started through the admin API, making sure
+/// only one runs at a time
+///
+/// Only the running migration and the latest finished one are kept. They are
+/// tracked in memory, so they are forgotten when the server restarts, which
+/// waits for a running migration to finish first. This also means that the check is per server: with
+/// several replicas, a migration started on another replica is only stopped
+/// by the lock the migration itself takes on the MAS database, and fails
+/// instead of being refused.
+#[derive(Clone)]
+pub struct SynapseMigrations {
+    inner: Option<Arc<InnerSynapseMigrations>>,
+}
+
+struct InnerSynapseMigrations {
+    runner: Arc<dyn SynapseMigrationRunner>,
+    task_tracker: TaskTracker,
+    jobs: Mutex<HashMap<Ulid, Arc<SynapseMigrationJob>>>,
+}
+
+impl SynapseMigrations {
+    /// Creates a new [`SynapseMigrations`], running the migrations with the
+    /// given runner in tasks of the given task tracker
+    #[must_use]
+    pub fn new(runner: impl SynapseMigrationRunner + 'static, task_tracker: &TaskTracker) -> Self {
+        Self {
+            inner: Some(Arc::new(InnerSynapseMigrations {
+                runner: Arc::new(runner),
+                task_tracker: task_tracker.clone(),
+                jobs: Mutex::default(),
+            })),
+        }
+    }
+
+    /// Creates a new [`SynapseMigrations`] which refuses to start migrations
+    #[must_use]
+    pub const fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    /// Start a migration in a background task
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if migrations are disabled, or if another migration
+    /// is still running.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    pub fn start(
+        &self,
+        clock: BoxClock,
+        rng: &mut BoxRng,
+    ) -> Result<Arc<SynapseMigrationJob>, StartMigrationError> {
+        let inner = self.inner.clone().ok_or(StartMigrationError::Disabled)?;
+        let mut jobs = inner.jobs.lock().unwrap();
+
+        if let Some(running) = jobs.values().find(|job| job.outcome().is_none()) {
+            return Err(StartMigrationError::AlreadyRunning(running.id));
+        }
+
+        let started_at = clock.now();
+        let phases: Arc<Mutex<Vec<MigrationProgress>>> = Arc::default();
+        let progress = Progress::with_callback({
+            let phases = Arc::clone(&phases);
+            move |progress: MigrationProgress| {
+                let mut phases = phases.lock().unwrap();
+                if let Some(known) = phases
+                    .iter_mut()
+                    .find(|known| known.phase.name() == progress.phase.name())
+                {
+                    *known = progress;
+                } else {
+                    phases.push(progress);
+                }
+            }
+        });
+
+        let job = Arc::new(SynapseMigrationJob {
+            id: Ulid::from_datetime_with_source(started_at.into(), rng),
+            started_at,
+            progress: progress.clone(),
+            phases,
+            warnings: Arc::default(),
+            outcome: Mutex::new(None),
+        });
+        jobs.insert(job.id, Arc::clone(&job));
+
+        let runner = Arc::clone(&inner.runner);
+        let warnings = Arc::clone(&job.warnings);
+        let task_job = Arc::clone(&job);
+        let task_inner = Arc::clone(&inner);
+        // The migration runs on the task tracker, so that a shutdown waits for it
+        // instead of leaving the MAS database halfway migrated
+        inner.task_tracker.spawn(async move {
+            // Run the migration in its own task, so that a panic is reported as a
+            // failure instead of leaving the migration running forever
+            let result =
+                match tokio::spawn(async move { runner.run(progress, warnings).await }).await {
+                    Ok(result) => result.map_err(|error| format!("{error:#}")),
+                    Err(error) => Err(format!("migration task failed: {error}")),
+                };
+
+            if let Err(error) = &result {
+                tracing::error!(migration.id = %task_job.id, "Synapse migration failed: {error}");
+            }
+
+            // This is now the latest finished migration, and no other one is running, so
+            // it is the only one kept
+            let mut jobs = task_inner.jobs.lock().unwrap();
+            *task_job.outcome.lock().unwrap() = Some(Arc::new(SynapseMigrationOutcome {
+                finished_at: clock.now(),
+                result,
+            }));
+            jobs.retain(|id, _| *id == task_job.id);
+        });
+
+        Ok(job)
+    }
+
+    /// Get the running migration or the latest finished one, if it has the
+    /// given ID
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    #[must_use]
+    pub fn get(&self, id: Ulid) -> Option<Arc<SynapseMigrationJob>> {
+        let inner = self.inner.as_ref()?;
+        inner.jobs.lock().unwrap().get(&id).cloned()
+    }
+}
@@ -57,6 +57,7 @@ use url::Url;
 use crate::{
     ActivityTracker, BoundActivityTracker, Limiter, RequesterFingerprint, graphql,
     passwords::{Hasher, PasswordManager},
+    synapse_migration::SynapseMigrations,
     upstream_oauth2::cache::MetadataCache,
 };
 
@@ -108,6 +109,7 @@ pub(crate) struct TestState {
     pub policy_factory: Arc<PolicyFactory>,
     pub graphql_schema: graphql::Schema,
     pub password_manager: PasswordManager,
+    pub synapse_migrations: SynapseMigrations,
     pub site_config: SiteConfig,
     pub activity_tracker: ActivityTracker,
     pub limiter: Limiter,
@@ -271,6 +273,7 @@ impl TestState {
             policy_factory,
             graphql_schema,
             password_manager,
+            synapse_migrations: SynapseMigrations::disabled(),
             site_config,
             activity_tracker,
             limiter,
@@ -536,6 +539,12 @@ impl FromRef<TestState> for PasswordManager {
     }
 }
 
+impl FromRef<TestState> for SynapseMigrations {
+    fn from_ref(input: &TestState) -> Self {
+        input.synapse_migrations.clone()
+    }
+}
+
 impl FromRef<TestState> for CookieManager {
     fn from_ref(input: &TestState) -> Self {
         input.cookie_manager.clone()
@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 
 use axum::{
     Form, Json,
@@ -13,13 +13,12 @@ use axum::{
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
 use mas_data_model::{UpstreamOAuthProvider, UpstreamOAuthProviderOnBackchannelLogout};
-use mas_jose::{
-    claims::{self, Claim, TimeOptions},
-    jwt::JwtDecodeError,
-};
 use mas_oidc_client::{
-    error::JwtVerificationError,
-    requests::jose::{JwtVerificationData, verify_signed_jwt},
+    error::LogoutTokenError,
+    requests::{
+        backchannel_logout::{LogoutToken, validate_logout_token},
+        jose::JwtVerificationData,
+    },
 };
 use mas_storage::{
     BoxClock, BoxRepository, BoxRng, Pagination,
@@ -31,7 +30,6 @@ use mas_storage::{
 };
 use oauth2_types::errors::{ClientError, ClientErrorCode};
 use serde::Deserialize;
-use serde_json::Value;
 use thiserror::Error;
 use ulid::Ulid;
 
@@ -47,21 +45,9 @@ pub enum RouteError {
     #[error(transparent)]
     InvalidRequestBody(#[from] FormRejection),
 
-    /// Logout token is not a JWT
-    #[error("failed to decode logout token")]
-    InvalidLogoutToken(#[from] JwtDecodeError),
-
-    /// Logout token failed to be verified
-    #[error("failed to verify logout token")]
-    LogoutTokenVerification(#[from] JwtVerificationError),
-
-    /// Logout token had invalid claims
-    #[error("invalid claims in logout token")]
-    InvalidLogoutTokenClaims(#[from] claims::ClaimError),
-
-    /// Logout token has neither a sub nor a sid claim
-    #[error("logout token has neither a sub nor a sid claim")]
-    NoSubOrSidClaim,
+    /// Logout token failed to be validated
+    #[error("failed to validate logout token")]
+    InvalidLogoutToken(#[from] LogoutTokenError),
 
     /// Provider not found
     #[error("provider not found")]
@@ -81,11 +67,7 @@ impl IntoResponse for RouteError {
             )
                 .into_response(),
 
-            e @ (Self::InvalidLogoutToken(_)
-            | Self::LogoutTokenVerification(_)
-            | Self::InvalidRequestBody(_)
-            | Self::InvalidLogoutTokenClaims(_)
-            | Self::NoSubOrSidClaim) => (
+            e @ (Self::InvalidLogoutToken(_) | Self::InvalidRequestBody(_)) => (
                 StatusCode::BAD_REQUEST,
                 Json(
                     ClientError::from(ClientErrorCode::InvalidRequest)
@@ -119,15 +101,6 @@ pub(crate) struct BackchannelLogoutRequest {
     logout_token: String,
 }
 
-#[derive(Deserialize)]
-struct LogoutTokenEvents {
-    #[allow(dead_code)] // We just want to check it deserializes
-    #[serde(rename = "http://schemas.openid.net/event/backchannel-logout")]
-    backchannel_logout: HashMap<String, Value>,
-}
-
-const EVENTS: Claim<LogoutTokenEvents> = Claim::new("events");
-
 #[tracing::instrument(
     name = "handlers.upstream_oauth2.backchannel_logout.post",
     fields(upstream_oauth_provider.id = %provider_id),
@@ -197,11 +170,8 @@ pub(crate) async fn post(
     //  If any of the validation steps fails, reject the Logout Token and return an
     // HTTP 400 Bad Request error. Otherwise, proceed to perform the logout actions.
     //
-    // The ISS and AUD claims are already checked by the verify_signed_jwt()
-    // function.
-
-    // This verifies (1), (2), (3) and the iss and aud claims for (4)
-    let token = verify_signed_jwt(
+    // The validate_logout_token() function checks (1) to (7)
+    let LogoutToken { sub, sid } = validate_logout_token(
         &request.logout_token,
         JwtVerificationData {
             issuer: provider.issuer.as_deref(),
@@ -209,23 +179,9 @@ pub(crate) async fn post(
             client_id: &provider.client_id,
             signing_algorithm: &provider.id_token_signed_response_alg,
         },
+        clock.now(),
     )?;
 
-    let (_header, mut claims) = token.into_parts();
-
-    let time_options = TimeOptions::new(clock.now());
-    claims::EXP.extract_required_with_options(&mut claims, &time_options)?; // (4)
-    claims::IAT.extract_required_with_options(&mut claims, &time_options)?; // (4)
-
-    let sub = claims::SUB.extract_optional(&mut claims)?; // (5)
-    let sid = claims::SID.extract_optional(&mut claims)?; // (5)
-    if sub.is_none() && sid.is_none() {
-        return Err(RouteError::NoSubOrSidClaim);
-    }
-
-    EVENTS.extract_required(&mut claims)?; // (6)
-    claims::NONCE.assert_absent(&claims)?; // (7)
-
     // Find the corresponding upstream OAuth 2.0 sessions
     let mut auth_session_filter = UpstreamOAuthSessionFilter::new().for_provider(&provider);
     if let Some(sub) = &sub {
@@ -304,6 +304,7 @@ pub(crate) async fn handler(
             redirect_uri: Some(redirect_uri),
             code_verifier: session.code_challenge_verifier.clone(),
         }),
+        None,
         clock.now(),
         &mut rng,
     )
@@ -549,7 +549,17 @@ mod oidc_frontchannel {
     pub const SID: Claim<String> = Claim::new("sid");
 }
 
-pub use self::{oidc_core::*, oidc_frontchannel::*, rfc7519::*};
+/// Claims defined in RFC9449 sec. 4.2
+/// <https://www.rfc-editor.org/rfc/rfc9449.html#section-4.2>
+mod rfc9449 {
+    use super::Claim;
+
+    pub const HTM: Claim<String> = Claim::new("htm");
+    pub const HTU: Claim<String> = Claim::new("htu");
+    pub const ATH: Claim<String> = Claim::new("ath");
+}
+
+pub use self::{oidc_core::*, oidc_frontchannel::*, rfc7519::*, rfc9449::*};
 
 #[cfg(test)]
 mod tests {
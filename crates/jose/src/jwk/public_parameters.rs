@@ -9,6 +9,7 @@ use mas_iana::jose::{
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use super::ParametersInfo;
 use crate::base64::Base64UrlNoPad;
@@ -50,6 +51,35 @@ impl JsonWebKeyPublicParameters {
             _ => None,
         }
     }
+
+    /// Compute the SHA-256 JWK Thumbprint of this key
+    ///
+    /// Ref: <https://www.rfc-editor.org/rfc/rfc7638.html>
+    #[must_use]
+    pub fn thumbprint(&self) -> Base64UrlNoPad {
+        // The required members, in lexicographic order
+        let members = match self {
+            Self::Rsa(params) => serde_json::json!({
+                "e": params.e,
+                "kty": "RSA",
+                "n": params.n,
+            }),
+            Self::Ec(params) => serde_json::json!({
+                "crv": params.crv,
+                "kty": "EC",
+                "x": params.x,
+                "y": params.y,
+            }),
+            Self::Okp(params) => serde_json::json!({
+                "crv": params.crv,
+                "kty": "OKP",
+                "x": params.x,
+            }),
+        };
+
+        let digest = Sha256::digest(members.to_string());
+        Base64UrlNoPad::new(digest.to_vec())
+    }
 }
 
 impl ParametersInfo for JsonWebKeyPublicParameters {
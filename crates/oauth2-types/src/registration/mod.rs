@@ -917,6 +917,20 @@ pub struct ClientRegistrationResponse {
     #[serde(default)]
     #[serde_as(as = "Option<TimestampSeconds<i64>>")]
     pub client_secret_expires_at: Option<DateTime<Utc>>,
+
+    /// A token that can be used at the client configuration endpoint to
+    /// manage the client registration, as defined in [RFC 7592].
+    ///
+    /// [RFC 7592]: https://www.rfc-editor.org/rfc/rfc7592
+    #[serde(default)]
+    pub registration_access_token: Option<String>,
+
+    /// The location of the client configuration endpoint, as defined in
+    /// [RFC 7592].
+    ///
+    /// [RFC 7592]: https://www.rfc-editor.org/rfc/rfc7592
+    #[serde(default)]
+    pub registration_client_uri: Option<Url>,
 }
 
 #[cfg(test)]
@@ -12,6 +12,7 @@ use mas_jose::{
     jwa::InvalidAlgorithm,
     jwt::{JwtDecodeError, JwtSignatureError, NoKeyWorked},
 };
+use mas_keystore::WrongAlgorithmError;
 use oauth2_types::{oidc::ProviderMetadataVerificationError, pkce::CodeChallengeError};
 use serde::Deserialize;
 use thiserror::Error;
@@ -40,6 +41,30 @@ pub enum Error {
 
     /// An error occurred requesting user info.
     UserInfo(#[from] UserInfoError),
+
+    /// An error occurred introspecting a token.
+    Introspection(#[from] IntrospectionError),
+
+    /// An error occurred revoking a token.
+    TokenRevocation(#[from] TokenRevocationError),
+
+    /// An error occurred revoking a token and verifying the revocation.
+    VerifiedTokenRevocation(#[from] VerifiedTokenRevocationError),
+
+    /// An error occurred registering a client.
+    Registration(#[from] RegistrationError),
+
+    /// An error occurred pushing an authorization request.
+    PushedAuthorization(#[from] PushedAuthorizationError),
+
+    /// An error occurred exchanging a token.
+    TokenExchange(#[from] TokenExchangeError),
+
+    /// An error occurred requesting a device code.
+    DeviceAuthorization(#[from] DeviceAuthorizationError),
+
+    /// An error occurred polling the token endpoint with a device code.
+    DeviceCodeToken(#[from] DeviceCodeTokenError),
 }
 
 /// All possible errors when fetching provider metadata.
@@ -74,6 +99,39 @@ pub enum AuthorizationError {
 
     /// An error occurred serializing the request.
     UrlEncoded(#[from] serde_urlencoded::ser::Error),
+
+    /// The `prompt` contains a value which is not defined by the
+    /// specifications.
+    UnknownPrompt(String),
+
+    /// The `none` prompt was combined with other values.
+    PromptNoneNotAlone,
+
+    /// An `acr_values` value is empty or contains whitespace.
+    InvalidAcrValue(String),
+
+    /// The `claims` parameter is not well-formed.
+    InvalidClaims(&'static str),
+}
+
+/// All possible errors when pushing an authorization request.
+#[derive(Debug, Error)]
+#[error("Request to the pushed authorization request endpoint failed")]
+pub enum PushedAuthorizationError {
+    /// An error occurred building the authorization request.
+    Authorization(#[from] AuthorizationError),
+
+    /// An error occurred serializing the request.
+    UrlEncoded(#[from] serde_urlencoded::ser::Error),
+
+    /// The HTTP client returned an error.
+    Http(#[from] reqwest::Error),
+
+    /// The server returned an error
+    OAuth2(#[from] OAuth2Error),
+
+    /// Error while injecting the client credentials into the request.
+    Credentials(#[from] CredentialsError),
 }
 
 /// All possible errors when requesting an access token.
@@ -88,6 +146,9 @@ pub enum TokenRequestError {
 
     /// Error while injecting the client credentials into the request.
     Credentials(#[from] CredentialsError),
+
+    /// Error while adding the DPoP proof to the request.
+    Dpop(#[from] DpopError),
 }
 
 /// All possible errors when exchanging a code for an access token.
@@ -105,15 +166,31 @@ pub enum TokenAuthorizationCodeError {
 /// All possible errors when refreshing an access token.
 #[derive(Debug, Error)]
 pub enum TokenRefreshError {
+    /// The provider rejected the refresh token, because it is invalid, expired
+    /// or revoked. Retrying won't help: the user needs to authenticate again.
+    #[error("The provider rejected the refresh token")]
+    InvalidGrant(#[source] OAuth2Error),
+
     /// An error occurred requesting the access token.
     #[error(transparent)]
-    Token(#[from] TokenRequestError),
+    Token(TokenRequestError),
 
     /// An error occurred validating the ID Token.
     #[error("Verifying the 'id_token' returned by the provider failed")]
     IdToken(#[from] IdTokenError),
 }
 
+impl From<TokenRequestError> for TokenRefreshError {
+    fn from(error: TokenRequestError) -> Self {
+        match error {
+            TokenRequestError::OAuth2(error) if error.error_code() == Some("invalid_grant") => {
+                Self::InvalidGrant(error)
+            }
+            error => Self::Token(error),
+        }
+    }
+}
+
 /// All possible errors when requesting user info.
 #[derive(Debug, Error)]
 pub enum UserInfoError {
@@ -147,6 +224,158 @@ pub enum UserInfoError {
     OAuth2(#[from] OAuth2Error),
 }
 
+/// All possible errors when introspecting a token.
+#[derive(Debug, Error)]
+#[error("Request to the introspection endpoint failed")]
+pub enum IntrospectionError {
+    /// The HTTP client returned an error.
+    Http(#[from] reqwest::Error),
+
+    /// The server returned an error
+    OAuth2(#[from] OAuth2Error),
+
+    /// Error while injecting the client credentials into the request.
+    Credentials(#[from] CredentialsError),
+
+    /// Error while adding the DPoP proof to the request.
+    Dpop(#[from] DpopError),
+
+    /// The request didn't complete within the timeout.
+    Timeout,
+}
+
+/// All possible errors when exchanging a token.
+#[derive(Debug, Error)]
+pub enum TokenExchangeError {
+    /// The HTTP client returned an error.
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    /// The server rejected the requested audience or resource.
+    #[error("The provider rejected the target of the token exchange")]
+    InvalidTarget(#[source] OAuth2Error),
+
+    /// The server rejected the request, for example because of an invalid
+    /// subject or actor token.
+    #[error("The provider rejected the token exchange request")]
+    InvalidRequest(#[source] OAuth2Error),
+
+    /// The server returned another error.
+    #[error(transparent)]
+    OAuth2(OAuth2Error),
+
+    /// Error while injecting the client credentials into the request.
+    #[error(transparent)]
+    Credentials(#[from] CredentialsError),
+
+    /// Error while adding the DPoP proof to the request.
+    #[error(transparent)]
+    Dpop(#[from] DpopError),
+}
+
+impl From<OAuth2Error> for TokenExchangeError {
+    fn from(error: OAuth2Error) -> Self {
+        match error.error_code() {
+            Some("invalid_target") => Self::InvalidTarget(error),
+            Some("invalid_request") => Self::InvalidRequest(error),
+            _ => Self::OAuth2(error),
+        }
+    }
+}
+
+/// All possible errors when requesting a device code.
+#[derive(Debug, Error)]
+#[error("Request to the device authorization endpoint failed")]
+pub enum DeviceAuthorizationError {
+    /// The HTTP client returned an error.
+    Http(#[from] reqwest::Error),
+
+    /// The server returned an error
+    OAuth2(#[from] OAuth2Error),
+
+    /// Error while injecting the client credentials into the request.
+    Credentials(#[from] CredentialsError),
+}
+
+/// All possible errors when polling the token endpoint with a device code.
+#[derive(Debug, Error)]
+pub enum DeviceCodeTokenError {
+    /// An error occurred requesting the access token.
+    #[error(transparent)]
+    Token(#[from] TokenRequestError),
+
+    /// The device code expired before the user authorized the device.
+    #[error("The device code expired before the authorization was granted")]
+    Expired,
+}
+
+/// All possible errors when revoking a token.
+#[derive(Debug, Error)]
+#[error("Request to the revocation endpoint failed")]
+pub enum TokenRevocationError {
+    /// The HTTP client returned an error.
+    Http(#[from] reqwest::Error),
+
+    /// The server returned an error
+    OAuth2(#[from] OAuth2Error),
+
+    /// Error while injecting the client credentials into the request.
+    Credentials(#[from] CredentialsError),
+
+    /// Error while adding the DPoP proof to the request.
+    Dpop(#[from] DpopError),
+
+    /// The request didn't complete within the timeout.
+    Timeout,
+}
+
+/// All possible errors when revoking a token and verifying that the associated
+/// access token was revoked too.
+#[derive(Debug, Error)]
+pub enum VerifiedTokenRevocationError {
+    /// An error occurred revoking the token.
+    #[error(transparent)]
+    Revocation(#[from] TokenRevocationError),
+
+    /// An error occurred introspecting the associated access token.
+    #[error(transparent)]
+    Introspection(#[from] IntrospectionError),
+
+    /// The associated access token is still active after the revocation.
+    #[error("The associated access token is still active after the revocation")]
+    AccessTokenStillActive,
+}
+
+/// All possible errors when registering a client.
+#[derive(Debug, Error)]
+pub enum RegistrationError {
+    /// The HTTP client returned an error.
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    /// The server rejected the client metadata.
+    #[error("The provider rejected the client metadata")]
+    InvalidClientMetadata(#[source] OAuth2Error),
+
+    /// The server rejected one of the redirect URIs.
+    #[error("The provider rejected one of the redirect URIs")]
+    InvalidRedirectUri(#[source] OAuth2Error),
+
+    /// The server returned another error.
+    #[error(transparent)]
+    OAuth2(OAuth2Error),
+}
+
+impl From<OAuth2Error> for RegistrationError {
+    fn from(error: OAuth2Error) -> Self {
+        match error.error_code() {
+            Some("invalid_client_metadata") => Self::InvalidClientMetadata(error),
+            Some("invalid_redirect_uri") => Self::InvalidRedirectUri(error),
+            _ => Self::OAuth2(error),
+        }
+    }
+}
+
 /// All possible errors when requesting a JWKS.
 #[derive(Debug, Error)]
 #[error("Failed to fetch JWKS")]
@@ -207,6 +436,46 @@ pub enum IdTokenError {
     WrongAuthTime,
 }
 
+/// All possible errors when validating a logout token.
+#[derive(Debug, Error)]
+pub enum LogoutTokenError {
+    /// An error occurred validating the logout token's signature and basic
+    /// claims.
+    #[error(transparent)]
+    Jwt(#[from] JwtVerificationError),
+
+    /// An error occurred extracting a claim.
+    #[error(transparent)]
+    Claim(#[from] ClaimError),
+
+    /// The logout token has neither a `sub` nor a `sid` claim.
+    #[error("logout token has neither a sub nor a sid claim")]
+    NoSubOrSidClaim,
+}
+
+/// All possible errors when validating a JWT-secured authorization response.
+#[derive(Debug, Error)]
+pub enum JarmError {
+    /// An error occurred validating the response's signature and basic
+    /// claims.
+    #[error(transparent)]
+    Jwt(#[from] JwtVerificationError),
+
+    /// An error occurred extracting a claim.
+    #[error(transparent)]
+    Claim(#[from] ClaimError),
+
+    /// The provider returned an error instead of an authorization code.
+    #[error("the provider returned an error: {error}")]
+    ErrorResponse {
+        /// The error code.
+        error: String,
+
+        /// The human-readable description of the error, if any.
+        error_description: Option<String>,
+    },
+}
+
 /// All errors that can occur when adding client credentials to the request.
 #[derive(Debug, Error)]
 pub enum CredentialsError {
@@ -236,6 +505,22 @@ pub enum CredentialsError {
     JwtSignature(#[from] JwtSignatureError),
 }
 
+/// All errors that can occur when creating a DPoP proof.
+#[derive(Debug, Error)]
+pub enum DpopError {
+    /// The key cannot be used with the algorithm.
+    #[error(transparent)]
+    WrongAlgorithm(#[from] WrongAlgorithmError),
+
+    /// An error occurred when building the claims of the proof.
+    #[error(transparent)]
+    Claims(#[from] ClaimError),
+
+    /// An error occurred when signing the proof.
+    #[error(transparent)]
+    Signature(#[from] JwtSignatureError),
+}
+
 #[derive(Debug, Deserialize)]
 struct OAuth2ErrorResponse {
     error: String,
@@ -268,6 +553,13 @@ pub struct OAuth2Error {
     inner: reqwest::Error,
 }
 
+impl OAuth2Error {
+    /// The error code returned by the provider, if any
+    pub(crate) fn error_code(&self) -> Option<&str> {
+        self.error.as_ref().map(|error| error.error.as_str())
+    }
+}
+
 impl std::fmt::Display for OAuth2Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(error) = &self.error {
@@ -47,11 +47,20 @@ pub mod error;
 pub mod requests;
 pub mod types;
 
-use std::fmt;
+use std::{fmt, sync::LazyLock};
 
 #[doc(inline)]
 pub use mas_jose as jose;
 
+static METER: LazyLock<opentelemetry::metrics::Meter> = LazyLock::new(|| {
+    let scope = opentelemetry::InstrumentationScope::builder(env!("CARGO_PKG_NAME"))
+        .with_version(env!("CARGO_PKG_VERSION"))
+        .with_schema_url(opentelemetry_semantic_conventions::SCHEMA_URL)
+        .build();
+
+    opentelemetry::global::meter_with_scope(scope)
+});
+
 // Wrapper around `String` that cannot be used in a meaningful way outside of
 // this crate. Used for string enums that only allow certain characters because
 // their variant can't be private.
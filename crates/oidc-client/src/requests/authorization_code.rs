@@ -35,7 +35,7 @@ use super::jose::JwtVerificationData;
 use crate::{
     error::{AuthorizationError, IdTokenError, TokenAuthorizationCodeError},
     requests::{jose::verify_id_token, token::request_access_token},
-    types::{IdToken, client_credentials::ClientCredentials},
+    types::{IdToken, client_credentials::ClientCredentials, dpop::DpopSigner},
 };
 
 /// The data necessary to build an authorization request.
@@ -66,9 +66,10 @@ pub struct AuthorizationRequestData {
     pub display: Option<Display>,
 
     /// Whether the Authorization Server should prompt the End-User for
-    /// reauthentication and consent.
+    /// reauthentication, consent or registration.
     ///
-    /// If [`Prompt::None`] is used, it must be the only value.
+    /// Several values can be combined, they are sent separated by spaces. If
+    /// [`Prompt::None`] is used, it must be the only value.
     pub prompt: Option<Vec<Prompt>>,
 
     /// The allowable elapsed time in seconds since the last time the End-User
@@ -88,10 +89,24 @@ pub struct AuthorizationRequestData {
     pub login_hint: Option<String>,
 
     /// Requested Authentication Context Class Reference values.
+    ///
+    /// They are sent separated by spaces, so they must not be empty or contain
+    /// whitespace.
     pub acr_values: Option<HashSet<String>>,
 
+    /// Individual claims to return from the userinfo endpoint and/or in the ID
+    /// Token.
+    ///
+    /// It must be a JSON object, whose `userinfo` and `id_token` members are
+    /// objects mapping claim names to `null` or to an object describing the
+    /// request for that claim.
+    pub claims: Option<serde_json::Value>,
+
     /// Requested response mode.
     pub response_mode: Option<ResponseMode>,
+
+    /// The JWK Thumbprint of the DPoP key to bind the authorization code to.
+    pub dpop_jkt: Option<String>,
 }
 
 impl AuthorizationRequestData {
@@ -111,7 +126,9 @@ impl AuthorizationRequestData {
             id_token_hint: None,
             login_hint: None,
             acr_values: None,
+            claims: None,
             response_mode: None,
+            dpop_jkt: None,
         }
     }
 
@@ -134,6 +151,9 @@ impl AuthorizationRequestData {
     }
 
     /// Set the `prompt` field of this `AuthorizationRequestData`.
+    ///
+    /// Use [`Prompt::Create`] to send the End-User to the registration page
+    /// of the Authorization Server.
     #[must_use]
     pub fn with_prompt(mut self, prompt: Vec<Prompt>) -> Self {
         self.prompt = Some(prompt);
@@ -175,12 +195,44 @@ impl AuthorizationRequestData {
         self
     }
 
+    /// Set the `claims` field of this `AuthorizationRequestData`.
+    ///
+    /// For example, to request the `email_verified` claim in the ID Token:
+    ///
+    /// ```
+    /// # use mas_oidc_client::requests::authorization_code::AuthorizationRequestData;
+    /// # let data = AuthorizationRequestData::new(
+    /// #     "client".to_owned(),
+    /// #     "openid".parse().unwrap(),
+    /// #     "http://localhost/".parse().unwrap(),
+    /// # );
+    /// let data = data.with_claims(serde_json::json!({
+    ///     "id_token": {
+    ///         "email_verified": { "essential": true },
+    ///     },
+    /// }));
+    /// ```
+    #[must_use]
+    pub fn with_claims(mut self, claims: serde_json::Value) -> Self {
+        self.claims = Some(claims);
+        self
+    }
+
     /// Set the `response_mode` field of this `AuthorizationRequestData`.
     #[must_use]
     pub fn with_response_mode(mut self, response_mode: ResponseMode) -> Self {
         self.response_mode = Some(response_mode);
         self
     }
+
+    /// Set the `dpop_jkt` field of this `AuthorizationRequestData`.
+    ///
+    /// The thumbprint can be obtained with [`DpopSigner::jkt`].
+    #[must_use]
+    pub fn with_dpop_jkt(mut self, dpop_jkt: String) -> Self {
+        self.dpop_jkt = Some(dpop_jkt);
+        self
+    }
 }
 
 /// The data necessary to validate a response from the Token endpoint in the
@@ -203,16 +255,85 @@ pub struct AuthorizationValidationData {
 }
 
 #[derive(Clone, Serialize)]
-struct FullAuthorizationRequest {
+pub(super) struct FullAuthorizationRequest {
     #[serde(flatten)]
     inner: AuthorizationRequest,
 
     #[serde(flatten, skip_serializing_if = "Option::is_none")]
     pkce: Option<pkce::AuthorizationRequest>,
+
+    /// The `claims` parameter, serialized as JSON.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    claims: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dpop_jkt: Option<String>,
+}
+
+/// Check that the `prompt` values are defined by the specifications, and that
+/// `none` is not combined with other values.
+fn validate_prompt(prompt: &[Prompt]) -> Result<(), AuthorizationError> {
+    if let Some(Prompt::Unknown(value)) = prompt
+        .iter()
+        .find(|value| matches!(value, Prompt::Unknown(_)))
+    {
+        return Err(AuthorizationError::UnknownPrompt(value.clone()));
+    }
+
+    if prompt.len() > 1 && prompt.contains(&Prompt::None) {
+        return Err(AuthorizationError::PromptNoneNotAlone);
+    }
+
+    Ok(())
+}
+
+/// Check that the `acr_values` can be sent separated by spaces.
+fn validate_acr_values(acr_values: &HashSet<String>) -> Result<(), AuthorizationError> {
+    if let Some(value) = acr_values
+        .iter()
+        .find(|value| value.is_empty() || value.contains(char::is_whitespace))
+    {
+        return Err(AuthorizationError::InvalidAcrValue(value.clone()));
+    }
+
+    Ok(())
+}
+
+/// Check that the `claims` parameter is well-formed, as defined in [OpenID
+/// Connect Core 1.0].
+///
+/// [OpenID Connect Core 1.0]: https://openid.net/specs/openid-connect-core-1_0.html#ClaimsParameter
+fn validate_claims(claims: &serde_json::Value) -> Result<(), AuthorizationError> {
+    let claims = claims
+        .as_object()
+        .ok_or(AuthorizationError::InvalidClaims("not a JSON object"))?;
+
+    for member in ["userinfo", "id_token"] {
+        let Some(requests) = claims.get(member) else {
+            continue;
+        };
+
+        let requests = requests
+            .as_object()
+            .ok_or(AuthorizationError::InvalidClaims(
+                "the `userinfo` and `id_token` members must be JSON objects",
+            ))?;
+
+        if requests
+            .values()
+            .any(|request| !request.is_null() && !request.is_object())
+        {
+            return Err(AuthorizationError::InvalidClaims(
+                "individual claim requests must be null or JSON objects",
+            ));
+        }
+    }
+
+    Ok(())
 }
 
 /// Build the authorization request.
-fn build_authorization_request(
+pub(super) fn build_authorization_request(
     authorization_data: AuthorizationRequestData,
     rng: &mut impl Rng,
 ) -> Result<(FullAuthorizationRequest, AuthorizationValidationData), AuthorizationError> {
@@ -228,9 +349,27 @@ fn build_authorization_request(
         id_token_hint,
         login_hint,
         acr_values,
+        claims,
         response_mode,
+        dpop_jkt,
     } = authorization_data;
 
+    // An empty list of prompt values is the same as no prompt
+    let prompt = prompt.filter(|prompt| !prompt.is_empty());
+    if let Some(prompt) = &prompt {
+        validate_prompt(prompt)?;
+    }
+
+    // An empty list of ACR values is the same as no ACR values
+    let acr_values = acr_values.filter(|acr_values| !acr_values.is_empty());
+    if let Some(acr_values) = &acr_values {
+        validate_acr_values(acr_values)?;
+    }
+
+    let claims = claims
+        .map(|claims| validate_claims(&claims).map(|()| claims.to_string()))
+        .transpose()?;
+
     let is_openid = scope.contains(&OPENID);
 
     // Generate a random CSRF "state" token and a nonce.
@@ -282,6 +421,8 @@ fn build_authorization_request(
             registration: None,
         },
         pkce,
+        claims,
+        dpop_jkt,
     };
 
     let auth_data = AuthorizationValidationData {
@@ -320,7 +461,8 @@ fn build_authorization_request(
 ///
 /// # Errors
 ///
-/// Returns an error if preparing the URL fails.
+/// Returns an error if the `prompt` values are invalid, or if preparing the URL
+/// fails.
 ///
 /// [`VerifiedClientMetadata`]: oauth2_types::registration::VerifiedClientMetadata
 /// [`ClientErrorCode`]: oauth2_types::errors::ClientErrorCode
@@ -385,6 +527,9 @@ pub fn build_authorization_url(
 ///   If it is not provided, the ID Token won't be verified. Note that in the
 ///   OpenID Connect specification, this verification is required.
 ///
+/// * `dpop_signer` - The signer of the DPoP proofs, to request DPoP-bound
+///   tokens.
+///
 /// * `now` - The current time.
 ///
 /// * `rng` - A random number generator.
@@ -402,6 +547,7 @@ pub async fn access_token_with_authorization_code(
     code: String,
     validation_data: AuthorizationValidationData,
     id_token_verification_data: Option<JwtVerificationData<'_>>,
+    dpop_signer: Option<&DpopSigner>,
     now: DateTime<Utc>,
     rng: &mut impl Rng,
 ) -> Result<(AccessTokenResponse, Option<IdToken<'static>>), TokenAuthorizationCodeError> {
@@ -416,6 +562,7 @@ pub async fn access_token_with_authorization_code(
             redirect_uri: Some(validation_data.redirect_uri),
             code_verifier: validation_data.code_challenge_verifier,
         }),
+        dpop_signer,
         now,
         rng,
     )
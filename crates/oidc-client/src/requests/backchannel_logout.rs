@@ -0,0 +1,94 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Methods for [Back-Channel Logout].
+//!
+//! [Back-Channel Logout]: https://openid.net/specs/openid-connect-backchannel-1_0.html
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use mas_jose::claims::{self, Claim, TimeOptions};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::jose::{JwtVerificationData, verify_signed_jwt};
+use crate::error::LogoutTokenError;
+
+#[derive(Deserialize)]
+struct LogoutTokenEvents {
+    #[allow(dead_code)] // We just want to check it deserializes
+    #[serde(rename = "http://schemas.openid.net/event/backchannel-logout")]
+    backchannel_logout: HashMap<String, Value>,
+}
+
+const EVENTS: Claim<LogoutTokenEvents> = Claim::new("events");
+
+/// The claims of a validated logout token, identifying what to log out.
+///
+/// At least one of `sub` and `sid` is present.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogoutToken {
+    /// The subject identifier of the end-user to log out.
+    pub sub: Option<String>,
+
+    /// The ID of the session to log out.
+    pub sid: Option<String>,
+}
+
+/// Decode and validate a [Logout Token].
+///
+/// Besides the checks of [`verify_signed_jwt()`], the following checks are
+/// performed:
+///
+/// * The `exp` claim must be present and the token must not have expired.
+///
+/// * The `iat` claim must be present and in the past.
+///
+/// * The `sub` claim, the `sid` claim, or both must be present.
+///
+/// * The `events` claim must be present and contain the back-channel logout
+///   event.
+///
+/// * The `nonce` claim must be absent.
+///
+/// # Arguments
+///
+/// * `logout_token` - The serialized Logout Token to decode and validate.
+///
+/// * `verification_data` - The data necessary to verify the Logout Token. The
+///   signing algorithm is the same as for ID Tokens.
+///
+/// * `now` - The current time.
+///
+/// # Errors
+///
+/// Returns an error if the data is invalid or verification fails.
+///
+/// [Logout Token]: https://openid.net/specs/openid-connect-backchannel-1_0.html#LogoutToken
+pub fn validate_logout_token(
+    logout_token: &str,
+    verification_data: JwtVerificationData<'_>,
+    now: DateTime<Utc>,
+) -> Result<LogoutToken, LogoutTokenError> {
+    let logout_token = verify_signed_jwt(logout_token, verification_data)?;
+
+    let (_header, mut claims) = logout_token.into_parts();
+
+    let time_options = TimeOptions::new(now);
+    claims::EXP.extract_required_with_options(&mut claims, &time_options)?;
+    claims::IAT.extract_required_with_options(&mut claims, &time_options)?;
+
+    let sub = claims::SUB.extract_optional(&mut claims)?;
+    let sid = claims::SID.extract_optional(&mut claims)?;
+    if sub.is_none() && sid.is_none() {
+        return Err(LogoutTokenError::NoSubOrSidClaim);
+    }
+
+    EVENTS.extract_required(&mut claims)?;
+    claims::NONCE.assert_absent(&claims)?;
+
+    Ok(LogoutToken { sub, sid })
+}
@@ -17,8 +17,9 @@ use rand::Rng;
 use url::Url;
 
 use crate::{
-    error::TokenRequestError, requests::token::request_access_token,
-    types::client_credentials::ClientCredentials,
+    error::TokenRequestError,
+    requests::token::request_access_token,
+    types::{client_credentials::ClientCredentials, dpop::DpopSigner},
 };
 
 /// Exchange an authorization code for an access token.
@@ -37,6 +38,9 @@ use crate::{
 ///
 /// * `scope` - The scope to authorize.
 ///
+/// * `dpop_signer` - The signer of the DPoP proofs, to request DPoP-bound
+///   tokens.
+///
 /// * `now` - The current time.
 ///
 /// * `rng` - A random number generator.
@@ -50,6 +54,7 @@ pub async fn access_token_with_client_credentials(
     client_credentials: ClientCredentials,
     token_endpoint: &Url,
     scope: Option<Scope>,
+    dpop_signer: Option<&DpopSigner>,
     now: DateTime<Utc>,
     rng: &mut impl Rng,
 ) -> Result<AccessTokenResponse, TokenRequestError> {
@@ -60,6 +65,7 @@ pub async fn access_token_with_client_credentials(
         client_credentials,
         token_endpoint,
         AccessTokenRequest::ClientCredentials(ClientCredentialsGrant { scope }),
+        dpop_signer,
         now,
         rng,
     )
@@ -0,0 +1,169 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Requests for the [Device Authorization Grant].
+//!
+//! [Device Authorization Grant]: https://www.rfc-editor.org/rfc/rfc8628
+
+use std::time::Instant;
+
+use chrono::{DateTime, Duration, Utc};
+use http::header::ACCEPT;
+use mas_http::RequestBuilderExt;
+use mime::APPLICATION_JSON;
+use oauth2_types::{
+    requests::{
+        AccessTokenRequest, AccessTokenResponse, DeviceAuthorizationRequest,
+        DeviceAuthorizationResponse, DeviceCodeGrant,
+    },
+    scope::Scope,
+};
+use rand::Rng;
+use url::Url;
+
+use crate::{
+    error::{DeviceAuthorizationError, DeviceCodeTokenError, ResponseExt, TokenRequestError},
+    requests::token::request_access_token,
+    types::{client_credentials::ClientCredentials, dpop::DpopSigner},
+};
+
+/// How much the polling interval is increased when the server responds with a
+/// `slow_down` error, as required by the specification.
+const SLOW_DOWN_INCREMENT: Duration = Duration::seconds(5);
+
+/// Request a device code, to start a device authorization flow.
+///
+/// The end user should then be asked to visit the `verification_uri` of the
+/// response and enter the `user_code`, while the device polls the token
+/// endpoint with [`poll_device_token`].
+///
+/// # Arguments
+///
+/// * `http_client` - The reqwest client to use for making HTTP requests.
+///
+/// * `client_credentials` - The credentials obtained when registering the
+///   client.
+///
+/// * `device_authorization_endpoint` - The URL of the issuer's Device
+///   Authorization endpoint.
+///
+/// * `scope` - The scope to authorize.
+///
+/// * `now` - The current time.
+///
+/// * `rng` - A random number generator.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the server returns an error.
+#[tracing::instrument(skip_all, fields(device_authorization_endpoint))]
+pub async fn request_device_code(
+    http_client: &reqwest::Client,
+    client_credentials: ClientCredentials,
+    device_authorization_endpoint: &Url,
+    scope: Option<Scope>,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<DeviceAuthorizationResponse, DeviceAuthorizationError> {
+    tracing::debug!("Requesting device code...");
+
+    let request = http_client
+        .post(device_authorization_endpoint.as_str())
+        .header(ACCEPT, APPLICATION_JSON.as_ref());
+
+    let response = client_credentials
+        .authenticated_form(request, &DeviceAuthorizationRequest { scope }, now, rng)?
+        .send_traced()
+        .await?
+        .error_from_oauth2_error_response()
+        .await?
+        .json()
+        .await?;
+
+    Ok(response)
+}
+
+/// Poll the token endpoint with a device code, until the end user authorized
+/// the device.
+///
+/// The requests are sent every `interval` of the device authorization
+/// response. If the server responds with a `slow_down` error, the interval is
+/// increased by 5 seconds for all the subsequent requests.
+///
+/// # Arguments
+///
+/// * `http_client` - The reqwest client to use for making HTTP requests.
+///
+/// * `client_credentials` - The credentials obtained when registering the
+///   client.
+///
+/// * `token_endpoint` - The URL of the issuer's Token endpoint.
+///
+/// * `device_authorization` - The response of the Device Authorization
+///   endpoint, obtained with [`request_device_code`].
+///
+/// * `dpop_signer` - The signer of the DPoP proofs, to request DPoP-bound
+///   tokens.
+///
+/// * `now` - The current time. It is advanced by the time spent polling for the
+///   subsequent requests.
+///
+/// * `rng` - A random number generator.
+///
+/// # Errors
+///
+/// Returns an error if a request fails, if the server returns an error other
+/// than `authorization_pending` or `slow_down`, or if the device code expired.
+#[tracing::instrument(skip_all, fields(token_endpoint))]
+pub async fn poll_device_token(
+    http_client: &reqwest::Client,
+    client_credentials: ClientCredentials,
+    token_endpoint: &Url,
+    device_authorization: &DeviceAuthorizationResponse,
+    dpop_signer: Option<&DpopSigner>,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<AccessTokenResponse, DeviceCodeTokenError> {
+    let start = Instant::now();
+    let mut interval = device_authorization.interval();
+
+    loop {
+        let elapsed = Duration::from_std(start.elapsed()).unwrap_or(Duration::MAX);
+        if elapsed >= device_authorization.expires_in {
+            return Err(DeviceCodeTokenError::Expired);
+        }
+
+        tracing::debug!("Polling the token endpoint with the device code...");
+
+        let result = request_access_token(
+            http_client,
+            client_credentials.clone(),
+            token_endpoint,
+            AccessTokenRequest::DeviceCode(DeviceCodeGrant {
+                device_code: device_authorization.device_code.clone(),
+            }),
+            dpop_signer,
+            now + elapsed,
+            rng,
+        )
+        .await;
+
+        match result {
+            Ok(response) => return Ok(response),
+            Err(TokenRequestError::OAuth2(error))
+                if error.error_code() == Some("authorization_pending") =>
+            {
+                tracing::debug!("The authorization is still pending");
+            }
+            Err(TokenRequestError::OAuth2(error)) if error.error_code() == Some("slow_down") => {
+                interval += SLOW_DOWN_INCREMENT;
+                tracing::debug!(?interval, "Asked to slow down, increasing the interval");
+            }
+            Err(error) => return Err(error.into()),
+        }
+
+        tokio::time::sleep(interval.to_std().unwrap_or_default()).await;
+    }
+}
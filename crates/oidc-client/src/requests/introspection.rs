@@ -0,0 +1,174 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Requests for the [Token Introspection] endpoint.
+//!
+//! [Token Introspection]: https://www.rfc-editor.org/rfc/rfc7662
+
+use std::{sync::LazyLock, time::Duration};
+
+use chrono::{DateTime, Utc};
+use http::{Method, header::ACCEPT};
+use mas_iana::oauth::OAuthTokenTypeHint;
+use mime::APPLICATION_JSON;
+use oauth2_types::requests::{IntrospectionRequest, IntrospectionResponse};
+use opentelemetry::{KeyValue, metrics::Counter};
+use rand::Rng;
+use url::Url;
+
+use crate::{
+    METER,
+    error::{IntrospectionError, ResponseExt},
+    requests::{OUTCOME, TOKEN_TYPE_HINT, token_type_hint_label},
+    types::{
+        client_credentials::{ClientAssertionCache, ClientCredentials},
+        dpop::{DpopSigner, with_dpop_proof},
+        retry::{RetryPolicy, send_with_retry, with_timeout},
+    },
+};
+
+static INTROSPECTION_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    METER
+        .u64_counter("mas.oidc_client.introspection_request")
+        .with_description("Number of token introspection requests sent to an upstream provider")
+        .with_unit("{request}")
+        .build()
+});
+
+/// Obtain information about a token.
+///
+/// # Arguments
+///
+/// * `http_client` - The reqwest client to use for making HTTP requests.
+///
+/// * `client_credentials` - The credentials obtained when registering the
+///   client.
+///
+/// * `introspection_endpoint` - The URL of the issuer's Introspection endpoint.
+///
+/// * `token` - The token to introspect.
+///
+/// * `token_type_hint` - A hint about the type of the token.
+///
+/// * `dpop_signer` - The signer of the DPoP proofs, to introspect DPoP-bound
+///   tokens.
+///
+/// * `retry_policy` - How to retry the request if it fails because of a
+///   transient error. If not provided, the request is sent only once.
+///
+/// * `client_assertion_cache` - The [`ClientAssertionCache`] to reuse client
+///   assertions from, if any.
+///
+/// * `timeout` - How long to wait for the response, including the retries. If
+///   not provided, the timeout of the HTTP client applies.
+///
+/// * `now` - The current time.
+///
+/// * `rng` - A random number generator.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the response is invalid.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all, fields(introspection_endpoint))]
+pub async fn introspect_token(
+    http_client: &reqwest::Client,
+    client_credentials: ClientCredentials,
+    introspection_endpoint: &Url,
+    token: String,
+    token_type_hint: Option<OAuthTokenTypeHint>,
+    dpop_signer: Option<&DpopSigner>,
+    retry_policy: Option<&RetryPolicy>,
+    client_assertion_cache: Option<&ClientAssertionCache>,
+    timeout: Option<Duration>,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<IntrospectionResponse, IntrospectionError> {
+    tracing::debug!("Introspecting token...");
+
+    let token_type_hint_label = token_type_hint_label(token_type_hint.as_ref());
+    let request = IntrospectionRequest {
+        token,
+        token_type_hint,
+    };
+
+    let result = send_introspection_request(
+        http_client,
+        &client_credentials,
+        introspection_endpoint,
+        &request,
+        dpop_signer,
+        retry_policy,
+        client_assertion_cache,
+        timeout,
+        now,
+        rng,
+    )
+    .await;
+
+    let outcome = if result.is_ok() { "success" } else { "error" };
+    INTROSPECTION_COUNTER.add(
+        1,
+        &[
+            KeyValue::new(TOKEN_TYPE_HINT, token_type_hint_label),
+            KeyValue::new(OUTCOME, outcome),
+        ],
+    );
+
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send_introspection_request(
+    http_client: &reqwest::Client,
+    client_credentials: &ClientCredentials,
+    introspection_endpoint: &Url,
+    request: &IntrospectionRequest,
+    dpop_signer: Option<&DpopSigner>,
+    retry_policy: Option<&RetryPolicy>,
+    client_assertion_cache: Option<&ClientAssertionCache>,
+    timeout: Option<Duration>,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<IntrospectionResponse, IntrospectionError> {
+    // The request is built for every attempt, so that the DPoP proof and the
+    // client assertion aren't reused
+    let build_request = |rng: &mut _| -> Result<_, IntrospectionError> {
+        let introspection_request = http_client
+            .post(introspection_endpoint.as_str())
+            .header(ACCEPT, APPLICATION_JSON.as_ref());
+        let introspection_request = with_dpop_proof(
+            introspection_request,
+            dpop_signer,
+            Method::POST,
+            introspection_endpoint,
+            now,
+            rng,
+        )?;
+
+        let introspection_request = client_credentials.authenticated_form_with_cache(
+            introspection_request,
+            request,
+            client_assertion_cache,
+            now,
+            rng,
+        )?;
+
+        Ok(introspection_request)
+    };
+
+    with_timeout(timeout, async {
+        let introspection_response = send_with_retry(build_request, retry_policy, rng)
+            .await?
+            .error_from_oauth2_error_response()
+            .await?
+            .json()
+            .await?;
+
+        Ok(introspection_response)
+    })
+    .await
+    .unwrap_or(Err(IntrospectionError::Timeout))
+}
@@ -0,0 +1,87 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Methods for the [JWT Secured Authorization Response Mode] (JARM).
+//!
+//! [JWT Secured Authorization Response Mode]: https://openid.net/specs/oauth-v2-jarm.html
+
+use chrono::{DateTime, Utc};
+use mas_jose::claims::{self, Claim, TimeOptions};
+
+use super::jose::{JwtVerificationData, verify_signed_jwt};
+use crate::error::JarmError;
+
+// The issuer was already checked by `verify_signed_jwt`, if it was provided
+const ISS: Claim<String> = Claim::new("iss");
+const CODE: Claim<String> = Claim::new("code");
+const STATE: Claim<String> = Claim::new("state");
+const ERROR: Claim<String> = Claim::new("error");
+const ERROR_DESCRIPTION: Claim<String> = Claim::new("error_description");
+
+/// The parameters of a validated authorization response, sent as a JWT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JarmResponse {
+    /// The authorization code, to exchange for an access token.
+    pub code: String,
+
+    /// The `state` of the authorization request, if it had one.
+    pub state: Option<String>,
+
+    /// The issuer of the response.
+    pub iss: String,
+}
+
+/// Decode and validate an authorization response sent as a JWT, in the
+/// `response` parameter.
+///
+/// Besides the checks of [`verify_signed_jwt()`], the following checks are
+/// performed:
+///
+/// * The `iss` claim must be present.
+///
+/// * The `exp` claim must be present and the response must not have expired.
+///
+/// * The `code` claim must be present, unless the response is an error.
+///
+/// # Arguments
+///
+/// * `response` - The serialized JWT of the `response` parameter.
+///
+/// * `verification_data` - The data necessary to verify the response. The
+///   issuer should be set, so that responses of other providers are rejected.
+///   The signing algorithm corresponds to the
+///   `authorization_signed_response_alg` field in the client metadata.
+///
+/// * `now` - The current time.
+///
+/// # Errors
+///
+/// Returns an error if the data is invalid, verification fails, or if the
+/// response is an error response.
+pub fn verify_jarm_response(
+    response: &str,
+    verification_data: JwtVerificationData<'_>,
+    now: DateTime<Utc>,
+) -> Result<JarmResponse, JarmError> {
+    let response = verify_signed_jwt(response, verification_data)?;
+
+    let (_header, mut claims) = response.into_parts();
+
+    let iss = ISS.extract_required(&mut claims)?;
+    claims::EXP.extract_required_with_options(&mut claims, TimeOptions::new(now))?;
+
+    if let Some(error) = ERROR.extract_optional(&mut claims)? {
+        let error_description = ERROR_DESCRIPTION.extract_optional(&mut claims)?;
+        return Err(JarmError::ErrorResponse {
+            error,
+            error_description,
+        });
+    }
+
+    let code = CODE.extract_required(&mut claims)?;
+    let state = STATE.extract_optional(&mut claims)?;
+
+    Ok(JarmResponse { code, state, iss })
+}
@@ -7,9 +7,37 @@
 //! Methods to interact with OpenID Connect and OAuth2.0 endpoints.
 
 pub mod authorization_code;
+pub mod backchannel_logout;
 pub mod client_credentials;
+pub mod device_authorization;
 pub mod discovery;
+pub mod introspection;
+pub mod jarm;
 pub mod jose;
+pub mod par;
 pub mod refresh_token;
+pub mod registration;
+pub mod revocation;
 pub mod token;
+pub mod token_exchange;
 pub mod userinfo;
+
+use mas_iana::oauth::OAuthTokenTypeHint;
+use opentelemetry::Key;
+
+const TOKEN_TYPE_HINT: Key = Key::from_static_str("token_type_hint");
+const OUTCOME: Key = Key::from_static_str("outcome");
+
+/// The value of the `token_type_hint` attribute of the metrics.
+///
+/// Unknown hints are grouped together, to keep the cardinality of the metrics
+/// bounded.
+fn token_type_hint_label(token_type_hint: Option<&OAuthTokenTypeHint>) -> &'static str {
+    match token_type_hint {
+        None => "none",
+        Some(OAuthTokenTypeHint::AccessToken) => "access_token",
+        Some(OAuthTokenTypeHint::RefreshToken) => "refresh_token",
+        Some(OAuthTokenTypeHint::Pct) => "pct",
+        Some(_) => "unknown",
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Requests for the [Pushed Authorization Request] endpoint.
+//!
+//! [Pushed Authorization Request]: https://www.rfc-editor.org/rfc/rfc9126
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use http::header::ACCEPT;
+use mas_http::RequestBuilderExt;
+use mime::APPLICATION_JSON;
+use oauth2_types::requests::PushedAuthorizationResponse;
+use rand::Rng;
+use url::Url;
+
+use super::authorization_code::{
+    AuthorizationRequestData, AuthorizationValidationData, build_authorization_request,
+};
+use crate::{
+    error::{PushedAuthorizationError, ResponseExt},
+    types::client_credentials::ClientCredentials,
+};
+
+/// Push an authorization request to the issuer.
+///
+/// The returned `request_uri` can then be used to build the URL for
+/// authenticating at the Authorization endpoint, with
+/// [`build_pushed_authorization_url`].
+///
+/// # Arguments
+///
+/// * `http_client` - The reqwest client to use for making HTTP requests.
+///
+/// * `client_credentials` - The credentials obtained when registering the
+///   client.
+///
+/// * `par_endpoint` - The URL of the issuer's Pushed Authorization Request
+///   endpoint.
+///
+/// * `authorization_data` - The data necessary to build the authorization
+///   request.
+///
+/// * `now` - The current time.
+///
+/// * `rng` - A random number generator.
+///
+/// # Returns
+///
+/// The response of the issuer, and the [`AuthorizationValidationData`] to
+/// validate this request.
+///
+/// # Errors
+///
+/// Returns an error if preparing the request fails, the request fails or the
+/// response is invalid.
+#[tracing::instrument(skip_all, fields(par_endpoint))]
+pub async fn push_authorization_request(
+    http_client: &reqwest::Client,
+    client_credentials: ClientCredentials,
+    par_endpoint: &Url,
+    authorization_data: AuthorizationRequestData,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<(PushedAuthorizationResponse, AuthorizationValidationData), PushedAuthorizationError> {
+    tracing::debug!(
+        scope = ?authorization_data.scope,
+        "Pushing authorization request..."
+    );
+
+    let (authorization_request, validation_data) =
+        build_authorization_request(authorization_data, rng)?;
+
+    // The client ID is added by the client credentials if needed, so remove it
+    // from the parameters to avoid sending it twice.
+    let authorization_query = serde_urlencoded::to_string(authorization_request)?;
+    let params: BTreeMap<String, String> = form_urlencoded::parse(authorization_query.as_bytes())
+        .into_owned()
+        .filter(|(key, _)| key != "client_id")
+        .collect();
+
+    let par_request = http_client
+        .post(par_endpoint.as_str())
+        .header(ACCEPT, APPLICATION_JSON.as_ref());
+
+    let par_response = client_credentials
+        .authenticated_form(par_request, &params, now, rng)?
+        .send_traced()
+        .await?
+        .error_from_oauth2_error_response()
+        .await?
+        .json()
+        .await?;
+
+    Ok((par_response, validation_data))
+}
+
+/// Build the URL for authenticating at the Authorization endpoint with a
+/// pushed authorization request.
+///
+/// # Arguments
+///
+/// * `authorization_endpoint` - The URL of the issuer's authorization endpoint.
+///
+/// * `client_id` - The ID of the client that pushed the authorization request.
+///
+/// * `request_uri` - The `request_uri` returned by
+///   [`push_authorization_request`].
+///
+/// # Returns
+///
+/// A URL to be opened in a web browser where the end-user will be able to
+/// authorize the pushed authorization request.
+#[must_use]
+pub fn build_pushed_authorization_url(
+    authorization_endpoint: Url,
+    client_id: &str,
+    request_uri: &str,
+) -> Url {
+    let mut authorization_url = authorization_endpoint;
+
+    authorization_url
+        .query_pairs_mut()
+        .append_pair("client_id", client_id)
+        .append_pair("request_uri", request_uri);
+
+    authorization_url
+}
@@ -21,13 +21,15 @@ use super::jose::JwtVerificationData;
 use crate::{
     error::{IdTokenError, TokenRefreshError},
     requests::{jose::verify_id_token, token::request_access_token},
-    types::{IdToken, client_credentials::ClientCredentials},
+    types::{IdToken, client_credentials::ClientCredentials, dpop::DpopSigner},
 };
 
-/// Exchange an authorization code for an access token.
+/// Exchange a refresh token for a new access token.
 ///
-/// This should be used as the first step for logging in, and to request a
-/// token with a new scope.
+/// The provider may rotate the refresh token, by returning a new one which
+/// supersedes the one used here. If it doesn't, the `refresh_token` of the
+/// returned response is set to the one used here, so that it is always the one
+/// to use for the next refresh.
 ///
 /// # Arguments
 ///
@@ -56,6 +58,9 @@ use crate::{
 /// * `auth_id_token` - If an ID Token is expected in the response, the ID token
 ///   that was returned from the latest authorization request.
 ///
+/// * `dpop_signer` - The signer of the DPoP proofs, to request DPoP-bound
+///   tokens.
+///
 /// * `now` - The current time.
 ///
 /// * `rng` - A random number generator.
@@ -64,6 +69,10 @@ use crate::{
 ///
 /// Returns an error if the request fails, the response is invalid or the
 /// verification of the ID Token fails.
+///
+/// If the provider rejects the refresh token, the error is
+/// [`TokenRefreshError::InvalidGrant`], which means that the user needs to
+/// authenticate again.
 #[allow(clippy::too_many_arguments)]
 #[tracing::instrument(skip_all, fields(token_endpoint))]
 pub async fn refresh_access_token(
@@ -74,24 +83,31 @@ pub async fn refresh_access_token(
     scope: Option<Scope>,
     id_token_verification_data: Option<JwtVerificationData<'_>>,
     auth_id_token: Option<&IdToken<'_>>,
+    dpop_signer: Option<&DpopSigner>,
     now: DateTime<Utc>,
     rng: &mut impl Rng,
 ) -> Result<(AccessTokenResponse, Option<IdToken<'static>>), TokenRefreshError> {
     tracing::debug!("Refreshing access token…");
 
-    let token_response = request_access_token(
+    let mut token_response = request_access_token(
         http_client,
         client_credentials,
         token_endpoint,
         AccessTokenRequest::RefreshToken(RefreshTokenGrant {
-            refresh_token,
+            refresh_token: refresh_token.clone(),
             scope,
         }),
+        dpop_signer,
         now,
         rng,
     )
     .await?;
 
+    // Without a new refresh token, the one we used stays valid
+    if token_response.refresh_token.is_none() {
+        token_response.refresh_token = Some(refresh_token);
+    }
+
     let id_token = if let Some((verification_data, id_token)) =
         id_token_verification_data.zip(token_response.id_token.as_ref())
     {
@@ -0,0 +1,62 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Requests for [Dynamic Client Registration].
+//!
+//! [Dynamic Client Registration]: https://www.rfc-editor.org/rfc/rfc7591
+
+use http::header::ACCEPT;
+use mas_http::RequestBuilderExt;
+use mime::APPLICATION_JSON;
+use oauth2_types::registration::{ClientMetadata, ClientRegistrationResponse};
+use url::Url;
+
+use crate::error::{RegistrationError, ResponseExt};
+
+/// Register a client with the provider.
+///
+/// # Arguments
+///
+/// * `http_client` - The reqwest client to use for making HTTP requests.
+///
+/// * `registration_endpoint` - The URL of the issuer's Registration endpoint.
+///
+/// * `client_metadata` - The metadata of the client to register.
+///
+/// * `initial_access_token` - The token to authorize the registration with, if
+///   the provider requires one.
+///
+/// # Errors
+///
+/// Returns an error if the request fails, the provider rejects the client
+/// metadata, or the response is invalid.
+#[tracing::instrument(skip_all, fields(registration_endpoint))]
+pub async fn register_client(
+    http_client: &reqwest::Client,
+    registration_endpoint: &Url,
+    client_metadata: &ClientMetadata,
+    initial_access_token: Option<&str>,
+) -> Result<ClientRegistrationResponse, RegistrationError> {
+    tracing::debug!(?client_metadata, "Registering client...");
+
+    let mut registration_request = http_client
+        .post(registration_endpoint.as_str())
+        .header(ACCEPT, APPLICATION_JSON.as_ref())
+        .json(client_metadata);
+
+    if let Some(initial_access_token) = initial_access_token {
+        registration_request = registration_request.bearer_auth(initial_access_token);
+    }
+
+    let registration_response = registration_request
+        .send_traced()
+        .await?
+        .error_from_oauth2_error_response()
+        .await?
+        .json()
+        .await?;
+
+    Ok(registration_response)
+}
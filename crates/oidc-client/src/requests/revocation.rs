@@ -0,0 +1,322 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Requests for the [Token Revocation] endpoint.
+//!
+//! [Token Revocation]: https://www.rfc-editor.org/rfc/rfc7009
+
+use std::{sync::LazyLock, time::Duration};
+
+use chrono::{DateTime, Utc};
+use http::Method;
+use mas_iana::oauth::OAuthTokenTypeHint;
+use oauth2_types::requests::RevocationRequest;
+use opentelemetry::{KeyValue, metrics::Counter};
+use rand::Rng;
+use url::Url;
+
+use crate::{
+    METER,
+    error::{ResponseExt, TokenRevocationError, VerifiedTokenRevocationError},
+    requests::{OUTCOME, TOKEN_TYPE_HINT, introspection::introspect_token, token_type_hint_label},
+    types::{
+        client_credentials::{ClientAssertionCache, ClientCredentials},
+        dpop::{DpopSigner, with_dpop_proof},
+        retry::{RetryPolicy, send_with_retry, with_timeout},
+    },
+};
+
+static REVOCATION_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    METER
+        .u64_counter("mas.oidc_client.revocation_request")
+        .with_description("Number of token revocation requests sent to an upstream provider")
+        .with_unit("{request}")
+        .build()
+});
+
+/// Revoke a token.
+///
+/// If the server rejects the `token_type_hint` with an
+/// `unsupported_token_type` error, the request is sent once more without the
+/// hint, as the server may still be able to find the token.
+///
+/// # Arguments
+///
+/// * `http_client` - The reqwest client to use for making HTTP requests.
+///
+/// * `client_credentials` - The credentials obtained when registering the
+///   client.
+///
+/// * `revocation_endpoint` - The URL of the issuer's Revocation endpoint.
+///
+/// * `token` - The token to revoke.
+///
+/// * `token_type_hint` - A hint about the type of the token.
+///
+/// * `dpop_signer` - The signer of the DPoP proofs, to revoke DPoP-bound
+///   tokens.
+///
+/// * `retry_policy` - How to retry the request if it fails because of a
+///   transient error. If not provided, the request is sent only once.
+///
+/// * `client_assertion_cache` - The [`ClientAssertionCache`] to reuse client
+///   assertions from, if any.
+///
+/// * `timeout` - How long to wait for the response, including the retries. If
+///   not provided, the timeout of the HTTP client applies.
+///
+/// * `now` - The current time.
+///
+/// * `rng` - A random number generator.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the server returns an error.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all, fields(revocation_endpoint))]
+pub async fn revoke_token(
+    http_client: &reqwest::Client,
+    client_credentials: ClientCredentials,
+    revocation_endpoint: &Url,
+    token: String,
+    token_type_hint: Option<OAuthTokenTypeHint>,
+    dpop_signer: Option<&DpopSigner>,
+    retry_policy: Option<&RetryPolicy>,
+    client_assertion_cache: Option<&ClientAssertionCache>,
+    timeout: Option<Duration>,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<(), TokenRevocationError> {
+    tracing::debug!("Revoking token...");
+
+    let token_type_hint_label = token_type_hint_label(token_type_hint.as_ref());
+    let request = RevocationRequest {
+        token,
+        token_type_hint,
+    };
+
+    let result = with_timeout(timeout, async {
+        let result = send_revocation_request(
+            http_client,
+            &client_credentials,
+            revocation_endpoint,
+            &request,
+            dpop_signer,
+            retry_policy,
+            client_assertion_cache,
+            now,
+            rng,
+        )
+        .await;
+
+        match result {
+            Err(TokenRevocationError::OAuth2(error))
+                if request.token_type_hint.is_some()
+                    && error.error_code() == Some("unsupported_token_type") =>
+            {
+                tracing::debug!("The token type hint was rejected, retrying without it");
+
+                let request = RevocationRequest {
+                    token_type_hint: None,
+                    ..request
+                };
+
+                send_revocation_request(
+                    http_client,
+                    &client_credentials,
+                    revocation_endpoint,
+                    &request,
+                    dpop_signer,
+                    retry_policy,
+                    client_assertion_cache,
+                    now,
+                    rng,
+                )
+                .await
+            }
+            result => result,
+        }
+    })
+    .await
+    .unwrap_or(Err(TokenRevocationError::Timeout));
+
+    let outcome = match &result {
+        Ok(()) => "success",
+        Err(TokenRevocationError::OAuth2(error))
+            if error.error_code() == Some("unsupported_token_type") =>
+        {
+            "unsupported"
+        }
+        Err(_) => "error",
+    };
+    REVOCATION_COUNTER.add(
+        1,
+        &[
+            KeyValue::new(TOKEN_TYPE_HINT, token_type_hint_label),
+            KeyValue::new(OUTCOME, outcome),
+        ],
+    );
+
+    result
+}
+
+/// How to verify that revoking a refresh token also revoked its access token,
+/// see [`revoke_token_and_verify`].
+#[derive(Debug, Clone)]
+pub struct CascadeVerification<'a> {
+    /// The URL of the issuer's Introspection endpoint.
+    pub introspection_endpoint: &'a Url,
+
+    /// The access token obtained along with the revoked refresh token.
+    pub access_token: String,
+}
+
+/// Revoke a token, and optionally verify that the server also revoked the
+/// associated access token.
+///
+/// [RFC 7009] says that revoking a refresh token should also invalidate the
+/// access tokens issued from it. When `verification` is provided, the access
+/// token is introspected after the revocation, which costs an extra request,
+/// to make sure the server did so.
+///
+/// # Arguments
+///
+/// * `http_client` - The reqwest client to use for making HTTP requests.
+///
+/// * `client_credentials` - The credentials obtained when registering the
+///   client.
+///
+/// * `revocation_endpoint` - The URL of the issuer's Revocation endpoint.
+///
+/// * `token` - The token to revoke.
+///
+/// * `token_type_hint` - A hint about the type of the token.
+///
+/// * `verification` - How to verify that the associated access token was
+///   revoked. If not provided, the revocation is not verified.
+///
+/// * `dpop_signer` - The signer of the DPoP proofs, to revoke and introspect
+///   DPoP-bound tokens.
+///
+/// * `retry_policy` - How to retry the requests if they fail because of a
+///   transient error. If not provided, the requests are sent only once.
+///
+/// * `client_assertion_cache` - The [`ClientAssertionCache`] to reuse client
+///   assertions from, if any.
+///
+/// * `timeout` - How long to wait for each response, including the retries. If
+///   not provided, the timeout of the HTTP client applies.
+///
+/// * `now` - The current time.
+///
+/// * `rng` - A random number generator.
+///
+/// # Errors
+///
+/// Returns an error if a request fails, if the server returns an error, or if
+/// the associated access token is still active after the revocation.
+///
+/// [RFC 7009]: https://www.rfc-editor.org/rfc/rfc7009#section-2.1
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all, fields(revocation_endpoint))]
+pub async fn revoke_token_and_verify(
+    http_client: &reqwest::Client,
+    client_credentials: ClientCredentials,
+    revocation_endpoint: &Url,
+    token: String,
+    token_type_hint: Option<OAuthTokenTypeHint>,
+    verification: Option<CascadeVerification<'_>>,
+    dpop_signer: Option<&DpopSigner>,
+    retry_policy: Option<&RetryPolicy>,
+    client_assertion_cache: Option<&ClientAssertionCache>,
+    timeout: Option<Duration>,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<(), VerifiedTokenRevocationError> {
+    revoke_token(
+        http_client,
+        client_credentials.clone(),
+        revocation_endpoint,
+        token,
+        token_type_hint,
+        dpop_signer,
+        retry_policy,
+        client_assertion_cache,
+        timeout,
+        now,
+        rng,
+    )
+    .await?;
+
+    let Some(verification) = verification else {
+        return Ok(());
+    };
+
+    tracing::debug!("Verifying that the associated access token was revoked...");
+
+    let response = introspect_token(
+        http_client,
+        client_credentials,
+        verification.introspection_endpoint,
+        verification.access_token,
+        Some(OAuthTokenTypeHint::AccessToken),
+        dpop_signer,
+        retry_policy,
+        client_assertion_cache,
+        timeout,
+        now,
+        rng,
+    )
+    .await?;
+
+    if response.active {
+        return Err(VerifiedTokenRevocationError::AccessTokenStillActive);
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send_revocation_request(
+    http_client: &reqwest::Client,
+    client_credentials: &ClientCredentials,
+    revocation_endpoint: &Url,
+    request: &RevocationRequest,
+    dpop_signer: Option<&DpopSigner>,
+    retry_policy: Option<&RetryPolicy>,
+    client_assertion_cache: Option<&ClientAssertionCache>,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<(), TokenRevocationError> {
+    // The request is built for every attempt, so that the DPoP proof and the
+    // client assertion aren't reused
+    let build_request = |rng: &mut _| -> Result<_, TokenRevocationError> {
+        let revocation_request = http_client.post(revocation_endpoint.as_str());
+        let revocation_request = with_dpop_proof(
+            revocation_request,
+            dpop_signer,
+            Method::POST,
+            revocation_endpoint,
+            now,
+            rng,
+        )?;
+        let revocation_request = client_credentials.authenticated_form_with_cache(
+            revocation_request,
+            request,
+            client_assertion_cache,
+            now,
+            rng,
+        )?;
+
+        Ok(revocation_request)
+    };
+
+    send_with_retry(build_request, retry_policy, rng)
+        .await?
+        .error_from_oauth2_error_response()
+        .await?;
+
+    Ok(())
+}
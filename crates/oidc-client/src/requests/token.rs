@@ -7,7 +7,7 @@
 //! Requests for the Token endpoint.
 
 use chrono::{DateTime, Utc};
-use http::header::ACCEPT;
+use http::{Method, header::ACCEPT};
 use mas_http::RequestBuilderExt;
 use mime::APPLICATION_JSON;
 use oauth2_types::requests::{AccessTokenRequest, AccessTokenResponse};
@@ -16,7 +16,10 @@ use url::Url;
 
 use crate::{
     error::{ResponseExt, TokenRequestError},
-    types::client_credentials::ClientCredentials,
+    types::{
+        client_credentials::ClientCredentials,
+        dpop::{DpopSigner, with_dpop_proof},
+    },
 };
 
 /// Request an access token.
@@ -32,6 +35,9 @@ use crate::{
 ///
 /// * `request` - The request to make at the Token endpoint.
 ///
+/// * `dpop_signer` - The signer of the DPoP proofs, to request DPoP-bound
+///   tokens.
+///
 /// * `now` - The current time.
 ///
 /// * `rng` - A random number generator.
@@ -45,6 +51,7 @@ pub async fn request_access_token(
     client_credentials: ClientCredentials,
     token_endpoint: &Url,
     request: AccessTokenRequest,
+    dpop_signer: Option<&DpopSigner>,
     now: DateTime<Utc>,
     rng: &mut impl Rng,
 ) -> Result<AccessTokenResponse, TokenRequestError> {
@@ -53,6 +60,14 @@ pub async fn request_access_token(
     let token_request = http_client
         .post(token_endpoint.as_str())
         .header(ACCEPT, APPLICATION_JSON.as_ref());
+    let token_request = with_dpop_proof(
+        token_request,
+        dpop_signer,
+        Method::POST,
+        token_endpoint,
+        now,
+        rng,
+    )?;
 
     let token_response = client_credentials
         .authenticated_form(token_request, &request, now, rng)?
@@ -0,0 +1,199 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Requests for [OAuth 2.0 Token Exchange].
+//!
+//! [OAuth 2.0 Token Exchange]: https://www.rfc-editor.org/rfc/rfc8693
+
+use chrono::{DateTime, Utc};
+use http::{Method, header::ACCEPT};
+use mas_http::RequestBuilderExt;
+use mime::APPLICATION_JSON;
+use oauth2_types::{requests::AccessTokenResponse, scope::Scope};
+use rand::Rng;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    error::{ResponseExt, TokenExchangeError},
+    types::{
+        client_credentials::ClientCredentials,
+        dpop::{DpopSigner, with_dpop_proof},
+    },
+};
+
+/// The grant type of token exchange requests.
+const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// The token type identifier of an OAuth 2.0 access token.
+pub const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// The token type identifier of an OAuth 2.0 refresh token.
+pub const REFRESH_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:refresh_token";
+
+/// The token type identifier of an OpenID Connect ID Token.
+pub const ID_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:id_token";
+
+/// The token type identifier of a JWT.
+pub const JWT_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:jwt";
+
+/// The data necessary to build a token exchange request.
+#[derive(Debug, Clone)]
+pub struct TokenExchangeData {
+    /// The token representing the identity of the party on behalf of whom the
+    /// request is being made.
+    pub subject_token: String,
+
+    /// The type of the subject token, e.g. [`ACCESS_TOKEN_TYPE`].
+    pub subject_token_type: String,
+
+    /// The token representing the identity of the acting party, along with
+    /// its type, for delegation.
+    pub actor_token: Option<(String, String)>,
+
+    /// The logical name of the target service where the client intends to use
+    /// the requested token.
+    pub audience: Option<String>,
+
+    /// The scope of the requested token.
+    pub scope: Option<Scope>,
+}
+
+impl TokenExchangeData {
+    /// Constructs a new `TokenExchangeData` with all the required fields.
+    #[must_use]
+    pub fn new(subject_token: String, subject_token_type: String) -> Self {
+        Self {
+            subject_token,
+            subject_token_type,
+            actor_token: None,
+            audience: None,
+            scope: None,
+        }
+    }
+
+    /// Set the `actor_token` field of this `TokenExchangeData`.
+    #[must_use]
+    pub fn with_actor_token(mut self, actor_token: String, actor_token_type: String) -> Self {
+        self.actor_token = Some((actor_token, actor_token_type));
+        self
+    }
+
+    /// Set the `audience` field of this `TokenExchangeData`.
+    #[must_use]
+    pub fn with_audience(mut self, audience: String) -> Self {
+        self.audience = Some(audience);
+        self
+    }
+
+    /// Set the `scope` field of this `TokenExchangeData`.
+    #[must_use]
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+}
+
+/// The body of a token exchange request.
+#[derive(Serialize)]
+struct TokenExchangeRequest<'a> {
+    grant_type: &'static str,
+
+    subject_token: &'a str,
+
+    subject_token_type: &'a str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actor_token: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actor_token_type: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audience: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<&'a Scope>,
+}
+
+impl<'a> From<&'a TokenExchangeData> for TokenExchangeRequest<'a> {
+    fn from(data: &'a TokenExchangeData) -> Self {
+        Self {
+            grant_type: TOKEN_EXCHANGE_GRANT_TYPE,
+            subject_token: &data.subject_token,
+            subject_token_type: &data.subject_token_type,
+            actor_token: data.actor_token.as_ref().map(|(token, _)| token.as_str()),
+            actor_token_type: data
+                .actor_token
+                .as_ref()
+                .map(|(_, token_type)| token_type.as_str()),
+            audience: data.audience.as_deref(),
+            scope: data.scope.as_ref(),
+        }
+    }
+}
+
+/// Exchange a token for another one.
+///
+/// # Arguments
+///
+/// * `http_client` - The reqwest client to use for making HTTP requests.
+///
+/// * `client_credentials` - The credentials obtained when registering the
+///   client.
+///
+/// * `token_endpoint` - The URL of the issuer's Token endpoint.
+///
+/// * `data` - The tokens to exchange and the parameters of the requested token.
+///
+/// * `dpop_signer` - The signer of the DPoP proofs, to request DPoP-bound
+///   tokens.
+///
+/// * `now` - The current time.
+///
+/// * `rng` - A random number generator.
+///
+/// # Errors
+///
+/// Returns an error if the request fails, the provider rejects the target or
+/// the request, or the response is invalid.
+#[tracing::instrument(skip_all, fields(token_endpoint))]
+pub async fn exchange_token(
+    http_client: &reqwest::Client,
+    client_credentials: ClientCredentials,
+    token_endpoint: &Url,
+    data: &TokenExchangeData,
+    dpop_signer: Option<&DpopSigner>,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<AccessTokenResponse, TokenExchangeError> {
+    tracing::debug!(
+        subject_token_type = data.subject_token_type,
+        "Exchanging token..."
+    );
+
+    let token_request = http_client
+        .post(token_endpoint.as_str())
+        .header(ACCEPT, APPLICATION_JSON.as_ref());
+    let token_request = with_dpop_proof(
+        token_request,
+        dpop_signer,
+        Method::POST,
+        token_endpoint,
+        now,
+        rng,
+    )?;
+
+    let token_response = client_credentials
+        .authenticated_form(token_request, &TokenExchangeRequest::from(data), now, rng)?
+        .send_traced()
+        .await?
+        .error_from_oauth2_error_response()
+        .await?
+        .json()
+        .await?;
+
+    Ok(token_response)
+}
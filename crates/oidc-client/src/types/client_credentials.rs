@@ -6,7 +6,11 @@
 
 //! Types and methods for client credentials.
 
-use std::{collections::HashMap, fmt};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
 
 use base64ct::{Base64UrlUnpadded, Encoding};
 use chrono::{DateTime, Duration, Utc};
@@ -136,6 +140,19 @@ impl ClientCredentials {
         form: &T,
         now: DateTime<Utc>,
         rng: &mut impl Rng,
+    ) -> Result<reqwest::RequestBuilder, CredentialsError> {
+        self.authenticated_form_with_cache(request, form, None, now, rng)
+    }
+
+    /// Apply these [`ClientCredentials`] to the given request with the given
+    /// form, reusing the client assertions stored in the given cache if any.
+    pub(crate) fn authenticated_form_with_cache<T: Serialize>(
+        &self,
+        request: reqwest::RequestBuilder,
+        form: &T,
+        assertion_cache: Option<&ClientAssertionCache>,
+        now: DateTime<Utc>,
+        rng: &mut impl Rng,
     ) -> Result<reqwest::RequestBuilder, CredentialsError> {
         let request = match self {
             ClientCredentials::None { client_id } => request.form(&RequestWithClientCredentials {
@@ -207,29 +224,38 @@ impl ClientCredentials {
                 signing_algorithm,
                 token_endpoint,
             } => {
-                let claims =
-                    prepare_claims(client_id.clone(), token_endpoint.to_string(), now, rng)?;
-
-                let key = keystore
-                    .signing_key_for_algorithm(signing_algorithm)
-                    .ok_or(CredentialsError::NoPrivateKeyFound)?;
-                let signer = key
-                    .params()
-                    .signing_key_for_alg(signing_algorithm)
-                    .map_err(|_| CredentialsError::JwtWrongAlgorithm)?;
-                let mut header = JsonWebSignatureHeader::new(signing_algorithm.clone());
-
-                if let Some(kid) = key.kid() {
-                    header = header.with_kid(kid);
-                }
-
-                let client_assertion = Jwt::sign(header, claims, &signer)?;
+                let sign = |rng: &mut _| {
+                    let claims =
+                        prepare_claims(client_id.clone(), token_endpoint.to_string(), now, rng)?;
+
+                    let key = keystore
+                        .signing_key_for_algorithm(signing_algorithm)
+                        .ok_or(CredentialsError::NoPrivateKeyFound)?;
+                    let signer = key
+                        .params()
+                        .signing_key_for_alg(signing_algorithm)
+                        .map_err(|_| CredentialsError::JwtWrongAlgorithm)?;
+                    let mut header = JsonWebSignatureHeader::new(signing_algorithm.clone());
+
+                    if let Some(kid) = key.kid() {
+                        header = header.with_kid(kid);
+                    }
+
+                    let client_assertion = Jwt::sign(header, claims, &signer)?;
+                    Ok::<_, CredentialsError>(client_assertion.into_string())
+                };
+
+                let client_assertion = if let Some(cache) = assertion_cache {
+                    cache.get_or_sign(token_endpoint.as_str(), client_id, now, || sign(rng))?
+                } else {
+                    sign(rng)?
+                };
 
                 request.form(&RequestWithClientCredentials {
                     body: form,
                     client_id: None,
                     client_secret: None,
-                    client_assertion: Some(client_assertion.as_str()),
+                    client_assertion: Some(&client_assertion),
                     client_assertion_type: Some(JwtBearerClientAssertionType),
                 })
             }
@@ -323,6 +349,82 @@ impl fmt::Debug for ClientCredentials {
     }
 }
 
+/// How long a client assertion is valid after it was signed.
+const CLIENT_ASSERTION_LIFETIME: Duration = Duration::minutes(5);
+
+/// How long before its expiration a cached client assertion is regenerated.
+const CLIENT_ASSERTION_EXPIRY_MARGIN: Duration = Duration::seconds(30);
+
+/// A cache of the client assertions signed for `private_key_jwt` client
+/// authentication.
+///
+/// Assertions are cached per audience and client ID, and reused until they
+/// are about to expire. This avoids signing a new JWT for every request when
+/// sending many requests to the same endpoint, like when introspecting tokens.
+///
+/// Reusing an assertion means sending the same `jti` claim more than once.
+/// [RFC 7523 section 3] allows the authorization server to only accept a `jti`
+/// once, so servers enforcing this will reject every request but the first
+/// one made with a cached assertion. This cache is therefore never used
+/// unless one is explicitly given to a request, which should only be done
+/// for servers known to accept reused assertions.
+///
+/// [RFC 7523 section 3]: https://www.rfc-editor.org/rfc/rfc7523#section-3
+///
+/// Cloning the cache is cheap, and the clones share the same assertions.
+#[derive(Debug, Clone, Default)]
+pub struct ClientAssertionCache {
+    assertions: Arc<Mutex<HashMap<(String, String), CachedClientAssertion>>>,
+}
+
+#[derive(Debug)]
+struct CachedClientAssertion {
+    assertion: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl ClientAssertionCache {
+    /// Create a new empty `ClientAssertionCache`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the cached assertion for the given audience and client ID, or sign a
+    /// new one with `sign` if there is none or it is about to expire.
+    fn get_or_sign<E>(
+        &self,
+        audience: &str,
+        client_id: &str,
+        now: DateTime<Utc>,
+        sign: impl FnOnce() -> Result<String, E>,
+    ) -> Result<String, E> {
+        let mut assertions = self
+            .assertions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let key = (audience.to_owned(), client_id.to_owned());
+
+        if let Some(cached) = assertions
+            .get(&key)
+            .filter(|cached| now < cached.expires_at - CLIENT_ASSERTION_EXPIRY_MARGIN)
+        {
+            return Ok(cached.assertion.clone());
+        }
+
+        let assertion = sign()?;
+        assertions.insert(
+            key,
+            CachedClientAssertion {
+                assertion: assertion.clone(),
+                expires_at: now + CLIENT_ASSERTION_LIFETIME,
+            },
+        );
+
+        Ok(assertion)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[serde(rename = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer")]
 struct JwtBearerClientAssertionType;
@@ -339,10 +441,7 @@ fn prepare_claims(
     claims::SUB.insert(&mut claims, iss)?;
     claims::AUD.insert(&mut claims, aud)?;
     claims::IAT.insert(&mut claims, now)?;
-    claims::EXP.insert(
-        &mut claims,
-        now + Duration::microseconds(5 * 60 * 1000 * 1000),
-    )?;
+    claims::EXP.insert(&mut claims, now + CLIENT_ASSERTION_LIFETIME)?;
 
     let mut jti = [0u8; 16];
     rng.fill(&mut jti);
@@ -0,0 +1,188 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Types and methods for [DPoP] proofs.
+//!
+//! [DPoP]: https://www.rfc-editor.org/rfc/rfc9449
+
+use std::{collections::HashMap, fmt};
+
+use base64ct::{Base64UrlUnpadded, Encoding};
+use chrono::{DateTime, Utc};
+use http::{HeaderName, Method};
+use mas_iana::jose::JsonWebSignatureAlg;
+use mas_jose::{
+    claims,
+    jwa::AsymmetricSigningKey,
+    jwk::{JsonWebKeyPublicParameters, PublicJsonWebKey},
+    jwt::{JsonWebSignatureHeader, Jwt},
+};
+use mas_keystore::PrivateKey;
+use rand::Rng;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::error::DpopError;
+
+/// The name of the HTTP header carrying the DPoP proof.
+pub const DPOP_HEADER: HeaderName = HeaderName::from_static("dpop");
+
+/// The `typ` header of DPoP proofs.
+const DPOP_JWT_TYPE: &str = "dpop+jwt";
+
+/// A key used to sign DPoP proofs.
+pub struct DpopSigner {
+    signing_key: AsymmetricSigningKey,
+    signing_algorithm: JsonWebSignatureAlg,
+    jwk: PublicJsonWebKey,
+}
+
+impl fmt::Debug for DpopSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DpopSigner")
+            .field("signing_algorithm", &self.signing_algorithm)
+            .field("jwk", &self.jwk)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DpopSigner {
+    /// Create a new `DpopSigner` with the given private key and signing
+    /// algorithm.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key cannot be used with the algorithm.
+    pub fn new(
+        key: &PrivateKey,
+        signing_algorithm: JsonWebSignatureAlg,
+    ) -> Result<Self, DpopError> {
+        let signing_key = key.signing_key_for_alg(&signing_algorithm)?;
+        let jwk = PublicJsonWebKey::new(JsonWebKeyPublicParameters::from(key));
+
+        Ok(Self {
+            signing_key,
+            signing_algorithm,
+            jwk,
+        })
+    }
+
+    /// The public key of this signer, as included in the DPoP proofs.
+    #[must_use]
+    pub fn jwk(&self) -> &PublicJsonWebKey {
+        &self.jwk
+    }
+
+    /// The JWK Thumbprint of the public key of this signer.
+    ///
+    /// This is the value to send as the `dpop_jkt` parameter of authorization
+    /// requests, to bind the authorization code to this key.
+    #[must_use]
+    pub fn jkt(&self) -> String {
+        self.jwk.params().thumbprint().encode()
+    }
+
+    /// Start building a DPoP proof for a request with the given HTTP method and
+    /// URL.
+    #[must_use]
+    pub fn proof(&self, method: Method, url: &Url) -> DpopProofBuilder<'_> {
+        DpopProofBuilder {
+            signer: self,
+            method,
+            url: url.clone(),
+            access_token: None,
+            nonce: None,
+        }
+    }
+}
+
+/// A builder for a DPoP proof JWT.
+#[derive(Debug)]
+pub struct DpopProofBuilder<'a> {
+    signer: &'a DpopSigner,
+    method: Method,
+    url: Url,
+    access_token: Option<&'a str>,
+    nonce: Option<String>,
+}
+
+impl<'a> DpopProofBuilder<'a> {
+    /// Bind the proof to the given access token.
+    ///
+    /// This is needed when using a DPoP-bound access token at a protected
+    /// resource.
+    #[must_use]
+    pub fn with_access_token(mut self, access_token: &'a str) -> Self {
+        self.access_token = Some(access_token);
+        self
+    }
+
+    /// Include a nonce provided by the server in the proof.
+    #[must_use]
+    pub fn with_nonce(mut self, nonce: String) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    /// Sign the DPoP proof.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if building the claims or signing the proof fails.
+    pub fn build(self, now: DateTime<Utc>, rng: &mut impl Rng) -> Result<String, DpopError> {
+        let mut claims = HashMap::new();
+
+        let mut jti = [0u8; 16];
+        rng.fill(&mut jti);
+        let jti = Base64UrlUnpadded::encode_string(&jti);
+        claims::JTI.insert(&mut claims, jti)?;
+
+        claims::HTM.insert(&mut claims, self.method.as_str().to_owned())?;
+
+        // The query and fragment parts are not included in the `htu` claim
+        let mut htu = self.url;
+        htu.set_query(None);
+        htu.set_fragment(None);
+        claims::HTU.insert(&mut claims, String::from(htu))?;
+
+        claims::IAT.insert(&mut claims, now)?;
+
+        if let Some(access_token) = self.access_token {
+            let ath = Base64UrlUnpadded::encode_string(&Sha256::digest(access_token));
+            claims::ATH.insert(&mut claims, ath)?;
+        }
+
+        if let Some(nonce) = self.nonce {
+            claims::NONCE.insert(&mut claims, nonce)?;
+        }
+
+        let header = JsonWebSignatureHeader::new(self.signer.signing_algorithm.clone())
+            .with_typ(DPOP_JWT_TYPE.to_owned())
+            .with_jwk(self.signer.jwk.clone());
+
+        let proof: Jwt<'_, HashMap<String, Value>> =
+            Jwt::sign(header, claims, &self.signer.signing_key)?;
+
+        Ok(proof.into_string())
+    }
+}
+
+/// Add a DPoP proof to the request, if a signer is provided.
+pub(crate) fn with_dpop_proof(
+    request: reqwest::RequestBuilder,
+    dpop_signer: Option<&DpopSigner>,
+    method: Method,
+    url: &Url,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Result<reqwest::RequestBuilder, DpopError> {
+    let Some(dpop_signer) = dpop_signer else {
+        return Ok(request);
+    };
+
+    let proof = dpop_signer.proof(method, url).build(now, rng)?;
+    Ok(request.header(DPOP_HEADER, proof))
+}
@@ -7,6 +7,8 @@
 //! OAuth 2.0 and OpenID Connect types.
 
 pub mod client_credentials;
+pub mod dpop;
+pub mod retry;
 
 use std::collections::HashMap;
 
@@ -0,0 +1,136 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Types and methods to retry requests on transient failures, and to bound
+//! how long they take.
+
+use std::time::Duration;
+
+use mas_http::RequestBuilderExt;
+use rand::Rng;
+
+/// How to retry requests which failed because of a transient error.
+///
+/// Requests are retried if the server responded with a 5xx status code, or if
+/// the request failed to be sent, for example because of a connection error.
+/// They are never retried on a 4xx status code.
+///
+/// The request is built again for every attempt, so that each one gets its
+/// own DPoP proof and, unless a client assertion cache is used, its own
+/// client assertion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+
+    /// The delay before the first retry.
+    ///
+    /// It is doubled on every subsequent retry.
+    pub base_delay: Duration,
+
+    /// The maximum random delay added to each retry, to avoid many clients
+    /// retrying at the same time.
+    pub jitter: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            jitter: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Constructs a new `RetryPolicy` with the given maximum number of
+    /// attempts, and the default delays.
+    #[must_use]
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Self::default()
+        }
+    }
+
+    /// Set the `base_delay` field of this `RetryPolicy`.
+    #[must_use]
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Set the `jitter` field of this `RetryPolicy`.
+    #[must_use]
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The delay to wait before the given retry, starting at 1.
+    fn delay(&self, retry: u32, rng: &mut impl Rng) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let jitter = rng.gen_range(Duration::ZERO..=self.jitter);
+        backoff.saturating_add(jitter)
+    }
+}
+
+/// Run the future, failing if it didn't complete within the timeout, if any.
+pub(crate) async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = T>,
+) -> Result<T, tokio::time::error::Elapsed> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await,
+        None => Ok(future.await),
+    }
+}
+
+/// Whether the result of a request is a transient failure which can be
+/// retried.
+fn is_transient_failure(result: &Result<reqwest::Response, reqwest::Error>) -> bool {
+    match result {
+        Ok(response) => response.status().is_server_error(),
+        Err(error) => error.is_connect() || error.is_timeout() || error.is_request(),
+    }
+}
+
+/// Send the request built by `build_request`, retrying it according to the
+/// given policy.
+///
+/// The request is built again before every attempt, so that the single-use
+/// parts of it, like the DPoP proof, aren't sent twice. If no policy is given,
+/// it is sent only once.
+pub(crate) async fn send_with_retry<R: Rng, E: From<reqwest::Error>>(
+    mut build_request: impl FnMut(&mut R) -> Result<reqwest::RequestBuilder, E>,
+    retry_policy: Option<&RetryPolicy>,
+    rng: &mut R,
+) -> Result<reqwest::Response, E> {
+    let Some(retry_policy) = retry_policy else {
+        return Ok(build_request(rng)?.send_traced().await?);
+    };
+
+    let mut attempt = 1;
+    loop {
+        let result = build_request(rng)?.send_traced().await;
+
+        if attempt >= retry_policy.max_attempts || !is_transient_failure(&result) {
+            return Ok(result?);
+        }
+
+        let delay = retry_policy.delay(attempt, rng);
+        tracing::warn!(
+            attempt,
+            ?delay,
+            "Request failed with a transient error, retrying"
+        );
+        tokio::time::sleep(delay).await;
+
+        attempt += 1;
+    }
+}
@@ -12,7 +12,7 @@ use mas_iana::oauth::{
 };
 use mas_jose::{claims::ClaimError, jwk::PublicJsonWebKeySet};
 use mas_oidc_client::{
-    error::{IdTokenError, TokenAuthorizationCodeError},
+    error::{AuthorizationError, IdTokenError, TokenAuthorizationCodeError},
     requests::{
         authorization_code::{
             AuthorizationRequestData, AuthorizationValidationData,
@@ -76,6 +76,7 @@ fn pass_authorization_url() {
     assert_eq!(query_pairs.get("id_token_hint"), None);
     assert_eq!(query_pairs.get("login_hint"), None);
     assert_eq!(query_pairs.get("acr_values"), None);
+    assert_eq!(query_pairs.get("claims"), None);
     assert_eq!(*query_pairs.get("state").unwrap(), validation_data.state);
     assert_eq!(query_pairs.get("nonce").unwrap(), "ox0PigY5l9xl5uTL");
     let code_challenge = query_pairs.get("code_challenge").unwrap();
@@ -131,6 +132,153 @@ fn pass_full_authorization_url() {
     assert_eq!(query_pairs.get("code_challenge_method"), None);
 }
 
+#[test]
+fn pass_authorization_url_combined_prompt() {
+    let issuer = Url::parse("http://localhost/").unwrap();
+    let authorization_endpoint = issuer.join("authorize").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    let authorization_data = AuthorizationRequestData::new(
+        CLIENT_ID.to_owned(),
+        [OPENID].into_iter().collect(),
+        Url::parse(REDIRECT_URI).unwrap(),
+    )
+    .with_prompt(vec![Prompt::Create, Prompt::Consent])
+    .with_login_hint("user@example.com".to_owned());
+
+    let (url, _validation_data) =
+        build_authorization_url(authorization_endpoint, authorization_data, &mut rng).unwrap();
+
+    let query_pairs = url.query_pairs().collect::<HashMap<_, _>>();
+    assert_eq!(query_pairs.get("prompt").unwrap(), "create consent");
+    assert_eq!(query_pairs.get("login_hint").unwrap(), "user@example.com");
+}
+
+#[test]
+fn fail_authorization_url_unknown_prompt() {
+    let issuer = Url::parse("http://localhost/").unwrap();
+    let authorization_endpoint = issuer.join("authorize").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    let authorization_data = AuthorizationRequestData::new(
+        CLIENT_ID.to_owned(),
+        [OPENID].into_iter().collect(),
+        Url::parse(REDIRECT_URI).unwrap(),
+    )
+    .with_prompt(vec![Prompt::Create, "register".parse().unwrap()]);
+
+    let error =
+        build_authorization_url(authorization_endpoint, authorization_data, &mut rng).unwrap_err();
+
+    assert_matches!(error, AuthorizationError::UnknownPrompt(value) if value == "register");
+}
+
+#[test]
+fn fail_authorization_url_prompt_none_not_alone() {
+    let issuer = Url::parse("http://localhost/").unwrap();
+    let authorization_endpoint = issuer.join("authorize").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    let authorization_data = AuthorizationRequestData::new(
+        CLIENT_ID.to_owned(),
+        [OPENID].into_iter().collect(),
+        Url::parse(REDIRECT_URI).unwrap(),
+    )
+    .with_prompt(vec![Prompt::None, Prompt::Create]);
+
+    let error =
+        build_authorization_url(authorization_endpoint, authorization_data, &mut rng).unwrap_err();
+
+    assert_matches!(error, AuthorizationError::PromptNoneNotAlone);
+}
+
+#[test]
+fn pass_authorization_url_acr_values_and_claims() {
+    let issuer = Url::parse("http://localhost/").unwrap();
+    let authorization_endpoint = issuer.join("authorize").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    let claims = serde_json::json!({
+        "userinfo": {
+            "email": null,
+        },
+        "id_token": {
+            "email_verified": { "essential": true },
+            "acr": { "values": ["urn:mace:incommon:iap:silver"] },
+        },
+    });
+
+    let authorization_data = AuthorizationRequestData::new(
+        CLIENT_ID.to_owned(),
+        [OPENID].into_iter().collect(),
+        Url::parse(REDIRECT_URI).unwrap(),
+    )
+    .with_acr_values(["urn:mace:incommon:iap:silver".to_owned(), "mfa".to_owned()].into())
+    .with_claims(claims.clone());
+
+    let (url, _validation_data) =
+        build_authorization_url(authorization_endpoint, authorization_data, &mut rng).unwrap();
+
+    let query_pairs = url.query_pairs().collect::<HashMap<_, _>>();
+
+    let mut acr_values = query_pairs
+        .get("acr_values")
+        .unwrap()
+        .split(' ')
+        .collect::<Vec<_>>();
+    acr_values.sort_unstable();
+    assert_eq!(acr_values, ["mfa", "urn:mace:incommon:iap:silver"]);
+
+    let sent_claims: serde_json::Value =
+        serde_json::from_str(query_pairs.get("claims").unwrap()).unwrap();
+    assert_eq!(sent_claims, claims);
+}
+
+#[test]
+fn fail_authorization_url_invalid_acr_value() {
+    let issuer = Url::parse("http://localhost/").unwrap();
+    let authorization_endpoint = issuer.join("authorize").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    let authorization_data = AuthorizationRequestData::new(
+        CLIENT_ID.to_owned(),
+        [OPENID].into_iter().collect(),
+        Url::parse(REDIRECT_URI).unwrap(),
+    )
+    .with_acr_values(["gold silver".to_owned()].into());
+
+    let error =
+        build_authorization_url(authorization_endpoint, authorization_data, &mut rng).unwrap_err();
+
+    assert_matches!(error, AuthorizationError::InvalidAcrValue(value) if value == "gold silver");
+}
+
+#[test]
+fn fail_authorization_url_invalid_claims() {
+    let issuer = Url::parse("http://localhost/").unwrap();
+    let authorization_endpoint = issuer.join("authorize").unwrap();
+
+    for claims in [
+        serde_json::json!(["email"]),
+        serde_json::json!({ "id_token": ["email"] }),
+        serde_json::json!({ "userinfo": { "email": true } }),
+    ] {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+        let authorization_data = AuthorizationRequestData::new(
+            CLIENT_ID.to_owned(),
+            [OPENID].into_iter().collect(),
+            Url::parse(REDIRECT_URI).unwrap(),
+        )
+        .with_claims(claims);
+
+        let error =
+            build_authorization_url(authorization_endpoint.clone(), authorization_data, &mut rng)
+                .unwrap_err();
+
+        assert_matches!(error, AuthorizationError::InvalidClaims(_));
+    }
+}
+
 /// Check if the given request to the token endpoint is valid.
 fn is_valid_token_endpoint_request(req: &Request) -> bool {
     let body = form_urlencoded::parse(&req.body).collect::<HashMap<_, _>>();
@@ -222,6 +370,7 @@ async fn pass_access_token_with_authorization_code() {
         AUTHORIZATION_CODE.to_owned(),
         validation_data,
         Some(id_token_verification_data),
+        None,
         now(),
         &mut rng,
     )
@@ -280,6 +429,7 @@ async fn fail_access_token_with_authorization_code_wrong_nonce() {
         AUTHORIZATION_CODE.to_owned(),
         validation_data,
         Some(id_token_verification_data),
+        None,
         now(),
         &mut rng,
     )
@@ -341,6 +491,7 @@ async fn fail_access_token_with_authorization_code_no_id_token() {
         AUTHORIZATION_CODE.to_owned(),
         validation_data,
         Some(id_token_verification_data),
+        None,
         now(),
         &mut rng,
     )
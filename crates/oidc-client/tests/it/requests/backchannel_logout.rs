@@ -0,0 +1,180 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
+use chrono::Duration;
+use mas_jose::{
+    claims::{self, ClaimError},
+    constraints::Constrainable,
+    jwk::PublicJsonWebKeySet,
+    jwt::{JsonWebSignatureHeader, Jwt},
+};
+use mas_oidc_client::{
+    error::LogoutTokenError,
+    requests::{
+        backchannel_logout::{LogoutToken, validate_logout_token},
+        jose::JwtVerificationData,
+    },
+};
+use serde_json::Value;
+
+use crate::{CLIENT_ID, ID_TOKEN_SIGNING_ALG, NONCE, SUBJECT_IDENTIFIER, keystore, now};
+
+const ISSUER: &str = "http://localhost/";
+const SESSION_ID: &str = "08a5019c-17e1-4977-8f42-65a12843ea02";
+
+/// Generate the claims of a valid logout token.
+fn logout_token_claims() -> HashMap<String, Value> {
+    let mut claims = HashMap::new();
+    let now = now();
+
+    claims::ISS.insert(&mut claims, ISSUER.to_owned()).unwrap();
+    claims::AUD
+        .insert(&mut claims, CLIENT_ID.to_owned())
+        .unwrap();
+    claims::SUB
+        .insert(&mut claims, SUBJECT_IDENTIFIER.to_owned())
+        .unwrap();
+    claims::SID
+        .insert(&mut claims, SESSION_ID.to_owned())
+        .unwrap();
+    claims::IAT.insert(&mut claims, now).unwrap();
+    claims::EXP
+        .insert(&mut claims, now + Duration::try_minutes(2).unwrap())
+        .unwrap();
+    claims::JTI.insert(&mut claims, "bWJq".to_owned()).unwrap();
+    claims.insert(
+        "events".to_owned(),
+        serde_json::json!({ "http://schemas.openid.net/event/backchannel-logout": {} }),
+    );
+
+    claims
+}
+
+/// Sign the given claims as a logout token.
+fn logout_token(claims: HashMap<String, Value>) -> (String, PublicJsonWebKeySet) {
+    let signing_alg = ID_TOKEN_SIGNING_ALG;
+    let keystore = keystore(&signing_alg);
+
+    let key = keystore.signing_key_for_algorithm(&signing_alg).unwrap();
+    let signer = key.params().signing_key_for_alg(&signing_alg).unwrap();
+    let header = JsonWebSignatureHeader::new(signing_alg).with_kid(key.kid().unwrap());
+    let logout_token = Jwt::sign(header, claims, &signer).unwrap();
+
+    (logout_token.into_string(), keystore.public_jwks())
+}
+
+fn validate(claims: HashMap<String, Value>) -> Result<LogoutToken, LogoutTokenError> {
+    let (logout_token, jwks) = logout_token(claims);
+    let client_id = CLIENT_ID.to_owned();
+
+    validate_logout_token(
+        &logout_token,
+        JwtVerificationData {
+            issuer: Some(ISSUER),
+            jwks: &jwks,
+            client_id: &client_id,
+            signing_algorithm: &ID_TOKEN_SIGNING_ALG,
+        },
+        now(),
+    )
+}
+
+#[test]
+fn pass_validate_logout_token() {
+    let logout_token = validate(logout_token_claims()).unwrap();
+
+    assert_eq!(logout_token.sub.as_deref(), Some(SUBJECT_IDENTIFIER));
+    assert_eq!(logout_token.sid.as_deref(), Some(SESSION_ID));
+
+    // Only one of `sub` and `sid` is required
+    let mut claims = logout_token_claims();
+    claims.remove("sub");
+    let logout_token = validate(claims).unwrap();
+    assert_eq!(logout_token.sub, None);
+    assert_eq!(logout_token.sid.as_deref(), Some(SESSION_ID));
+
+    let mut claims = logout_token_claims();
+    claims.remove("sid");
+    let logout_token = validate(claims).unwrap();
+    assert_eq!(logout_token.sub.as_deref(), Some(SUBJECT_IDENTIFIER));
+    assert_eq!(logout_token.sid, None);
+}
+
+#[test]
+fn fail_validate_logout_token_no_sub_or_sid() {
+    let mut claims = logout_token_claims();
+    claims.remove("sub");
+    claims.remove("sid");
+
+    let error = validate(claims).unwrap_err();
+    assert_matches!(error, LogoutTokenError::NoSubOrSidClaim);
+}
+
+#[test]
+fn fail_validate_logout_token_missing_events() {
+    let mut claims = logout_token_claims();
+    claims.remove("events");
+
+    let error = validate(claims).unwrap_err();
+    assert_matches!(
+        error,
+        LogoutTokenError::Claim(ClaimError::MissingClaim("events"))
+    );
+}
+
+#[test]
+fn fail_validate_logout_token_wrong_event() {
+    let mut claims = logout_token_claims();
+    claims.insert(
+        "events".to_owned(),
+        serde_json::json!({ "http://schemas.openid.net/event/something-else": {} }),
+    );
+
+    let error = validate(claims).unwrap_err();
+    assert_matches!(
+        error,
+        LogoutTokenError::Claim(ClaimError::InvalidClaim("events"))
+    );
+}
+
+#[test]
+fn fail_validate_logout_token_with_nonce() {
+    let mut claims = logout_token_claims();
+    claims::NONCE.insert(&mut claims, NONCE.to_owned()).unwrap();
+
+    let error = validate(claims).unwrap_err();
+    assert_matches!(
+        error,
+        LogoutTokenError::Claim(ClaimError::InvalidClaim("nonce"))
+    );
+}
+
+#[test]
+fn fail_validate_logout_token_expired() {
+    let mut claims = logout_token_claims();
+    claims::EXP
+        .insert(&mut claims, now() - Duration::try_hours(1).unwrap())
+        .unwrap();
+
+    let error = validate(claims).unwrap_err();
+    assert_matches!(
+        error,
+        LogoutTokenError::Claim(ClaimError::ValidationError { claim: "exp", .. })
+    );
+}
+
+#[test]
+fn fail_validate_logout_token_wrong_audience() {
+    let mut claims = logout_token_claims();
+    claims::AUD
+        .insert(&mut claims, "another-client".to_owned())
+        .unwrap();
+
+    let error = validate(claims).unwrap_err();
+    assert_matches!(error, LogoutTokenError::Jwt(_));
+}
@@ -87,6 +87,7 @@ async fn pass_access_token_with_client_credentials() {
         client_credentials,
         &token_endpoint,
         Some(scope),
+        None,
         now(),
         &mut rng,
     )
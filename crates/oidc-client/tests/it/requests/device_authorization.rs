@@ -0,0 +1,186 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
+use chrono::Duration;
+use mas_iana::oauth::{OAuthAccessTokenType, OAuthClientAuthenticationMethod};
+use mas_oidc_client::{
+    error::{DeviceCodeTokenError, TokenRequestError},
+    requests::device_authorization::{poll_device_token, request_device_code},
+};
+use oauth2_types::{
+    requests::{AccessTokenResponse, DeviceAuthorizationResponse},
+    scope::{PROFILE, Scope},
+};
+use rand::SeedableRng;
+use wiremock::{
+    Mock, Request, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::{ACCESS_TOKEN, CLIENT_ID, client_credentials, init_test, now};
+
+const DEVICE_CODE: &str = "D3v1c3C0d3";
+const USER_CODE: &str = "ABCD-EFGH";
+
+fn device_authorization(issuer: &url::Url) -> DeviceAuthorizationResponse {
+    DeviceAuthorizationResponse {
+        device_code: DEVICE_CODE.to_owned(),
+        user_code: USER_CODE.to_owned(),
+        verification_uri: issuer.join("device").unwrap(),
+        verification_uri_complete: None,
+        expires_in: Duration::minutes(10),
+        interval: Some(Duration::zero()),
+    }
+}
+
+#[tokio::test]
+async fn pass_request_device_code() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials = client_credentials(&OAuthClientAuthenticationMethod::None, &issuer);
+    let device_authorization_endpoint = issuer.join("device_authorization").unwrap();
+    let scope = [PROFILE].into_iter().collect::<Scope>();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/device_authorization"))
+        .and(|req: &Request| {
+            let query_pairs = form_urlencoded::parse(&req.body).collect::<HashMap<_, _>>();
+
+            if query_pairs.get("scope").map(AsRef::as_ref) != Some("profile") {
+                println!("Wrong or missing scope");
+                return false;
+            }
+            if query_pairs.get("client_id").map(AsRef::as_ref) != Some(CLIENT_ID) {
+                println!("Wrong or missing client ID");
+                return false;
+            }
+
+            true
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(device_authorization(&issuer)))
+        .mount(&mock_server)
+        .await;
+
+    let response = request_device_code(
+        &http_client,
+        client_credentials,
+        &device_authorization_endpoint,
+        Some(scope),
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.device_code, DEVICE_CODE);
+    assert_eq!(response.user_code, USER_CODE);
+    assert_eq!(response.verification_uri, issuer.join("device").unwrap());
+    assert_eq!(response.expires_in, Duration::minutes(10));
+}
+
+#[tokio::test]
+async fn pass_poll_device_token() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials = client_credentials(&OAuthClientAuthenticationMethod::None, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    // The authorization is pending twice, then granted
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_json(serde_json::json!({ "error": "authorization_pending" })),
+        )
+        .up_to_n_times(2)
+        .expect(2)
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .and(|req: &Request| {
+            let query_pairs = form_urlencoded::parse(&req.body).collect::<HashMap<_, _>>();
+
+            if query_pairs.get("grant_type").map(AsRef::as_ref)
+                != Some("urn:ietf:params:oauth:grant-type:device_code")
+            {
+                println!("Wrong or missing grant type");
+                return false;
+            }
+            if query_pairs.get("device_code").map(AsRef::as_ref) != Some(DEVICE_CODE) {
+                println!("Wrong or missing device code");
+                return false;
+            }
+
+            true
+        })
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(AccessTokenResponse {
+                access_token: ACCESS_TOKEN.to_owned(),
+                refresh_token: None,
+                id_token: None,
+                token_type: OAuthAccessTokenType::Bearer,
+                expires_in: None,
+                scope: None,
+            }),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let response = poll_device_token(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        &device_authorization(&issuer),
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.access_token, ACCESS_TOKEN);
+}
+
+#[tokio::test]
+async fn fail_poll_device_token_denied() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials = client_credentials(&OAuthClientAuthenticationMethod::None, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_json(serde_json::json!({ "error": "access_denied" })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let error = poll_device_token(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        &device_authorization(&issuer),
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(
+        error,
+        DeviceCodeTokenError::Token(TokenRequestError::OAuth2(_))
+    );
+}
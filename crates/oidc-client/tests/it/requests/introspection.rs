@@ -0,0 +1,417 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::{collections::HashMap, time::Duration};
+
+use assert_matches::assert_matches;
+use mas_iana::oauth::{OAuthClientAuthenticationMethod, OAuthTokenTypeHint};
+use mas_oidc_client::{
+    error::IntrospectionError,
+    requests::introspection::introspect_token,
+    types::{client_credentials::ClientAssertionCache, retry::RetryPolicy},
+};
+use rand::SeedableRng;
+use wiremock::{
+    Mock, Request, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::{ACCESS_TOKEN, CLIENT_ID, CLIENT_SECRET, client_credentials, init_test, now};
+
+#[tokio::test]
+async fn pass_introspect_token() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .and(|req: &Request| {
+            let query_pairs = form_urlencoded::parse(&req.body).collect::<HashMap<_, _>>();
+
+            if query_pairs.get("token").map(AsRef::as_ref) != Some(ACCESS_TOKEN) {
+                println!("Wrong or missing token");
+                return false;
+            }
+            if query_pairs.get("token_type_hint").map(AsRef::as_ref) != Some("access_token") {
+                println!("Wrong or missing token type hint");
+                return false;
+            }
+            if query_pairs.get("client_id").map(AsRef::as_ref) != Some(CLIENT_ID) {
+                println!("Wrong or missing client ID");
+                return false;
+            }
+            if query_pairs.get("client_secret").map(AsRef::as_ref) != Some(CLIENT_SECRET) {
+                println!("Wrong or missing client secret");
+                return false;
+            }
+
+            true
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "active": true,
+            "scope": "openid profile",
+            "client_id": CLIENT_ID,
+            "username": "alice",
+            "token_type": "access_token",
+            "exp": 1_700_000_000,
+            "sub": "SubjectID",
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let response = introspect_token(
+        &http_client,
+        client_credentials,
+        &introspection_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        Some(OAuthTokenTypeHint::AccessToken),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    assert!(response.active);
+    assert!(response.scope.unwrap().contains("profile"));
+    assert_eq!(response.client_id.as_deref(), Some(CLIENT_ID));
+    assert_eq!(response.username.as_deref(), Some("alice"));
+    assert_eq!(response.token_type, Some(OAuthTokenTypeHint::AccessToken));
+    assert_eq!(response.exp.unwrap().timestamp(), 1_700_000_000);
+    assert_eq!(response.sub.as_deref(), Some("SubjectID"));
+}
+
+#[tokio::test]
+async fn pass_introspect_inactive_token() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "active": false })),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let response = introspect_token(
+        &http_client,
+        client_credentials,
+        &introspection_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    assert!(!response.active);
+    assert_eq!(response.scope, None);
+}
+
+#[tokio::test]
+async fn fail_introspect_token_invalid_client() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(
+            ResponseTemplate::new(401)
+                .set_body_json(serde_json::json!({ "error": "invalid_client" })),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let error = introspect_token(
+        &http_client,
+        client_credentials,
+        &introspection_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, IntrospectionError::OAuth2(_));
+}
+
+#[tokio::test]
+async fn pass_introspect_token_retry() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let retry_policy = RetryPolicy::new(3)
+        .with_base_delay(Duration::ZERO)
+        .with_jitter(Duration::ZERO);
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    // Fail twice, then succeed
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .expect(2)
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "active": true })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let response = introspect_token(
+        &http_client,
+        client_credentials,
+        &introspection_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        None,
+        None,
+        Some(&retry_policy),
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    assert!(response.active);
+}
+
+#[tokio::test]
+async fn pass_introspect_token_retry_signs_new_client_assertion() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::PrivateKeyJwt, &issuer);
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let retry_policy = RetryPolicy::new(2)
+        .with_base_delay(Duration::ZERO)
+        .with_jitter(Duration::ZERO);
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    // Fail once, then succeed
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "active": true })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    introspect_token(
+        &http_client,
+        client_credentials,
+        &introspection_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        None,
+        None,
+        Some(&retry_policy),
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    let assertions = mock_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|req| {
+            form_urlencoded::parse(&req.body)
+                .find(|(key, _)| key == "client_assertion")
+                .unwrap()
+                .1
+                .into_owned()
+        })
+        .collect::<Vec<_>>();
+
+    // The retry doesn't reuse the client assertion of the first attempt
+    assert_eq!(assertions.len(), 2);
+    assert_ne!(assertions[0], assertions[1]);
+}
+
+#[tokio::test]
+async fn fail_introspect_token_retry_exhausted() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let retry_policy = RetryPolicy::new(2)
+        .with_base_delay(Duration::ZERO)
+        .with_jitter(Duration::ZERO);
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(
+            ResponseTemplate::new(503)
+                .set_body_json(serde_json::json!({ "error": "temporarily_unavailable" })),
+        )
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let error = introspect_token(
+        &http_client,
+        client_credentials,
+        &introspection_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        None,
+        None,
+        Some(&retry_policy),
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, IntrospectionError::OAuth2(_));
+}
+
+#[tokio::test]
+async fn fail_introspect_token_no_retry_on_client_error() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let retry_policy = RetryPolicy::new(3)
+        .with_base_delay(Duration::ZERO)
+        .with_jitter(Duration::ZERO);
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_json(serde_json::json!({ "error": "invalid_request" })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let error = introspect_token(
+        &http_client,
+        client_credentials,
+        &introspection_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        None,
+        None,
+        Some(&retry_policy),
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, IntrospectionError::OAuth2(_));
+}
+
+#[tokio::test]
+async fn pass_introspect_token_reuses_cached_client_assertion() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::PrivateKeyJwt, &issuer);
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+    let cache = ClientAssertionCache::new();
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "active": false })),
+        )
+        .expect(3)
+        .mount(&mock_server)
+        .await;
+
+    // The first request signs an assertion, the second one is within its
+    // validity window, and the third one is after it expired
+    for now in [
+        now(),
+        now() + chrono::Duration::minutes(1),
+        now() + chrono::Duration::minutes(10),
+    ] {
+        introspect_token(
+            &http_client,
+            client_credentials.clone(),
+            &introspection_endpoint,
+            ACCESS_TOKEN.to_owned(),
+            None,
+            None,
+            None,
+            Some(&cache),
+            None,
+            now,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+    }
+
+    let assertions = mock_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|req| {
+            form_urlencoded::parse(&req.body)
+                .find(|(key, _)| key == "client_assertion")
+                .unwrap()
+                .1
+                .into_owned()
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(assertions.len(), 3);
+    assert_eq!(assertions[0], assertions[1]);
+    assert_ne!(assertions[1], assertions[2]);
+}
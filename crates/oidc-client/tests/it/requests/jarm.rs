@@ -0,0 +1,147 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
+use chrono::Duration;
+use mas_iana::jose::JsonWebSignatureAlg;
+use mas_jose::{
+    claims::{self, ClaimError},
+    constraints::Constrainable,
+    jwk::PublicJsonWebKeySet,
+    jwt::{JsonWebSignatureHeader, Jwt},
+};
+use mas_oidc_client::{
+    error::{JarmError, JwtVerificationError},
+    requests::{
+        jarm::{JarmResponse, verify_jarm_response},
+        jose::JwtVerificationData,
+    },
+};
+use serde_json::Value;
+
+use crate::{AUTHORIZATION_CODE, CLIENT_ID, ID_TOKEN_SIGNING_ALG, keystore, now};
+
+const ISSUER: &str = "http://localhost/";
+const STATE: &str = "S7473";
+
+/// Generate the claims of a valid authorization response.
+fn response_claims() -> HashMap<String, Value> {
+    let mut claims = HashMap::new();
+
+    claims::ISS.insert(&mut claims, ISSUER.to_owned()).unwrap();
+    claims::AUD
+        .insert(&mut claims, CLIENT_ID.to_owned())
+        .unwrap();
+    claims::EXP
+        .insert(&mut claims, now() + Duration::try_minutes(10).unwrap())
+        .unwrap();
+    claims.insert("code".to_owned(), AUTHORIZATION_CODE.into());
+    claims.insert("state".to_owned(), STATE.into());
+
+    claims
+}
+
+/// Sign the given claims as an authorization response.
+fn response(claims: HashMap<String, Value>) -> (String, PublicJsonWebKeySet) {
+    let signing_alg = ID_TOKEN_SIGNING_ALG;
+    let keystore = keystore(&signing_alg);
+
+    let key = keystore.signing_key_for_algorithm(&signing_alg).unwrap();
+    let signer = key.params().signing_key_for_alg(&signing_alg).unwrap();
+    let header = JsonWebSignatureHeader::new(signing_alg).with_kid(key.kid().unwrap());
+    let response = Jwt::sign(header, claims, &signer).unwrap();
+
+    (response.into_string(), keystore.public_jwks())
+}
+
+fn verify(claims: HashMap<String, Value>, issuer: &str) -> Result<JarmResponse, JarmError> {
+    let (response, jwks) = response(claims);
+    let client_id = CLIENT_ID.to_owned();
+
+    verify_jarm_response(
+        &response,
+        JwtVerificationData {
+            issuer: Some(issuer),
+            jwks: &jwks,
+            client_id: &client_id,
+            signing_algorithm: &ID_TOKEN_SIGNING_ALG,
+        },
+        now(),
+    )
+}
+
+#[test]
+fn pass_verify_jarm_response() {
+    let response = verify(response_claims(), ISSUER).unwrap();
+
+    assert_eq!(
+        response,
+        JarmResponse {
+            code: AUTHORIZATION_CODE.to_owned(),
+            state: Some(STATE.to_owned()),
+            iss: ISSUER.to_owned(),
+        }
+    );
+}
+
+#[test]
+fn fail_verify_jarm_response_wrong_issuer() {
+    let error = verify(response_claims(), "http://distanthost/").unwrap_err();
+
+    assert_matches!(
+        error,
+        JarmError::Jwt(JwtVerificationError::Claim(ClaimError::ValidationError {
+            claim: "iss",
+            ..
+        }))
+    );
+}
+
+#[test]
+fn fail_verify_jarm_response_wrong_signature() {
+    let (response, _) = response(response_claims());
+    let other_jwks = keystore(&JsonWebSignatureAlg::Es256).public_jwks();
+    let client_id = CLIENT_ID.to_owned();
+
+    let error = verify_jarm_response(
+        &response,
+        JwtVerificationData {
+            issuer: Some(ISSUER),
+            jwks: &other_jwks,
+            client_id: &client_id,
+            signing_algorithm: &ID_TOKEN_SIGNING_ALG,
+        },
+        now(),
+    )
+    .unwrap_err();
+
+    assert_matches!(error, JarmError::Jwt(JwtVerificationError::JwtSignature(_)));
+}
+
+#[test]
+fn fail_verify_jarm_response_expired() {
+    let mut claims = response_claims();
+    claims::EXP
+        .insert(&mut claims, now() - Duration::try_minutes(10).unwrap())
+        .unwrap();
+
+    let error = verify(claims, ISSUER).unwrap_err();
+    assert_matches!(error, JarmError::Claim(_));
+}
+
+#[test]
+fn fail_verify_jarm_response_error() {
+    let mut claims = response_claims();
+    claims.remove("code");
+    claims.insert("error".to_owned(), "access_denied".into());
+
+    let error = verify(claims, ISSUER).unwrap_err();
+    assert_matches!(
+        error,
+        JarmError::ErrorResponse { error, error_description: None } if error == "access_denied"
+    );
+}
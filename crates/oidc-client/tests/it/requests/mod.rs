@@ -5,8 +5,16 @@
 // Please see LICENSE files in the repository root for full details.
 
 mod authorization_code;
+mod backchannel_logout;
 mod client_credentials;
+mod device_authorization;
 mod discovery;
+mod introspection;
+mod jarm;
 mod jose;
+mod par;
 mod refresh_token;
+mod registration;
+mod revocation;
+mod token_exchange;
 mod userinfo;
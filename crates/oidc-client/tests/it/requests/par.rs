@@ -0,0 +1,153 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
+use chrono::Duration;
+use mas_iana::oauth::{OAuthClientAuthenticationMethod, PkceCodeChallengeMethod};
+use mas_oidc_client::{
+    error::PushedAuthorizationError,
+    requests::{
+        authorization_code::AuthorizationRequestData,
+        par::{build_pushed_authorization_url, push_authorization_request},
+    },
+};
+use oauth2_types::scope::OPENID;
+use rand::SeedableRng;
+use url::Url;
+use wiremock::{
+    Mock, Request, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::{CLIENT_ID, CLIENT_SECRET, REDIRECT_URI, client_credentials, init_test, now};
+
+const REQUEST_URI: &str = "urn:ietf:params:oauth:request_uri:6esc_11ACC5bwc014ltc14eY22c";
+
+fn authorization_data() -> AuthorizationRequestData {
+    AuthorizationRequestData::new(
+        CLIENT_ID.to_owned(),
+        [OPENID].into_iter().collect(),
+        Url::parse(REDIRECT_URI).unwrap(),
+    )
+    .with_code_challenge_methods_supported(vec![PkceCodeChallengeMethod::S256])
+}
+
+#[tokio::test]
+async fn pass_push_authorization_request() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let par_endpoint = issuer.join("par").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/par"))
+        .and(|req: &Request| {
+            let query_pairs = form_urlencoded::parse(&req.body).collect::<Vec<_>>();
+
+            let client_ids = query_pairs
+                .iter()
+                .filter(|(key, _)| key == "client_id")
+                .collect::<Vec<_>>();
+            if client_ids.len() != 1 || client_ids[0].1 != CLIENT_ID {
+                println!("Wrong, missing or duplicate client ID");
+                return false;
+            }
+
+            let query_pairs = query_pairs.into_iter().collect::<HashMap<_, _>>();
+            if query_pairs.get("client_secret").map(AsRef::as_ref) != Some(CLIENT_SECRET) {
+                println!("Wrong or missing client secret");
+                return false;
+            }
+            if query_pairs.get("response_type").map(AsRef::as_ref) != Some("code") {
+                println!("Wrong or missing response type");
+                return false;
+            }
+            if query_pairs.get("redirect_uri").map(AsRef::as_ref) != Some(REDIRECT_URI) {
+                println!("Wrong or missing redirect URI");
+                return false;
+            }
+            if !query_pairs.contains_key("state") {
+                println!("Missing state");
+                return false;
+            }
+            if !query_pairs.contains_key("code_challenge") {
+                println!("Missing code challenge");
+                return false;
+            }
+
+            true
+        })
+        .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+            "request_uri": REQUEST_URI,
+            "expires_in": 60,
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let (response, validation_data) = push_authorization_request(
+        &http_client,
+        client_credentials,
+        &par_endpoint,
+        authorization_data(),
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.request_uri, REQUEST_URI);
+    assert_eq!(response.expires_in, Duration::try_seconds(60).unwrap());
+    assert_eq!(validation_data.redirect_uri.as_str(), REDIRECT_URI);
+    assert!(validation_data.nonce.is_some());
+    assert!(validation_data.code_challenge_verifier.is_some());
+}
+
+#[tokio::test]
+async fn fail_push_authorization_request_invalid_request() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let par_endpoint = issuer.join("par").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/par"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "error": "invalid_request",
+            "error_description": "The redirect_uri is not allowed",
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let error = push_authorization_request(
+        &http_client,
+        client_credentials,
+        &par_endpoint,
+        authorization_data(),
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    let error = assert_matches!(error, PushedAuthorizationError::OAuth2(error) => error);
+    assert!(error.to_string().contains("invalid_request"));
+}
+
+#[test]
+fn pass_build_pushed_authorization_url() {
+    let authorization_endpoint = Url::parse("http://localhost/authorize?foo=bar").unwrap();
+
+    let url = build_pushed_authorization_url(authorization_endpoint, CLIENT_ID, REQUEST_URI);
+
+    let query_pairs = url.query_pairs().collect::<HashMap<_, _>>();
+    assert_eq!(query_pairs.len(), 3);
+    assert_eq!(query_pairs.get("foo").unwrap(), "bar");
+    assert_eq!(query_pairs.get("client_id").unwrap(), CLIENT_ID);
+    assert_eq!(query_pairs.get("request_uri").unwrap(), REQUEST_URI);
+}
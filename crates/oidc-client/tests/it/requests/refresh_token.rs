@@ -8,7 +8,7 @@ use std::collections::HashMap;
 
 use assert_matches::assert_matches;
 use mas_iana::oauth::{OAuthAccessTokenType, OAuthClientAuthenticationMethod};
-use mas_oidc_client::requests::refresh_token::refresh_access_token;
+use mas_oidc_client::{error::TokenRefreshError, requests::refresh_token::refresh_access_token};
 use oauth2_types::requests::AccessTokenResponse;
 use rand::SeedableRng;
 use wiremock::{
@@ -18,6 +18,8 @@ use wiremock::{
 
 use crate::{ACCESS_TOKEN, CLIENT_ID, REFRESH_TOKEN, client_credentials, init_test, now};
 
+const ROTATED_REFRESH_TOKEN: &str = "RefreshToken2";
+
 #[tokio::test]
 async fn pass_refresh_access_token() {
     let (http_client, mock_server, issuer) = init_test().await;
@@ -78,6 +80,7 @@ async fn pass_refresh_access_token() {
         None,
         None,
         None,
+        None,
         now(),
         &mut rng,
     )
@@ -85,6 +88,123 @@ async fn pass_refresh_access_token() {
     .unwrap();
 
     assert_eq!(response.access_token, ACCESS_TOKEN);
-    assert_eq!(response.refresh_token, None);
+    // The refresh token wasn't rotated, so the one we used stays valid
+    assert_eq!(response.refresh_token.as_deref(), Some(REFRESH_TOKEN));
     assert_matches!(response_id_token, None);
 }
+
+#[tokio::test]
+async fn pass_refresh_access_token_rotated() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials = client_credentials(&OAuthClientAuthenticationMethod::None, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(AccessTokenResponse {
+                access_token: ACCESS_TOKEN.to_owned(),
+                refresh_token: Some(ROTATED_REFRESH_TOKEN.to_owned()),
+                id_token: None,
+                token_type: OAuthAccessTokenType::Bearer,
+                expires_in: None,
+                scope: None,
+            }),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let (response, _) = refresh_access_token(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        REFRESH_TOKEN.to_owned(),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    // The new refresh token supersedes the one we used
+    assert_eq!(response.access_token, ACCESS_TOKEN);
+    assert_eq!(
+        response.refresh_token.as_deref(),
+        Some(ROTATED_REFRESH_TOKEN)
+    );
+}
+
+#[tokio::test]
+async fn fail_refresh_access_token_invalid_grant() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials = client_credentials(&OAuthClientAuthenticationMethod::None, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "error": "invalid_grant",
+            "error_description": "The refresh token was revoked",
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let error = refresh_access_token(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        REFRESH_TOKEN.to_owned(),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, TokenRefreshError::InvalidGrant(_));
+}
+
+#[tokio::test]
+async fn fail_refresh_access_token_server_error() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials = client_credentials(&OAuthClientAuthenticationMethod::None, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(503).set_body_json(serde_json::json!({
+            "error": "temporarily_unavailable",
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let error = refresh_access_token(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        REFRESH_TOKEN.to_owned(),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    // Other errors aren't mistaken for a rejected refresh token
+    assert_matches!(error, TokenRefreshError::Token(_));
+}
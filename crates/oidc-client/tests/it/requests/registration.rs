@@ -0,0 +1,137 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use assert_matches::assert_matches;
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_oidc_client::{error::RegistrationError, requests::registration::register_client};
+use oauth2_types::registration::{ClientMetadata, Localized};
+use url::Url;
+use wiremock::{
+    Mock, Request, ResponseTemplate,
+    matchers::{bearer_token, method, path},
+};
+
+use crate::{CLIENT_ID, CLIENT_SECRET, REDIRECT_URI, init_test};
+
+const INITIAL_ACCESS_TOKEN: &str = "InitialAccessToken1";
+
+fn client_metadata() -> ClientMetadata {
+    ClientMetadata {
+        redirect_uris: Some(vec![Url::parse(REDIRECT_URI).unwrap()]),
+        client_name: Some(Localized::new("Test client".to_owned(), [])),
+        token_endpoint_auth_method: Some(OAuthClientAuthenticationMethod::ClientSecretPost),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn pass_register_client() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let registration_endpoint = issuer.join("register").unwrap();
+    let client_metadata = client_metadata();
+
+    let expected_metadata = client_metadata.clone();
+    Mock::given(method("POST"))
+        .and(path("/register"))
+        .and(bearer_token(INITIAL_ACCESS_TOKEN))
+        .and(move |req: &Request| {
+            let Ok(metadata) = serde_json::from_slice::<ClientMetadata>(&req.body) else {
+                println!("Body is not valid client metadata");
+                return false;
+            };
+
+            if metadata != expected_metadata {
+                println!("Client metadata doesn't match");
+                return false;
+            }
+
+            true
+        })
+        .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+            "client_id": CLIENT_ID,
+            "client_secret": CLIENT_SECRET,
+            "client_id_issued_at": 1_700_000_000,
+            "client_secret_expires_at": 0,
+            "registration_access_token": "RegistrationAccessToken1",
+            "registration_client_uri": issuer.join("register/client").unwrap(),
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let response = register_client(
+        &http_client,
+        &registration_endpoint,
+        &client_metadata,
+        Some(INITIAL_ACCESS_TOKEN),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.client_id, CLIENT_ID);
+    assert_eq!(response.client_secret.as_deref(), Some(CLIENT_SECRET));
+    assert_eq!(
+        response.client_id_issued_at.unwrap().timestamp(),
+        1_700_000_000
+    );
+    assert_eq!(
+        response.registration_access_token.as_deref(),
+        Some("RegistrationAccessToken1")
+    );
+    assert_eq!(
+        response.registration_client_uri,
+        Some(issuer.join("register/client").unwrap())
+    );
+}
+
+#[tokio::test]
+async fn fail_register_client_invalid_metadata() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let registration_endpoint = issuer.join("register").unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/register"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "error": "invalid_client_metadata",
+            "error_description": "Missing client name",
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let error = register_client(
+        &http_client,
+        &registration_endpoint,
+        &client_metadata(),
+        None,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, RegistrationError::InvalidClientMetadata(_));
+}
+
+#[tokio::test]
+async fn fail_register_client_invalid_redirect_uri() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let registration_endpoint = issuer.join("register").unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/register"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "error": "invalid_redirect_uri",
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let error = register_client(
+        &http_client,
+        &registration_endpoint,
+        &client_metadata(),
+        None,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, RegistrationError::InvalidRedirectUri(_));
+}
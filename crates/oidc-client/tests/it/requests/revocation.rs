@@ -0,0 +1,375 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::{collections::HashMap, time::Duration};
+
+use assert_matches::assert_matches;
+use mas_iana::oauth::{OAuthClientAuthenticationMethod, OAuthTokenTypeHint};
+use mas_oidc_client::{
+    error::{TokenRevocationError, VerifiedTokenRevocationError},
+    requests::revocation::{CascadeVerification, revoke_token, revoke_token_and_verify},
+};
+use rand::SeedableRng;
+use wiremock::{
+    Mock, Request, ResponseTemplate,
+    matchers::{body_string_contains, method, path},
+};
+
+use crate::{
+    ACCESS_TOKEN, CLIENT_ID, CLIENT_SECRET, REFRESH_TOKEN, client_credentials, init_test, now,
+};
+
+/// Whether the request has a `token_type_hint` parameter.
+fn has_token_type_hint(req: &Request) -> bool {
+    form_urlencoded::parse(&req.body).any(|(key, _)| key == "token_type_hint")
+}
+
+#[tokio::test]
+async fn pass_revoke_token() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let revocation_endpoint = issuer.join("revoke").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .and(|req: &Request| {
+            let query_pairs = form_urlencoded::parse(&req.body).collect::<HashMap<_, _>>();
+
+            if query_pairs.get("token").map(AsRef::as_ref) != Some(ACCESS_TOKEN) {
+                println!("Wrong or missing token");
+                return false;
+            }
+            if query_pairs.get("token_type_hint").map(AsRef::as_ref) != Some("access_token") {
+                println!("Wrong or missing token type hint");
+                return false;
+            }
+            if query_pairs.get("client_id").map(AsRef::as_ref) != Some(CLIENT_ID) {
+                println!("Wrong or missing client ID");
+                return false;
+            }
+            if query_pairs.get("client_secret").map(AsRef::as_ref) != Some(CLIENT_SECRET) {
+                println!("Wrong or missing client secret");
+                return false;
+            }
+
+            true
+        })
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    revoke_token(
+        &http_client,
+        client_credentials,
+        &revocation_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        Some(OAuthTokenTypeHint::AccessToken),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn pass_revoke_token_retry_without_hint() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let revocation_endpoint = issuer.join("revoke").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    // The request with the hint is rejected
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .and(body_string_contains("token_type_hint=refresh_token"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_json(serde_json::json!({ "error": "unsupported_token_type" })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // The request without the hint succeeds
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .and(|req: &Request| !has_token_type_hint(req))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    revoke_token(
+        &http_client,
+        client_credentials,
+        &revocation_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        Some(OAuthTokenTypeHint::RefreshToken),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn fail_revoke_token_unsupported_token_type_without_hint() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let revocation_endpoint = issuer.join("revoke").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    // Without a hint, the error is not retried
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_json(serde_json::json!({ "error": "unsupported_token_type" })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let error = revoke_token(
+        &http_client,
+        client_credentials,
+        &revocation_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, TokenRevocationError::OAuth2(_));
+}
+
+#[tokio::test]
+async fn fail_revoke_token_invalid_client() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let revocation_endpoint = issuer.join("revoke").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    // Other errors are not retried without the hint
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .respond_with(
+            ResponseTemplate::new(401)
+                .set_body_json(serde_json::json!({ "error": "invalid_client" })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let error = revoke_token(
+        &http_client,
+        client_credentials,
+        &revocation_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        Some(OAuthTokenTypeHint::AccessToken),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, TokenRevocationError::OAuth2(_));
+}
+
+#[tokio::test]
+async fn pass_revoke_token_and_verify() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let revocation_endpoint = issuer.join("revoke").unwrap();
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .and(body_string_contains(REFRESH_TOKEN))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .and(body_string_contains(ACCESS_TOKEN))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "active": false })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    revoke_token_and_verify(
+        &http_client,
+        client_credentials,
+        &revocation_endpoint,
+        REFRESH_TOKEN.to_owned(),
+        Some(OAuthTokenTypeHint::RefreshToken),
+        Some(CascadeVerification {
+            introspection_endpoint: &introspection_endpoint,
+            access_token: ACCESS_TOKEN.to_owned(),
+        }),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn fail_revoke_token_and_verify_access_token_still_active() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let revocation_endpoint = issuer.join("revoke").unwrap();
+    let introspection_endpoint = issuer.join("introspect").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // The server didn't revoke the access token along with the refresh token
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "active": true })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let error = revoke_token_and_verify(
+        &http_client,
+        client_credentials,
+        &revocation_endpoint,
+        REFRESH_TOKEN.to_owned(),
+        Some(OAuthTokenTypeHint::RefreshToken),
+        Some(CascadeVerification {
+            introspection_endpoint: &introspection_endpoint,
+            access_token: ACCESS_TOKEN.to_owned(),
+        }),
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, VerifiedTokenRevocationError::AccessTokenStillActive);
+}
+
+#[tokio::test]
+async fn pass_revoke_token_and_verify_without_verification() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let revocation_endpoint = issuer.join("revoke").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // Without a verification, no introspection request is sent
+    Mock::given(method("POST"))
+        .and(path("/introspect"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    revoke_token_and_verify(
+        &http_client,
+        client_credentials,
+        &revocation_endpoint,
+        REFRESH_TOKEN.to_owned(),
+        Some(OAuthTokenTypeHint::RefreshToken),
+        None,
+        None,
+        None,
+        None,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn fail_revoke_token_timeout() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let revocation_endpoint = issuer.join("revoke").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/revoke"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let error = revoke_token(
+        &http_client,
+        client_credentials,
+        &revocation_endpoint,
+        ACCESS_TOKEN.to_owned(),
+        Some(OAuthTokenTypeHint::AccessToken),
+        None,
+        None,
+        None,
+        Some(Duration::from_millis(100)),
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, TokenRevocationError::Timeout);
+}
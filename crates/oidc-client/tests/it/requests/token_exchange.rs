@@ -0,0 +1,187 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
+use mas_iana::oauth::{OAuthAccessTokenType, OAuthClientAuthenticationMethod};
+use mas_oidc_client::{
+    error::TokenExchangeError,
+    requests::token_exchange::{
+        ACCESS_TOKEN_TYPE, JWT_TOKEN_TYPE, TokenExchangeData, exchange_token,
+    },
+};
+use oauth2_types::requests::AccessTokenResponse;
+use rand::SeedableRng;
+use wiremock::{
+    Mock, Request, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::{ACCESS_TOKEN, CLIENT_ID, CLIENT_SECRET, client_credentials, init_test, now};
+
+const SUBJECT_TOKEN: &str = "SubjectToken1";
+const ACTOR_TOKEN: &str = "ActorToken1";
+const AUDIENCE: &str = "https://resource.example.com/";
+
+#[tokio::test]
+async fn pass_exchange_token() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .and(|req: &Request| {
+            let query_pairs = form_urlencoded::parse(&req.body).collect::<HashMap<_, _>>();
+
+            if query_pairs.get("grant_type").map(AsRef::as_ref)
+                != Some("urn:ietf:params:oauth:grant-type:token-exchange")
+            {
+                println!("Wrong or missing grant type");
+                return false;
+            }
+            if query_pairs.get("subject_token").map(AsRef::as_ref) != Some(SUBJECT_TOKEN) {
+                println!("Wrong or missing subject token");
+                return false;
+            }
+            if query_pairs.get("subject_token_type").map(AsRef::as_ref) != Some(ACCESS_TOKEN_TYPE) {
+                println!("Wrong or missing subject token type");
+                return false;
+            }
+            if query_pairs.get("actor_token").map(AsRef::as_ref) != Some(ACTOR_TOKEN) {
+                println!("Wrong or missing actor token");
+                return false;
+            }
+            if query_pairs.get("actor_token_type").map(AsRef::as_ref) != Some(JWT_TOKEN_TYPE) {
+                println!("Wrong or missing actor token type");
+                return false;
+            }
+            if query_pairs.get("audience").map(AsRef::as_ref) != Some(AUDIENCE) {
+                println!("Wrong or missing audience");
+                return false;
+            }
+            if query_pairs.get("scope").map(AsRef::as_ref) != Some("openid") {
+                println!("Wrong or missing scope");
+                return false;
+            }
+            if query_pairs.get("client_id").map(AsRef::as_ref) != Some(CLIENT_ID) {
+                println!("Wrong or missing client ID");
+                return false;
+            }
+            if query_pairs.get("client_secret").map(AsRef::as_ref) != Some(CLIENT_SECRET) {
+                println!("Wrong or missing client secret");
+                return false;
+            }
+
+            true
+        })
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(AccessTokenResponse {
+                access_token: ACCESS_TOKEN.to_owned(),
+                refresh_token: None,
+                id_token: None,
+                token_type: OAuthAccessTokenType::Bearer,
+                expires_in: None,
+                scope: None,
+            }),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let data = TokenExchangeData::new(SUBJECT_TOKEN.to_owned(), ACCESS_TOKEN_TYPE.to_owned())
+        .with_actor_token(ACTOR_TOKEN.to_owned(), JWT_TOKEN_TYPE.to_owned())
+        .with_audience(AUDIENCE.to_owned())
+        .with_scope("openid".parse().unwrap());
+
+    let response = exchange_token(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        &data,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.access_token, ACCESS_TOKEN);
+    assert_eq!(response.token_type, OAuthAccessTokenType::Bearer);
+}
+
+#[tokio::test]
+async fn fail_exchange_token_invalid_target() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "error": "invalid_target",
+            "error_description": "The audience is not allowed",
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let data = TokenExchangeData::new(SUBJECT_TOKEN.to_owned(), ACCESS_TOKEN_TYPE.to_owned())
+        .with_audience(AUDIENCE.to_owned());
+
+    let error = exchange_token(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        &data,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, TokenExchangeError::InvalidTarget(_));
+}
+
+#[tokio::test]
+async fn fail_exchange_token_invalid_request() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_json(serde_json::json!({ "error": "invalid_request" })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let data = TokenExchangeData::new(SUBJECT_TOKEN.to_owned(), ACCESS_TOKEN_TYPE.to_owned());
+
+    let error = exchange_token(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        &data,
+        None,
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap_err();
+
+    assert_matches!(error, TokenExchangeError::InvalidRequest(_));
+}
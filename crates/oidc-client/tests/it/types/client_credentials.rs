@@ -68,6 +68,7 @@ async fn pass_none() {
         client_credentials,
         &token_endpoint,
         None,
+        None,
         now(),
         &mut rng,
     )
@@ -110,6 +111,7 @@ async fn pass_client_secret_basic() {
         client_credentials,
         &token_endpoint,
         None,
+        None,
         now(),
         &mut rng,
     )
@@ -167,6 +169,7 @@ async fn pass_client_secret_post() {
         client_credentials,
         &token_endpoint,
         None,
+        None,
         now(),
         &mut rng,
     )
@@ -241,6 +244,7 @@ async fn pass_client_secret_jwt() {
         client_credentials,
         &token_endpoint,
         None,
+        None,
         now(),
         &mut rng,
     )
@@ -317,6 +321,7 @@ async fn pass_private_key_jwt() {
         client_credentials,
         &token_endpoint,
         None,
+        None,
         now(),
         &mut rng,
     )
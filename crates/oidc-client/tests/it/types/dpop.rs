@@ -0,0 +1,178 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::collections::HashMap;
+
+use http::Method;
+use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
+use mas_jose::{
+    claims,
+    jwk::PublicJsonWebKeySet,
+    jwt::{JsonWebSignatureHeader, Jwt},
+};
+use mas_keystore::PrivateKey;
+use mas_oidc_client::{
+    requests::{
+        authorization_code::{AuthorizationRequestData, build_authorization_url},
+        client_credentials::access_token_with_client_credentials,
+    },
+    types::dpop::{DPOP_HEADER, DpopSigner},
+};
+use oauth2_types::scope::OPENID;
+use rand::SeedableRng;
+use serde_json::Value;
+use url::Url;
+use wiremock::{
+    Mock, Request, ResponseTemplate,
+    matchers::{header_exists, method, path},
+};
+
+use crate::{ACCESS_TOKEN, CLIENT_ID, REDIRECT_URI, client_credentials, init_test, now};
+
+fn dpop_signer() -> DpopSigner {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+    let key = PrivateKey::generate_ec_p256(&mut rng);
+    DpopSigner::new(&key, JsonWebSignatureAlg::Es256).unwrap()
+}
+
+/// Decode and verify a DPoP proof, returning its header and claims.
+fn decode_proof(proof: &str) -> (JsonWebSignatureHeader, HashMap<String, Value>) {
+    let jwt = Jwt::<HashMap<String, Value>>::try_from(proof).unwrap();
+
+    let jwk = jwt
+        .header()
+        .jwk()
+        .expect("the proof should include the JWK");
+    let jwks = PublicJsonWebKeySet::new(vec![jwk.clone()]);
+    jwt.verify_with_jwks(&jwks).unwrap();
+
+    (jwt.header().clone(), jwt.payload().clone())
+}
+
+#[test]
+fn pass_dpop_proof() {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+    let signer = dpop_signer();
+    let url = Url::parse("https://server.example.com/token?foo=bar#fragment").unwrap();
+
+    let proof = signer
+        .proof(Method::POST, &url)
+        .with_access_token("Kz~8mXK1EalYznwH-LC-1fBAo.4Ljp~zsPE_NeO.gxU")
+        .with_nonce("eyJ7S_zG.eyJH0-Z.HX4w-7v".to_owned())
+        .build(now(), &mut rng)
+        .unwrap();
+
+    let (header, mut claims) = decode_proof(&proof);
+    assert_eq!(header.typ(), Some("dpop+jwt"));
+    assert_eq!(header.alg(), &JsonWebSignatureAlg::Es256);
+    assert_eq!(header.jwk(), Some(signer.jwk()));
+
+    let htm = claims::HTM.extract_required(&mut claims).unwrap();
+    assert_eq!(htm, "POST");
+
+    // The query and fragment are stripped from the URL
+    let htu = claims::HTU.extract_required(&mut claims).unwrap();
+    assert_eq!(htu, "https://server.example.com/token");
+
+    let jti = claims::JTI.extract_required(&mut claims).unwrap();
+    assert!(!jti.is_empty());
+
+    let iat = claims.remove("iat").unwrap();
+    assert_eq!(iat, now().timestamp());
+
+    // Example from RFC9449 sec. 7.1
+    let ath = claims::ATH.extract_required(&mut claims).unwrap();
+    assert_eq!(ath, "fUHyO2r2Z3DZ53EsNrWBb0xWXoaNy59IiKCAqksmQEo");
+
+    let nonce = claims.remove("nonce").unwrap();
+    assert_eq!(nonce, "eyJ7S_zG.eyJH0-Z.HX4w-7v");
+
+    assert!(claims.is_empty());
+
+    // Each proof has a different identifier
+    let other_proof = signer
+        .proof(Method::POST, &url)
+        .build(now(), &mut rng)
+        .unwrap();
+    let (_, mut other_claims) = decode_proof(&other_proof);
+    let other_jti = claims::JTI.extract_required(&mut other_claims).unwrap();
+    assert_ne!(jti, other_jti);
+}
+
+#[tokio::test]
+async fn pass_access_token_with_dpop() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let client_credentials =
+        client_credentials(&OAuthClientAuthenticationMethod::ClientSecretPost, &issuer);
+    let token_endpoint = issuer.join("token").unwrap();
+    let signer = dpop_signer();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    let expected_htu = token_endpoint.to_string();
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .and(header_exists(DPOP_HEADER.as_str()))
+        .and(move |req: &Request| {
+            let proof = req.headers.get(DPOP_HEADER).unwrap().to_str().unwrap();
+            let (_, mut claims) = decode_proof(proof);
+
+            if claims::HTM.extract_required(&mut claims).unwrap() != "POST" {
+                println!("Wrong htm claim");
+                return false;
+            }
+            if claims::HTU.extract_required(&mut claims).unwrap() != expected_htu {
+                println!("Wrong htu claim");
+                return false;
+            }
+
+            true
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "access_token": ACCESS_TOKEN,
+            "token_type": "DPoP",
+            "expires_in": 300,
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let response = access_token_with_client_credentials(
+        &http_client,
+        client_credentials,
+        &token_endpoint,
+        None,
+        Some(&signer),
+        now(),
+        &mut rng,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.access_token, ACCESS_TOKEN);
+}
+
+#[test]
+fn pass_authorization_url_with_dpop_jkt() {
+    let signer = dpop_signer();
+    let authorization_endpoint = Url::parse("http://localhost/authorize").unwrap();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+    let (url, _) = build_authorization_url(
+        authorization_endpoint,
+        AuthorizationRequestData::new(
+            CLIENT_ID.to_owned(),
+            [OPENID].into_iter().collect(),
+            Url::parse(REDIRECT_URI).unwrap(),
+        )
+        .with_dpop_jkt(signer.jkt()),
+        &mut rng,
+    )
+    .unwrap();
+
+    let query_pairs = url.query_pairs().collect::<HashMap<_, _>>();
+    assert_eq!(
+        query_pairs.get("dpop_jkt").unwrap(),
+        &signer.jwk().params().thumbprint().encode()
+    );
+}
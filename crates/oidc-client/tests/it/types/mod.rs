@@ -5,3 +5,4 @@
 // Please see LICENSE files in the repository root for full details.
 
 mod client_credentials;
+mod dpop;
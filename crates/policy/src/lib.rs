@@ -39,20 +39,13 @@ pub enum LoadError {
     #[error("invalid policy data")]
     InvalidData(#[source] anyhow::Error),
 
+    #[error("invalid policy data schema")]
+    InvalidDataSchema(#[source] Box<jsonschema::ValidationError<'static>>),
+
     #[error("failed to instantiate a test instance")]
     Instantiate(#[source] InstantiateError),
 }
 
-impl LoadError {
-    /// Creates an example of an invalid data error, used for API response
-    /// documentation
-    #[doc(hidden)]
-    #[must_use]
-    pub fn invalid_data_example() -> Self {
-        Self::InvalidData(anyhow::Error::msg("Failed to merge policy data objects"))
-    }
-}
-
 #[derive(Debug, Error)]
 pub enum InstantiateError {
     #[error("failed to create WASM runtime")]
@@ -194,6 +187,8 @@ pub struct PolicyFactory {
     data: Data,
     dynamic_data: ArcSwap<DynamicData>,
     entrypoints: Entrypoints,
+    data_schema: serde_json::Value,
+    data_validator: Option<jsonschema::Validator>,
 }
 
 impl PolicyFactory {
@@ -237,6 +232,8 @@ impl PolicyFactory {
             data,
             dynamic_data,
             entrypoints,
+            data_schema: serde_json::json!({}),
+            data_validator: None,
         };
 
         // Try to instantiate
@@ -248,6 +245,64 @@ impl PolicyFactory {
         Ok(factory)
     }
 
+    /// Set the JSON Schema which the dynamic data of the policy must match.
+    ///
+    /// The schema is compiled once here, and then enforced by
+    /// [`PolicyFactory::validate_data`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the schema itself is invalid.
+    pub fn with_data_schema(mut self, data_schema: &serde_json::Value) -> Result<Self, LoadError> {
+        let data_validator = jsonschema::validator_for(data_schema)
+            .map_err(|e| LoadError::InvalidDataSchema(Box::new(e)))?;
+        self.data_schema = data_schema.clone();
+        self.data_validator = Some(data_validator);
+        Ok(self)
+    }
+
+    /// Get the JSON Schema which the dynamic data of the policy must match.
+    ///
+    /// This is an empty schema, which allows anything, unless one was set
+    /// with [`PolicyFactory::with_data_schema`].
+    #[must_use]
+    pub fn data_schema(&self) -> &serde_json::Value {
+        &self.data_schema
+    }
+
+    /// Validate the given dynamic data against the schema set with
+    /// [`PolicyFactory::with_data_schema`].
+    ///
+    /// Any data is valid if no schema was set.
+    ///
+    /// # Errors
+    ///
+    /// Returns the list of schema violations, each prefixed by the JSON
+    /// pointer to the offending value, unless it is the whole data.
+    pub fn validate_data(&self, data: &serde_json::Value) -> Result<(), Vec<String>> {
+        let Some(validator) = &self.data_validator else {
+            return Ok(());
+        };
+
+        let errors: Vec<String> = validator
+            .iter_errors(data)
+            .map(|error| {
+                let path = error.instance_path.to_string();
+                if path.is_empty() {
+                    error.to_string()
+                } else {
+                    format!("{path}: {error}")
+                }
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     /// Set the dynamic data for the policy.
     ///
     /// The `dynamic_data` object is merged with the static data given when the
@@ -569,6 +624,8 @@ mod tests {
             .set_dynamic_data(mas_data_model::PolicyData {
                 id: Ulid::nil(),
                 created_at: SystemTime::now().into(),
+                created_by_user_id: None,
+                created_by_client_id: None,
                 data: serde_json::json!({
                     "emails": {
                         "banned_addresses": {
@@ -595,6 +652,53 @@ mod tests {
         assert!(!res.valid());
     }
 
+    #[tokio::test]
+    async fn test_data_schema() {
+        let data = Data::new("example.com".to_owned());
+
+        #[allow(clippy::disallowed_types)]
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("..")
+            .join("policies")
+            .join("policy.wasm");
+
+        let file = tokio::fs::File::open(path).await.unwrap();
+
+        let entrypoints = Entrypoints {
+            register: "register/violation".to_owned(),
+            client_registration: "client_registration/violation".to_owned(),
+            authorization_grant: "authorization_grant/violation".to_owned(),
+            email: "email/violation".to_owned(),
+        };
+
+        let factory = PolicyFactory::load(file, data, entrypoints).await.unwrap();
+
+        // Without a schema, anything goes
+        assert!(factory.validate_data(&serde_json::json!(42)).is_ok());
+
+        let factory = factory
+            .with_data_schema(&serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "emails": {
+                        "type": "object",
+                    },
+                },
+            }))
+            .unwrap_or_else(|_| panic!("the schema should be valid"));
+
+        assert!(
+            factory
+                .validate_data(&serde_json::json!({"emails": {}}))
+                .is_ok()
+        );
+        assert_eq!(
+            factory.validate_data(&serde_json::json!({"emails": 42})),
+            Err(vec!["/emails: 42 is not of type \"object\"".to_owned()])
+        );
+    }
+
     #[tokio::test]
     async fn test_big_dynamic_data() {
         let data = Data::new("example.com".to_owned());
@@ -627,6 +731,8 @@ mod tests {
             .set_dynamic_data(mas_data_model::PolicyData {
                 id: Ulid::nil(),
                 created_at: SystemTime::now().into(),
+                created_by_user_id: None,
+                created_by_client_id: None,
                 data: json,
             })
             .await
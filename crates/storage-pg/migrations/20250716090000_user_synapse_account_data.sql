@@ -0,0 +1,21 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Keeps the global account data of users imported from Synapse, when asked
+-- for. MAS doesn't use it itself, and Synapse keeps serving it, so this is
+-- only an archive of it.
+CREATE TABLE user_synapse_account_data(
+    -- The owner of the account data
+    user_id UUID NOT NULL
+      REFERENCES users(user_id) ON DELETE CASCADE,
+
+    -- The type of account data, e.g. `m.push_rules`
+    account_data_type TEXT NOT NULL,
+
+    -- The content of the account data, as the JSON text stored by Synapse
+    content TEXT NOT NULL,
+
+    PRIMARY KEY (user_id, account_data_type)
+);
@@ -0,0 +1,21 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Keeps the raw values of compatibility session fields which couldn't be
+-- migrated from Synapse as-is, for example unparseable IP addresses.
+-- MAS doesn't use them itself, they are only kept for forensic reasons.
+CREATE TABLE compat_session_migration_notes(
+    -- The session the note is about
+    compat_session_id UUID NOT NULL
+      REFERENCES compat_sessions(compat_session_id) ON DELETE CASCADE,
+
+    -- The field of the session which couldn't be migrated, e.g. `last_active_ip`
+    field TEXT NOT NULL,
+
+    -- The raw value of the field, as stored by Synapse
+    raw_value TEXT NOT NULL,
+
+    PRIMARY KEY (compat_session_id, field)
+);
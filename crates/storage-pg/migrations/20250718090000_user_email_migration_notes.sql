@@ -0,0 +1,21 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Keeps the raw values of user email fields which were changed when migrated
+-- from Synapse, for example email addresses which were normalized.
+-- MAS doesn't use them itself, they are only kept for forensic reasons.
+CREATE TABLE user_email_migration_notes(
+    -- The email the note is about
+    user_email_id UUID NOT NULL
+      REFERENCES user_emails(user_email_id) ON DELETE CASCADE,
+
+    -- The field of the email which was changed, e.g. `email`
+    field TEXT NOT NULL,
+
+    -- The raw value of the field, as stored by Synapse
+    raw_value TEXT NOT NULL,
+
+    PRIMARY KEY (user_email_id, field)
+);
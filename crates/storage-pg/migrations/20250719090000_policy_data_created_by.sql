@@ -0,0 +1,13 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Record which user and client set the policy data through the admin API
+ALTER TABLE "policy_data"
+  ADD COLUMN "created_by_user_id" UUID
+    REFERENCES "users" ("user_id")
+    ON DELETE SET NULL,
+  ADD COLUMN "created_by_oauth2_client_id" UUID
+    REFERENCES "oauth2_clients" ("oauth2_client_id")
+    ON DELETE SET NULL;
@@ -0,0 +1,21 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Keeps the sync filters of users imported from Synapse, when asked for. MAS
+-- doesn't use them itself, and Synapse keeps serving them, so this is only an
+-- archive of them.
+CREATE TABLE user_synapse_filters(
+    -- The owner of the filter
+    user_id UUID NOT NULL
+      REFERENCES users(user_id) ON DELETE CASCADE,
+
+    -- The ID of the filter, as given out by Synapse, unique per user
+    filter_id BIGINT NOT NULL,
+
+    -- The filter, as the JSON bytes stored by Synapse
+    filter_json BYTEA NOT NULL,
+
+    PRIMARY KEY (user_id, filter_id)
+);
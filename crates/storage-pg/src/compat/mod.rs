@@ -34,6 +34,7 @@ mod tests {
     use rand_chacha::ChaChaRng;
     use sqlx::PgPool;
     use ulid::Ulid;
+    use uuid::Uuid;
 
     use crate::PgRepository;
 
@@ -313,6 +314,94 @@ mod tests {
         assert_eq!(repo.compat_session().count(active).await.unwrap(), 0);
     }
 
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_remove_orphaned_sessions(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &clock, "john".to_owned())
+            .await
+            .unwrap();
+
+        // A session with a device but no token isn't orphaned
+        let device = Device::generate(&mut rng);
+        let device_session = repo
+            .compat_session()
+            .add(&mut rng, &clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+
+        // Deviceless sessions can only be created by syn2mas
+        let orphaned_id = Ulid::from_datetime_with_source(clock.now().into(), &mut rng);
+        let deviceless_id = Ulid::from_datetime_with_source(clock.now().into(), &mut rng);
+        sqlx::query(
+            "INSERT INTO compat_sessions (compat_session_id, user_id, created_at)
+             VALUES ($1, $3, $4), ($2, $3, $4)",
+        )
+        .bind(Uuid::from(orphaned_id))
+        .bind(Uuid::from(deviceless_id))
+        .bind(Uuid::from(user.id))
+        .bind(clock.now())
+        .execute(&mut **repo)
+        .await
+        .unwrap();
+
+        // A deviceless session with a token isn't orphaned
+        let deviceless_session = repo
+            .compat_session()
+            .lookup(deviceless_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.compat_access_token()
+            .add(
+                &mut rng,
+                &clock,
+                &deviceless_session,
+                "token".to_owned(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let orphaned = CompatSessionFilter::new().orphaned_only();
+        assert_eq!(repo.compat_session().count(orphaned).await.unwrap(), 1);
+        let list = repo
+            .compat_session()
+            .list(orphaned, Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(list.edges.len(), 1);
+        assert_eq!(list.edges[0].0.id, orphaned_id);
+
+        assert_eq!(repo.compat_session().remove_orphaned().await.unwrap(), 1);
+        assert_eq!(repo.compat_session().count(orphaned).await.unwrap(), 0);
+        assert!(
+            repo.compat_session()
+                .lookup(orphaned_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.compat_session()
+                .lookup(deviceless_id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            repo.compat_session()
+                .lookup(device_session.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
     #[sqlx::test(migrator = "crate::MIGRATOR")]
     async fn test_access_token_repository(pool: PgPool) {
         const FIRST_TOKEN: &str = "first_access_token";
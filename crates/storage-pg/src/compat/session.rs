@@ -27,7 +27,7 @@ use uuid::Uuid;
 use crate::{
     DatabaseError, DatabaseInconsistencyError,
     filter::{Filter, StatementExt, StatementWithJoinsExt},
-    iden::{CompatSessions, CompatSsoLogins, UserSessions},
+    iden::{CompatAccessTokens, CompatSessions, CompatSsoLogins, UserSessions},
     pagination::QueryBuilderExt,
     tracing::ExecuteExt,
 };
@@ -252,6 +252,29 @@ impl Filter for CompatSessionFilter<'_> {
             .add_option(self.device().map(|device| {
                 Expr::col((CompatSessions::Table, CompatSessions::DeviceId)).eq(device.as_str())
             }))
+            .add_option(self.orphaned().then(|| {
+                sea_query::Condition::all()
+                    .add(Expr::col((CompatSessions::Table, CompatSessions::DeviceId)).is_null())
+                    .add(
+                        Expr::exists(
+                            Query::select()
+                                .expr(Expr::cust("1"))
+                                .from(CompatAccessTokens::Table)
+                                .and_where(
+                                    Expr::col((
+                                        CompatAccessTokens::Table,
+                                        CompatAccessTokens::CompatSessionId,
+                                    ))
+                                    .equals((
+                                        CompatSessions::Table,
+                                        CompatSessions::CompatSessionId,
+                                    )),
+                                )
+                                .take(),
+                        )
+                        .not(),
+                    )
+            }))
     }
 }
 
@@ -554,6 +577,28 @@ impl CompatSessionRepository for PgCompatSessionRepository<'_> {
             .map_err(DatabaseError::to_invalid_operation)
     }
 
+    #[tracing::instrument(
+        name = "db.compat_session.remove_orphaned",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn remove_orphaned(&mut self) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::delete()
+            .from_table(CompatSessions::Table)
+            .apply_filter(CompatSessionFilter::new().orphaned_only())
+            .build_sqlx(PostgresQueryBuilder);
+
+        let res = sqlx::query_with(&sql, arguments)
+            .traced()
+            .execute(&mut *self.conn)
+            .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.compat_session.record_batch_activity",
         skip_all,
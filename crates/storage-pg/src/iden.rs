@@ -66,6 +66,12 @@ pub enum CompatSessions {
     LastActiveIp,
 }
 
+#[derive(sea_query::Iden)]
+pub enum CompatAccessTokens {
+    Table,
+    CompatSessionId,
+}
+
 #[derive(sea_query::Iden)]
 pub enum CompatSsoLogins {
     Table,
@@ -188,3 +194,15 @@ pub enum UserRegistrationTokens {
     ExpiresAt,
     RevokedAt,
 }
+
+#[derive(sea_query::Iden)]
+#[iden = "policy_data"]
+pub enum PolicyDataEntries {
+    Table,
+    PolicyDataId,
+    CreatedAt,
+    CreatedByUserId,
+    #[iden = "created_by_oauth2_client_id"]
+    CreatedByOAuth2ClientId,
+    Data,
+}
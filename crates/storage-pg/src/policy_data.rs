@@ -7,15 +7,30 @@
 //! storage.
 
 use async_trait::async_trait;
-use mas_data_model::PolicyData;
-use mas_storage::{Clock, policy_data::PolicyDataRepository};
+use mas_data_model::{PolicyData, Session};
+use mas_storage::{
+    Clock, Page, Pagination,
+    policy_data::{PolicyDataFilter, PolicyDataRepository},
+};
 use rand::RngCore;
+use sea_query::{Expr, Order, PostgresQueryBuilder, Query, enum_def};
+use sea_query_binder::SqlxBinder;
 use serde_json::Value;
 use sqlx::{PgConnection, types::Json};
 use ulid::Ulid;
 use uuid::Uuid;
 
-use crate::{DatabaseError, ExecuteExt};
+use crate::{
+    DatabaseError, ExecuteExt,
+    filter::{Filter, StatementExt},
+    iden::PolicyDataEntries,
+    pagination::QueryBuilderExt,
+};
+
+/// The ID of the advisory lock used to serialize updates to the policy data.
+/// This is the ASCII encoding of `policy_d`, which makes it unlikely to collide
+/// with the locks taken on users.
+const POLICY_DATA_LOCK_ID: i64 = 0x706f_6c69_6379_5f64;
 
 /// An implementation of [`PolicyDataRepository`] for a PostgreSQL connection.
 pub struct PgPolicyDataRepository<'c> {
@@ -31,9 +46,13 @@ impl<'c> PgPolicyDataRepository<'c> {
     }
 }
 
+#[derive(sqlx::FromRow)]
+#[enum_def]
 struct PolicyDataLookup {
     policy_data_id: Uuid,
     created_at: chrono::DateTime<chrono::Utc>,
+    created_by_user_id: Option<Uuid>,
+    created_by_oauth2_client_id: Option<Uuid>,
     data: Json<Value>,
 }
 
@@ -42,11 +61,46 @@ impl From<PolicyDataLookup> for PolicyData {
         PolicyData {
             id: value.policy_data_id.into(),
             created_at: value.created_at,
+            created_by_user_id: value.created_by_user_id.map(Ulid::from),
+            created_by_client_id: value.created_by_oauth2_client_id.map(Ulid::from),
             data: value.data.0,
         }
     }
 }
 
+impl Filter for PolicyDataFilter {
+    fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
+        sea_query::Condition::all()
+            .add_option(self.client_id().map(|client_id| {
+                Expr::col((
+                    PolicyDataEntries::Table,
+                    PolicyDataEntries::CreatedByOAuth2ClientId,
+                ))
+                .eq(Uuid::from(client_id))
+            }))
+            .add_option(self.created_after().map(|created_after| {
+                Expr::col((PolicyDataEntries::Table, PolicyDataEntries::CreatedAt))
+                    .gt(created_after)
+            }))
+            .add_option(self.is_latest_only().then(|| {
+                Expr::col((PolicyDataEntries::Table, PolicyDataEntries::PolicyDataId)).in_subquery(
+                    Query::select()
+                        .expr(Expr::col((
+                            PolicyDataEntries::Table,
+                            PolicyDataEntries::PolicyDataId,
+                        )))
+                        .from(PolicyDataEntries::Table)
+                        .order_by(
+                            (PolicyDataEntries::Table, PolicyDataEntries::PolicyDataId),
+                            Order::Desc,
+                        )
+                        .limit(1)
+                        .take(),
+                )
+            }))
+    }
+}
+
 #[async_trait]
 impl PolicyDataRepository for PgPolicyDataRepository<'_> {
     type Error = DatabaseError;
@@ -63,7 +117,11 @@ impl PolicyDataRepository for PgPolicyDataRepository<'_> {
         let row = sqlx::query_as!(
             PolicyDataLookup,
             r#"
-            SELECT policy_data_id, created_at, data
+            SELECT policy_data_id
+                 , created_at
+                 , created_by_user_id
+                 , created_by_oauth2_client_id
+                 , data
             FROM policy_data
             ORDER BY policy_data_id DESC
             LIMIT 1
@@ -80,6 +138,40 @@ impl PolicyDataRepository for PgPolicyDataRepository<'_> {
         Ok(Some(row.into()))
     }
 
+    #[tracing::instrument(
+        name = "db.policy_data.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            policy_data.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<PolicyData>, Self::Error> {
+        let row = sqlx::query_as!(
+            PolicyDataLookup,
+            r#"
+            SELECT policy_data_id
+                 , created_at
+                 , created_by_user_id
+                 , created_by_oauth2_client_id
+                 , data
+            FROM policy_data
+            WHERE policy_data_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.into()))
+    }
+
     #[tracing::instrument(
         name = "db.policy_data.set",
         skip_all,
@@ -92,18 +184,29 @@ impl PolicyDataRepository for PgPolicyDataRepository<'_> {
         &mut self,
         rng: &mut (dyn RngCore + Send),
         clock: &dyn Clock,
+        created_by: Option<&Session>,
         data: Value,
     ) -> Result<PolicyData, Self::Error> {
         let created_at = clock.now();
         let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        let created_by_user_id = created_by.and_then(|session| session.user_id);
+        let created_by_client_id = created_by.map(|session| session.client_id);
 
         sqlx::query!(
             r#"
-            INSERT INTO policy_data (policy_data_id, created_at, data)
-            VALUES ($1, $2, $3)
+            INSERT INTO policy_data
+                ( policy_data_id
+                , created_at
+                , created_by_user_id
+                , created_by_oauth2_client_id
+                , data
+                )
+            VALUES ($1, $2, $3, $4, $5)
             "#,
             Uuid::from(id),
             created_at,
+            created_by_user_id.map(Uuid::from),
+            created_by_client_id.map(Uuid::from),
             data,
         )
         .traced()
@@ -113,6 +216,8 @@ impl PolicyDataRepository for PgPolicyDataRepository<'_> {
         Ok(PolicyData {
             id,
             created_at,
+            created_by_user_id,
+            created_by_client_id,
             data,
         })
     }
@@ -147,17 +252,127 @@ impl PolicyDataRepository for PgPolicyDataRepository<'_> {
             .try_into()
             .map_err(DatabaseError::to_invalid_operation)?)
     }
+
+    #[tracing::instrument(
+        name = "db.policy_data.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: PolicyDataFilter,
+        pagination: Pagination,
+    ) -> Result<Page<PolicyData>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((PolicyDataEntries::Table, PolicyDataEntries::PolicyDataId)),
+                PolicyDataLookupIden::PolicyDataId,
+            )
+            .expr_as(
+                Expr::col((PolicyDataEntries::Table, PolicyDataEntries::CreatedAt)),
+                PolicyDataLookupIden::CreatedAt,
+            )
+            .expr_as(
+                Expr::col((PolicyDataEntries::Table, PolicyDataEntries::CreatedByUserId)),
+                PolicyDataLookupIden::CreatedByUserId,
+            )
+            .expr_as(
+                Expr::col((
+                    PolicyDataEntries::Table,
+                    PolicyDataEntries::CreatedByOAuth2ClientId,
+                )),
+                PolicyDataLookupIden::CreatedByOauth2ClientId,
+            )
+            .expr_as(
+                Expr::col((PolicyDataEntries::Table, PolicyDataEntries::Data)),
+                PolicyDataLookupIden::Data,
+            )
+            .from(PolicyDataEntries::Table)
+            .apply_filter(filter)
+            .generate_pagination(
+                (PolicyDataEntries::Table, PolicyDataEntries::PolicyDataId),
+                pagination,
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<PolicyDataLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination.process(edges).map(PolicyData::from);
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.policy_data.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(&mut self, filter: PolicyDataFilter) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((PolicyDataEntries::Table, PolicyDataEntries::PolicyDataId)).count())
+            .from(PolicyDataEntries::Table)
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+
+    #[tracing::instrument(
+        name = "db.policy_data.acquire_lock",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn acquire_lock(&mut self) -> Result<(), Self::Error> {
+        // Use a PG advisory lock, which will be released when the transaction is
+        // committed or rolled back. The lock ID is fixed, as there is only one policy
+        // data.
+        sqlx::query!(
+            r#"
+                SELECT pg_advisory_xact_lock($1)
+            "#,
+            POLICY_DATA_LOCK_ID,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use mas_storage::{clock::MockClock, policy_data::PolicyDataRepository};
+    use mas_storage::{
+        Pagination,
+        clock::MockClock,
+        policy_data::{PolicyDataFilter, PolicyDataRepository},
+    };
     use rand::SeedableRng;
     use rand_chacha::ChaChaRng;
     use serde_json::json;
     use sqlx::PgPool;
+    use ulid::Ulid;
 
-    use crate::policy_data::PgPolicyDataRepository;
+    use crate::policy_data::{POLICY_DATA_LOCK_ID, PgPolicyDataRepository};
 
     #[sqlx::test(migrator = "crate::MIGRATOR")]
     async fn test_policy_data(pool: PgPool) {
@@ -172,7 +387,10 @@ mod tests {
 
         // Set some data
         let value1 = json!({"hello": "world"});
-        let policy_data1 = repo.set(&mut rng, &clock, value1.clone()).await.unwrap();
+        let policy_data1 = repo
+            .set(&mut rng, &clock, None, value1.clone())
+            .await
+            .unwrap();
         assert_eq!(policy_data1.data, value1);
 
         let data_fetched1 = repo.get().await.unwrap().unwrap();
@@ -181,19 +399,32 @@ mod tests {
         // Set some new data
         clock.advance(chrono::Duration::seconds(1));
         let value2 = json!({"foo": "bar"});
-        let policy_data2 = repo.set(&mut rng, &clock, value2.clone()).await.unwrap();
+        let policy_data2 = repo
+            .set(&mut rng, &clock, None, value2.clone())
+            .await
+            .unwrap();
         assert_eq!(policy_data2.data, value2);
 
         // Check the new data is fetched
         let data_fetched2 = repo.get().await.unwrap().unwrap();
         assert_eq!(data_fetched2, policy_data2);
 
+        // Older entries can still be looked up by their ID
+        let data_looked_up1 = repo.lookup(policy_data1.id).await.unwrap().unwrap();
+        assert_eq!(data_looked_up1, policy_data1);
+        let data_looked_up2 = repo.lookup(policy_data2.id).await.unwrap().unwrap();
+        assert_eq!(data_looked_up2, policy_data2);
+
         // Prune until the first entry
         let affected = repo.prune(1).await.unwrap();
         let data_fetched3 = repo.get().await.unwrap().unwrap();
         assert_eq!(data_fetched3, policy_data2);
         assert_eq!(affected, 1);
 
+        // The pruned entry can't be looked up anymore
+        let data_looked_up1 = repo.lookup(policy_data1.id).await.unwrap();
+        assert_eq!(data_looked_up1, None);
+
         // Do a raw query to check the other rows were pruned
         let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM policy_data")
             .fetch_one(&mut *conn)
@@ -201,4 +432,103 @@ mod tests {
             .unwrap();
         assert_eq!(count, 1);
     }
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_policy_data_list(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut conn = pool.acquire().await.unwrap();
+        let mut repo = PgPolicyDataRepository::new(&mut conn);
+
+        let all = PolicyDataFilter::new();
+        let latest = PolicyDataFilter::new().latest_only();
+
+        // Nothing to list at first
+        assert_eq!(repo.count(all).await.unwrap(), 0);
+        assert_eq!(repo.count(latest).await.unwrap(), 0);
+        let page = repo.list(latest, Pagination::first(10)).await.unwrap();
+        assert!(page.edges.is_empty());
+
+        let policy_data1 = repo
+            .set(&mut rng, &clock, None, json!({"version": 1}))
+            .await
+            .unwrap();
+        clock.advance(chrono::Duration::seconds(1));
+        let policy_data2 = repo
+            .set(&mut rng, &clock, None, json!({"version": 2}))
+            .await
+            .unwrap();
+        clock.advance(chrono::Duration::seconds(1));
+        let policy_data3 = repo
+            .set(&mut rng, &clock, None, json!({"version": 3}))
+            .await
+            .unwrap();
+
+        assert_eq!(repo.count(all).await.unwrap(), 3);
+        assert_eq!(repo.count(latest).await.unwrap(), 1);
+
+        // Only the versions set after the first one
+        let recent = PolicyDataFilter::new().with_created_after(policy_data1.created_at);
+        assert_eq!(repo.count(recent).await.unwrap(), 2);
+
+        // None of them were set by a client
+        let by_client = PolicyDataFilter::new().for_client_id(Ulid::nil());
+        assert_eq!(repo.count(by_client).await.unwrap(), 0);
+
+        // Versions are listed in chronological order
+        let page = repo.list(all, Pagination::first(10)).await.unwrap();
+        assert!(!page.has_next_page);
+        assert_eq!(
+            page.edges,
+            vec![
+                policy_data1.clone(),
+                policy_data2.clone(),
+                policy_data3.clone()
+            ]
+        );
+
+        // Paginate after the first version
+        let page = repo
+            .list(all, Pagination::first(1).after(policy_data1.id))
+            .await
+            .unwrap();
+        assert!(page.has_next_page);
+        assert_eq!(page.edges, vec![policy_data2]);
+
+        // Only the latest version
+        let page = repo.list(latest, Pagination::first(10)).await.unwrap();
+        assert!(!page.has_next_page);
+        assert_eq!(page.edges, vec![policy_data3]);
+    }
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_policy_data_lock(pool: PgPool) {
+        let try_lock = "SELECT pg_try_advisory_xact_lock($1)";
+
+        let mut txn = pool.begin().await.unwrap();
+        PgPolicyDataRepository::new(&mut txn)
+            .acquire_lock()
+            .await
+            .unwrap();
+
+        // The lock is held by the first transaction
+        let mut other = pool.begin().await.unwrap();
+        let locked: bool = sqlx::query_scalar(try_lock)
+            .bind(POLICY_DATA_LOCK_ID)
+            .fetch_one(&mut *other)
+            .await
+            .unwrap();
+        assert!(!locked);
+        other.rollback().await.unwrap();
+
+        // Once the first transaction is done, the lock is released
+        txn.commit().await.unwrap();
+        let mut other = pool.begin().await.unwrap();
+        let locked: bool = sqlx::query_scalar(try_lock)
+            .bind(POLICY_DATA_LOCK_ID)
+            .fetch_one(&mut *other)
+            .await
+            .unwrap();
+        assert!(locked);
+    }
 }
@@ -435,4 +435,46 @@ impl UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'_> {
 
         Ok(())
     }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.remap_provider",
+        skip_all,
+        fields(
+            db.query.text,
+            from.id = %from.id,
+            to.id = %to.id,
+        ),
+        err,
+    )]
+    async fn remap_provider(
+        &mut self,
+        from: &UpstreamOAuthProvider,
+        to: &UpstreamOAuthProvider,
+    ) -> Result<usize, Self::Error> {
+        // Links with a subject which already exists on the target provider are
+        // skipped, as they would violate the unique constraint on
+        // (provider, subject)
+        let res = sqlx::query!(
+            r#"
+                UPDATE upstream_oauth_links AS l SET
+                    upstream_oauth_provider_id = $2
+                WHERE l.upstream_oauth_provider_id = $1
+                  AND NOT EXISTS (
+                    SELECT 1
+                    FROM upstream_oauth_links AS o
+                    WHERE o.upstream_oauth_provider_id = $2
+                      AND o.subject = l.subject
+                  )
+            "#,
+            Uuid::from(from.id),
+            Uuid::from(to.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        res.rows_affected()
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
 }
@@ -32,7 +32,7 @@ mod tests {
             UpstreamOAuthProviderParams, UpstreamOAuthProviderRepository,
             UpstreamOAuthSessionFilter, UpstreamOAuthSessionRepository,
         },
-        user::UserRepository,
+        user::{UserFilter, UserRepository},
     };
     use oauth2_types::scope::{OPENID, Scope};
     use rand::SeedableRng;
@@ -212,6 +212,24 @@ mod tests {
 
         assert_eq!(repo.upstream_oauth_link().count(filter).await.unwrap(), 1);
 
+        // The user can be found by the link
+        let user_filter = UserFilter::new()
+            .with_upstream_oauth_provider(&provider)
+            .with_upstream_oauth_subject("a-subject");
+        let users = repo
+            .user()
+            .list(user_filter, Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(users.edges.len(), 1);
+        assert_eq!(users.edges[0].id, user.id);
+        assert_eq!(repo.user().count(user_filter).await.unwrap(), 1);
+
+        let user_filter = UserFilter::new()
+            .with_upstream_oauth_provider(&provider)
+            .with_upstream_oauth_subject("another-subject");
+        assert_eq!(repo.user().count(user_filter).await.unwrap(), 0);
+
         // There should be exactly one enabled provider
         assert_eq!(
             repo.upstream_oauth_provider()
@@ -301,6 +319,95 @@ mod tests {
         );
     }
 
+    /// Test that links can be moved from one provider to another
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_link_remap_provider(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        let mut providers = Vec::with_capacity(2);
+        for client_id in ["old-client", "new-client"] {
+            let provider = repo
+                .upstream_oauth_provider()
+                .add(
+                    &mut rng,
+                    &clock,
+                    UpstreamOAuthProviderParams {
+                        issuer: None,
+                        human_name: None,
+                        brand_name: None,
+                        scope: Scope::from_iter([OPENID]),
+                        token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                        fetch_userinfo: false,
+                        userinfo_signed_response_alg: None,
+                        token_endpoint_signing_alg: None,
+                        id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                        client_id: client_id.to_owned(),
+                        encrypted_client_secret: None,
+                        claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                        token_endpoint_override: None,
+                        authorization_endpoint_override: None,
+                        userinfo_endpoint_override: None,
+                        jwks_uri_override: None,
+                        discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                        pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                        response_mode: None,
+                        additional_authorization_parameters: Vec::new(),
+                        forward_login_hint: false,
+                        ui_order: 0,
+                        on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                    },
+                )
+                .await
+                .unwrap();
+            providers.push(provider);
+        }
+        let [old, new] = providers.try_into().unwrap();
+
+        for subject in ["alice", "bob", "charlie"] {
+            repo.upstream_oauth_link()
+                .add(&mut rng, &clock, &old, subject.to_owned(), None)
+                .await
+                .unwrap();
+        }
+
+        // Bob already has a link on the new provider
+        repo.upstream_oauth_link()
+            .add(&mut rng, &clock, &new, "bob".to_owned(), None)
+            .await
+            .unwrap();
+
+        let moved = repo
+            .upstream_oauth_link()
+            .remap_provider(&old, &new)
+            .await
+            .unwrap();
+        assert_eq!(moved, 2);
+
+        let link = repo
+            .upstream_oauth_link()
+            .find_by_subject(&new, "alice")
+            .await
+            .unwrap()
+            .expect("link to be moved to the new provider");
+        assert_eq!(link.provider_id, new.id);
+
+        // The conflicting link stays on the old provider
+        let filter = UpstreamOAuthLinkFilter::new().for_provider(&old);
+        assert_eq!(repo.upstream_oauth_link().count(filter).await.unwrap(), 1);
+        let filter = UpstreamOAuthLinkFilter::new().for_provider(&new);
+        assert_eq!(repo.upstream_oauth_link().count(filter).await.unwrap(), 3);
+
+        // Remapping again is a no-op
+        let moved = repo
+            .upstream_oauth_link()
+            .remap_provider(&old, &new)
+            .await
+            .unwrap();
+        assert_eq!(moved, 0);
+    }
+
     /// Test that the pagination works as expected in the upstream OAuth
     /// provider repository
     #[sqlx::test(migrator = "crate::MIGRATOR")]
@@ -8,13 +8,14 @@
 //! repositories
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use mas_data_model::User;
 use mas_storage::{
     Clock,
     user::{UserFilter, UserRepository},
 };
 use rand::RngCore;
-use sea_query::{Expr, PostgresQueryBuilder, Query};
+use sea_query::{Expr, Func, Iden, IntoIden, PostgresQueryBuilder, Query, SimpleExpr};
 use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
@@ -23,7 +24,7 @@ use uuid::Uuid;
 use crate::{
     DatabaseError,
     filter::{Filter, StatementExt},
-    iden::Users,
+    iden::{CompatSessions, OAuth2Sessions, UpstreamOAuthLinks, UserSessions, Users},
     pagination::QueryBuilderExt,
     tracing::ExecuteExt,
 };
@@ -96,6 +97,38 @@ impl From<UserLookup> for User {
     }
 }
 
+/// Builds an expression matching the users with a session in the given table,
+/// which matches the given condition.
+fn has_session<T>(table: T, user_id: T, condition: SimpleExpr) -> SimpleExpr
+where
+    T: Iden + 'static,
+{
+    let table = table.into_iden();
+    Expr::exists(
+        Query::select()
+            .expr(Expr::cust("1"))
+            .from(table.clone())
+            .and_where(Expr::col((table, user_id)).equals((Users::Table, Users::UserId)))
+            .and_where(condition)
+            .take(),
+    )
+}
+
+/// Builds a condition on a session table matching the sessions active since
+/// the given time, counting the sessions which never recorded any activity as
+/// active when they were created.
+fn active_since<T>(table: T, last_active_at: T, created_at: T, since: DateTime<Utc>) -> SimpleExpr
+where
+    T: Iden + 'static,
+{
+    let table = table.into_iden();
+    Expr::expr(Func::coalesce([
+        Expr::col((table.clone(), last_active_at)).into(),
+        Expr::col((table, created_at)).into(),
+    ]))
+    .gte(since)
+}
+
 impl Filter for UserFilter<'_> {
     fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
         sea_query::Condition::all()
@@ -117,6 +150,108 @@ impl Filter for UserFilter<'_> {
             .add_option(self.can_request_admin().map(|can_request_admin| {
                 Expr::col((Users::Table, Users::CanRequestAdmin)).eq(can_request_admin)
             }))
+            .add_option(
+                // Both the provider and the subject have to match the same link
+                (self.upstream_oauth_provider().is_some()
+                    || self.upstream_oauth_subject().is_some())
+                .then(|| {
+                    let link_condition = sea_query::Condition::all()
+                        .add_option(self.upstream_oauth_provider().map(|provider| {
+                            Expr::col((
+                                UpstreamOAuthLinks::Table,
+                                UpstreamOAuthLinks::UpstreamOAuthProviderId,
+                            ))
+                            .eq(Uuid::from(provider.id))
+                        }))
+                        .add_option(self.upstream_oauth_subject().map(|subject| {
+                            Expr::col((UpstreamOAuthLinks::Table, UpstreamOAuthLinks::Subject))
+                                .eq(subject)
+                        }));
+
+                    Expr::col((Users::Table, Users::UserId)).in_subquery(
+                        Query::select()
+                            .expr(Expr::col((
+                                UpstreamOAuthLinks::Table,
+                                UpstreamOAuthLinks::UserId,
+                            )))
+                            .from(UpstreamOAuthLinks::Table)
+                            .cond_where(link_condition)
+                            .take(),
+                    )
+                }),
+            )
+            .add_option(self.last_active_before().map(|before| {
+                sea_query::Condition::all()
+                    .add(Expr::col((Users::Table, Users::CreatedAt)).lt(before))
+                    .add(
+                        has_session(
+                            CompatSessions::Table,
+                            CompatSessions::UserId,
+                            active_since(
+                                CompatSessions::Table,
+                                CompatSessions::LastActiveAt,
+                                CompatSessions::CreatedAt,
+                                before,
+                            ),
+                        )
+                        .not(),
+                    )
+                    .add(
+                        has_session(
+                            OAuth2Sessions::Table,
+                            OAuth2Sessions::UserId,
+                            active_since(
+                                OAuth2Sessions::Table,
+                                OAuth2Sessions::LastActiveAt,
+                                OAuth2Sessions::CreatedAt,
+                                before,
+                            ),
+                        )
+                        .not(),
+                    )
+                    .add(
+                        has_session(
+                            UserSessions::Table,
+                            UserSessions::UserId,
+                            active_since(
+                                UserSessions::Table,
+                                UserSessions::LastActiveAt,
+                                UserSessions::CreatedAt,
+                                before,
+                            ),
+                        )
+                        .not(),
+                    )
+            }))
+            .add_option(self.without_active_sessions().then(|| {
+                sea_query::Condition::all()
+                    .add(
+                        has_session(
+                            CompatSessions::Table,
+                            CompatSessions::UserId,
+                            Expr::col((CompatSessions::Table, CompatSessions::FinishedAt))
+                                .is_null(),
+                        )
+                        .not(),
+                    )
+                    .add(
+                        has_session(
+                            OAuth2Sessions::Table,
+                            OAuth2Sessions::UserId,
+                            Expr::col((OAuth2Sessions::Table, OAuth2Sessions::FinishedAt))
+                                .is_null(),
+                        )
+                        .not(),
+                    )
+                    .add(
+                        has_session(
+                            UserSessions::Table,
+                            UserSessions::UserId,
+                            Expr::col((UserSessions::Table, UserSessions::FinishedAt)).is_null(),
+                        )
+                        .not(),
+                    )
+            }))
     }
 }
 
@@ -315,6 +450,34 @@ impl UserRepository for PgUserRepository<'_> {
         Ok(user)
     }
 
+    #[tracing::instrument(
+        name = "db.user.lock_bulk",
+        skip_all,
+        fields(db.query.text),
+        err,
+    )]
+    async fn lock_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: UserFilter<'_>,
+    ) -> Result<usize, Self::Error> {
+        let locked_at = clock.now();
+        let (sql, arguments) = Query::update()
+            .table(Users::Table)
+            .value(Users::LockedAt, locked_at)
+            .apply_filter(filter)
+            // Keep the time at which the already locked users were locked
+            .and_where(Expr::col((Users::Table, Users::LockedAt)).is_null())
+            .build_sqlx(PostgresQueryBuilder);
+
+        let res = sqlx::query_with(&sql, arguments)
+            .traced()
+            .execute(&mut *self.conn)
+            .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.user.unlock",
         skip_all,
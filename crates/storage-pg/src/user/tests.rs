@@ -219,6 +219,95 @@ async fn test_user_repo(pool: PgPool) {
     repo.save().await.unwrap();
 }
 
+/// Test [`UserRepository::lock_bulk`] with the session-based filters
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_repo_lock_bulk(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    // Alice never had a session
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+
+    // Bob has a session which he used a while ago, then finished
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob".to_owned())
+        .await
+        .unwrap();
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &bob, None)
+        .await
+        .unwrap();
+    repo.browser_session()
+        .finish(&clock, session)
+        .await
+        .unwrap();
+
+    clock.advance(Duration::try_days(30).unwrap());
+    let cutoff = clock.now();
+    clock.advance(Duration::try_days(30).unwrap());
+
+    // Charlie has a recent session, still active
+    let charlie = repo
+        .user()
+        .add(&mut rng, &clock, "charlie".to_owned())
+        .await
+        .unwrap();
+    repo.browser_session()
+        .add(&mut rng, &clock, &charlie, None)
+        .await
+        .unwrap();
+
+    // Dave was created after the cutoff, and never had a session
+    let dave = repo
+        .user()
+        .add(&mut rng, &clock, "dave".to_owned())
+        .await
+        .unwrap();
+
+    let all = UserFilter::new();
+    let inactive = all.with_last_active_before(cutoff);
+    let without_sessions = all.without_active_sessions_only();
+
+    assert_eq!(repo.user().count(inactive).await.unwrap(), 2);
+    assert_eq!(repo.user().count(without_sessions).await.unwrap(), 3);
+    assert_eq!(
+        repo.user()
+            .count(without_sessions.with_last_active_before(cutoff))
+            .await
+            .unwrap(),
+        2
+    );
+
+    // Lock the users inactive since the cutoff
+    let locked = repo.user().lock_bulk(&clock, inactive).await.unwrap();
+    assert_eq!(locked, 2);
+
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert!(!alice.is_valid());
+    let bob = repo.user().lookup(bob.id).await.unwrap().unwrap();
+    assert!(!bob.is_valid());
+    let charlie = repo.user().lookup(charlie.id).await.unwrap().unwrap();
+    assert!(charlie.is_valid());
+    let dave = repo.user().lookup(dave.id).await.unwrap().unwrap();
+    assert!(dave.is_valid());
+
+    // Locking again doesn't touch the already locked users
+    clock.advance(Duration::try_minutes(1).unwrap());
+    let locked = repo.user().lock_bulk(&clock, inactive).await.unwrap();
+    assert_eq!(locked, 0);
+    let alice_again = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert_eq!(alice_again.locked_at, alice.locked_at);
+
+    repo.save().await.unwrap();
+}
+
 /// Test [`UserRepository::find_by_username`] with different casings.
 #[sqlx::test(migrator = "crate::MIGRATOR")]
 async fn test_user_repo_find_by_username(pool: PgPool) {
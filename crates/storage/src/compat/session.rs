@@ -65,6 +65,7 @@ pub struct CompatSessionFilter<'a> {
     device: Option<&'a Device>,
     last_active_before: Option<DateTime<Utc>>,
     last_active_after: Option<DateTime<Utc>>,
+    orphaned: bool,
 }
 
 impl<'a> CompatSessionFilter<'a> {
@@ -198,6 +199,24 @@ impl<'a> CompatSessionFilter<'a> {
     pub fn auth_type(&self) -> Option<CompatSessionType> {
         self.auth_type
     }
+
+    /// Only return orphaned compatibility sessions, which have no device and
+    /// no access token, and therefore can't be used.
+    ///
+    /// MAS never creates those by itself: sessions without a device are only
+    /// imported from Synapse along with their access token, and are left
+    /// without one if the token is removed from the database by other means.
+    #[must_use]
+    pub fn orphaned_only(mut self) -> Self {
+        self.orphaned = true;
+        self
+    }
+
+    /// Whether to only return orphaned compatibility sessions
+    #[must_use]
+    pub fn orphaned(&self) -> bool {
+        self.orphaned
+    }
 }
 
 /// A [`CompatSessionRepository`] helps interacting with
@@ -315,6 +334,16 @@ pub trait CompatSessionRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn count(&mut self, filter: CompatSessionFilter<'_>) -> Result<usize, Self::Error>;
 
+    /// Delete the orphaned [`CompatSession`]s, as matched by
+    /// [`CompatSessionFilter::orphaned_only`]
+    ///
+    /// Returns the number of deleted sessions
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn remove_orphaned(&mut self) -> Result<usize, Self::Error>;
+
     /// Record a batch of [`CompatSession`] activity
     ///
     /// # Parameters
@@ -397,6 +426,8 @@ repository_impl!(CompatSessionRepository:
 
     async fn count(&mut self, filter: CompatSessionFilter<'_>) -> Result<usize, Self::Error>;
 
+    async fn remove_orphaned(&mut self) -> Result<usize, Self::Error>;
+
     async fn record_batch_activity(
         &mut self,
         activity: Vec<(Ulid, DateTime<Utc>, Option<IpAddr>)>,
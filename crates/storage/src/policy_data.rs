@@ -6,10 +6,71 @@
 //! Repositories to interact with the policy data saved in the storage backend.
 
 use async_trait::async_trait;
-use mas_data_model::PolicyData;
+use chrono::{DateTime, Utc};
+use mas_data_model::{PolicyData, Session};
 use rand_core::RngCore;
+use ulid::Ulid;
 
-use crate::{Clock, repository_impl};
+use crate::{Clock, Page, Pagination, repository_impl};
+
+/// Filter parameters for listing [`PolicyData`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct PolicyDataFilter {
+    latest_only: bool,
+    created_by_client_id: Option<Ulid>,
+    created_after: Option<DateTime<Utc>>,
+}
+
+impl PolicyDataFilter {
+    /// Create a new [`PolicyDataFilter`] with default values
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return only the latest policy data
+    #[must_use]
+    pub const fn latest_only(mut self) -> Self {
+        self.latest_only = true;
+        self
+    }
+
+    /// Whether only the latest policy data should be returned
+    #[must_use]
+    pub const fn is_latest_only(&self) -> bool {
+        self.latest_only
+    }
+
+    /// Only return the policy data set by the given OAuth 2.0 client
+    #[must_use]
+    pub const fn for_client_id(mut self, client_id: Ulid) -> Self {
+        self.created_by_client_id = Some(client_id);
+        self
+    }
+
+    /// Get the OAuth 2.0 client ID filter
+    ///
+    /// Returns [`None`] if no client filter was set
+    #[must_use]
+    pub const fn client_id(&self) -> Option<Ulid> {
+        self.created_by_client_id
+    }
+
+    /// Only return the policy data set after the given time
+    #[must_use]
+    pub const fn with_created_after(mut self, created_after: DateTime<Utc>) -> Self {
+        self.created_after = Some(created_after);
+        self
+    }
+
+    /// Get the created after filter
+    ///
+    /// Returns [`None`] if no created after filter was set
+    #[must_use]
+    pub const fn created_after(&self) -> Option<DateTime<Utc>> {
+        self.created_after
+    }
+}
 
 /// A [`PolicyDataRepository`] helps interacting with the policy data saved in
 /// the storage backend.
@@ -28,6 +89,19 @@ pub trait PolicyDataRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn get(&mut self) -> Result<Option<PolicyData>, Self::Error>;
 
+    /// Lookup a [`PolicyData`] by its ID
+    ///
+    /// Returns `None` if no [`PolicyData`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`PolicyData`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<PolicyData>, Self::Error>;
+
     /// Set the latest policy data
     ///
     /// Returns the newly created policy data.
@@ -36,6 +110,7 @@ pub trait PolicyDataRepository: Send + Sync {
     ///
     /// * `rng`: The random number generator to use
     /// * `clock`: The clock used to generate the timestamps
+    /// * `created_by`: The session which is setting the policy data, if any
     /// * `data`: The policy data to set
     ///
     /// # Errors
@@ -45,6 +120,7 @@ pub trait PolicyDataRepository: Send + Sync {
         &mut self,
         rng: &mut (dyn RngCore + Send),
         clock: &dyn Clock,
+        created_by: Option<&Session>,
         data: serde_json::Value,
     ) -> Result<PolicyData, Self::Error>;
 
@@ -60,17 +136,69 @@ pub trait PolicyDataRepository: Send + Sync {
     ///
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn prune(&mut self, keep: usize) -> Result<usize, Self::Error>;
+
+    /// List [`PolicyData`] with the given filter and pagination
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(
+        &mut self,
+        filter: PolicyDataFilter,
+        pagination: Pagination,
+    ) -> Result<Page<PolicyData>, Self::Error>;
+
+    /// Count the [`PolicyData`] with the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self, filter: PolicyDataFilter) -> Result<usize, Self::Error>;
+
+    /// Acquire a lock on the policy data, to make sure concurrent updates
+    /// happen in a sequential way. The lock is released when the repository
+    /// is saved or rolled back.
+    ///
+    /// This should be called before checking the current policy data when
+    /// setting it conditionally.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn acquire_lock(&mut self) -> Result<(), Self::Error>;
 }
 
 repository_impl!(PolicyDataRepository:
     async fn get(&mut self) -> Result<Option<PolicyData>, Self::Error>;
 
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<PolicyData>, Self::Error>;
+
     async fn set(
         &mut self,
         rng: &mut (dyn RngCore + Send),
         clock: &dyn Clock,
+        created_by: Option<&Session>,
         data: serde_json::Value,
     ) -> Result<PolicyData, Self::Error>;
 
     async fn prune(&mut self, keep: usize) -> Result<usize, Self::Error>;
+
+    async fn list(
+        &mut self,
+        filter: PolicyDataFilter,
+        pagination: Pagination,
+    ) -> Result<Page<PolicyData>, Self::Error>;
+
+    async fn count(&mut self, filter: PolicyDataFilter) -> Result<usize, Self::Error>;
+
+    async fn acquire_lock(&mut self) -> Result<(), Self::Error>;
 );
@@ -216,6 +216,27 @@ pub trait UpstreamOAuthLinkRepository: Send + Sync {
         clock: &dyn Clock,
         upstream_oauth_link: UpstreamOAuthLink,
     ) -> Result<(), Self::Error>;
+
+    /// Move all the [`UpstreamOAuthLink`] of a provider to another provider
+    ///
+    /// Links whose subject already exists on the target provider are left
+    /// untouched on the source provider.
+    ///
+    /// Returns the number of links which were moved
+    ///
+    /// # Parameters
+    ///
+    /// * `from`: The upstream OAuth provider to move the links from
+    /// * `to`: The upstream OAuth provider to move the links to
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn remap_provider(
+        &mut self,
+        from: &UpstreamOAuthProvider,
+        to: &UpstreamOAuthProvider,
+    ) -> Result<usize, Self::Error>;
 }
 
 repository_impl!(UpstreamOAuthLinkRepository:
@@ -251,4 +272,10 @@ repository_impl!(UpstreamOAuthLinkRepository:
     async fn count(&mut self, filter: UpstreamOAuthLinkFilter<'_>) -> Result<usize, Self::Error>;
 
     async fn remove(&mut self, clock: &dyn Clock, upstream_oauth_link: UpstreamOAuthLink) -> Result<(), Self::Error>;
+
+    async fn remap_provider(
+        &mut self,
+        from: &UpstreamOAuthProvider,
+        to: &UpstreamOAuthProvider,
+    ) -> Result<usize, Self::Error>;
 );
@@ -7,7 +7,8 @@
 //! Repositories to interact with entities related to user accounts
 
 use async_trait::async_trait;
-use mas_data_model::User;
+use chrono::{DateTime, Utc};
+use mas_data_model::{UpstreamOAuthProvider, User};
 use rand_core::RngCore;
 use ulid::Ulid;
 
@@ -75,10 +76,13 @@ impl UserState {
 pub struct UserFilter<'a> {
     state: Option<UserState>,
     can_request_admin: Option<bool>,
-    _phantom: std::marker::PhantomData<&'a ()>,
+    upstream_oauth_provider: Option<&'a UpstreamOAuthProvider>,
+    upstream_oauth_subject: Option<&'a str>,
+    last_active_before: Option<DateTime<Utc>>,
+    without_active_sessions: bool,
 }
 
-impl UserFilter<'_> {
+impl<'a> UserFilter<'a> {
     /// Create a new [`UserFilter`] with default values
     #[must_use]
     pub fn new() -> Self {
@@ -120,6 +124,41 @@ impl UserFilter<'_> {
         self
     }
 
+    /// Filter for users linked to the given upstream OAuth provider
+    ///
+    /// Combined with [`Self::with_upstream_oauth_subject`], the provider and
+    /// the subject have to match the same link.
+    #[must_use]
+    pub fn with_upstream_oauth_provider(mut self, provider: &'a UpstreamOAuthProvider) -> Self {
+        self.upstream_oauth_provider = Some(provider);
+        self
+    }
+
+    /// Filter for users linked to an upstream account with the given subject
+    #[must_use]
+    pub fn with_upstream_oauth_subject(mut self, subject: &'a str) -> Self {
+        self.upstream_oauth_subject = Some(subject);
+        self
+    }
+
+    /// Filter for users created before the given time, with no session,
+    /// finished or not, active since then
+    ///
+    /// The sessions which never recorded any activity count as active when
+    /// they were created.
+    #[must_use]
+    pub fn with_last_active_before(mut self, last_active_before: DateTime<Utc>) -> Self {
+        self.last_active_before = Some(last_active_before);
+        self
+    }
+
+    /// Filter for users with no unfinished session
+    #[must_use]
+    pub fn without_active_sessions_only(mut self) -> Self {
+        self.without_active_sessions = true;
+        self
+    }
+
     /// Get the state filter
     ///
     /// Returns [`None`] if no state filter was set
@@ -135,6 +174,36 @@ impl UserFilter<'_> {
     pub fn can_request_admin(&self) -> Option<bool> {
         self.can_request_admin
     }
+
+    /// Get the upstream OAuth provider filter
+    ///
+    /// Returns [`None`] if no upstream OAuth provider filter was set
+    #[must_use]
+    pub fn upstream_oauth_provider(&self) -> Option<&UpstreamOAuthProvider> {
+        self.upstream_oauth_provider
+    }
+
+    /// Get the upstream OAuth subject filter
+    ///
+    /// Returns [`None`] if no upstream OAuth subject filter was set
+    #[must_use]
+    pub fn upstream_oauth_subject(&self) -> Option<&str> {
+        self.upstream_oauth_subject
+    }
+
+    /// Get the last activity filter
+    ///
+    /// Returns [`None`] if no last activity filter was set
+    #[must_use]
+    pub fn last_active_before(&self) -> Option<DateTime<Utc>> {
+        self.last_active_before
+    }
+
+    /// Whether to only match users with no unfinished session
+    #[must_use]
+    pub fn without_active_sessions(&self) -> bool {
+        self.without_active_sessions
+    }
 }
 
 /// A [`UserRepository`] helps interacting with [`User`] saved in the storage
@@ -217,6 +286,25 @@ pub trait UserRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn lock(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
 
+    /// Lock all the [`User`]s matching the given filter, which aren't locked
+    /// already
+    ///
+    /// Returns the number of users locked
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `filter`: The filter to apply
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lock_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: UserFilter<'_>,
+    ) -> Result<usize, Self::Error>;
+
     /// Unlock a [`User`]
     ///
     /// Returns the unlocked [`User`]
@@ -326,6 +414,11 @@ repository_impl!(UserRepository:
     ) -> Result<User, Self::Error>;
     async fn exists(&mut self, username: &str) -> Result<bool, Self::Error>;
     async fn lock(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
+    async fn lock_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: UserFilter<'_>,
+    ) -> Result<usize, Self::Error>;
     async fn unlock(&mut self, user: User) -> Result<User, Self::Error>;
     async fn deactivate(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
     async fn reactivate(&mut self, user: User) -> Result<User, Self::Error>;
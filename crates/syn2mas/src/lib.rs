@@ -12,13 +12,27 @@ mod telemetry;
 
 type RandomState = rustc_hash::FxBuildHasher;
 type HashMap<K, V> = rustc_hash::FxHashMap<K, V>;
+type HashSet<T> = rustc_hash::FxHashSet<T>;
 
 pub use self::{
-    mas_writer::{MasWriter, checks::mas_pre_migration_checks, locking::LockedMasDatabase},
-    migration::migrate,
-    progress::{Progress, ProgressCounter, ProgressStage},
+    mas_writer::{
+        CopyFormat, InvalidCopyFormat, InvalidWriteMode, MasExistingUser, MasWriter, OrphanedRows,
+        WriteMode, checks::mas_pre_migration_checks, locking::LockedMasDatabase,
+    },
+    migration::{
+        DuplicateEmailPolicy, Error as MigrationError, FailureMode, FallbackTimestampPolicy,
+        InvalidDuplicateEmailPolicy, InvalidFallbackTimestampPolicy, InvalidInvalidIpPolicy,
+        InvalidInvalidUsernamePolicy, InvalidIpAnonymization, InvalidIpPolicy,
+        InvalidMissingUserPolicy, InvalidPhase, InvalidPhaseTimeout, InvalidPuppetTokenPolicy,
+        InvalidShadowBannedPolicy, InvalidUnknownPasswordSchemePolicy,
+        InvalidUnknownProviderPolicy, InvalidUserAuthFilter, InvalidUsernamePolicy,
+        IpAnonymization, MigrationOptions, MigrationReport, MigrationRowError, MissingUserPolicy,
+        Phase, PhaseTimeout, PuppetTokenPolicy, ShadowBannedPolicy, UnknownPasswordSchemePolicy,
+        UnknownProviderPolicy, UserAuthFilter, UserMapBacking, migrate,
+    },
+    progress::{EntityType, MigrationProgress, Progress, ProgressCounter, ProgressStage},
     synapse_reader::{
-        SynapseReader,
+        CountMode, InvalidCountMode, SynapseConnection, SynapseReader,
         checks::{
             synapse_config_check, synapse_config_check_against_mas_config, synapse_database_check,
         },
@@ -37,7 +37,9 @@ pub enum Error {
 
 /// Check that a MAS database is ready for being migrated to.
 ///
-/// Concretely, this checks that the database is empty.
+/// Concretely, this checks that the database is empty, unless
+/// `allow_existing_data` is set, in which case it only checks that the
+/// database looks like a MAS database.
 ///
 /// If syn2mas is already in progress on this database, the checks are skipped.
 ///
@@ -46,11 +48,15 @@ pub enum Error {
 /// Errors are returned under the following circumstances:
 ///
 /// - If any database access error occurs.
-/// - If any MAS tables involved in the migration are not empty.
+/// - If any MAS tables involved in the migration are not empty, and
+///   `allow_existing_data` is not set.
 /// - If we can't check whether syn2mas is already in progress on this database
 ///   or not.
 #[tracing::instrument(name = "syn2mas.mas_pre_migration_checks", skip_all)]
-pub async fn mas_pre_migration_checks(mas_connection: &mut LockedMasDatabase) -> Result<(), Error> {
+pub async fn mas_pre_migration_checks(
+    mas_connection: &mut LockedMasDatabase,
+    allow_existing_data: bool,
+) -> Result<(), Error> {
     if is_syn2mas_in_progress(mas_connection.as_mut())
         .await
         .map_err(Error::UnableToCheckInProgress)?
@@ -72,7 +78,7 @@ pub async fn mas_pre_migration_checks(mas_connection: &mut LockedMasDatabase) ->
             .into_maybe_not_mas(table)?
             .is_some();
 
-        if row_present {
+        if row_present && !allow_existing_data {
             return Err(Error::MasDatabaseNotEmpty { table });
         }
     }
@@ -0,0 +1,240 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Encoding of rows for the Postgres `COPY FROM STDIN` statement, used to
+//! write the high-volume tables.
+//!
+//! See <https://www.postgresql.org/docs/current/sql-copy.html#id-1.9.3.55.9> for
+//! the details of both formats.
+
+use std::{net::IpAddr, str::FromStr};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgConnection;
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::IntoDatabase as _;
+
+/// The format of the rows sent with `COPY`, see
+/// [`MasWriter::set_copy_format`](super::MasWriter::set_copy_format).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    /// Tab-separated text, with the values escaped
+    Text,
+
+    /// The binary format, which Postgres parses faster, and which needs no
+    /// escaping
+    Binary,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid copy format {0:?}, expected `text` or `binary`")]
+pub struct InvalidCopyFormat(String);
+
+impl FromStr for CopyFormat {
+    type Err = InvalidCopyFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "binary" => Ok(Self::Binary),
+            _ => Err(InvalidCopyFormat(s.to_owned())),
+        }
+    }
+}
+
+/// The signature, flags and header extension length starting binary `COPY`
+/// data.
+const BINARY_HEADER: &[u8] = b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0";
+
+/// Microseconds between the Unix epoch and the Postgres epoch, 2000-01-01.
+const POSTGRES_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
+/// The address families of `INET` values in the binary format.
+const PGSQL_AF_INET: u8 = 2;
+const PGSQL_AF_INET6: u8 = 3;
+
+/// Encodes rows in a [`CopyFormat`].
+pub(super) struct CopyEncoder {
+    format: CopyFormat,
+    buf: Vec<u8>,
+    /// Whether the next value is the first of its row, in the text format
+    row_start: bool,
+}
+
+impl CopyEncoder {
+    pub(super) fn new(format: CopyFormat) -> Self {
+        let mut buf = Vec::new();
+        if format == CopyFormat::Binary {
+            buf.extend_from_slice(BINARY_HEADER);
+        }
+
+        Self {
+            format,
+            buf,
+            row_start: true,
+        }
+    }
+
+    /// Starts a row with the given number of columns.
+    pub(super) fn start_row(&mut self, columns: i16) {
+        match self.format {
+            CopyFormat::Text => self.row_start = true,
+            CopyFormat::Binary => self.buf.extend_from_slice(&columns.to_be_bytes()),
+        }
+    }
+
+    /// Ends the current row.
+    pub(super) fn end_row(&mut self) {
+        if self.format == CopyFormat::Text {
+            self.buf.push(b'\n');
+        }
+    }
+
+    /// Writes a single value, given its text and binary encodings, or `NULL`.
+    fn value(&mut self, text: Option<&str>, binary: impl FnOnce(&mut Vec<u8>)) {
+        match self.format {
+            CopyFormat::Text => {
+                if !self.row_start {
+                    self.buf.push(b'\t');
+                }
+                self.row_start = false;
+
+                let Some(text) = text else {
+                    self.buf.extend_from_slice(b"\\N");
+                    return;
+                };
+
+                for byte in text.bytes() {
+                    match byte {
+                        b'\\' => self.buf.extend_from_slice(b"\\\\"),
+                        b'\n' => self.buf.extend_from_slice(b"\\n"),
+                        b'\r' => self.buf.extend_from_slice(b"\\r"),
+                        b'\t' => self.buf.extend_from_slice(b"\\t"),
+                        byte => self.buf.push(byte),
+                    }
+                }
+            }
+
+            CopyFormat::Binary => {
+                if text.is_none() {
+                    self.buf.extend_from_slice(&(-1_i32).to_be_bytes());
+                    return;
+                }
+
+                // Write the value after a placeholder for its length
+                let length_at = self.buf.len();
+                self.buf.extend_from_slice(&[0; 4]);
+                binary(&mut self.buf);
+                let length = i32::try_from(self.buf.len() - length_at - 4)
+                    .expect("values should be shorter than 2 GiB");
+                self.buf[length_at..length_at + 4].copy_from_slice(&length.to_be_bytes());
+            }
+        }
+    }
+
+    pub(super) fn uuid(&mut self, value: Uuid) {
+        self.value(Some(&value.to_string()), |buf| {
+            buf.extend_from_slice(value.as_bytes());
+        });
+    }
+
+    pub(super) fn text(&mut self, value: Option<&str>) {
+        self.value(value, |buf| {
+            buf.extend_from_slice(value.unwrap_or_default().as_bytes());
+        });
+    }
+
+    pub(super) fn bool(&mut self, value: bool) {
+        self.value(Some(if value { "t" } else { "f" }), |buf| {
+            buf.push(u8::from(value));
+        });
+    }
+
+    /// Writes a `TIMESTAMP WITH TIME ZONE`, truncated to the microsecond like
+    /// Postgres stores them.
+    pub(super) fn timestamp(&mut self, value: Option<DateTime<Utc>>) {
+        let text = value.map(|value| value.to_rfc3339_opts(SecondsFormat::Micros, true));
+        self.value(text.as_deref(), |buf| {
+            let micros = value.unwrap_or_default().timestamp_micros() - POSTGRES_EPOCH_MICROS;
+            buf.extend_from_slice(&micros.to_be_bytes());
+        });
+    }
+
+    pub(super) fn inet(&mut self, value: Option<IpAddr>) {
+        let text = value.map(|value| value.to_string());
+        self.value(text.as_deref(), |buf| match value {
+            Some(IpAddr::V4(address)) => {
+                buf.extend_from_slice(&[PGSQL_AF_INET, 32, 0, 4]);
+                buf.extend_from_slice(&address.octets());
+            }
+            Some(IpAddr::V6(address)) => {
+                buf.extend_from_slice(&[PGSQL_AF_INET6, 128, 0, 16]);
+                buf.extend_from_slice(&address.octets());
+            }
+            None => {}
+        });
+    }
+
+    /// Finishes the data, returning the bytes to send.
+    pub(super) fn finish(mut self) -> Vec<u8> {
+        if self.format == CopyFormat::Binary {
+            self.buf.extend_from_slice(&(-1_i16).to_be_bytes());
+        }
+        self.buf
+    }
+}
+
+/// Sends the rows encoded by a [`CopyEncoder`] into the given columns of a
+/// table.
+pub(super) async fn copy_in(
+    conn: &mut PgConnection,
+    table: &str,
+    columns: &[&str],
+    encoder: CopyEncoder,
+) -> Result<(), super::Error> {
+    let format = match encoder.format {
+        CopyFormat::Text => "text",
+        CopyFormat::Binary => "binary",
+    };
+    let statement = format!(
+        "COPY {table} ({}) FROM STDIN WITH (FORMAT {format})",
+        columns.join(", ")
+    );
+
+    let mut copy = conn
+        .copy_in_raw(&statement)
+        .await
+        .into_database_with(|| format!("starting to copy into {table}"))?;
+    copy.send(encoder.finish())
+        .await
+        .into_database_with(|| format!("copying into {table}"))?;
+    copy.finish()
+        .await
+        .into_database_with(|| format!("finishing to copy into {table}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CopyEncoder, CopyFormat};
+
+    #[test]
+    fn test_text_escaping() {
+        let mut encoder = CopyEncoder::new(CopyFormat::Text);
+        encoder.start_row(3);
+        encoder.text(Some("tab\there\\ and\nnewline"));
+        encoder.text(None);
+        encoder.bool(true);
+        encoder.end_row();
+
+        assert_eq!(
+            encoder.finish(),
+            b"tab\\there\\\\ and\\nnewline\t\\N\tt\n".to_vec()
+        );
+    }
+}
@@ -0,0 +1,17 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+INSERT INTO users
+  (
+    user_id,
+    username,
+    created_at
+  )
+  VALUES
+  (
+    '00000000-0000-0000-0000-000000000001',
+    'alice',
+    '2011-12-13 14:15:16Z'
+  );
@@ -9,24 +9,36 @@
 
 use std::{
     fmt::Display,
+    fs::File,
+    io::{BufWriter, Write},
     net::IpAddr,
+    num::{NonZeroU32, NonZeroUsize},
+    str::FromStr,
     sync::{
         Arc,
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
     },
+    time::Duration,
 };
 
+use camino::{Utf8Path, Utf8PathBuf};
 use chrono::{DateTime, Utc};
 use futures_util::{FutureExt, TryStreamExt, future::BoxFuture};
-use sqlx::{Executor, PgConnection, query, query_as};
+use serde::Serialize;
+use sqlx::{Executor, PgConnection, query, query_as, query_scalar};
 use thiserror::Error;
 use thiserror_ext::{Construct, ContextInto};
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::{
+    Mutex, Semaphore,
+    mpsc::{self, Receiver, Sender},
+};
 use tracing::{Instrument, error, info, warn};
 use uuid::{NonNilUuid, Uuid};
 
+pub use self::copy::{CopyFormat, InvalidCopyFormat};
 use self::{
     constraint_pausing::{ConstraintDescription, IndexDescription},
+    copy::{CopyEncoder, copy_in},
     locking::LockedMasDatabase,
 };
 use crate::Progress;
@@ -35,6 +47,7 @@ pub mod checks;
 pub mod locking;
 
 mod constraint_pausing;
+mod copy;
 
 #[derive(Debug, Error, Construct, ContextInto)]
 pub enum Error {
@@ -55,10 +68,47 @@ pub enum Error {
     #[error("bug in syn2mas: write buffers not finished")]
     WriteBuffersNotFinished,
 
+    #[error("bug in syn2mas: rows written after the writes were committed")]
+    WritesCommitted,
+
+    #[error("the MAS database is not empty: found {count} rows in `{table}`")]
+    NotEmpty { table: &'static str, count: i64 },
+
+    #[error(
+        "a previous migration didn't finish, and the MAS database already had data before it \
+        started, which can't be told apart from the migrated rows: either restore the MAS \
+        database from a backup, or abandon the unfinished migration while keeping its rows, \
+        before migrating again"
+    )]
+    ResumeWithExistingData,
+
+    #[error("failed to export rows to {path}")]
+    Export {
+        #[source]
+        source: std::io::Error,
+        path: Utf8PathBuf,
+    },
+
     #[error("{0}")]
     Multiple(MultipleErrors),
 }
 
+impl Error {
+    /// Whether this is a serialization failure or a deadlock detected by
+    /// Postgres, after which the write can be retried.
+    fn is_transient(&self) -> bool {
+        let Self::Database {
+            source: sqlx::Error::Database(error),
+            ..
+        } = self
+        else {
+            return false;
+        };
+
+        matches!(error.code().as_deref(), Some("40001" | "40P01"))
+    }
+}
+
 #[derive(Debug)]
 pub struct MultipleErrors {
     errors: Vec<Error>,
@@ -84,9 +134,18 @@ struct WriterConnectionPool {
     /// How many connections are in circulation
     num_connections: usize,
 
+    /// How many batches can be written or waiting for a connection at once
+    max_in_flight_batches: u32,
+
+    /// Limits the batches in flight to `max_in_flight_batches`
+    in_flight_batches: Arc<Semaphore>,
+
+    /// Set once a batch failed to be written, so that no more are started
+    failed: Arc<AtomicBool>,
+
     /// A receiver handle to get a writer connection
     /// The writer connection will be mid-transaction!
-    connection_rx: Receiver<Result<PgConnection, Error>>,
+    connection_rx: Arc<Mutex<Receiver<Result<PgConnection, Error>>>>,
 
     /// A sender handle to return a writer connection to the pool
     /// The connection should still be mid-transaction!
@@ -103,51 +162,90 @@ impl WriterConnectionPool {
                 .expect("there should be room for this connection");
         }
 
+        // By default, a batch is only started once a connection is free to write it
+        let max_in_flight_batches =
+            u32::try_from(num_connections).expect("too many writer connections");
+
         WriterConnectionPool {
             num_connections,
-            connection_rx,
+            max_in_flight_batches,
+            in_flight_batches: Arc::new(Semaphore::new(num_connections)),
+            failed: Arc::default(),
+            connection_rx: Arc::new(Mutex::new(connection_rx)),
             connection_tx,
         }
     }
 
+    /// Sets how many batches can be written or waiting for a connection at
+    /// once. This must be called before any batch is written.
+    pub fn set_max_in_flight_batches(&mut self, max_in_flight_batches: NonZeroU32) {
+        debug_assert_eq!(
+            self.in_flight_batches.available_permits(),
+            self.max_in_flight_batches as usize,
+            "batches were written before setting the maximum number of batches in flight"
+        );
+        self.max_in_flight_batches = max_in_flight_batches.get();
+        self.in_flight_batches = Arc::new(Semaphore::new(max_in_flight_batches.get() as usize));
+    }
+
+    /// Runs the task with the next free connection, in the background.
+    ///
+    /// This only waits if the maximum number of batches in flight is reached,
+    /// so that the caller can prepare the next batch while this one is being
+    /// written.
     pub async fn spawn_with_connection<F>(&mut self, task: F) -> Result<(), Error>
     where
         F: for<'conn> FnOnce(&'conn mut PgConnection) -> BoxFuture<'conn, Result<(), Error>>
             + Send
             + 'static,
     {
-        match self.connection_rx.recv().await {
-            Some(Ok(mut connection)) => {
-                let connection_tx = self.connection_tx.clone();
-                tokio::task::spawn(
-                    async move {
-                        let to_return = match task(&mut connection).await {
-                            Ok(()) => Ok(connection),
-                            Err(error) => {
-                                error!("error in writer: {error}");
-                                Err(error)
-                            }
-                        };
-                        // This should always succeed in sending unless we're already shutting
-                        // down for some other reason.
-                        let _: Result<_, _> = connection_tx.send(to_return).await;
+        if self.failed.load(Ordering::SeqCst) {
+            return Err(Error::WriterConnectionPoolError);
+        }
+
+        let permit = Arc::clone(&self.in_flight_batches)
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let connection_rx = Arc::clone(&self.connection_rx);
+        let connection_tx = self.connection_tx.clone();
+        let failed = Arc::clone(&self.failed);
+
+        tokio::task::spawn(
+            async move {
+                let connection = connection_rx.lock().await.recv().await;
+                let to_return = match connection {
+                    Some(Ok(mut connection)) => match task(&mut connection).await {
+                        Ok(()) => Ok(connection),
+                        Err(error) => {
+                            error!("error in writer: {error}");
+                            failed.store(true, Ordering::SeqCst);
+                            Err(error)
+                        }
+                    },
+                    // Another batch failed on this connection: skip this one and give the
+                    // error back, so that it is reported when finishing
+                    Some(Err(error)) => Err(error),
+                    None => {
+                        unreachable!(
+                            "we still hold a reference to the sender, so this shouldn't happen"
+                        )
                     }
-                    .instrument(tracing::debug_span!("spawn_with_connection")),
-                );
+                };
 
-                Ok(())
-            }
-            Some(Err(error)) => {
                 // This should always succeed in sending unless we're already shutting
                 // down for some other reason.
-                let _: Result<_, _> = self.connection_tx.send(Err(error)).await;
+                let _: Result<_, _> = connection_tx.send(to_return).await;
 
-                Err(Error::WriterConnectionPoolError)
+                // Release the sender before the permit, as `finish` waits for all the
+                // permits before waiting for all the senders to be dropped
+                drop(connection_tx);
+                drop(permit);
             }
-            None => {
-                unreachable!("we still hold a reference to the sender, so this shouldn't happen")
-            }
-        }
+            .instrument(tracing::debug_span!("spawn_with_connection")),
+        );
+
+        Ok(())
     }
 
     /// Finishes writing to the database, committing all changes.
@@ -166,12 +264,23 @@ impl WriterConnectionPool {
 
         let Self {
             num_connections,
-            mut connection_rx,
+            max_in_flight_batches,
+            in_flight_batches,
+            failed: _,
+            connection_rx,
             connection_tx,
         } = self;
+
+        // Wait for all the batches in flight to be written
+        let _permits = in_flight_batches
+            .acquire_many(max_in_flight_batches)
+            .await
+            .expect("the semaphore is never closed");
+
         // Drop the sender handle so we gracefully allow the receiver to close
         drop(connection_tx);
 
+        let mut connection_rx = connection_rx.lock().await;
         let mut finished_connections = 0;
 
         while let Some(connection_or_error) = connection_rx.recv().await {
@@ -223,7 +332,7 @@ impl FinishChecker {
     }
 
     /// Check that all handles have been declared as finished.
-    pub fn check_all_finished(self) -> Result<(), Error> {
+    pub fn check_all_finished(&self) -> Result<(), Error> {
         if self.counter.load(Ordering::SeqCst) == 0 {
             Ok(())
         } else {
@@ -239,10 +348,83 @@ impl FinishCheckerHandle {
     }
 }
 
+/// Number of rows referencing rows which don't exist, found by
+/// [`MasWriter::verify_referential_integrity`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OrphanedRows {
+    /// Compatibility access tokens of a missing session
+    pub access_tokens: i64,
+
+    /// Compatibility refresh tokens of a missing session or access token
+    pub refresh_tokens: i64,
+
+    /// Email addresses and unsupported third-party IDs of a missing user
+    pub threepids: i64,
+
+    /// Upstream OAuth links of a missing user
+    pub upstream_oauth_links: i64,
+}
+
+impl OrphanedRows {
+    /// Whether no orphaned rows were found
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for OrphanedRows {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} access tokens, {} refresh tokens, {} third-party IDs and {} upstream links",
+            self.access_tokens, self.refresh_tokens, self.threepids, self.upstream_oauth_links
+        )
+    }
+}
+
+/// How the rows are written to the MAS database, see
+/// [`MasWriter::set_write_mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Insert every row, so that a row written twice makes the migration fail
+    /// when the primary keys are restored
+    #[default]
+    InsertOnly,
+
+    /// Skip the compatibility sessions, tokens and session migration notes
+    /// which already exist in the MAS database, keeping the primary keys of
+    /// their tables while the migration runs
+    Upsert,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid write mode {0:?}, expected `insert-only` or `upsert`")]
+pub struct InvalidWriteMode(String);
+
+impl FromStr for WriteMode {
+    type Err = InvalidWriteMode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insert-only" => Ok(Self::InsertOnly),
+            "upsert" => Ok(Self::Upsert),
+            _ => Err(InvalidWriteMode(s.to_owned())),
+        }
+    }
+}
+
 pub struct MasWriter {
     conn: LockedMasDatabase,
-    writer_pool: WriterConnectionPool,
+    /// The writer connections, until their writes are committed
+    writer_pool: Option<WriterConnectionPool>,
     dry_run: bool,
+    discard_rows: bool,
+    batch_size: NonZeroUsize,
+    write_mode: WriteMode,
+    copy_format: Option<CopyFormat>,
+    batch_retries: u32,
+    export: Option<RowExport>,
 
     indices_to_restore: Vec<IndexDescription>,
     constraints_to_restore: Vec<ConstraintDescription>,
@@ -250,13 +432,59 @@ pub struct MasWriter {
     write_buffer_finish_checker: FinishChecker,
 }
 
-pub trait WriteBatch: Send + Sync + Sized + 'static {
+/// The file the written rows are exported to, see
+/// [`MasWriter::export_rows_to`].
+struct RowExport {
+    path: Utf8PathBuf,
+    file: BufWriter<File>,
+}
+
+/// A line of the export file.
+#[derive(Serialize)]
+struct ExportedRow<'a, T> {
+    table: &'static str,
+    row: &'a T,
+}
+
+pub trait WriteBatch: Serialize + Send + Sync + Sized + 'static {
+    /// The MAS table the rows are written to
+    const TABLE: &'static str;
+
     fn write_batch(
         conn: &mut PgConnection,
-        batch: Vec<Self>,
+        batch: &[Self],
     ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Writes the batch like [`WriteBatch::write_batch`], but skips the rows
+    /// which already exist in the MAS database, see [`WriteMode::Upsert`].
+    ///
+    /// The primary keys of the [`UPSERTED_TABLES`] are kept in that mode, so
+    /// that this can rely on `ON CONFLICT DO NOTHING`. Rows which are never
+    /// written again by a re-run keep the default, which writes the batch
+    /// as-is.
+    fn upsert_batch(
+        conn: &mut PgConnection,
+        batch: &[Self],
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        Self::write_batch(conn, batch)
+    }
+
+    /// Writes the batch like [`WriteBatch::write_batch`], but with a `COPY`
+    /// statement sending the rows in the given format, see
+    /// [`MasWriter::set_copy_format`].
+    ///
+    /// Only the high-volume tables are worth it: the others keep the default,
+    /// which writes the batch as-is.
+    fn copy_batch(
+        conn: &mut PgConnection,
+        batch: &[Self],
+        _format: CopyFormat,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        Self::write_batch(conn, batch)
+    }
 }
 
+#[derive(Clone, Serialize)]
 pub struct MasNewUser {
     pub user_id: NonNilUuid,
     pub username: String,
@@ -271,7 +499,9 @@ pub struct MasNewUser {
 }
 
 impl WriteBatch for MasNewUser {
-    async fn write_batch(conn: &mut PgConnection, batch: Vec<Self>) -> Result<(), Error> {
+    const TABLE: &'static str = "users";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
         // `UNNEST` is a fast way to do bulk inserts, as it lets us send multiple rows
         // in one statement without having to change the statement
         // SQL thus altering the query plan. See <https://github.com/launchbadge/sqlx/blob/main/FAQ.md#how-can-i-bind-an-array-to-a-values-clause-how-can-i-do-bulk-inserts>.
@@ -279,7 +509,7 @@ impl WriteBatch for MasNewUser {
         // `COPY FROM STDIN` statement, which is allegedly the best
         // for insert performance, but is less simple to encode.
         let mut user_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
-        let mut usernames: Vec<String> = Vec::with_capacity(batch.len());
+        let mut usernames: Vec<&str> = Vec::with_capacity(batch.len());
         let mut created_ats: Vec<DateTime<Utc>> = Vec::with_capacity(batch.len());
         let mut locked_ats: Vec<Option<DateTime<Utc>>> = Vec::with_capacity(batch.len());
         let mut deactivated_ats: Vec<Option<DateTime<Utc>>> = Vec::with_capacity(batch.len());
@@ -297,11 +527,11 @@ impl WriteBatch for MasNewUser {
         {
             user_ids.push(user_id.get());
             usernames.push(username);
-            created_ats.push(created_at);
-            locked_ats.push(locked_at);
-            deactivated_ats.push(deactivated_at);
-            can_request_admins.push(can_request_admin);
-            is_guests.push(is_guest);
+            created_ats.push(*created_at);
+            locked_ats.push(*locked_at);
+            deactivated_ats.push(*deactivated_at);
+            can_request_admins.push(*can_request_admin);
+            is_guests.push(*is_guest);
         }
 
         sqlx::query!(
@@ -318,7 +548,7 @@ impl WriteBatch for MasNewUser {
               $6::BOOL[], $7::BOOL[])
             "#,
             &user_ids[..],
-            &usernames[..],
+            &usernames[..] as &[&str],
             &created_ats[..],
             // We need to override the typing for arrays of optionals (sqlx limitation)
             &locked_ats[..] as &[Option<DateTime<Utc>>],
@@ -334,18 +564,32 @@ impl WriteBatch for MasNewUser {
     }
 }
 
+/// A user already present in the MAS database, as loaded by
+/// [`MasWriter::load_user_localpart_map`].
+///
+/// Only the ID is loaded: everything else about the user is read from Synapse
+/// again, as the MAS user may have changed since it was migrated.
+#[derive(Debug, Clone, Copy)]
+pub struct MasExistingUser {
+    pub user_id: NonNilUuid,
+}
+
+#[derive(Serialize)]
 pub struct MasNewUserPassword {
     pub user_password_id: Uuid,
     pub user_id: NonNilUuid,
+    #[serde(skip)]
     pub hashed_password: String,
     pub created_at: DateTime<Utc>,
 }
 
 impl WriteBatch for MasNewUserPassword {
-    async fn write_batch(conn: &mut PgConnection, batch: Vec<Self>) -> Result<(), Error> {
+    const TABLE: &'static str = "user_passwords";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
         let mut user_password_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         let mut user_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
-        let mut hashed_passwords: Vec<String> = Vec::with_capacity(batch.len());
+        let mut hashed_passwords: Vec<&str> = Vec::with_capacity(batch.len());
         let mut created_ats: Vec<DateTime<Utc>> = Vec::with_capacity(batch.len());
         let mut versions: Vec<i32> = Vec::with_capacity(batch.len());
         for MasNewUserPassword {
@@ -355,10 +599,10 @@ impl WriteBatch for MasNewUserPassword {
             created_at,
         } in batch
         {
-            user_password_ids.push(user_password_id);
+            user_password_ids.push(*user_password_id);
             user_ids.push(user_id.get());
             hashed_passwords.push(hashed_password);
-            created_ats.push(created_at);
+            created_ats.push(*created_at);
             versions.push(MIGRATED_PASSWORD_VERSION.into());
         }
 
@@ -370,7 +614,7 @@ impl WriteBatch for MasNewUserPassword {
             "#,
             &user_password_ids[..],
             &user_ids[..],
-            &hashed_passwords[..],
+            &hashed_passwords[..] as &[&str],
             &created_ats[..],
             &versions[..],
         ).execute(&mut *conn).await.into_database("writing users to MAS")?;
@@ -379,6 +623,7 @@ impl WriteBatch for MasNewUserPassword {
     }
 }
 
+#[derive(Serialize)]
 pub struct MasNewEmailThreepid {
     pub user_email_id: Uuid,
     pub user_id: NonNilUuid,
@@ -387,10 +632,12 @@ pub struct MasNewEmailThreepid {
 }
 
 impl WriteBatch for MasNewEmailThreepid {
-    async fn write_batch(conn: &mut PgConnection, batch: Vec<Self>) -> Result<(), Error> {
+    const TABLE: &'static str = "user_emails";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
         let mut user_email_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         let mut user_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
-        let mut emails: Vec<String> = Vec::with_capacity(batch.len());
+        let mut emails: Vec<&str> = Vec::with_capacity(batch.len());
         let mut created_ats: Vec<DateTime<Utc>> = Vec::with_capacity(batch.len());
 
         for MasNewEmailThreepid {
@@ -400,10 +647,10 @@ impl WriteBatch for MasNewEmailThreepid {
             created_at,
         } in batch
         {
-            user_email_ids.push(user_email_id);
+            user_email_ids.push(*user_email_id);
             user_ids.push(user_id.get());
             emails.push(email);
-            created_ats.push(created_at);
+            created_ats.push(*created_at);
         }
 
         // `confirmed_at` is going to get removed in a future MAS release,
@@ -416,7 +663,7 @@ impl WriteBatch for MasNewEmailThreepid {
             "#,
             &user_email_ids[..],
             &user_ids[..],
-            &emails[..],
+            &emails[..] as &[&str],
             &created_ats[..],
         ).execute(&mut *conn).await.into_database("writing emails to MAS")?;
 
@@ -424,6 +671,7 @@ impl WriteBatch for MasNewEmailThreepid {
     }
 }
 
+#[derive(Serialize)]
 pub struct MasNewUnsupportedThreepid {
     pub user_id: NonNilUuid,
     pub medium: String,
@@ -432,10 +680,12 @@ pub struct MasNewUnsupportedThreepid {
 }
 
 impl WriteBatch for MasNewUnsupportedThreepid {
-    async fn write_batch(conn: &mut PgConnection, batch: Vec<Self>) -> Result<(), Error> {
+    const TABLE: &'static str = "user_unsupported_third_party_ids";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
         let mut user_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
-        let mut mediums: Vec<String> = Vec::with_capacity(batch.len());
-        let mut addresses: Vec<String> = Vec::with_capacity(batch.len());
+        let mut mediums: Vec<&str> = Vec::with_capacity(batch.len());
+        let mut addresses: Vec<&str> = Vec::with_capacity(batch.len());
         let mut created_ats: Vec<DateTime<Utc>> = Vec::with_capacity(batch.len());
 
         for MasNewUnsupportedThreepid {
@@ -448,7 +698,7 @@ impl WriteBatch for MasNewUnsupportedThreepid {
             user_ids.push(user_id.get());
             mediums.push(medium);
             addresses.push(address);
-            created_ats.push(created_at);
+            created_ats.push(*created_at);
         }
 
         sqlx::query!(
@@ -458,8 +708,8 @@ impl WriteBatch for MasNewUnsupportedThreepid {
             SELECT * FROM UNNEST($1::UUID[], $2::TEXT[], $3::TEXT[], $4::TIMESTAMP WITH TIME ZONE[])
             "#,
             &user_ids[..],
-            &mediums[..],
-            &addresses[..],
+            &mediums[..] as &[&str],
+            &addresses[..] as &[&str],
             &created_ats[..],
         )
         .execute(&mut *conn)
@@ -470,6 +720,95 @@ impl WriteBatch for MasNewUnsupportedThreepid {
     }
 }
 
+#[derive(Serialize)]
+pub struct MasNewAccountData {
+    pub user_id: NonNilUuid,
+    pub account_data_type: String,
+    pub content: String,
+}
+
+impl WriteBatch for MasNewAccountData {
+    const TABLE: &'static str = "user_account_data";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        let mut user_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
+        let mut account_data_types: Vec<&str> = Vec::with_capacity(batch.len());
+        let mut contents: Vec<&str> = Vec::with_capacity(batch.len());
+
+        for MasNewAccountData {
+            user_id,
+            account_data_type,
+            content,
+        } in batch
+        {
+            user_ids.push(user_id.get());
+            account_data_types.push(account_data_type);
+            contents.push(content);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO syn2mas__user_synapse_account_data
+            (user_id, account_data_type, content)
+            SELECT * FROM UNNEST($1::UUID[], $2::TEXT[], $3::TEXT[])
+            "#,
+            &user_ids[..],
+            &account_data_types[..] as &[&str],
+            &contents[..] as &[&str],
+        )
+        .execute(&mut *conn)
+        .await
+        .into_database("writing account data to MAS")?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+pub struct MasNewUserFilter {
+    pub user_id: NonNilUuid,
+    pub filter_id: i64,
+    pub filter_json: Vec<u8>,
+}
+
+impl WriteBatch for MasNewUserFilter {
+    const TABLE: &'static str = "user_synapse_filters";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        let mut user_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
+        let mut filter_ids: Vec<i64> = Vec::with_capacity(batch.len());
+        let mut filter_jsons: Vec<&[u8]> = Vec::with_capacity(batch.len());
+
+        for MasNewUserFilter {
+            user_id,
+            filter_id,
+            filter_json,
+        } in batch
+        {
+            user_ids.push(user_id.get());
+            filter_ids.push(*filter_id);
+            filter_jsons.push(filter_json);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO syn2mas__user_synapse_filters
+            (user_id, filter_id, filter_json)
+            SELECT * FROM UNNEST($1::UUID[], $2::BIGINT[], $3::BYTEA[])
+            "#,
+            &user_ids[..],
+            &filter_ids[..],
+            &filter_jsons[..] as &[&[u8]],
+        )
+        .execute(&mut *conn)
+        .await
+        .into_database("writing user filters to MAS")?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
 pub struct MasNewUpstreamOauthLink {
     pub link_id: Uuid,
     pub user_id: NonNilUuid,
@@ -479,11 +818,13 @@ pub struct MasNewUpstreamOauthLink {
 }
 
 impl WriteBatch for MasNewUpstreamOauthLink {
-    async fn write_batch(conn: &mut PgConnection, batch: Vec<Self>) -> Result<(), Error> {
+    const TABLE: &'static str = "upstream_oauth_links";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
         let mut link_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         let mut user_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         let mut upstream_provider_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
-        let mut subjects: Vec<String> = Vec::with_capacity(batch.len());
+        let mut subjects: Vec<&str> = Vec::with_capacity(batch.len());
         let mut created_ats: Vec<DateTime<Utc>> = Vec::with_capacity(batch.len());
 
         for MasNewUpstreamOauthLink {
@@ -494,11 +835,11 @@ impl WriteBatch for MasNewUpstreamOauthLink {
             created_at,
         } in batch
         {
-            link_ids.push(link_id);
+            link_ids.push(*link_id);
             user_ids.push(user_id.get());
-            upstream_provider_ids.push(upstream_provider_id);
+            upstream_provider_ids.push(*upstream_provider_id);
             subjects.push(subject);
-            created_ats.push(created_at);
+            created_ats.push(*created_at);
         }
 
         sqlx::query!(
@@ -510,7 +851,7 @@ impl WriteBatch for MasNewUpstreamOauthLink {
             &link_ids[..],
             &user_ids[..],
             &upstream_provider_ids[..],
-            &subjects[..],
+            &subjects[..] as &[&str],
             &created_ats[..],
         ).execute(&mut *conn).await.into_database("writing unsupported threepids to MAS")?;
 
@@ -518,12 +859,14 @@ impl WriteBatch for MasNewUpstreamOauthLink {
     }
 }
 
+#[derive(Serialize)]
 pub struct MasNewCompatSession {
     pub session_id: Uuid,
     pub user_id: NonNilUuid,
     pub device_id: Option<String>,
     pub human_name: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
     pub is_synapse_admin: bool,
     pub last_active_at: Option<DateTime<Utc>>,
     pub last_active_ip: Option<IpAddr>,
@@ -531,16 +874,74 @@ pub struct MasNewCompatSession {
 }
 
 impl WriteBatch for MasNewCompatSession {
-    async fn write_batch(conn: &mut PgConnection, batch: Vec<Self>) -> Result<(), Error> {
+    const TABLE: &'static str = "compat_sessions";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        Self::write(conn, batch, WriteMode::InsertOnly).await
+    }
+
+    async fn upsert_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        Self::write(conn, batch, WriteMode::Upsert).await
+    }
+
+    async fn copy_batch(
+        conn: &mut PgConnection,
+        batch: &[Self],
+        format: CopyFormat,
+    ) -> Result<(), Error> {
+        let mut encoder = CopyEncoder::new(format);
+        for session in batch {
+            encoder.start_row(10);
+            encoder.uuid(session.session_id);
+            encoder.uuid(session.user_id.get());
+            encoder.text(session.device_id.as_deref());
+            encoder.text(session.human_name.as_deref());
+            encoder.timestamp(Some(session.created_at));
+            encoder.timestamp(session.finished_at);
+            encoder.bool(session.is_synapse_admin);
+            encoder.timestamp(session.last_active_at);
+            encoder.inet(session.last_active_ip);
+            encoder.text(session.user_agent.as_deref());
+            encoder.end_row();
+        }
+
+        copy_in(
+            conn,
+            "syn2mas__compat_sessions",
+            &[
+                "compat_session_id",
+                "user_id",
+                "device_id",
+                "human_name",
+                "created_at",
+                "finished_at",
+                "is_synapse_admin",
+                "last_active_at",
+                "last_active_ip",
+                "user_agent",
+            ],
+            encoder,
+        )
+        .await
+    }
+}
+
+impl MasNewCompatSession {
+    async fn write(
+        conn: &mut PgConnection,
+        batch: &[Self],
+        write_mode: WriteMode,
+    ) -> Result<(), Error> {
         let mut session_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         let mut user_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
-        let mut device_ids: Vec<Option<String>> = Vec::with_capacity(batch.len());
-        let mut human_names: Vec<Option<String>> = Vec::with_capacity(batch.len());
+        let mut device_ids: Vec<Option<&str>> = Vec::with_capacity(batch.len());
+        let mut human_names: Vec<Option<&str>> = Vec::with_capacity(batch.len());
         let mut created_ats: Vec<DateTime<Utc>> = Vec::with_capacity(batch.len());
+        let mut finished_ats: Vec<Option<DateTime<Utc>>> = Vec::with_capacity(batch.len());
         let mut is_synapse_admins: Vec<bool> = Vec::with_capacity(batch.len());
         let mut last_active_ats: Vec<Option<DateTime<Utc>>> = Vec::with_capacity(batch.len());
         let mut last_active_ips: Vec<Option<IpAddr>> = Vec::with_capacity(batch.len());
-        let mut user_agents: Vec<Option<String>> = Vec::with_capacity(batch.len());
+        let mut user_agents: Vec<Option<&str>> = Vec::with_capacity(batch.len());
 
         for MasNewCompatSession {
             session_id,
@@ -548,70 +949,161 @@ impl WriteBatch for MasNewCompatSession {
             device_id,
             human_name,
             created_at,
+            finished_at,
             is_synapse_admin,
             last_active_at,
             last_active_ip,
             user_agent,
         } in batch
         {
-            session_ids.push(session_id);
+            session_ids.push(*session_id);
             user_ids.push(user_id.get());
-            device_ids.push(device_id);
-            human_names.push(human_name);
-            created_ats.push(created_at);
-            is_synapse_admins.push(is_synapse_admin);
-            last_active_ats.push(last_active_at);
-            last_active_ips.push(last_active_ip);
-            user_agents.push(user_agent);
+            device_ids.push(device_id.as_deref());
+            human_names.push(human_name.as_deref());
+            created_ats.push(*created_at);
+            finished_ats.push(*finished_at);
+            is_synapse_admins.push(*is_synapse_admin);
+            last_active_ats.push(*last_active_at);
+            last_active_ips.push(*last_active_ip);
+            user_agents.push(user_agent.as_deref());
         }
 
-        sqlx::query!(
-            r#"
-            INSERT INTO syn2mas__compat_sessions (
-              compat_session_id, user_id,
-              device_id, human_name,
-              created_at, is_synapse_admin,
-              last_active_at, last_active_ip,
-              user_agent)
-            SELECT * FROM UNNEST(
-              $1::UUID[], $2::UUID[],
-              $3::TEXT[], $4::TEXT[],
-              $5::TIMESTAMP WITH TIME ZONE[], $6::BOOLEAN[],
-              $7::TIMESTAMP WITH TIME ZONE[], $8::INET[],
-              $9::TEXT[])
-            "#,
-            &session_ids[..],
-            &user_ids[..],
-            &device_ids[..] as &[Option<String>],
-            &human_names[..] as &[Option<String>],
-            &created_ats[..],
-            &is_synapse_admins[..],
-            // We need to override the typing for arrays of optionals (sqlx limitation)
-            &last_active_ats[..] as &[Option<DateTime<Utc>>],
-            &last_active_ips[..] as &[Option<IpAddr>],
-            &user_agents[..] as &[Option<String>],
-        )
-        .execute(&mut *conn)
-        .await
-        .into_database("writing compat sessions to MAS")?;
+        match write_mode {
+            WriteMode::InsertOnly => sqlx::query!(
+                r#"
+                INSERT INTO syn2mas__compat_sessions (
+                  compat_session_id, user_id,
+                  device_id, human_name,
+                  created_at, finished_at,
+                  is_synapse_admin,
+                  last_active_at, last_active_ip,
+                  user_agent)
+                SELECT * FROM UNNEST(
+                  $1::UUID[], $2::UUID[],
+                  $3::TEXT[], $4::TEXT[],
+                  $5::TIMESTAMP WITH TIME ZONE[], $6::TIMESTAMP WITH TIME ZONE[],
+                  $7::BOOLEAN[],
+                  $8::TIMESTAMP WITH TIME ZONE[], $9::INET[],
+                  $10::TEXT[])
+                "#,
+                &session_ids[..],
+                &user_ids[..],
+                &device_ids[..] as &[Option<&str>],
+                &human_names[..] as &[Option<&str>],
+                &created_ats[..],
+                // We need to override the typing for arrays of optionals (sqlx limitation)
+                &finished_ats[..] as &[Option<DateTime<Utc>>],
+                &is_synapse_admins[..],
+                &last_active_ats[..] as &[Option<DateTime<Utc>>],
+                &last_active_ips[..] as &[Option<IpAddr>],
+                &user_agents[..] as &[Option<&str>],
+            )
+            .execute(&mut *conn)
+            .await
+            .into_database("writing compat sessions to MAS")?,
+
+            WriteMode::Upsert => sqlx::query!(
+                r#"
+                INSERT INTO syn2mas__compat_sessions (
+                  compat_session_id, user_id,
+                  device_id, human_name,
+                  created_at, finished_at,
+                  is_synapse_admin,
+                  last_active_at, last_active_ip,
+                  user_agent)
+                SELECT * FROM UNNEST(
+                  $1::UUID[], $2::UUID[],
+                  $3::TEXT[], $4::TEXT[],
+                  $5::TIMESTAMP WITH TIME ZONE[], $6::TIMESTAMP WITH TIME ZONE[],
+                  $7::BOOLEAN[],
+                  $8::TIMESTAMP WITH TIME ZONE[], $9::INET[],
+                  $10::TEXT[]
+                )
+                ON CONFLICT DO NOTHING
+                "#,
+                &session_ids[..],
+                &user_ids[..],
+                &device_ids[..] as &[Option<&str>],
+                &human_names[..] as &[Option<&str>],
+                &created_ats[..],
+                // We need to override the typing for arrays of optionals (sqlx limitation)
+                &finished_ats[..] as &[Option<DateTime<Utc>>],
+                &is_synapse_admins[..],
+                &last_active_ats[..] as &[Option<DateTime<Utc>>],
+                &last_active_ips[..] as &[Option<IpAddr>],
+                &user_agents[..] as &[Option<&str>],
+            )
+            .execute(&mut *conn)
+            .await
+            .into_database("upserting compat sessions to MAS")?,
+        };
 
         Ok(())
     }
 }
 
+#[derive(Serialize)]
 pub struct MasNewCompatAccessToken {
     pub token_id: Uuid,
     pub session_id: Uuid,
+    #[serde(skip)]
     pub access_token: String,
     pub created_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
 }
 
 impl WriteBatch for MasNewCompatAccessToken {
-    async fn write_batch(conn: &mut PgConnection, batch: Vec<Self>) -> Result<(), Error> {
+    const TABLE: &'static str = "compat_access_tokens";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        Self::write(conn, batch, WriteMode::InsertOnly).await
+    }
+
+    async fn upsert_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        Self::write(conn, batch, WriteMode::Upsert).await
+    }
+
+    async fn copy_batch(
+        conn: &mut PgConnection,
+        batch: &[Self],
+        format: CopyFormat,
+    ) -> Result<(), Error> {
+        let mut encoder = CopyEncoder::new(format);
+        for token in batch {
+            encoder.start_row(5);
+            encoder.uuid(token.token_id);
+            encoder.uuid(token.session_id);
+            encoder.text(Some(&token.access_token));
+            encoder.timestamp(Some(token.created_at));
+            encoder.timestamp(token.expires_at);
+            encoder.end_row();
+        }
+
+        copy_in(
+            conn,
+            "syn2mas__compat_access_tokens",
+            &[
+                "compat_access_token_id",
+                "compat_session_id",
+                "access_token",
+                "created_at",
+                "expires_at",
+            ],
+            encoder,
+        )
+        .await
+    }
+}
+
+impl MasNewCompatAccessToken {
+    async fn write(
+        conn: &mut PgConnection,
+        batch: &[Self],
+        write_mode: WriteMode,
+    ) -> Result<(), Error> {
         let mut token_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         let mut session_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
-        let mut access_tokens: Vec<String> = Vec::with_capacity(batch.len());
+        let mut access_tokens: Vec<&str> = Vec::with_capacity(batch.len());
         let mut created_ats: Vec<DateTime<Utc>> = Vec::with_capacity(batch.len());
         let mut expires_ats: Vec<Option<DateTime<Utc>>> = Vec::with_capacity(batch.len());
 
@@ -623,57 +1115,105 @@ impl WriteBatch for MasNewCompatAccessToken {
             expires_at,
         } in batch
         {
-            token_ids.push(token_id);
-            session_ids.push(session_id);
+            token_ids.push(*token_id);
+            session_ids.push(*session_id);
             access_tokens.push(access_token);
-            created_ats.push(created_at);
-            expires_ats.push(expires_at);
+            created_ats.push(*created_at);
+            expires_ats.push(*expires_at);
         }
 
-        sqlx::query!(
-            r#"
-            INSERT INTO syn2mas__compat_access_tokens (
-              compat_access_token_id,
-              compat_session_id,
-              access_token,
-              created_at,
-              expires_at)
-            SELECT * FROM UNNEST(
-              $1::UUID[],
-              $2::UUID[],
-              $3::TEXT[],
-              $4::TIMESTAMP WITH TIME ZONE[],
-              $5::TIMESTAMP WITH TIME ZONE[])
-            "#,
-            &token_ids[..],
-            &session_ids[..],
-            &access_tokens[..],
-            &created_ats[..],
-            // We need to override the typing for arrays of optionals (sqlx limitation)
-            &expires_ats[..] as &[Option<DateTime<Utc>>],
-        )
-        .execute(&mut *conn)
-        .await
-        .into_database("writing compat access tokens to MAS")?;
+        match write_mode {
+            WriteMode::InsertOnly => sqlx::query!(
+                r#"
+                INSERT INTO syn2mas__compat_access_tokens (
+                  compat_access_token_id,
+                  compat_session_id,
+                  access_token,
+                  created_at,
+                  expires_at)
+                SELECT * FROM UNNEST(
+                  $1::UUID[],
+                  $2::UUID[],
+                  $3::TEXT[],
+                  $4::TIMESTAMP WITH TIME ZONE[],
+                  $5::TIMESTAMP WITH TIME ZONE[])
+                "#,
+                &token_ids[..],
+                &session_ids[..],
+                &access_tokens[..] as &[&str],
+                &created_ats[..],
+                // We need to override the typing for arrays of optionals (sqlx limitation)
+                &expires_ats[..] as &[Option<DateTime<Utc>>],
+            )
+            .execute(&mut *conn)
+            .await
+            .into_database("writing compat access tokens to MAS")?,
+
+            WriteMode::Upsert => sqlx::query!(
+                r#"
+                INSERT INTO syn2mas__compat_access_tokens (
+                  compat_access_token_id,
+                  compat_session_id,
+                  access_token,
+                  created_at,
+                  expires_at)
+                SELECT * FROM UNNEST(
+                  $1::UUID[],
+                  $2::UUID[],
+                  $3::TEXT[],
+                  $4::TIMESTAMP WITH TIME ZONE[],
+                  $5::TIMESTAMP WITH TIME ZONE[]
+                )
+                ON CONFLICT DO NOTHING
+                "#,
+                &token_ids[..],
+                &session_ids[..],
+                &access_tokens[..] as &[&str],
+                &created_ats[..],
+                // We need to override the typing for arrays of optionals (sqlx limitation)
+                &expires_ats[..] as &[Option<DateTime<Utc>>],
+            )
+            .execute(&mut *conn)
+            .await
+            .into_database("upserting compat access tokens to MAS")?,
+        };
 
         Ok(())
     }
 }
 
+#[derive(Serialize)]
 pub struct MasNewCompatRefreshToken {
     pub refresh_token_id: Uuid,
     pub session_id: Uuid,
     pub access_token_id: Uuid,
+    #[serde(skip)]
     pub refresh_token: String,
     pub created_at: DateTime<Utc>,
 }
 
 impl WriteBatch for MasNewCompatRefreshToken {
-    async fn write_batch(conn: &mut PgConnection, batch: Vec<Self>) -> Result<(), Error> {
+    const TABLE: &'static str = "compat_refresh_tokens";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        Self::write(conn, batch, WriteMode::InsertOnly).await
+    }
+
+    async fn upsert_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        Self::write(conn, batch, WriteMode::Upsert).await
+    }
+}
+
+impl MasNewCompatRefreshToken {
+    async fn write(
+        conn: &mut PgConnection,
+        batch: &[Self],
+        write_mode: WriteMode,
+    ) -> Result<(), Error> {
         let mut refresh_token_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         let mut session_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         let mut access_token_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
-        let mut refresh_tokens: Vec<String> = Vec::with_capacity(batch.len());
+        let mut refresh_tokens: Vec<&str> = Vec::with_capacity(batch.len());
         let mut created_ats: Vec<DateTime<Utc>> = Vec::with_capacity(batch.len());
 
         for MasNewCompatRefreshToken {
@@ -684,37 +1224,185 @@ impl WriteBatch for MasNewCompatRefreshToken {
             created_at,
         } in batch
         {
-            refresh_token_ids.push(refresh_token_id);
-            session_ids.push(session_id);
-            access_token_ids.push(access_token_id);
+            refresh_token_ids.push(*refresh_token_id);
+            session_ids.push(*session_id);
+            access_token_ids.push(*access_token_id);
             refresh_tokens.push(refresh_token);
-            created_ats.push(created_at);
+            created_ats.push(*created_at);
+        }
+
+        match write_mode {
+            WriteMode::InsertOnly => sqlx::query!(
+                r#"
+                INSERT INTO syn2mas__compat_refresh_tokens (
+                  compat_refresh_token_id,
+                  compat_session_id,
+                  compat_access_token_id,
+                  refresh_token,
+                  created_at)
+                SELECT * FROM UNNEST(
+                  $1::UUID[],
+                  $2::UUID[],
+                  $3::UUID[],
+                  $4::TEXT[],
+                  $5::TIMESTAMP WITH TIME ZONE[])
+                "#,
+                &refresh_token_ids[..],
+                &session_ids[..],
+                &access_token_ids[..],
+                &refresh_tokens[..] as &[&str],
+                &created_ats[..],
+            )
+            .execute(&mut *conn)
+            .await
+            .into_database("writing compat refresh tokens to MAS")?,
+
+            WriteMode::Upsert => sqlx::query!(
+                r#"
+                INSERT INTO syn2mas__compat_refresh_tokens (
+                  compat_refresh_token_id,
+                  compat_session_id,
+                  compat_access_token_id,
+                  refresh_token,
+                  created_at)
+                SELECT * FROM UNNEST(
+                  $1::UUID[],
+                  $2::UUID[],
+                  $3::UUID[],
+                  $4::TEXT[],
+                  $5::TIMESTAMP WITH TIME ZONE[]
+                )
+                ON CONFLICT DO NOTHING
+                "#,
+                &refresh_token_ids[..],
+                &session_ids[..],
+                &access_token_ids[..],
+                &refresh_tokens[..] as &[&str],
+                &created_ats[..],
+            )
+            .execute(&mut *conn)
+            .await
+            .into_database("upserting compat refresh tokens to MAS")?,
+        };
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+pub struct MasNewMigrationNote {
+    pub session_id: Uuid,
+    pub field: String,
+    pub raw_value: String,
+}
+
+impl WriteBatch for MasNewMigrationNote {
+    const TABLE: &'static str = "compat_session_migration_notes";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        Self::write(conn, batch, WriteMode::InsertOnly).await
+    }
+
+    async fn upsert_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        Self::write(conn, batch, WriteMode::Upsert).await
+    }
+}
+
+impl MasNewMigrationNote {
+    async fn write(
+        conn: &mut PgConnection,
+        batch: &[Self],
+        write_mode: WriteMode,
+    ) -> Result<(), Error> {
+        let mut session_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
+        let mut fields: Vec<&str> = Vec::with_capacity(batch.len());
+        let mut raw_values: Vec<&str> = Vec::with_capacity(batch.len());
+
+        for MasNewMigrationNote {
+            session_id,
+            field,
+            raw_value,
+        } in batch
+        {
+            session_ids.push(*session_id);
+            fields.push(field);
+            raw_values.push(raw_value);
+        }
+
+        match write_mode {
+            WriteMode::InsertOnly => sqlx::query!(
+                r#"
+                INSERT INTO syn2mas__compat_session_migration_notes
+                (compat_session_id, field, raw_value)
+                SELECT * FROM UNNEST($1::UUID[], $2::TEXT[], $3::TEXT[])
+                "#,
+                &session_ids[..],
+                &fields[..] as &[&str],
+                &raw_values[..] as &[&str],
+            )
+            .execute(&mut *conn)
+            .await
+            .into_database("writing migration notes to MAS")?,
+
+            WriteMode::Upsert => sqlx::query!(
+                r#"
+                INSERT INTO syn2mas__compat_session_migration_notes
+                (compat_session_id, field, raw_value)
+                SELECT * FROM UNNEST($1::UUID[], $2::TEXT[], $3::TEXT[])
+                ON CONFLICT DO NOTHING
+                "#,
+                &session_ids[..],
+                &fields[..] as &[&str],
+                &raw_values[..] as &[&str],
+            )
+            .execute(&mut *conn)
+            .await
+            .into_database("upserting migration notes to MAS")?,
+        };
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+pub struct MasNewUserEmailMigrationNote {
+    pub user_email_id: Uuid,
+    pub field: String,
+    pub raw_value: String,
+}
+
+impl WriteBatch for MasNewUserEmailMigrationNote {
+    const TABLE: &'static str = "user_email_migration_notes";
+
+    async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+        let mut user_email_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
+        let mut fields: Vec<&str> = Vec::with_capacity(batch.len());
+        let mut raw_values: Vec<&str> = Vec::with_capacity(batch.len());
+
+        for MasNewUserEmailMigrationNote {
+            user_email_id,
+            field,
+            raw_value,
+        } in batch
+        {
+            user_email_ids.push(*user_email_id);
+            fields.push(field);
+            raw_values.push(raw_value);
         }
 
         sqlx::query!(
             r#"
-            INSERT INTO syn2mas__compat_refresh_tokens (
-              compat_refresh_token_id,
-              compat_session_id,
-              compat_access_token_id,
-              refresh_token,
-              created_at)
-            SELECT * FROM UNNEST(
-              $1::UUID[],
-              $2::UUID[],
-              $3::UUID[],
-              $4::TEXT[],
-              $5::TIMESTAMP WITH TIME ZONE[])
+            INSERT INTO syn2mas__user_email_migration_notes
+            (user_email_id, field, raw_value)
+            SELECT * FROM UNNEST($1::UUID[], $2::TEXT[], $3::TEXT[])
             "#,
-            &refresh_token_ids[..],
-            &session_ids[..],
-            &access_token_ids[..],
-            &refresh_tokens[..],
-            &created_ats[..],
+            &user_email_ids[..],
+            &fields[..] as &[&str],
+            &raw_values[..] as &[&str],
         )
         .execute(&mut *conn)
         .await
-        .into_database("writing compat refresh tokens to MAS")?;
+        .into_database("writing user email migration notes to MAS")?;
 
         Ok(())
     }
@@ -732,16 +1420,66 @@ pub const MAS_TABLES_AFFECTED_BY_MIGRATION: &[&str] = &[
     "user_passwords",
     "user_emails",
     "user_unsupported_third_party_ids",
+    "user_synapse_account_data",
+    "user_synapse_filters",
     "upstream_oauth_links",
     "compat_sessions",
     "compat_access_tokens",
     "compat_refresh_tokens",
+    "compat_session_migration_notes",
+    "user_email_migration_notes",
 ];
 
-/// Detect whether a syn2mas migration has started on the given database.
-///
-/// Concretly, this checks for the presence of syn2mas restoration tables.
-///
+/// The tables whose rows are skipped if they already exist with
+/// [`WriteMode::Upsert`]. Their primary keys are restored as soon as that mode
+/// is set, instead of at the end of the migration.
+const UPSERTED_TABLES: &[&str] = &[
+    "compat_sessions",
+    "compat_access_tokens",
+    "compat_refresh_tokens",
+    "compat_session_migration_notes",
+];
+
+/// Whether the MAS tables already had rows when the in-progress migration
+/// started, as recorded in the `syn2mas_restore_state` table.
+///
+/// Migrations started by older versions of syn2mas don't have that table, and
+/// are assumed to have started from an empty database, as they had to before
+/// existing users could be reused.
+async fn started_with_existing_data(conn: &mut PgConnection) -> Result<bool, Error> {
+    let has_state_table = query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM pg_tables
+            WHERE schemaname = current_schema
+            AND tablename = 'syn2mas_restore_state'
+        ) AS "exists!"
+        "#,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .into_database("failed to query the syn2mas restore state table")?;
+
+    if !has_state_table {
+        return Ok(false);
+    }
+
+    let had_existing_data = query_scalar!(
+        r#"
+        SELECT had_existing_data FROM syn2mas_restore_state
+        "#,
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .into_database("failed to get syn2mas restore data (state)")?;
+
+    Ok(had_existing_data.unwrap_or(false))
+}
+
+/// Detect whether a syn2mas migration has started on the given database.
+///
+/// Concretly, this checks for the presence of syn2mas restoration tables.
+///
 /// Returns `true` if syn2mas has started, or `false` if it hasn't.
 ///
 /// # Errors
@@ -809,6 +1547,13 @@ impl MasWriter {
         let constraints_to_restore;
 
         if syn2mas_started {
+            // The tables can only be truncated if the previous migration started from
+            // an empty database, as they would otherwise lose the rows which were
+            // there before, like the users reused by the migration
+            if started_with_existing_data(conn.as_mut()).await? {
+                return Err(Error::ResumeWithExistingData);
+            }
+
             // We are resuming from a partially-done syn2mas migration
             // We should reset the database so that we're starting from scratch.
             warn!("Partial syn2mas migration has already been done; resetting.");
@@ -843,6 +1588,29 @@ impl MasWriter {
                 .await
                 .into_database("could not create temporary tables")?;
 
+            // Record whether there already is data in the tables, so that a later run
+            // doesn't truncate them if this one doesn't finish
+            let mut had_existing_data = false;
+            for table in MAS_TABLES_AFFECTED_BY_MIGRATION {
+                let has_rows: bool =
+                    sqlx::query_scalar(&format!("SELECT EXISTS (SELECT 1 FROM syn2mas__{table})"))
+                        .fetch_one(conn.as_mut())
+                        .await
+                        .into_database_with(|| format!("checking for rows in {table}"))?;
+                had_existing_data |= has_rows;
+            }
+
+            query!(
+                r#"
+                INSERT INTO syn2mas_restore_state (had_existing_data)
+                VALUES ($1)
+                "#,
+                had_existing_data,
+            )
+            .execute(conn.as_mut())
+            .await
+            .into_database("failed to save restore data (state)")?;
+
             // Pause (temporarily drop) indices and constraints in order to improve
             // performance of bulk data loading.
             (indices_to_restore, constraints_to_restore) =
@@ -905,13 +1673,364 @@ impl MasWriter {
         Ok(Self {
             conn,
             dry_run,
-            writer_pool: WriterConnectionPool::new(writer_connections),
+            discard_rows: false,
+            batch_size: WRITE_BUFFER_BATCH_SIZE,
+            write_mode: WriteMode::default(),
+            copy_format: None,
+            batch_retries: DEFAULT_BATCH_RETRIES,
+            export: None,
+            writer_pool: Some(WriterConnectionPool::new(writer_connections)),
             indices_to_restore,
             constraints_to_restore,
             write_buffer_finish_checker: FinishChecker::default(),
         })
     }
 
+    /// Checks that the MAS tables which would conflict with migrated rows
+    /// (`users`, `compat_sessions` and `upstream_oauth_links`) are empty.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned in the following conditions:
+    ///
+    /// - If the database connection experiences an error.
+    /// - If one of the tables is not empty, with the number of rows found.
+    #[tracing::instrument(name = "syn2mas.mas_writer.verify_empty", skip_all)]
+    pub async fn verify_empty(&mut self) -> Result<(), Error> {
+        for table in ["users", "compat_sessions", "upstream_oauth_links"] {
+            // The tables are renamed with the `syn2mas__` prefix for the duration of the
+            // migration
+            let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM syn2mas__{table}"))
+                .fetch_one(self.conn.as_mut())
+                .await
+                .into_database_with(|| format!("counting rows in {table}"))?;
+
+            if count > 0 {
+                return Err(Error::NotEmpty { table, count });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads the users already present in the MAS database, keyed by their
+    /// username (the Synapse localpart).
+    ///
+    /// This lets the phases depending on the users run against the users
+    /// migrated by a prior run, without migrating them again.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned in the following conditions:
+    ///
+    /// - If the database connection experiences an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a user has a nil ID, which MAS never generates.
+    #[tracing::instrument(name = "syn2mas.mas_writer.load_user_localpart_map", skip_all)]
+    pub async fn load_user_localpart_map(
+        &mut self,
+    ) -> Result<std::collections::HashMap<String, MasExistingUser>, Error> {
+        // The tables are renamed with the `syn2mas__` prefix for the duration of the
+        // migration
+        let users = query!(
+            r#"
+            SELECT user_id, username
+            FROM syn2mas__users
+            "#
+        )
+        .fetch(self.conn.as_mut())
+        .map_ok(|row| {
+            let user_id = NonNilUuid::new(row.user_id).expect("nil user ID in MAS database");
+            (row.username, MasExistingUser { user_id })
+        })
+        .try_collect()
+        .await
+        .into_database("loading existing users")?;
+
+        Ok(users)
+    }
+
+    /// Makes this writer discard all the rows written through its
+    /// [`MasWriteBuffer`]s, instead of writing them to the database.
+    ///
+    /// This is used to count the rows a migration would write, without writing
+    /// any of them.
+    pub fn discard_rows(&mut self) {
+        self.discard_rows = true;
+    }
+
+    /// Sets how many rows the [`MasWriteBuffer`]s created after this call
+    /// write to the database at once. Defaults to 4096.
+    ///
+    /// Larger batches mean fewer round-trips to the database, but a higher
+    /// peak memory usage, both in syn2mas and in Postgres.
+    pub fn set_batch_size(&mut self, batch_size: NonZeroUsize) {
+        self.batch_size = batch_size;
+    }
+
+    /// Sets how the [`MasWriteBuffer`]s created after this call, and the
+    /// migration notes, write their rows. Defaults to
+    /// [`WriteMode::InsertOnly`].
+    ///
+    /// [`WriteMode::Upsert`] makes re-running a phase against the rows of a
+    /// prior run safe, as long as the IDs of the rows are deterministic. It
+    /// restores the primary keys of the [`UPSERTED_TABLES`] right away, so
+    /// that the existing rows are skipped with `ON CONFLICT DO NOTHING`, which
+    /// makes writing to those tables a bit slower. It must be set before any
+    /// row is written, as the writer transactions would otherwise hold locks
+    /// on the tables.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned in the following conditions:
+    ///
+    /// - If the database connection experiences an error.
+    /// - If a primary key can't be restored, because the rows of the prior run
+    ///   have duplicate IDs.
+    pub async fn set_write_mode(&mut self, write_mode: WriteMode) -> Result<(), Error> {
+        if write_mode == WriteMode::Upsert {
+            self.restore_upserted_primary_keys().await?;
+        }
+        self.write_mode = write_mode;
+        Ok(())
+    }
+
+    /// Restores the primary keys of the [`UPSERTED_TABLES`], and removes them
+    /// from the constraints restored at the end of the migration, including
+    /// the persisted ones in case this migration doesn't finish.
+    async fn restore_upserted_primary_keys(&mut self) -> Result<(), Error> {
+        let tables: Vec<String> = UPSERTED_TABLES
+            .iter()
+            .map(|table| format!("syn2mas__{table}"))
+            .collect();
+        let (primary_keys, constraints_to_restore): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.constraints_to_restore)
+                .into_iter()
+                .partition(|constraint| {
+                    tables.contains(&constraint.table_name)
+                        && constraint.definition.starts_with("PRIMARY KEY")
+                });
+        self.constraints_to_restore = constraints_to_restore;
+
+        if primary_keys.is_empty() {
+            return Ok(());
+        }
+
+        query("BEGIN TRANSACTION ISOLATION LEVEL READ COMMITTED;")
+            .execute(self.conn.as_mut())
+            .await
+            .into_database("begin MAS transaction")?;
+
+        for constraint in &primary_keys {
+            constraint_pausing::restore_constraint(self.conn.as_mut(), constraint).await?;
+            query!(
+                r#"
+                DELETE FROM syn2mas_restore_constraints
+                WHERE table_name = $1 AND name = $2
+                "#,
+                constraint.table_name,
+                constraint.name,
+            )
+            .execute(self.conn.as_mut())
+            .await
+            .into_database("failed to update restore data (constraint)")?;
+        }
+
+        query("COMMIT;")
+            .execute(self.conn.as_mut())
+            .await
+            .into_database("ending MAS transaction")?;
+
+        Ok(())
+    }
+
+    /// Sets whether the [`MasWriteBuffer`]s created after this call write the
+    /// compatibility sessions and access tokens with a `COPY` statement in
+    /// the given format, instead of an `INSERT` statement. Defaults to
+    /// `None`, which uses `INSERT`.
+    ///
+    /// `COPY` is the fastest way to load rows into Postgres, especially in
+    /// the binary format. It is only used with [`WriteMode::InsertOnly`].
+    pub fn set_copy_format(&mut self, copy_format: Option<CopyFormat>) {
+        self.copy_format = copy_format;
+    }
+
+    /// Sets how many times the batches written by the [`MasWriteBuffer`]s
+    /// created after this call, and the migration notes, are retried after a
+    /// serialization failure or a deadlock. Defaults to 3.
+    ///
+    /// The retries wait 100ms, then twice as long as the previous one. A
+    /// batch which still fails after that makes the migration fail, like any
+    /// other error.
+    pub fn set_batch_retries(&mut self, batch_retries: u32) {
+        self.batch_retries = batch_retries;
+    }
+
+    /// Sets how many batches can be written to the database or waiting for a
+    /// writer connection at once. Defaults to the number of writer
+    /// connections.
+    ///
+    /// Flushing a [`MasWriteBuffer`] only waits when this many batches are in
+    /// flight, so allowing more batches than connections lets the migration
+    /// keep reading from Synapse while the database is slow to respond, at
+    /// the cost of keeping more batches in memory.
+    ///
+    /// This must be called before any row is written.
+    pub fn set_max_in_flight_batches(&mut self, max_in_flight_batches: NonZeroU32) {
+        if let Some(writer_pool) = &mut self.writer_pool {
+            writer_pool.set_max_in_flight_batches(max_in_flight_batches);
+        }
+    }
+
+    /// Makes this writer also write every row it writes to the database to the
+    /// given file, as JSON Lines.
+    ///
+    /// Each line is an object with the `table` the row is written to and the
+    /// `row` itself. The secrets (password hashes and tokens) are left out.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned in the following conditions:
+    ///
+    /// - If the file can't be created.
+    pub fn export_rows_to(&mut self, path: &Utf8Path) -> Result<(), Error> {
+        let file = File::create(path).map_err(|source| Error::Export {
+            source,
+            path: path.to_owned(),
+        })?;
+        self.export = Some(RowExport {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+        });
+        Ok(())
+    }
+
+    /// The writer connections, to write rows with.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the writes were already committed.
+    fn writer_pool(&mut self) -> Result<&mut WriterConnectionPool, Error> {
+        self.writer_pool.as_mut().ok_or(Error::WritesCommitted)
+    }
+
+    /// Commits the transactions of all the writer connections, if not done
+    /// already. No rows can be written afterwards.
+    async fn commit_writes(&mut self) -> Result<(), Error> {
+        let Some(writer_pool) = self.writer_pool.take() else {
+            return Ok(());
+        };
+
+        self.write_buffer_finish_checker.check_all_finished()?;
+
+        writer_pool
+            .finish()
+            .await
+            .map_err(|errors| Error::Multiple(MultipleErrors::from(errors)))
+    }
+
+    /// Checks that the rows written by the migration don't reference rows
+    /// which don't exist, returning how many such orphaned rows were found.
+    ///
+    /// The foreign key constraints are only restored by [`MasWriter::finish`],
+    /// so this catches bugs in the migration with a clearer report than a
+    /// failure to restore a constraint.
+    ///
+    /// This commits the rows written so far, so no more rows can be written
+    /// afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned in the following conditions:
+    ///
+    /// - If some write buffers were not finished.
+    /// - If committing the writes failed.
+    /// - If the database connection experiences an error.
+    #[tracing::instrument(name = "syn2mas.mas_writer.verify_referential_integrity", skip_all)]
+    pub async fn verify_referential_integrity(&mut self) -> Result<OrphanedRows, Error> {
+        self.commit_writes().await?;
+
+        // The tables are renamed with the `syn2mas__` prefix for the duration of the
+        // migration
+        let row = query!(
+            r#"
+            SELECT
+                (
+                    SELECT COUNT(*)
+                    FROM syn2mas__compat_access_tokens t
+                    LEFT JOIN syn2mas__compat_sessions s
+                        ON s.compat_session_id = t.compat_session_id
+                    WHERE s.compat_session_id IS NULL
+                ) AS "access_tokens!",
+                (
+                    SELECT COUNT(*)
+                    FROM syn2mas__compat_refresh_tokens t
+                    LEFT JOIN syn2mas__compat_sessions s
+                        ON s.compat_session_id = t.compat_session_id
+                    LEFT JOIN syn2mas__compat_access_tokens a
+                        ON a.compat_access_token_id = t.compat_access_token_id
+                    WHERE s.compat_session_id IS NULL
+                       OR a.compat_access_token_id IS NULL
+                ) AS "refresh_tokens!",
+                (
+                    SELECT COUNT(*)
+                    FROM syn2mas__user_emails e
+                    LEFT JOIN syn2mas__users u ON u.user_id = e.user_id
+                    WHERE u.user_id IS NULL
+                ) + (
+                    SELECT COUNT(*)
+                    FROM syn2mas__user_unsupported_third_party_ids t
+                    LEFT JOIN syn2mas__users u ON u.user_id = t.user_id
+                    WHERE u.user_id IS NULL
+                ) AS "threepids!",
+                (
+                    SELECT COUNT(*)
+                    FROM syn2mas__upstream_oauth_links l
+                    LEFT JOIN syn2mas__users u ON u.user_id = l.user_id
+                    WHERE l.user_id IS NOT NULL
+                      AND u.user_id IS NULL
+                ) AS "upstream_oauth_links!"
+            "#
+        )
+        .fetch_one(self.conn.as_mut())
+        .await
+        .into_database("counting orphaned rows")?;
+
+        Ok(OrphanedRows {
+            access_tokens: row.access_tokens,
+            refresh_tokens: row.refresh_tokens,
+            threepids: row.threepids,
+            upstream_oauth_links: row.upstream_oauth_links,
+        })
+    }
+
+    /// Writes the given rows to the export file, if any.
+    fn export_rows<T: WriteBatch>(&mut self, rows: &[T]) -> Result<(), Error> {
+        let Some(RowExport { path, file }) = &mut self.export else {
+            return Ok(());
+        };
+
+        for row in rows {
+            serde_json::to_writer(
+                &mut *file,
+                &ExportedRow {
+                    table: T::TABLE,
+                    row,
+                },
+            )
+            .map_err(std::io::Error::from)
+            .and_then(|()| file.write_all(b"\n"))
+            .map_err(|source| Error::Export {
+                source,
+                path: path.clone(),
+            })?;
+        }
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip_all)]
     async fn pause_indices(
         conn: &mut PgConnection,
@@ -969,6 +2088,77 @@ impl MasWriter {
         Ok(())
     }
 
+    /// Abandon a migration which didn't finish, keeping the rows it committed
+    /// instead of truncating them.
+    ///
+    /// This restores the indices and constraints of the tables, renames them
+    /// back and drops the `syn2mas_restore_*` tables, in a single
+    /// transaction, like [`MasWriter::finish`] does at the end of a
+    /// migration. It is the way out of [`Error::ResumeWithExistingData`],
+    /// short of restoring a backup.
+    ///
+    /// Returns `false` if there was no unfinished migration to abandon.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned in the following conditions:
+    ///
+    /// - If the database connection experiences an error.
+    /// - If the committed rows break a constraint once it is restored, for
+    ///   example because a phase was interrupted before the rows it depends on
+    ///   were written.
+    #[tracing::instrument(name = "syn2mas.mas_writer.abandon_unfinished_migration", skip_all)]
+    pub async fn abandon_unfinished_migration(
+        conn: &mut LockedMasDatabase,
+        progress: &Progress,
+    ) -> Result<bool, Error> {
+        query("BEGIN TRANSACTION ISOLATION LEVEL READ COMMITTED;")
+            .execute(conn.as_mut())
+            .await
+            .into_database("begin MAS transaction")?;
+
+        if !is_syn2mas_in_progress(conn.as_mut()).await? {
+            query("ROLLBACK;")
+                .execute(conn.as_mut())
+                .await
+                .into_database("rolling back MAS transaction")?;
+            return Ok(false);
+        }
+
+        warn!("Abandoning the unfinished syn2mas migration, keeping the rows it wrote.");
+
+        let indices_to_restore = query_as!(
+            IndexDescription,
+            "SELECT table_name, name, definition FROM syn2mas_restore_indices ORDER BY order_key"
+        )
+        .fetch_all(conn.as_mut())
+        .await
+        .into_database("failed to get syn2mas restore data (index descriptions)")?;
+        let constraints_to_restore = query_as!(
+            ConstraintDescription,
+            "SELECT table_name, name, definition FROM syn2mas_restore_constraints ORDER BY order_key"
+        )
+            .fetch_all(conn.as_mut())
+            .await
+            .into_database("failed to get syn2mas restore data (constraint descriptions)")?;
+
+        Self::restore_indices(conn, &indices_to_restore, &constraints_to_restore, progress).await?;
+
+        conn.as_mut()
+            .execute_many(include_str!("syn2mas_revert_temporary_tables.sql"))
+            // We don't care about any query results
+            .try_collect::<Vec<_>>()
+            .await
+            .into_database("could not revert temporary tables")?;
+
+        query("COMMIT;")
+            .execute(conn.as_mut())
+            .await
+            .into_database("ending MAS transaction")?;
+
+        Ok(true)
+    }
+
     /// Finish writing to the MAS database, flushing and committing all changes.
     /// It returns the unlocked underlying connection.
     ///
@@ -979,13 +2169,12 @@ impl MasWriter {
     /// - If the database connection experiences an error.
     #[tracing::instrument(skip_all)]
     pub async fn finish(mut self, progress: &Progress) -> Result<PgConnection, Error> {
-        self.write_buffer_finish_checker.check_all_finished()?;
+        self.commit_writes().await?;
 
-        // Commit all writer transactions to the database.
-        self.writer_pool
-            .finish()
-            .await
-            .map_err(|errors| Error::Multiple(MultipleErrors::from(errors)))?;
+        if let Some(RowExport { path, mut file }) = self.export.take() {
+            file.flush()
+                .map_err(|source| Error::Export { source, path })?;
+        }
 
         // Now all the data has been migrated, finish off by restoring indices and
         // constraints!
@@ -1047,14 +2236,89 @@ impl MasWriter {
     }
 }
 
-// How many entries to buffer at once, before writing a batch of rows to the
-// database.
-const WRITE_BUFFER_BATCH_SIZE: usize = 4096;
+/// How many entries to buffer at once by default, before writing a batch of
+/// rows to the database.
+const WRITE_BUFFER_BATCH_SIZE: NonZeroUsize = NonZeroUsize::new(4096).unwrap();
+
+/// How many times a batch is retried by default after a transient error.
+const DEFAULT_BATCH_RETRIES: u32 = 3;
+
+/// How long to wait before the first retry of a batch, doubled for each of the
+/// following ones.
+const BATCH_RETRY_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Writes a batch with the given write mode and copy format.
+async fn write_batch<T: WriteBatch>(
+    conn: &mut PgConnection,
+    rows: &[T],
+    write_mode: WriteMode,
+    copy_format: Option<CopyFormat>,
+) -> Result<(), Error> {
+    match (write_mode, copy_format) {
+        (WriteMode::InsertOnly, None) => T::write_batch(conn, rows).await,
+        (WriteMode::InsertOnly, Some(format)) => T::copy_batch(conn, rows, format).await,
+        (WriteMode::Upsert, _) => T::upsert_batch(conn, rows).await,
+    }
+}
+
+/// Writes a batch like [`write_batch`], retrying it up to `retries` times if
+/// it fails on a serialization failure or a deadlock.
+///
+/// Such an error aborts the transaction of the writer connection, so each
+/// attempt runs in a savepoint, which is rolled back before retrying.
+async fn write_batch_with_retries<T: WriteBatch>(
+    conn: &mut PgConnection,
+    rows: &[T],
+    write_mode: WriteMode,
+    copy_format: Option<CopyFormat>,
+    retries: u32,
+) -> Result<(), Error> {
+    if retries == 0 {
+        return write_batch(conn, rows, write_mode, copy_format).await;
+    }
+
+    let mut attempt = 0;
+    let mut backoff = BATCH_RETRY_INITIAL_BACKOFF;
+    loop {
+        conn.execute("SAVEPOINT syn2mas_batch")
+            .await
+            .into_database("creating the savepoint of a batch")?;
+
+        match write_batch(conn, rows, write_mode, copy_format).await {
+            Ok(()) => {
+                conn.execute("RELEASE SAVEPOINT syn2mas_batch")
+                    .await
+                    .into_database("releasing the savepoint of a batch")?;
+                return Ok(());
+            }
+
+            Err(error) if attempt < retries && error.is_transient() => {
+                attempt += 1;
+                warn!(
+                    table = T::TABLE,
+                    attempt, "Transient error writing a batch, retrying in {backoff:?}: {error}"
+                );
+
+                conn.execute("ROLLBACK TO SAVEPOINT syn2mas_batch")
+                    .await
+                    .into_database("rolling back the savepoint of a batch")?;
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+
+            Err(error) => return Err(error),
+        }
+    }
+}
 
 /// A buffer for writing rows to the MAS database.
 /// Generic over the type of rows.
 pub struct MasWriteBuffer<T> {
     rows: Vec<T>,
+    batch_size: usize,
+    write_mode: WriteMode,
+    copy_format: Option<CopyFormat>,
+    batch_retries: u32,
     finish_checker_handle: FinishCheckerHandle,
 }
 
@@ -1062,9 +2326,18 @@ impl<T> MasWriteBuffer<T>
 where
     T: WriteBatch,
 {
+    /// Creates a buffer which writes its rows in batches of the writer's
+    /// batch size and with its write mode, copy format and retries, see
+    /// [`MasWriter::set_batch_size`], [`MasWriter::set_write_mode`],
+    /// [`MasWriter::set_copy_format`] and [`MasWriter::set_batch_retries`].
     pub fn new(writer: &MasWriter) -> Self {
+        let batch_size = writer.batch_size.get();
         MasWriteBuffer {
-            rows: Vec::with_capacity(WRITE_BUFFER_BATCH_SIZE),
+            rows: Vec::with_capacity(batch_size),
+            batch_size,
+            write_mode: writer.write_mode,
+            copy_format: writer.copy_format,
+            batch_retries: writer.batch_retries,
             finish_checker_handle: writer.write_buffer_finish_checker.handle(),
         }
     }
@@ -1079,11 +2352,25 @@ where
         if self.rows.is_empty() {
             return Ok(());
         }
+        if writer.discard_rows {
+            self.rows.clear();
+            return Ok(());
+        }
         let rows = std::mem::take(&mut self.rows);
-        self.rows.reserve_exact(WRITE_BUFFER_BATCH_SIZE);
+        self.rows.reserve_exact(self.batch_size);
+        writer.export_rows(&rows)?;
+        let write_mode = self.write_mode;
+        let copy_format = self.copy_format;
+        let batch_retries = self.batch_retries;
         writer
-            .writer_pool
-            .spawn_with_connection(move |conn| T::write_batch(conn, rows).boxed())
+            .writer_pool()?
+            .spawn_with_connection(move |conn| {
+                async move {
+                    write_batch_with_retries(conn, &rows, write_mode, copy_format, batch_retries)
+                        .await
+                }
+                .boxed()
+            })
             .boxed()
             .await?;
         Ok(())
@@ -1091,7 +2378,7 @@ where
 
     pub async fn write(&mut self, writer: &mut MasWriter, row: T) -> Result<(), Error> {
         self.rows.push(row);
-        if self.rows.len() >= WRITE_BUFFER_BATCH_SIZE {
+        if self.rows.len() >= self.batch_size {
             self.flush(writer).await?;
         }
         Ok(())
@@ -1100,20 +2387,31 @@ where
 
 #[cfg(test)]
 mod test {
-    use std::collections::{BTreeMap, BTreeSet};
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        net::IpAddr,
+        num::{NonZeroU32, NonZeroUsize},
+        sync::{
+            Arc,
+            atomic::{AtomicU32, Ordering},
+        },
+        time::Duration,
+    };
 
-    use chrono::DateTime;
+    use chrono::{DateTime, Utc};
     use futures_util::TryStreamExt;
     use serde::Serialize;
-    use sqlx::{Column, PgConnection, PgPool, Row};
+    use sqlx::{Column, Either, Executor, PgConnection, PgPool, Row};
     use uuid::{NonNilUuid, Uuid};
 
     use crate::{
         LockedMasDatabase, MasWriter, Progress,
         mas_writer::{
-            MasNewCompatAccessToken, MasNewCompatRefreshToken, MasNewCompatSession,
-            MasNewEmailThreepid, MasNewUnsupportedThreepid, MasNewUpstreamOauthLink, MasNewUser,
-            MasNewUserPassword, MasWriteBuffer,
+            CopyFormat, Error, IntoDatabase as _, MasNewAccountData, MasNewCompatAccessToken,
+            MasNewCompatRefreshToken, MasNewCompatSession, MasNewEmailThreepid,
+            MasNewMigrationNote, MasNewUnsupportedThreepid, MasNewUpstreamOauthLink, MasNewUser,
+            MasNewUserEmailMigrationNote, MasNewUserFilter, MasNewUserPassword, MasWriteBuffer,
+            OrphanedRows, WriteBatch, WriteMode, is_syn2mas_in_progress,
         },
     };
 
@@ -1221,35 +2519,151 @@ mod test {
             .expect("failed to construct MasWriter")
     }
 
-    /// Tests writing a single user, without a password.
+    /// Tests that `verify_empty` passes on an empty database.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
-    async fn test_write_user(pool: PgPool) {
+    async fn test_verify_empty(pool: PgPool) {
         let mut writer = make_mas_writer(&pool).await;
-        let mut buffer = MasWriteBuffer::new(&writer);
 
-        buffer
-            .write(
-                &mut writer,
-                MasNewUser {
-                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
-                    username: "alice".to_owned(),
-                    created_at: DateTime::default(),
-                    locked_at: None,
-                    deactivated_at: None,
-                    can_request_admin: false,
-                    is_guest: false,
-                },
-            )
+        writer
+            .verify_empty()
             .await
-            .expect("failed to write user");
+            .expect("empty database should pass verification");
+    }
 
-        buffer
-            .finish(&mut writer)
-            .await
-            .expect("failed to finish MasWriter");
+    /// Tests that `verify_empty` reports the table with existing rows.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR", fixtures("existing_user"))]
+    async fn test_verify_empty_with_existing_user(pool: PgPool) {
+        let mut writer = make_mas_writer(&pool).await;
 
-        let mut conn = writer
-            .finish(&Progress::default())
+        let error = writer
+            .verify_empty()
+            .await
+            .expect_err("database with a user should fail verification");
+
+        assert!(matches!(
+            error,
+            Error::NotEmpty {
+                table: "users",
+                count: 1
+            }
+        ));
+    }
+
+    /// Tests that a migration which started with existing data and didn't
+    /// finish isn't reset by the next one, as that would lose the existing
+    /// data.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR", fixtures("existing_user"))]
+    async fn test_resume_with_existing_data(pool: PgPool) {
+        // The first migration starts with the existing user, then fails before
+        // finishing
+        let writer = make_mas_writer(&pool).await;
+        drop(writer);
+
+        // The lock of the first migration is only released once the server
+        // notices its connection was closed
+        let locked_main_conn = loop {
+            let main_conn = pool.acquire().await.unwrap().detach();
+            match LockedMasDatabase::try_new(main_conn)
+                .await
+                .expect("failed to lock MAS database")
+            {
+                Either::Left(locked) => break locked,
+                Either::Right(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+
+        let Err(error) = MasWriter::new(locked_main_conn, Vec::new(), false).await else {
+            panic!("the migration should refuse to start over");
+        };
+        assert!(matches!(error, Error::ResumeWithExistingData));
+
+        // The existing user is still there
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM syn2mas__users")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    /// Tests that a migration which started with existing data and didn't
+    /// finish can be abandoned while keeping its rows, after which a new one
+    /// can start.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR", fixtures("existing_user"))]
+    async fn test_abandon_unfinished_migration(pool: PgPool) {
+        let writer = make_mas_writer(&pool).await;
+        drop(writer);
+
+        let mut locked_main_conn = loop {
+            let main_conn = pool.acquire().await.unwrap().detach();
+            match LockedMasDatabase::try_new(main_conn)
+                .await
+                .expect("failed to lock MAS database")
+            {
+                Either::Left(locked) => break locked,
+                Either::Right(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+
+        let progress = Progress::default();
+        assert!(
+            MasWriter::abandon_unfinished_migration(&mut locked_main_conn, &progress)
+                .await
+                .expect("failed to abandon the migration")
+        );
+        assert!(
+            !is_syn2mas_in_progress(locked_main_conn.as_mut())
+                .await
+                .unwrap()
+        );
+        // There is nothing left to abandon
+        assert!(
+            !MasWriter::abandon_unfinished_migration(&mut locked_main_conn, &progress)
+                .await
+                .unwrap()
+        );
+
+        // The existing user is back in its table
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let writer_conn = pool.acquire().await.unwrap().detach();
+        MasWriter::new(locked_main_conn, vec![writer_conn], false)
+            .await
+            .expect("a new migration should start");
+    }
+
+    /// Tests writing a single user, without a password.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_write_user(pool: PgPool) {
+        let mut writer = make_mas_writer(&pool).await;
+        let mut buffer = MasWriteBuffer::new(&writer);
+
+        buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish MasWriter");
+
+        let mut conn = writer
+            .finish(&Progress::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -1366,6 +2780,78 @@ mod test {
         assert_db_snapshot!(&mut conn);
     }
 
+    /// Tests writing a single user, with an e-mail address whose original
+    /// value was kept as a migration note.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_write_user_with_email_migration_note(pool: PgPool) {
+        let mut writer = make_mas_writer(&pool).await;
+
+        let mut user_buffer = MasWriteBuffer::new(&writer);
+        let mut email_buffer = MasWriteBuffer::new(&writer);
+        let mut note_buffer = MasWriteBuffer::new(&writer);
+
+        user_buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        email_buffer
+            .write(
+                &mut writer,
+                MasNewEmailThreepid {
+                    user_email_id: Uuid::from_u128(2u128),
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    email: "alice@example.org".to_owned(),
+                    created_at: DateTime::default(),
+                },
+            )
+            .await
+            .expect("failed to write e-mail");
+
+        note_buffer
+            .write(
+                &mut writer,
+                MasNewUserEmailMigrationNote {
+                    user_email_id: Uuid::from_u128(2u128),
+                    field: "email".to_owned(),
+                    raw_value: " Alice@Example.org".to_owned(),
+                },
+            )
+            .await
+            .expect("failed to write e-mail migration note");
+
+        user_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+        email_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish email buffer");
+        note_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish note buffer");
+
+        let mut conn = writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        assert_db_snapshot!(&mut conn);
+    }
+
     /// Tests writing a single user, with a unsupported third-party ID
     /// associated.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
@@ -1421,6 +2907,112 @@ mod test {
         assert_db_snapshot!(&mut conn);
     }
 
+    /// Tests writing a single user, with some account data.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_write_user_with_account_data(pool: PgPool) {
+        let mut writer = make_mas_writer(&pool).await;
+
+        let mut user_buffer = MasWriteBuffer::new(&writer);
+        let mut account_data_buffer = MasWriteBuffer::new(&writer);
+
+        user_buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        account_data_buffer
+            .write(
+                &mut writer,
+                MasNewAccountData {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    account_data_type: "im.vector.setting.breadcrumbs".to_owned(),
+                    content: r#"{"recent_rooms":["!room:example.com"]}"#.to_owned(),
+                },
+            )
+            .await
+            .expect("failed to write account data");
+
+        user_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+        account_data_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish account data buffer");
+
+        let mut conn = writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        assert_db_snapshot!(&mut conn);
+    }
+
+    /// Tests writing a single user, with a sync filter.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_write_user_with_filter(pool: PgPool) {
+        let mut writer = make_mas_writer(&pool).await;
+
+        let mut user_buffer = MasWriteBuffer::new(&writer);
+        let mut filter_buffer = MasWriteBuffer::new(&writer);
+
+        user_buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        filter_buffer
+            .write(
+                &mut writer,
+                MasNewUserFilter {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    filter_id: 0,
+                    filter_json: br#"{"room":{"timeline":{"limit":10}}}"#.to_vec(),
+                },
+            )
+            .await
+            .expect("failed to write user filter");
+
+        user_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+        filter_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user filter buffer");
+
+        let mut conn = writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        assert_db_snapshot!(&mut conn);
+    }
+
     /// Tests writing a single user, with a link to an upstream provider.
     /// There needs to be an upstream provider in the database already — in the
     /// real migration, this is done by running a provider sync first.
@@ -1509,6 +3101,7 @@ mod test {
                     user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
                     session_id: Uuid::from_u128(5u128),
                     created_at: DateTime::default(),
+                    finished_at: None,
                     device_id: Some("ADEVICE".to_owned()),
                     human_name: Some("alice's pinephone".to_owned()),
                     is_synapse_admin: true,
@@ -1537,14 +3130,59 @@ mod test {
         assert_db_snapshot!(&mut conn);
     }
 
-    /// Tests writing a single user, with a device and an access token.
+    /// Tests loading the users written by a prior migration.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
-    async fn test_write_user_with_access_token(pool: PgPool) {
+    async fn test_load_user_localpart_map(pool: PgPool) {
+        const USER_ID: NonNilUuid = NonNilUuid::new(Uuid::from_u128(1u128)).unwrap();
+
+        let mut writer = make_mas_writer(&pool).await;
+        let mut buffer = MasWriteBuffer::new(&writer);
+
+        buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: USER_ID,
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: Some(DateTime::default()),
+                    can_request_admin: true,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish MasWriteBuffer");
+
+        writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        let mut writer = make_mas_writer(&pool).await;
+        let users = writer
+            .load_user_localpart_map()
+            .await
+            .expect("failed to load users");
+
+        assert_eq!(users.len(), 1);
+        let alice = users["alice"];
+        assert_eq!(alice.user_id, USER_ID);
+    }
+
+    /// Tests writing a migration note about a device (compat session).
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_write_migration_note(pool: PgPool) {
         let mut writer = make_mas_writer(&pool).await;
 
         let mut user_buffer = MasWriteBuffer::new(&writer);
         let mut session_buffer = MasWriteBuffer::new(&writer);
-        let mut token_buffer = MasWriteBuffer::new(&writer);
+        let mut note_buffer = MasWriteBuffer::new(&writer);
 
         user_buffer
             .write(
@@ -1569,6 +3207,7 @@ mod test {
                     user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
                     session_id: Uuid::from_u128(5u128),
                     created_at: DateTime::default(),
+                    finished_at: None,
                     device_id: Some("ADEVICE".to_owned()),
                     human_name: None,
                     is_synapse_admin: false,
@@ -1580,19 +3219,17 @@ mod test {
             .await
             .expect("failed to write compat session");
 
-        token_buffer
+        note_buffer
             .write(
                 &mut writer,
-                MasNewCompatAccessToken {
-                    token_id: Uuid::from_u128(6u128),
+                MasNewMigrationNote {
                     session_id: Uuid::from_u128(5u128),
-                    access_token: "syt_zxcvzxcvzxcvzxcv_zxcv".to_owned(),
-                    created_at: DateTime::default(),
-                    expires_at: None,
+                    field: "last_active_ip".to_owned(),
+                    raw_value: "not an IP".to_owned(),
                 },
             )
             .await
-            .expect("failed to write access token");
+            .expect("failed to write migration note");
 
         user_buffer
             .finish(&mut writer)
@@ -1602,10 +3239,10 @@ mod test {
             .finish(&mut writer)
             .await
             .expect("failed to finish session buffer");
-        token_buffer
+        note_buffer
             .finish(&mut writer)
             .await
-            .expect("failed to finish token buffer");
+            .expect("failed to finish note buffer");
 
         let mut conn = writer
             .finish(&Progress::default())
@@ -1615,7 +3252,152 @@ mod test {
         assert_db_snapshot!(&mut conn);
     }
 
-    /// Tests writing a single user, with a device, an access token and a
+    /// Tests exporting the written rows, leaving out the password hash.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_export_rows(pool: PgPool) {
+        const USER_ID: NonNilUuid = NonNilUuid::new(Uuid::from_u128(1u128)).unwrap();
+
+        let export_path = camino::Utf8PathBuf::try_from(std::env::temp_dir())
+            .unwrap()
+            .join(format!("syn2mas-export-{}.jsonl", std::process::id()));
+
+        let mut writer = make_mas_writer(&pool).await;
+        writer
+            .export_rows_to(&export_path)
+            .expect("failed to open export file");
+
+        let mut user_buffer = MasWriteBuffer::new(&writer);
+        let mut password_buffer = MasWriteBuffer::new(&writer);
+
+        user_buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: USER_ID,
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        password_buffer
+            .write(
+                &mut writer,
+                MasNewUserPassword {
+                    user_password_id: Uuid::from_u128(42u128),
+                    user_id: USER_ID,
+                    hashed_password: "$bcrypt$aaaaaaaaaaa".to_owned(),
+                    created_at: DateTime::default(),
+                },
+            )
+            .await
+            .expect("failed to write password");
+
+        user_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+        password_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish password buffer");
+
+        writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        let export = std::fs::read_to_string(&export_path).expect("failed to read export file");
+        std::fs::remove_file(&export_path).expect("failed to remove export file");
+
+        insta::assert_snapshot!(export);
+    }
+
+    /// Tests writing a single user, with a device and an access token.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_write_user_with_access_token(pool: PgPool) {
+        let mut writer = make_mas_writer(&pool).await;
+
+        let mut user_buffer = MasWriteBuffer::new(&writer);
+        let mut session_buffer = MasWriteBuffer::new(&writer);
+        let mut token_buffer = MasWriteBuffer::new(&writer);
+
+        user_buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        session_buffer
+            .write(
+                &mut writer,
+                MasNewCompatSession {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    session_id: Uuid::from_u128(5u128),
+                    created_at: DateTime::default(),
+                    finished_at: None,
+                    device_id: Some("ADEVICE".to_owned()),
+                    human_name: None,
+                    is_synapse_admin: false,
+                    last_active_at: None,
+                    last_active_ip: None,
+                    user_agent: None,
+                },
+            )
+            .await
+            .expect("failed to write compat session");
+
+        token_buffer
+            .write(
+                &mut writer,
+                MasNewCompatAccessToken {
+                    token_id: Uuid::from_u128(6u128),
+                    session_id: Uuid::from_u128(5u128),
+                    access_token: "syt_zxcvzxcvzxcvzxcv_zxcv".to_owned(),
+                    created_at: DateTime::default(),
+                    expires_at: None,
+                },
+            )
+            .await
+            .expect("failed to write access token");
+
+        user_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+        session_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish session buffer");
+        token_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish token buffer");
+
+        let mut conn = writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        assert_db_snapshot!(&mut conn);
+    }
+
+    /// Tests writing a single user, with a device, an access token and a
     /// refresh token.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_write_user_with_refresh_token(pool: PgPool) {
@@ -1649,6 +3431,7 @@ mod test {
                     user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
                     session_id: Uuid::from_u128(5u128),
                     created_at: DateTime::default(),
+                    finished_at: None,
                     device_id: Some("ADEVICE".to_owned()),
                     human_name: None,
                     is_synapse_admin: false,
@@ -1712,4 +3495,453 @@ mod test {
 
         assert_db_snapshot!(&mut conn);
     }
+
+    /// Tests that rows referencing missing rows are found before the
+    /// constraints are restored.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_verify_referential_integrity(pool: PgPool) {
+        let mut writer = make_mas_writer(&pool).await;
+
+        let mut user_buffer = MasWriteBuffer::new(&writer);
+        let mut email_buffer = MasWriteBuffer::new(&writer);
+        let mut token_buffer = MasWriteBuffer::new(&writer);
+
+        user_buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        // One email of an existing user, one of a missing user
+        for (user_email_id, user_id) in [(2u128, 1u128), (3u128, 4u128)] {
+            email_buffer
+                .write(
+                    &mut writer,
+                    MasNewEmailThreepid {
+                        user_email_id: Uuid::from_u128(user_email_id),
+                        user_id: NonNilUuid::new(Uuid::from_u128(user_id)).unwrap(),
+                        email: "alice@example.org".to_owned(),
+                        created_at: DateTime::default(),
+                    },
+                )
+                .await
+                .expect("failed to write e-mail");
+        }
+
+        // An access token of a missing session
+        token_buffer
+            .write(
+                &mut writer,
+                MasNewCompatAccessToken {
+                    token_id: Uuid::from_u128(6u128),
+                    session_id: Uuid::from_u128(5u128),
+                    access_token: "syt_zxcvzxcvzxcvzxcv_zxcv".to_owned(),
+                    created_at: DateTime::default(),
+                    expires_at: None,
+                },
+            )
+            .await
+            .expect("failed to write access token");
+
+        user_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+        email_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish email buffer");
+        token_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish access token buffer");
+
+        let orphaned_rows = writer
+            .verify_referential_integrity()
+            .await
+            .expect("failed to verify referential integrity");
+
+        assert_eq!(
+            orphaned_rows,
+            OrphanedRows {
+                access_tokens: 1,
+                refresh_tokens: 0,
+                threepids: 1,
+                upstream_oauth_links: 0,
+            }
+        );
+    }
+
+    /// Tests writing more batches at once than there are writer connections.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_write_with_batches_in_flight(pool: PgPool) {
+        let mut writer = make_mas_writer(&pool).await;
+        writer.set_batch_size(NonZeroUsize::new(1).unwrap());
+        writer.set_max_in_flight_batches(NonZeroU32::new(4).unwrap());
+
+        let mut buffer = MasWriteBuffer::new(&writer);
+        for i in 1..=10u128 {
+            buffer
+                .write(
+                    &mut writer,
+                    MasNewUser {
+                        user_id: NonNilUuid::new(Uuid::from_u128(i)).unwrap(),
+                        username: format!("user{i}"),
+                        created_at: DateTime::default(),
+                        locked_at: None,
+                        deactivated_at: None,
+                        can_request_admin: false,
+                        is_guest: false,
+                    },
+                )
+                .await
+                .expect("failed to write user");
+        }
+        buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish MasWriteBuffer");
+
+        let mut conn = writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 10);
+    }
+
+    /// Tests that re-writing the compat sessions of a prior run in upsert mode
+    /// skips the existing rows.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_upsert_compat_sessions(pool: PgPool) {
+        const USER_ID: NonNilUuid = NonNilUuid::new(Uuid::from_u128(1u128)).unwrap();
+
+        fn session(session_id: u128) -> MasNewCompatSession {
+            MasNewCompatSession {
+                user_id: USER_ID,
+                session_id: Uuid::from_u128(session_id),
+                created_at: DateTime::default(),
+                finished_at: None,
+                device_id: Some(format!("DEVICE{session_id}")),
+                human_name: None,
+                is_synapse_admin: false,
+                last_active_at: None,
+                last_active_ip: None,
+                user_agent: None,
+            }
+        }
+
+        let mut writer = make_mas_writer(&pool).await;
+        let mut user_buffer = MasWriteBuffer::new(&writer);
+        let mut session_buffer = MasWriteBuffer::new(&writer);
+
+        user_buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: USER_ID,
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+        session_buffer
+            .write(&mut writer, session(5))
+            .await
+            .expect("failed to write compat session");
+
+        user_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+        session_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish session buffer");
+        writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        // Re-run the devices, with one already written by the prior run
+        let mut writer = make_mas_writer(&pool).await;
+        writer
+            .set_write_mode(WriteMode::Upsert)
+            .await
+            .expect("failed to set the write mode");
+        let mut session_buffer = MasWriteBuffer::new(&writer);
+
+        for session_id in [5, 6] {
+            session_buffer
+                .write(&mut writer, session(session_id))
+                .await
+                .expect("failed to write compat session");
+        }
+        session_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish session buffer");
+
+        let mut conn = writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM compat_sessions")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    /// Writes compat sessions and access tokens with `COPY` in the given
+    /// format, and checks that they read back the same.
+    async fn check_copy_round_trip(pool: PgPool, format: CopyFormat) {
+        const USER_ID: NonNilUuid = NonNilUuid::new(Uuid::from_u128(1u128)).unwrap();
+
+        let timestamp = DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap();
+        let ips: [Option<IpAddr>; 3] = [
+            Some("203.0.113.1".parse().unwrap()),
+            Some("2001:db8::1".parse().unwrap()),
+            None,
+        ];
+
+        let mut writer = make_mas_writer(&pool).await;
+        writer.set_copy_format(Some(format));
+        let mut user_buffer = MasWriteBuffer::new(&writer);
+        let mut session_buffer = MasWriteBuffer::new(&writer);
+        let mut token_buffer = MasWriteBuffer::new(&writer);
+
+        user_buffer
+            .write(
+                &mut writer,
+                MasNewUser {
+                    user_id: USER_ID,
+                    username: "alice".to_owned(),
+                    created_at: DateTime::default(),
+                    locked_at: None,
+                    deactivated_at: None,
+                    can_request_admin: false,
+                    is_guest: false,
+                },
+            )
+            .await
+            .expect("failed to write user");
+
+        for (session_id, ip) in (5u128..).zip(ips) {
+            session_buffer
+                .write(
+                    &mut writer,
+                    MasNewCompatSession {
+                        user_id: USER_ID,
+                        session_id: Uuid::from_u128(session_id),
+                        created_at: timestamp,
+                        finished_at: None,
+                        device_id: Some(format!("DEVICE{session_id}")),
+                        human_name: Some("alice's\tphone".to_owned()),
+                        is_synapse_admin: session_id == 5,
+                        last_active_at: ip.map(|_| timestamp),
+                        last_active_ip: ip,
+                        user_agent: None,
+                    },
+                )
+                .await
+                .expect("failed to write compat session");
+        }
+
+        token_buffer
+            .write(
+                &mut writer,
+                MasNewCompatAccessToken {
+                    token_id: Uuid::from_u128(10u128),
+                    session_id: Uuid::from_u128(5u128),
+                    access_token: "syt_zxcvzxcvzxcvzxcv_zxcv".to_owned(),
+                    created_at: timestamp,
+                    expires_at: None,
+                },
+            )
+            .await
+            .expect("failed to write access token");
+
+        user_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+        session_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish session buffer");
+        token_buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish token buffer");
+
+        let mut conn = writer
+            .finish(&Progress::default())
+            .await
+            .expect("failed to finish MasWriter");
+
+        let rows = sqlx::query(
+            "SELECT host(last_active_ip) AS host, last_active_at, created_at, human_name, \
+                is_synapse_admin FROM compat_sessions ORDER BY compat_session_id",
+        )
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 3);
+        for (row, ip) in rows.into_iter().zip(ips) {
+            assert_eq!(
+                row.get::<Option<String>, _>("host"),
+                ip.map(|ip| ip.to_string())
+            );
+            assert_eq!(
+                row.get::<Option<DateTime<Utc>>, _>("last_active_at"),
+                ip.map(|_| timestamp)
+            );
+            assert_eq!(row.get::<DateTime<Utc>, _>("created_at"), timestamp);
+            assert_eq!(row.get::<String, _>("human_name"), "alice's\tphone");
+            assert_eq!(row.get::<bool, _>("is_synapse_admin"), ip == ips[0]);
+        }
+
+        let (access_token, created_at, expires_at): (String, DateTime<Utc>, Option<DateTime<Utc>>) =
+            sqlx::query_as("SELECT access_token, created_at, expires_at FROM compat_access_tokens")
+                .fetch_one(&mut conn)
+                .await
+                .unwrap();
+        assert_eq!(access_token, "syt_zxcvzxcvzxcvzxcv_zxcv");
+        assert_eq!(created_at, timestamp);
+        assert_eq!(expires_at, None);
+    }
+
+    /// Tests writing compat sessions and access tokens with a text `COPY`.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_copy_text(pool: PgPool) {
+        check_copy_round_trip(pool, CopyFormat::Text).await;
+    }
+
+    /// Tests writing compat sessions and access tokens with a binary `COPY`.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_copy_binary(pool: PgPool) {
+        check_copy_round_trip(pool, CopyFormat::Binary).await;
+    }
+
+    /// A user which fails to be written the first time, after inserting it,
+    /// with the error Postgres raises on a deadlock.
+    #[derive(Serialize)]
+    struct DeadlockingUser {
+        #[serde(skip)]
+        attempts: Arc<AtomicU32>,
+        user: MasNewUser,
+    }
+
+    impl WriteBatch for DeadlockingUser {
+        const TABLE: &'static str = "users";
+
+        async fn write_batch(conn: &mut PgConnection, batch: &[Self]) -> Result<(), Error> {
+            let users: Vec<MasNewUser> = batch.iter().map(|row| row.user.clone()).collect();
+            MasNewUser::write_batch(conn, &users).await?;
+
+            if batch[0].attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                conn.execute(
+                    "DO $$ BEGIN RAISE EXCEPTION 'injected deadlock' \
+                    USING ERRCODE = 'deadlock_detected'; END $$",
+                )
+                .await
+                .into_database("injecting a deadlock")?;
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Writes a single [`DeadlockingUser`] with the given number of retries,
+    /// returning the number of attempts and the outcome of the migration.
+    async fn write_deadlocking_user(
+        pool: &PgPool,
+        batch_retries: u32,
+    ) -> (u32, Result<PgConnection, Error>) {
+        let attempts = Arc::new(AtomicU32::new(0));
+
+        let mut writer = make_mas_writer(pool).await;
+        writer.set_batch_retries(batch_retries);
+        let mut buffer = MasWriteBuffer::new(&writer);
+        buffer
+            .write(
+                &mut writer,
+                DeadlockingUser {
+                    attempts: Arc::clone(&attempts),
+                    user: MasNewUser {
+                        user_id: NonNilUuid::new(Uuid::from_u128(1u128)).unwrap(),
+                        username: "alice".to_owned(),
+                        created_at: DateTime::default(),
+                        locked_at: None,
+                        deactivated_at: None,
+                        can_request_admin: false,
+                        is_guest: false,
+                    },
+                },
+            )
+            .await
+            .expect("failed to write user");
+        buffer
+            .finish(&mut writer)
+            .await
+            .expect("failed to finish user buffer");
+
+        let result = writer.finish(&Progress::default()).await;
+        (attempts.load(Ordering::SeqCst), result)
+    }
+
+    /// Tests that a batch failing on a deadlock is rolled back and retried.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_retry_deadlocked_batch(pool: PgPool) {
+        let (attempts, result) = write_deadlocking_user(&pool, 1).await;
+        let mut conn = result.expect("failed to finish MasWriter");
+        assert_eq!(attempts, 2);
+
+        // The user inserted by the first attempt was rolled back
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    /// Tests that a batch failing on a deadlock fails the migration when it
+    /// isn't retried.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_deadlocked_batch_without_retries(pool: PgPool) {
+        let (attempts, result) = write_deadlocking_user(&pool, 0).await;
+        assert_eq!(attempts, 1);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_write_mode() {
+        assert_eq!(
+            "insert-only".parse::<WriteMode>().unwrap(),
+            WriteMode::InsertOnly
+        );
+        assert_eq!("upsert".parse::<WriteMode>().unwrap(), WriteMode::Upsert);
+        assert!("merge".parse::<WriteMode>().is_err());
+    }
 }
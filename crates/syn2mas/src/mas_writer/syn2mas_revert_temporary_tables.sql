@@ -7,6 +7,8 @@
 
 DROP TABLE syn2mas_restore_constraints;
 DROP TABLE syn2mas_restore_indices;
+-- Migrations started by older versions of syn2mas don't have this one
+DROP TABLE IF EXISTS syn2mas_restore_state;
 
 ALTER TABLE syn2mas__users RENAME TO users;
 ALTER TABLE syn2mas__user_passwords RENAME TO user_passwords;
@@ -16,3 +18,8 @@ ALTER TABLE syn2mas__upstream_oauth_links RENAME TO upstream_oauth_links;
 ALTER TABLE syn2mas__compat_sessions RENAME TO compat_sessions;
 ALTER TABLE syn2mas__compat_access_tokens RENAME TO compat_access_tokens;
 ALTER TABLE syn2mas__compat_refresh_tokens RENAME TO compat_refresh_tokens;
+-- Migrations started by older versions of syn2mas don't have these ones
+ALTER TABLE IF EXISTS syn2mas__user_synapse_account_data RENAME TO user_synapse_account_data;
+ALTER TABLE IF EXISTS syn2mas__user_synapse_filters RENAME TO user_synapse_filters;
+ALTER TABLE IF EXISTS syn2mas__compat_session_migration_notes RENAME TO compat_session_migration_notes;
+ALTER TABLE IF EXISTS syn2mas__user_email_migration_notes RENAME TO user_email_migration_notes;
@@ -36,12 +36,22 @@ CREATE TABLE syn2mas_restore_indices (
     definition TEXT NOT NULL
 );
 
+-- whether the tables touched by the migration already had rows when it
+-- started, in which case they can't be truncated to start over
+CREATE TABLE syn2mas_restore_state (
+    had_existing_data BOOLEAN NOT NULL
+);
+
 -- Now we rename all tables that we touch during the migration.
 ALTER TABLE users RENAME TO syn2mas__users;
 ALTER TABLE user_passwords RENAME TO syn2mas__user_passwords;
 ALTER TABLE user_emails RENAME TO syn2mas__user_emails;
 ALTER TABLE user_unsupported_third_party_ids RENAME TO syn2mas__user_unsupported_third_party_ids;
+ALTER TABLE user_synapse_account_data RENAME TO syn2mas__user_synapse_account_data;
+ALTER TABLE user_synapse_filters RENAME TO syn2mas__user_synapse_filters;
 ALTER TABLE upstream_oauth_links RENAME TO syn2mas__upstream_oauth_links;
 ALTER TABLE compat_sessions RENAME TO syn2mas__compat_sessions;
 ALTER TABLE compat_access_tokens RENAME TO syn2mas__compat_access_tokens;
 ALTER TABLE compat_refresh_tokens RENAME TO syn2mas__compat_refresh_tokens;
+ALTER TABLE compat_session_migration_notes RENAME TO syn2mas__compat_session_migration_notes;
+ALTER TABLE user_email_migration_notes RENAME TO syn2mas__user_email_migration_notes;
@@ -10,14 +10,98 @@
 //!
 //! This module does not implement any of the safety checks that should be run
 //! *before* the migration.
-
-use std::time::Instant;
+//!
+//! ## Transactional guarantees
+//!
+//! Before anything is written, the [`MasWriter`] renames the MAS tables it
+//! writes to with a `syn2mas__` prefix, and saves the indices and constraints
+//! it drops in the `syn2mas_restore_*` tables. The migrated rows are therefore
+//! never visible to MAS until [`MasWriter::finish`] restores the indices and
+//! constraints and renames the tables back, in a single transaction.
+//!
+//! All the rows are written through the [`MasWriter`]'s writer connections,
+//! each of which holds a single transaction for all the phases. These
+//! transactions are committed once every phase has completed, one after the
+//! other, by [`MasWriter::verify_referential_integrity`]: the integrity check
+//! and the row count check which follow it need to see the rows written by
+//! every connection, which a transaction can't see in the others until they
+//! are committed. This means that:
+//!
+//! - a migration interrupted during a phase leaves none of its rows behind;
+//! - a migration failing the integrity or row count checks, or interrupted
+//!   while committing, leaves some or all of its rows committed in the
+//!   `syn2mas__` tables, where MAS can't see them.
+//!
+//! Either way, a migration can't be resumed: a phase depends on the in-memory
+//! state built by the previous ones, and the committed rows may be incomplete.
+//! When a new [`MasWriter`] finds the leftovers of a migration which didn't
+//! finish (the `syn2mas_restore_*` tables), it truncates the `syn2mas__` tables
+//! and starts over from scratch. If the MAS database already had data when
+//! that migration started, like the users reused by
+//! [`MigrationOptions::existing_users`], it fails instead, as truncating the
+//! tables would lose that data.
+//!
+//! Holding the writer transactions open for all the phases is a tradeoff. It
+//! doesn't cost memory on the Postgres side, as uncommitted rows are written
+//! to the tables like committed ones, only invisible to other transactions.
+//! However, the writer connections keep their locks on the `syn2mas__` tables
+//! and hold back `VACUUM` on the whole MAS database until the phases are
+//! done, and a failure late in a multi-million-row import throws away all of
+//! the work done so far.
+//!
+//! Per-phase savepoints wouldn't make a failure any cheaper: a phase can't be
+//! retried on its own, as it depends on the in-memory `MigrationState`
+//! built by the previous phases (the user and device lookup tables), so a
+//! failed phase aborts the whole migration anyway.
+//!
+//! ## Concurrency
+//!
+//! The phases run one after the other, because the [`SynapseReader`] reads
+//! everything from a single Synapse transaction, so that the whole migration
+//! sees one consistent snapshot of the Synapse database, protected by the
+//! table locks taken by that transaction. Only one query can stream rows from
+//! that transaction at a time.
+//!
+//! Within a phase, writes are already concurrent: each [`MasWriteBuffer`]
+//! hands its batches over to the [`MasWriter`]'s pool of writer connections,
+//! while the phase keeps on reading from Synapse.
+//!
+//! ## Memory usage
+//!
+//! The lookup table of the users grows with the number of users, and is read
+//! for every row of the phases after the users one. It is kept in memory by
+//! default, which is the fastest. With [`UserMapBacking::OnDisk`], it is kept
+//! in files instead: every lookup then reads from them, relying on the page
+//! cache of the operating system, which makes those phases slower but caps
+//! the memory used by the table.
+//!
+//! ## Erased users
+//!
+//! Users deactivated with the `erase` flag in Synapse are listed in its
+//! `erased_users` table: they asked for their personal data to be forgotten,
+//! as allowed by the GDPR. The migration honours that by migrating nothing but
+//! the user itself, deactivated and locked, so that its localpart can't be
+//! reused. Their third-party IDs, upstream links, account data and sync
+//! filters are skipped, as well as their password, and their devices and
+//! tokens are skipped like for any deactivated user.
+
+mod user_map;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
+    num::{NonZeroU32, NonZeroUsize},
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
 use chrono::{DateTime, Utc};
 use compact_str::CompactString;
 use futures_util::{SinkExt, StreamExt as _, TryFutureExt, TryStreamExt as _};
 use mas_storage::Clock;
 use rand::{RngCore, SeedableRng};
+use regex::RegexSet;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use thiserror_ext::ContextInto;
 use tokio_util::sync::PollSender;
@@ -25,17 +109,22 @@ use tracing::{Instrument as _, Level, info};
 use ulid::Ulid;
 use uuid::{NonNilUuid, Uuid};
 
+use self::user_map::UserMap;
+pub use self::user_map::UserMapBacking;
 use crate::{
-    HashMap, ProgressCounter, RandomState, SynapseReader,
+    HashMap, HashSet, ProgressCounter, RandomState, SynapseReader,
     mas_writer::{
-        self, MasNewCompatAccessToken, MasNewCompatRefreshToken, MasNewCompatSession,
-        MasNewEmailThreepid, MasNewUnsupportedThreepid, MasNewUpstreamOauthLink, MasNewUser,
-        MasNewUserPassword, MasWriteBuffer, MasWriter,
+        self, CopyFormat, MasExistingUser, MasNewAccountData, MasNewCompatAccessToken,
+        MasNewCompatRefreshToken, MasNewCompatSession, MasNewEmailThreepid, MasNewMigrationNote,
+        MasNewUnsupportedThreepid, MasNewUpstreamOauthLink, MasNewUser,
+        MasNewUserEmailMigrationNote, MasNewUserFilter, MasNewUserPassword, MasWriteBuffer,
+        MasWriter, OrphanedRows, WriteMode,
     },
     progress::{EntityType, Progress},
     synapse_reader::{
-        self, ExtractLocalpartError, FullUserId, SynapseAccessToken, SynapseDevice,
-        SynapseExternalId, SynapseRefreshableTokenPair, SynapseThreepid, SynapseUser,
+        self, CountMode, ExtractLocalpartError, FullUserId, RawText, SynapseAccessToken,
+        SynapseAccountData, SynapseDevice, SynapseExternalId, SynapseRefreshableTokenPair,
+        SynapseRowCounts, SynapseThreepid, SynapseUser, SynapseUserFilter,
     },
 };
 
@@ -56,6 +145,8 @@ pub enum Error {
         source: ExtractLocalpartError,
         user: FullUserId,
     },
+    #[error("user {user} has a localpart which MAS doesn't allow: {reason}")]
+    InvalidUsername { user: FullUserId, reason: String },
     #[error("channel closed")]
     ChannelClosed,
 
@@ -77,6 +168,74 @@ pub enum Error {
         /// a user that is using this auth provider
         user: FullUserId,
     },
+    #[error(
+        "found users with the server name {found:?} in the Synapse database, but the configured server name is {expected:?}"
+    )]
+    UnexpectedServerName { found: String, expected: String },
+    #[error(
+        "Synapse is configured with the server name {synapse:?}, but the migration was asked to use {mas:?}"
+    )]
+    ServerNameMismatch { synapse: String, mas: String },
+    #[error("user {user} has the email address {email:?} several times, once normalized")]
+    DuplicateEmail { user: FullUserId, email: String },
+    #[error("users {first_user} and {second_user} both map to the MAS localpart {localpart:?}")]
+    DuplicateLocalpart {
+        localpart: String,
+        first_user: FullUserId,
+        second_user: FullUserId,
+    },
+    #[error("the migration left rows referencing missing rows: {0}")]
+    OrphanedRows(OrphanedRows),
+    #[error("failed to access the lookup table of the users ({context}): {source}")]
+    UserMap {
+        source: std::io::Error,
+        context: String,
+    },
+    #[error(
+        "the users must be either migrated by the users phase or loaded from a prior run, but not both or neither"
+    )]
+    InvalidUsersSource,
+    #[error("the {phase} phase didn't finish within {elapsed:?}")]
+    PhaseTimeout { phase: Phase, elapsed: Duration },
+    #[error("user {user} has a password hash which MAS can't verify (scheme {scheme:?})")]
+    UnknownPasswordScheme { user: FullUserId, scheme: String },
+    #[error(
+        "Synapse had {expected} rows in {table}, but {actual} were migrated and {skipped} skipped"
+    )]
+    CountMismatch {
+        table: String,
+        expected: u64,
+        actual: u64,
+        skipped: u64,
+    },
+}
+
+impl Error {
+    /// A stable code identifying the kind of error, for tools which need to
+    /// tell them apart without parsing the message
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Synapse { .. } => "synapse_database",
+            Self::Mas { .. } => "mas_database",
+            Self::ExtractLocalpart { .. } => "extract_localpart",
+            Self::InvalidUsername { .. } => "invalid_username",
+            Self::ChannelClosed => "channel_closed",
+            Self::Join { .. } => "task_failed",
+            Self::MissingUserFromDependentTable { .. } => "missing_user_from_dependent_table",
+            Self::MissingAuthProviderMapping { .. } => "missing_auth_provider_mapping",
+            Self::UnexpectedServerName { .. } => "unexpected_server_name",
+            Self::ServerNameMismatch { .. } => "server_name_mismatch",
+            Self::DuplicateEmail { .. } => "duplicate_email",
+            Self::DuplicateLocalpart { .. } => "duplicate_localpart",
+            Self::OrphanedRows(_) => "orphaned_rows",
+            Self::UserMap { .. } => "user_map",
+            Self::InvalidUsersSource => "invalid_users_source",
+            Self::PhaseTimeout { .. } => "phase_timeout",
+            Self::UnknownPasswordScheme { .. } => "unknown_password_scheme",
+            Self::CountMismatch { .. } => "count_mismatch",
+        }
+    }
 }
 
 bitflags::bitflags! {
@@ -86,6 +245,8 @@ bitflags::bitflags! {
         const IS_DEACTIVATED = 0b0000_0010;
         const IS_GUEST = 0b0000_0100;
         const IS_APPSERVICE = 0b0000_1000;
+        const IN_APPSERVICE_NAMESPACE = 0b0001_0000;
+        const IS_ERASED = 0b0010_0000;
     }
 }
 
@@ -105,6 +266,14 @@ impl UserFlags {
     const fn is_appservice(self) -> bool {
         self.contains(UserFlags::IS_APPSERVICE)
     }
+
+    const fn is_in_appservice_namespace(self) -> bool {
+        self.contains(UserFlags::IN_APPSERVICE_NAMESPACE)
+    }
+
+    const fn is_erased(self) -> bool {
+        self.contains(UserFlags::IS_ERASED)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -113,12 +282,1025 @@ struct UserInfo {
     flags: UserFlags,
 }
 
+/// Generates the IDs of the migrated rows.
+#[derive(Debug, Clone, Copy)]
+struct IdGenerator {
+    /// Whether to derive the random part of the IDs from the identity of the
+    /// Synapse rows, instead of using the RNG
+    deterministic: bool,
+}
+
+impl IdGenerator {
+    /// Generate the ID of a row created at `created_at`, which is uniquely
+    /// identified in Synapse by the `identity` parts.
+    fn ulid(self, created_at: DateTime<Utc>, identity: &[&str], rng: &mut impl RngCore) -> Ulid {
+        if !self.deterministic {
+            return Ulid::from_datetime_with_source(created_at.into(), rng);
+        }
+
+        let mut hasher = Sha256::new();
+        for part in identity {
+            hasher.update(part.as_bytes());
+            // Separate the parts, so that ("ab", "c") and ("a", "bc") don't collide
+            hasher.update([0]);
+        }
+        let digest = hasher.finalize();
+        let mut random = [0; 16];
+        random.copy_from_slice(&digest[..16]);
+
+        // Like `Ulid::from_datetime`, timestamps before the UNIX epoch are clamped
+        let timestamp_ms = u64::try_from(created_at.timestamp_millis()).unwrap_or(0);
+        Ulid::from_parts(timestamp_ms, u128::from_be_bytes(random))
+    }
+}
+
+/// Policy deciding which timestamp to use for the sessions and tokens which
+/// have no creation time in the Synapse database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FallbackTimestampPolicy {
+    /// Use the time at which the migration runs
+    #[default]
+    Now,
+
+    /// Use a fixed timestamp
+    Fixed(DateTime<Utc>),
+
+    /// Use the creation time of the user owning the session or token
+    UserCreation,
+}
+
+impl FallbackTimestampPolicy {
+    fn timestamp(self, now: DateTime<Utc>, mas_user_id: NonNilUuid) -> DateTime<Utc> {
+        match self {
+            Self::Now => now,
+            Self::Fixed(timestamp) => timestamp,
+            // MAS user IDs are ULIDs generated from the user creation time
+            Self::UserCreation => Ulid::from(mas_user_id.get()).datetime().into(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "invalid fallback timestamp policy {0:?}, expected `now`, `user-creation` or an RFC 3339 timestamp"
+)]
+pub struct InvalidFallbackTimestampPolicy(String);
+
+impl FromStr for FallbackTimestampPolicy {
+    type Err = InvalidFallbackTimestampPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "now" => Ok(Self::Now),
+            "user-creation" => Ok(Self::UserCreation),
+            _ => DateTime::parse_from_rfc3339(s)
+                .map(|timestamp| Self::Fixed(timestamp.to_utc()))
+                .map_err(|_| InvalidFallbackTimestampPolicy(s.to_owned())),
+        }
+    }
+}
+
+/// What to do when a single row can't be migrated, for example because of
+/// an invalid user ID.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Abort the whole migration
+    #[default]
+    Abort,
+
+    /// Skip the row, as well as the rows depending on it, and collect the
+    /// error in [`MigrationReport::row_errors`]
+    Collect,
+}
+
+/// What to do with the external IDs of a Synapse auth provider which has no
+/// matching upstream OAuth 2.0 provider in MAS.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownProviderPolicy {
+    /// Fail with [`Error::MissingAuthProviderMapping`], like any other row
+    /// which can't be migrated
+    #[default]
+    Fail,
+
+    /// Skip the external ID, log the provider once and count it in
+    /// [`MigrationReport::skipped_external_ids`]
+    SkipWithWarning,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid unknown provider policy {0:?}, expected `fail` or `skip-with-warning`")]
+pub struct InvalidUnknownProviderPolicy(String);
+
+impl FromStr for UnknownProviderPolicy {
+    type Err = InvalidUnknownProviderPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(Self::Fail),
+            "skip-with-warning" => Ok(Self::SkipWithWarning),
+            _ => Err(InvalidUnknownProviderPolicy(s.to_owned())),
+        }
+    }
+}
+
+/// What to do with the rows of tables depending on the users table, whose
+/// user is missing from the Synapse `users` table, as left behind by old
+/// Synapse bugs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingUserPolicy {
+    /// Fail with [`Error::MissingUserFromDependentTable`], like any other row
+    /// which can't be migrated
+    #[default]
+    Abort,
+
+    /// Skip the row, and list the table and user in
+    /// [`MigrationReport::missing_users`]
+    SkipAndReport,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid missing user policy {0:?}, expected `abort` or `skip-and-report`")]
+pub struct InvalidMissingUserPolicy(String);
+
+impl FromStr for MissingUserPolicy {
+    type Err = InvalidMissingUserPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "skip-and-report" => Ok(Self::SkipAndReport),
+            _ => Err(InvalidMissingUserPolicy(s.to_owned())),
+        }
+    }
+}
+
+/// What to do with the access tokens created by Synapse admins to act as
+/// another user, which have `puppets_user_id` set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PuppetTokenPolicy {
+    /// Skip the tokens, as MAS has no notion of puppeting
+    #[default]
+    Skip,
+
+    /// Migrate the tokens as tokens of the puppeted user, in deviceless
+    /// compatibility sessions, and record the admin who created them in a
+    /// `puppeted_by` migration note of those sessions
+    RecordNote,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid puppet token policy {0:?}, expected `skip` or `record-note`")]
+pub struct InvalidPuppetTokenPolicy(String);
+
+impl FromStr for PuppetTokenPolicy {
+    type Err = InvalidPuppetTokenPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "record-note" => Ok(Self::RecordNote),
+            _ => Err(InvalidPuppetTokenPolicy(s.to_owned())),
+        }
+    }
+}
+
+/// What to do with the users Synapse shadow-banned, which MAS has no notion
+/// of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShadowBannedPolicy {
+    /// Lock the users, so that they can't log in or use their sessions
+    #[default]
+    Lock,
+
+    /// Migrate the users as regular users, lifting their ban
+    Ignore,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid shadow-banned policy {0:?}, expected `lock` or `ignore`")]
+pub struct InvalidShadowBannedPolicy(String);
+
+impl FromStr for ShadowBannedPolicy {
+    type Err = InvalidShadowBannedPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lock" => Ok(Self::Lock),
+            "ignore" => Ok(Self::Ignore),
+            _ => Err(InvalidShadowBannedPolicy(s.to_owned())),
+        }
+    }
+}
+
+/// Which users to migrate depending on how they log in, so that the users
+/// with a password and the users relying on SSO can be migrated in separate
+/// runs.
+///
+/// Users are told apart by whether Synapse has a password hash for them: a
+/// user with both a password and an external ID is a password user, migrated
+/// along with their upstream OAuth 2.0 links.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UserAuthFilter {
+    /// Migrate all the users
+    #[default]
+    All,
+
+    /// Only migrate the users which have a password
+    PasswordOnly,
+
+    /// Only migrate the users which have no password, and can only log in
+    /// through an upstream OAuth 2.0 provider
+    SsoOnly,
+}
+
+impl UserAuthFilter {
+    /// Whether a user is migrated, depending on whether they have a password
+    const fn includes(self, has_password: bool) -> bool {
+        match self {
+            Self::All => true,
+            Self::PasswordOnly => has_password,
+            Self::SsoOnly => !has_password,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid user auth filter {0:?}, expected `all`, `password-only` or `sso-only`")]
+pub struct InvalidUserAuthFilter(String);
+
+impl FromStr for UserAuthFilter {
+    type Err = InvalidUserAuthFilter;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "password-only" => Ok(Self::PasswordOnly),
+            "sso-only" => Ok(Self::SsoOnly),
+            _ => Err(InvalidUserAuthFilter(s.to_owned())),
+        }
+    }
+}
+
+/// What to do with the password hashes which aren't in the bcrypt format
+/// Synapse uses, and which MAS therefore can't verify.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownPasswordSchemePolicy {
+    /// Fail with [`Error::UnknownPasswordScheme`], like any other row which
+    /// can't be migrated
+    #[default]
+    Fail,
+
+    /// Migrate the users without their password, and count them in
+    /// [`MigrationReport::skipped_passwords`]
+    SkipPassword,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid unknown password scheme policy {0:?}, expected `fail` or `skip-password`")]
+pub struct InvalidUnknownPasswordSchemePolicy(String);
+
+impl FromStr for UnknownPasswordSchemePolicy {
+    type Err = InvalidUnknownPasswordSchemePolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(Self::Fail),
+            "skip-password" => Ok(Self::SkipPassword),
+            _ => Err(InvalidUnknownPasswordSchemePolicy(s.to_owned())),
+        }
+    }
+}
+
+/// What to do with the email addresses of a user which end up the same once
+/// normalized, for example `Alice@example.com` and `alice@example.com`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateEmailPolicy {
+    /// Fail with [`Error::DuplicateEmail`], like any other row which can't be
+    /// migrated
+    #[default]
+    Fail,
+
+    /// Only migrate the first of the duplicate addresses, and count the others
+    /// in [`MigrationReport::skipped_duplicate_emails`]
+    SkipWithWarning,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid duplicate email policy {0:?}, expected `fail` or `skip-with-warning`")]
+pub struct InvalidDuplicateEmailPolicy(String);
+
+impl FromStr for DuplicateEmailPolicy {
+    type Err = InvalidDuplicateEmailPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(Self::Fail),
+            "skip-with-warning" => Ok(Self::SkipWithWarning),
+            _ => Err(InvalidDuplicateEmailPolicy(s.to_owned())),
+        }
+    }
+}
+
+/// What to do with session IP addresses which fail to parse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidIpPolicy {
+    /// Drop the IP address, logging a warning
+    #[default]
+    Drop,
+
+    /// Drop the IP address from the session, but keep the raw value as a
+    /// migration note in the MAS database
+    PreserveRaw,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid IP policy {0:?}, expected `drop` or `preserve-raw`")]
+pub struct InvalidInvalidIpPolicy(String);
+
+impl FromStr for InvalidIpPolicy {
+    type Err = InvalidInvalidIpPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Self::Drop),
+            "preserve-raw" => Ok(Self::PreserveRaw),
+            _ => Err(InvalidInvalidIpPolicy(s.to_owned())),
+        }
+    }
+}
+
+/// What to do with the users whose localpart MAS wouldn't accept as a new
+/// username, which older versions of Synapse allowed.
+///
+/// MAS looks usernames up case-insensitively, so it supports such users as
+/// they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidUsernamePolicy {
+    /// Migrate the users as they are, logging a warning and counting them in
+    /// [`MigrationReport::invalid_usernames`]
+    #[default]
+    Warn,
+
+    /// Fail with [`Error::InvalidUsername`], like any other row which can't
+    /// be migrated
+    Fail,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid username policy {0:?}, expected `warn` or `fail`")]
+pub struct InvalidInvalidUsernamePolicy(String);
+
+impl FromStr for InvalidUsernamePolicy {
+    type Err = InvalidInvalidUsernamePolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "fail" => Ok(Self::Fail),
+            _ => Err(InvalidInvalidUsernamePolicy(s.to_owned())),
+        }
+    }
+}
+
+/// How to anonymize the IP addresses the sessions were last active from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpAnonymization {
+    /// Keep the IP addresses as-is
+    #[default]
+    None,
+
+    /// Zero the bits of IPv4 addresses beyond the given prefix length, keeping
+    /// IPv6 addresses as-is. IPv4-mapped IPv6 addresses are truncated as IPv4
+    /// addresses. Prefix lengths above 32 keep the addresses as-is.
+    TruncateV4(u8),
+
+    /// Zero the bits of IPv6 addresses beyond the given prefix length, keeping
+    /// IPv4 addresses as-is. Prefix lengths above 128 keep the addresses as-is.
+    TruncateV6(u8),
+}
+
+impl IpAnonymization {
+    fn apply(self, ip: IpAddr) -> IpAddr {
+        // Synapse behind a dual-stack listener records IPv4 clients as
+        // IPv4-mapped IPv6 addresses, which must be truncated as IPv4 ones
+        match (self, ip.to_canonical()) {
+            (Self::TruncateV4(prefix), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(prefix.min(32)))
+                    .unwrap_or(0);
+                IpAddr::V4((u32::from(ip) & mask).into())
+            }
+            (Self::TruncateV6(prefix), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(prefix.min(128)))
+                    .unwrap_or(0);
+                IpAddr::V6((u128::from(ip) & mask).into())
+            }
+            _ => ip,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "invalid IP anonymization {0:?}, expected `none`, `v4:<prefix length up to 32>` or `v6:<prefix length up to 128>`"
+)]
+pub struct InvalidIpAnonymization(String);
+
+impl FromStr for IpAnonymization {
+    type Err = InvalidIpAnonymization;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidIpAnonymization(s.to_owned());
+
+        if s == "none" {
+            return Ok(Self::None);
+        }
+
+        let (family, prefix) = s.split_once(':').ok_or_else(invalid)?;
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        match family {
+            "v4" if prefix <= 32 => Ok(Self::TruncateV4(prefix)),
+            "v6" if prefix <= 128 => Ok(Self::TruncateV6(prefix)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A phase of the migration, which can be run on its own with
+/// [`MigrationOptions::only_phases`].
+///
+/// The phases run in the order of [`Phase::all`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Users, with their passwords
+    ///
+    /// Every other phase depends on the users. When this phase doesn't run,
+    /// the users migrated by a prior run are loaded instead, see
+    /// [`MigrationOptions::existing_users`].
+    Users,
+
+    /// Third-party IDs, migrated as email addresses or unsupported
+    /// third-party IDs
+    Threepids,
+
+    /// External IDs, migrated as upstream OAuth 2.0 links
+    ExternalIds,
+
+    /// Global account data, only migrated when asked for, with
+    /// [`MigrationOptions::migrate_account_data`] or
+    /// [`MigrationOptions::only_phases`]
+    AccountData,
+
+    /// Sync filters, only migrated when asked for, with
+    /// [`MigrationOptions::migrate_user_filters`] or
+    /// [`MigrationOptions::only_phases`]
+    UserFilters,
+
+    /// Access tokens without a refresh token, migrated as compatibility
+    /// sessions
+    UnrefreshableTokens,
+
+    /// Access tokens with their refresh token, migrated as compatibility
+    /// sessions
+    RefreshableTokens,
+
+    /// Devices, which reuse the compatibility sessions created for their
+    /// tokens by the token phases of the same run
+    ///
+    /// Without those phases, a new compatibility session is created for each
+    /// device.
+    Devices,
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "invalid migration phase {0:?}, expected `users`, `threepids`, `external-ids`, `account-data`, `user-filters`, `unrefreshable-tokens`, `refreshable-tokens` or `devices`"
+)]
+pub struct InvalidPhase(String);
+
+impl Phase {
+    /// Iterates over every phase, in the order the migration runs them
+    pub fn all() -> impl Iterator<Item = Self> {
+        [
+            Self::Users,
+            Self::Threepids,
+            Self::ExternalIds,
+            Self::AccountData,
+            Self::UserFilters,
+            Self::UnrefreshableTokens,
+            Self::RefreshableTokens,
+            Self::Devices,
+        ]
+        .into_iter()
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Users => "users",
+            Self::Threepids => "threepids",
+            Self::ExternalIds => "external-ids",
+            Self::AccountData => "account-data",
+            Self::UserFilters => "user-filters",
+            Self::UnrefreshableTokens => "unrefreshable-tokens",
+            Self::RefreshableTokens => "refreshable-tokens",
+            Self::Devices => "devices",
+        })
+    }
+}
+
+impl FromStr for Phase {
+    type Err = InvalidPhase;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "users" => Ok(Self::Users),
+            "threepids" => Ok(Self::Threepids),
+            "external-ids" => Ok(Self::ExternalIds),
+            "account-data" => Ok(Self::AccountData),
+            "user-filters" => Ok(Self::UserFilters),
+            "unrefreshable-tokens" => Ok(Self::UnrefreshableTokens),
+            "refreshable-tokens" => Ok(Self::RefreshableTokens),
+            "devices" => Ok(Self::Devices),
+            _ => Err(InvalidPhase(s.to_owned())),
+        }
+    }
+}
+
+/// The longest a phase of the migration can run for, see
+/// [`MigrationOptions::phase_timeouts`], parsed from `<phase>=<seconds>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseTimeout {
+    pub phase: Phase,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid phase timeout {0:?}, expected `<phase>=<seconds>`")]
+pub struct InvalidPhaseTimeout(String);
+
+impl FromStr for PhaseTimeout {
+    type Err = InvalidPhaseTimeout;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidPhaseTimeout(s.to_owned());
+        let (phase, seconds) = s.split_once('=').ok_or_else(invalid)?;
+        let phase = phase.parse().map_err(|_| invalid())?;
+        let seconds: u64 = seconds.parse().map_err(|_| invalid())?;
+        if seconds == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            phase,
+            timeout: Duration::from_secs(seconds),
+        })
+    }
+}
+
+/// A row which couldn't be migrated, collected when using
+/// [`FailureMode::Collect`].
+#[derive(Debug, Error)]
+#[error("failed to migrate a row from `{table}`: {error}")]
+pub struct MigrationRowError {
+    /// The Synapse table the row comes from
+    pub table: &'static str,
+
+    /// Why the row couldn't be migrated
+    #[source]
+    pub error: Error,
+}
+
+/// Options controlling how a migration is performed.
+#[derive(Debug, Clone, Default)]
+#[expect(clippy::struct_excessive_bools)]
+pub struct MigrationOptions {
+    /// Run every migration phase, but discard all the rows instead of writing
+    /// them to the MAS database.
+    ///
+    /// The returned [`MigrationReport`] then tells how many rows of each type
+    /// *would* have been written.
+    pub count_only: bool,
+
+    /// Which timestamp to use for the sessions and tokens which have no
+    /// creation time in the Synapse database
+    pub fallback_timestamp_policy: FallbackTimestampPolicy,
+
+    /// Don't check that the MAS database is empty before migrating
+    ///
+    /// Without this, the migration fails early if there are already users,
+    /// compatibility sessions or upstream links in the MAS database, instead
+    /// of failing on conflicting rows halfway through.
+    pub allow_existing_data: bool,
+
+    /// What to do when a single row can't be migrated
+    pub failure_mode: FailureMode,
+
+    /// What to do with the external IDs of auth providers missing from the
+    /// provider mapping
+    pub unknown_provider_policy: UnknownProviderPolicy,
+
+    /// What to do with the email addresses of a user which are duplicates of
+    /// each other once normalized
+    pub duplicate_email_policy: DuplicateEmailPolicy,
+
+    /// What to do with the rows whose user is missing from the Synapse
+    /// `users` table
+    pub missing_user_policy: MissingUserPolicy,
+
+    /// Migrate the devices Synapse flags as hidden, which are only used to
+    /// store cross-signing keys, as compatibility sessions too
+    pub include_hidden_devices: bool,
+
+    /// Tell apart the devices of a user sharing the same display name, like
+    /// the default names of some clients, by appending the day they were last
+    /// seen to their name
+    ///
+    /// This only changes the name shown in the list of sessions, the device
+    /// IDs are kept as-is.
+    pub dedupe_device_names: bool,
+
+    /// What to do with session IP addresses which fail to parse
+    pub invalid_ip_policy: InvalidIpPolicy,
+
+    /// How to anonymize the session IP addresses. This doesn't apply to the
+    /// raw values kept with [`InvalidIpPolicy::PreserveRaw`]
+    pub ip_anonymization: IpAnonymization,
+
+    /// What to do with the access tokens Synapse admins created to act as
+    /// another user
+    pub puppet_token_policy: PuppetTokenPolicy,
+
+    /// The creation time to use for the users which have none in the Synapse
+    /// database, defaults to the UNIX epoch
+    pub fallback_user_creation_time: DateTime<Utc>,
+
+    /// How many rows to write to the MAS database at once, defaults to 4096
+    ///
+    /// Larger batches mean fewer round-trips to the database, but a higher
+    /// peak memory usage.
+    pub batch_size: Option<NonZeroUsize>,
+
+    /// How many batches can be written to the MAS database or waiting for a
+    /// connection at once, defaults to the number of writer connections
+    ///
+    /// More batches in flight let the migration keep reading from Synapse
+    /// while the MAS database is slow to respond, but mean a higher peak
+    /// memory usage.
+    pub max_in_flight_batches: Option<NonZeroU32>,
+
+    /// How many times a batch is retried after a serialization failure or a
+    /// deadlock in the MAS database, defaults to 3
+    pub batch_retries: Option<u32>,
+
+    /// How to count the Synapse rows, used to report progress and to size
+    /// the lookup tables
+    pub count_mode: CountMode,
+
+    /// If set, only migrate the users with these localparts
+    ///
+    /// The rows of the other tables belonging to other users are skipped.
+    pub user_filter: Option<std::collections::HashSet<String>>,
+
+    /// Only migrate the users with a password, or the users without one
+    ///
+    /// Like with [`MigrationOptions::user_filter`], the rows of the other
+    /// tables belonging to the other users are skipped. The second of the two
+    /// runs needs [`MigrationOptions::allow_existing_data`].
+    pub user_auth_filter: UserAuthFilter,
+
+    /// The user namespaces of the application services, matched against the
+    /// full user IDs
+    ///
+    /// The users matching one of them which weren't registered by the
+    /// application service itself are still migrated, but without their
+    /// password.
+    pub appservice_namespaces: Option<RegexSet>,
+
+    /// The users migrated by a prior run, as loaded by
+    /// [`MasWriter::load_user_localpart_map`]
+    ///
+    /// If set, the users phase doesn't run. The Synapse users are read again
+    /// and matched by localpart to the users of this map, so that the other
+    /// phases treat them like the prior run did, regardless of the changes
+    /// made in MAS since. The rows of the Synapse users missing from it are
+    /// handled according to [`MigrationOptions::missing_user_policy`], unless
+    /// the prior run didn't migrate them on purpose, like the application
+    /// service users.
+    pub existing_users: Option<std::collections::HashMap<String, MasExistingUser>>,
+
+    /// If set, only run these phases
+    ///
+    /// Unless [`MigrationOptions::existing_users`] is set, these must include
+    /// [`Phase::Users`], and the other way around.
+    pub only_phases: Option<std::collections::HashSet<Phase>>,
+
+    /// Also migrate the global account data of the users, which MAS doesn't
+    /// use itself, into a side table
+    ///
+    /// Synapse keeps serving the account data, so the table is only an
+    /// archive of it.
+    pub migrate_account_data: bool,
+
+    /// Also migrate the sync filters of the users, which MAS doesn't use
+    /// itself, into a side table
+    ///
+    /// Synapse keeps serving the sync filters, so the table is only an archive
+    /// of them.
+    pub migrate_user_filters: bool,
+
+    /// Derive the IDs of the migrated rows from the Synapse rows they come
+    /// from, instead of generating them randomly
+    ///
+    /// Two migrations of the same Synapse database then produce the same MAS
+    /// IDs, as long as the timestamps used don't depend on the time of the
+    /// migration, see [`FallbackTimestampPolicy::Now`].
+    pub deterministic_ids: bool,
+
+    /// How the compatibility sessions, their tokens and their migration notes
+    /// are written to the MAS database
+    ///
+    /// [`WriteMode::Upsert`] skips the rows which already exist, so that a
+    /// re-run of the devices phase with [`MigrationOptions::deterministic_ids`]
+    /// doesn't fail on the rows of the prior run.
+    pub write_mode: WriteMode,
+
+    /// If set, write the compatibility sessions and access tokens with a
+    /// `COPY` statement in this format, see [`MasWriter::set_copy_format`]
+    pub copy_format: Option<CopyFormat>,
+
+    /// If set, also write the migrated rows to this file, as JSON Lines
+    ///
+    /// Secrets, like the password hashes and the tokens, are left out.
+    pub export_path: Option<camino::Utf8PathBuf>,
+
+    /// Where to keep the lookup table of the users, which every phase after
+    /// the users one reads for each row
+    pub user_map_backing: UserMapBacking,
+
+    /// The `server_name` Synapse is configured with, checked against the
+    /// server name given to [`migrate`] before anything is migrated
+    pub synapse_server_name: Option<String>,
+
+    /// If set, only the users with these localparts can request admin
+    /// privileges in MAS, instead of the users who were admins in Synapse
+    pub admin_allowlist: Option<std::collections::HashSet<CompactString>>,
+
+    /// What to do with the users Synapse shadow-banned
+    pub shadow_banned_policy: ShadowBannedPolicy,
+
+    /// What to do with the password hashes MAS can't verify
+    pub unknown_password_scheme_policy: UnknownPasswordSchemePolicy,
+
+    /// What to do with the users whose localpart MAS wouldn't accept as a new
+    /// username
+    pub invalid_username_policy: InvalidUsernamePolicy,
+
+    /// The longest each phase can run for, after which the migration fails
+    /// with [`Error::PhaseTimeout`]
+    ///
+    /// The phases missing from this map can run for as long as they need.
+    pub phase_timeouts: std::collections::HashMap<Phase, Duration>,
+
+    /// Don't check that every Synapse row was either migrated or skipped on
+    /// purpose once the migration is done
+    ///
+    /// The check fails the migration with [`Error::CountMismatch`]. As
+    /// estimated counts can't be reconciled, it counts the rows exactly when
+    /// they were only estimated with [`CountMode::Estimate`].
+    pub skip_count_check: bool,
+}
+
+impl MigrationOptions {
+    fn runs_phase(&self, phase: Phase) -> bool {
+        match (&self.only_phases, phase) {
+            (Some(phases), _) => phases.contains(&phase),
+            (None, Phase::Users) => self.existing_users.is_none(),
+            (None, Phase::AccountData) => self.migrate_account_data,
+            (None, Phase::UserFilters) => self.migrate_user_filters,
+            (None, _) => true,
+        }
+    }
+}
+
+/// Report of what a migration did.
+///
+/// The row counts are the number of rows of each type that were written (or
+/// would have been written, see [`MigrationOptions::count_only`]) to the MAS
+/// database.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// Number of users migrated
+    pub users: u64,
+
+    /// Number of third-party IDs migrated, including unsupported ones
+    pub threepids: u64,
+
+    /// Number of external IDs migrated as upstream OAuth 2.0 links
+    pub external_ids: u64,
+
+    /// Number of external IDs skipped because their auth provider has no
+    /// mapping, when using [`UnknownProviderPolicy::SkipWithWarning`]
+    pub skipped_external_ids: u64,
+
+    /// Number of global account data entries migrated, see
+    /// [`MigrationOptions::migrate_account_data`]
+    pub account_data: u64,
+
+    /// Number of sync filters migrated, see
+    /// [`MigrationOptions::migrate_user_filters`]
+    pub user_filters: u64,
+
+    /// Number of devices migrated as compatibility sessions
+    pub devices: u64,
+
+    /// Number of access tokens migrated, refreshable or not
+    pub access_tokens: u64,
+
+    /// Number of refresh tokens migrated
+    pub refresh_tokens: u64,
+
+    /// Number of deviceless access tokens which had already expired, whose
+    /// compatibility session was migrated as finished
+    pub expired_sessions: u64,
+
+    /// Number of access tokens created by Synapse admins to act as another
+    /// user, whether they were migrated or not, see [`PuppetTokenPolicy`]
+    pub puppet_tokens: u64,
+
+    /// Number of migrated users which were flagged as Synapse admins
+    pub synapse_admins: u64,
+
+    /// Number of migrated users matching one of the
+    /// [`MigrationOptions::appservice_namespaces`], whose password wasn't
+    /// migrated
+    pub appservice_namespace_users: u64,
+
+    /// Number of migrated users which were erased from Synapse, of which
+    /// nothing but the user itself was migrated
+    pub erased_users: u64,
+
+    /// Number of migrated users which were shadow-banned in Synapse, locked
+    /// unless using [`ShadowBannedPolicy::Ignore`]
+    pub shadow_banned_users: u64,
+
+    /// Number of migrated users whose password hash wasn't in a format MAS
+    /// can verify, and which were migrated without it, when using
+    /// [`UnknownPasswordSchemePolicy::SkipPassword`]
+    pub skipped_passwords: u64,
+
+    /// Number of migrated users whose localpart MAS wouldn't accept as a new
+    /// username, when using [`InvalidUsernamePolicy::Warn`]
+    pub invalid_usernames: u64,
+
+    /// Number of session IP addresses which failed to parse and were dropped
+    pub dropped_ips: u64,
+
+    /// Number of session IP addresses which failed to parse and were kept as
+    /// migration notes, when using [`InvalidIpPolicy::PreserveRaw`]
+    pub preserved_ips: u64,
+
+    /// Number of hidden devices skipped, unless
+    /// [`MigrationOptions::include_hidden_devices`] is set
+    pub hidden_devices: u64,
+
+    /// Number of devices whose display name was shared with another device of
+    /// the same user and was made unique, when
+    /// [`MigrationOptions::dedupe_device_names`] is set
+    pub renamed_devices: u64,
+
+    /// Number of device user agents which were truncated, or had their invalid
+    /// UTF-8 sequences replaced
+    pub sanitized_user_agents: u64,
+
+    /// Number of third-party IDs with a medium other than `email`, migrated
+    /// as unsupported third-party IDs
+    pub unsupported_threepids: u64,
+
+    /// Number of email addresses which were changed by normalizing them, and
+    /// whose original value was kept as a migration note
+    pub normalized_emails: u64,
+
+    /// Number of email addresses skipped as duplicates of another address of
+    /// the same user, when using [`DuplicateEmailPolicy::SkipWithWarning`]
+    pub skipped_duplicate_emails: u64,
+
+    /// Number of rows skipped because their user is missing from the Synapse
+    /// `users` table, when using [`MissingUserPolicy::SkipAndReport`]
+    pub skipped_rows_of_missing_users: u64,
+
+    /// The users missing from the Synapse `users` table, by Synapse table
+    /// which had rows for them, when using [`MissingUserPolicy::SkipAndReport`]
+    pub missing_users: BTreeMap<&'static str, BTreeSet<FullUserId>>,
+
+    /// Rows which couldn't be migrated, when using [`FailureMode::Collect`]
+    pub row_errors: Vec<MigrationRowError>,
+
+    /// The Synapse tables whose row counts were checked once the migration
+    /// was done, empty when the check was skipped, see
+    /// [`MigrationOptions::skip_count_check`]
+    ///
+    /// The tables of [`UNCHECKED_COUNT_TABLES`] are never checked.
+    pub count_checked_tables: Vec<&'static str>,
+}
+
+impl MigrationReport {
+    /// The total number of rows migrated, across all the entity types
+    #[must_use]
+    pub fn total_rows(&self) -> u64 {
+        self.users
+            + self.threepids
+            + self.external_ids
+            + self.account_data
+            + self.user_filters
+            + self.devices
+            + self.access_tokens
+            + self.refresh_tokens
+    }
+}
+
+impl std::fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} users ({} Synapse admins, {} in appservice namespaces, {} erased, {} shadow-banned, {} without their password of unknown scheme, {} with a username MAS wouldn't accept), {} third-party IDs ({} unsupported, {} emails normalized, {} duplicate emails skipped), {} upstream links ({} of unknown providers skipped), {} account data entries, {} user filters, {} devices ({} hidden skipped, {} renamed, {} invalid IPs dropped, {} preserved, {} user agents sanitized), {} access tokens ({} expired sessions finished, {} puppet tokens), {} refresh tokens",
+            self.users,
+            self.synapse_admins,
+            self.appservice_namespace_users,
+            self.erased_users,
+            self.shadow_banned_users,
+            self.skipped_passwords,
+            self.invalid_usernames,
+            self.threepids,
+            self.unsupported_threepids,
+            self.normalized_emails,
+            self.skipped_duplicate_emails,
+            self.external_ids,
+            self.skipped_external_ids,
+            self.account_data,
+            self.user_filters,
+            self.devices,
+            self.hidden_devices,
+            self.renamed_devices,
+            self.dropped_ips,
+            self.preserved_ips,
+            self.sanitized_user_agents,
+            self.access_tokens,
+            self.expired_sessions,
+            self.puppet_tokens,
+            self.refresh_tokens,
+        )?;
+
+        if self.skipped_rows_of_missing_users > 0 {
+            let missing_users: BTreeSet<_> = self.missing_users.values().flatten().collect();
+            write!(
+                f,
+                ", {} rows of {} missing users skipped",
+                self.skipped_rows_of_missing_users,
+                missing_users.len()
+            )?;
+        }
+
+        if !self.row_errors.is_empty() {
+            write!(f, ", {} rows failed to migrate", self.row_errors.len())?;
+        }
+
+        if self.count_checked_tables.is_empty() {
+            write!(f, ", row counts not checked")?;
+        } else {
+            write!(
+                f,
+                ", row counts checked for {} but not for {}",
+                self.count_checked_tables.join(", "),
+                UNCHECKED_COUNT_TABLES.join(", "),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
 struct MigrationState {
     /// The server name we're migrating from
     server_name: String,
 
     /// Lookup table from user localpart to that user's infos
-    users: HashMap<CompactString, UserInfo>,
+    users: UserMap,
+
+    /// The Synapse user IDs of the users in [`MigrationState::users`] which
+    /// aren't the one built from their localpart, so that duplicate localparts
+    /// are reported with the Synapse user migrated first. The other user IDs
+    /// aren't kept, so that this stays small for very large homeservers.
+    noncanonical_user_ids: HashMap<CompactString, FullUserId>,
+
+    /// The users erased from Synapse, see the module documentation
+    erased_users: HashSet<FullUserId>,
+
+    /// The display names shared by several devices of a user, by Synapse user,
+    /// only filled when [`MigrationOptions::dedupe_device_names`] is set
+    duplicate_device_names: HashMap<FullUserId, HashSet<String>>,
 
     /// Mapping of MAS user ID + device ID to a MAS compat session ID.
     devices_to_compat_sessions: HashMap<(NonNilUuid, CompactString), Uuid>,
@@ -126,10 +1308,234 @@ struct MigrationState {
     /// A mapping of Synapse external ID providers to MAS upstream OAuth 2.0
     /// provider ID
     provider_id_mapping: std::collections::HashMap<String, Uuid>,
+
+    /// Which timestamp to use for the sessions and tokens which have no
+    /// creation time
+    fallback_timestamp_policy: FallbackTimestampPolicy,
+
+    /// What to do when a single row can't be migrated
+    failure_mode: FailureMode,
+
+    /// What to do with the external IDs of auth providers missing from
+    /// `provider_id_mapping`
+    unknown_provider_policy: UnknownProviderPolicy,
+
+    /// What to do with the duplicate email addresses of a user
+    duplicate_email_policy: DuplicateEmailPolicy,
+
+    /// What to do with the rows whose user is missing
+    missing_user_policy: MissingUserPolicy,
+
+    /// Whether to migrate the devices Synapse flags as hidden
+    include_hidden_devices: bool,
+
+    /// What to do with session IP addresses which fail to parse
+    invalid_ip_policy: InvalidIpPolicy,
+
+    /// How to anonymize the session IP addresses
+    ip_anonymization: IpAnonymization,
+
+    /// What to do with the access tokens created to act as another user
+    puppet_token_policy: PuppetTokenPolicy,
+
+    /// The creation time to use for the users which have none
+    fallback_user_creation_time: DateTime<Utc>,
+
+    /// If set, only migrate the users with these localparts
+    user_filter: Option<std::collections::HashSet<String>>,
+
+    /// Whether to only migrate the users with or without a password
+    user_auth_filter: UserAuthFilter,
+
+    /// If set, the localparts of the only users which can request admin
+    admin_allowlist: Option<std::collections::HashSet<CompactString>>,
+
+    /// What to do with the shadow-banned users
+    shadow_banned_policy: ShadowBannedPolicy,
+
+    /// What to do with the password hashes MAS can't verify
+    unknown_password_scheme_policy: UnknownPasswordSchemePolicy,
+
+    /// What to do with the users whose localpart MAS wouldn't accept
+    invalid_username_policy: InvalidUsernamePolicy,
+
+    /// The user namespaces of the application services
+    appservice_namespaces: Option<RegexSet>,
+
+    /// How to generate the IDs of the migrated rows
+    ids: IdGenerator,
+
+    /// Synapse users which failed to migrate, whose dependent rows must be
+    /// skipped too
+    failed_users: HashSet<FullUserId>,
+
+    /// Number of rows skipped on purpose, by Synapse table, for the tables
+    /// which were read in full
+    skipped_rows: BTreeMap<&'static str, u64>,
+
+    /// Report of what the migration did so far, accumulated by each phase
+    report: MigrationReport,
+}
+
+impl MigrationState {
+    /// Computes the flags of a Synapse user, kept in the lookup table of the
+    /// users for the phases depending on them.
+    fn user_flags(&self, user: &SynapseUser, is_erased: bool) -> UserFlags {
+        let mut flags = UserFlags::empty();
+        if bool::from(user.admin) {
+            flags |= UserFlags::IS_SYNAPSE_ADMIN;
+        }
+        if bool::from(user.deactivated) {
+            flags |= UserFlags::IS_DEACTIVATED;
+        }
+        if bool::from(user.is_guest) {
+            flags |= UserFlags::IS_GUEST;
+        }
+        if user.appservice_id.is_some() {
+            flags |= UserFlags::IS_APPSERVICE;
+        } else if self
+            .appservice_namespaces
+            .as_ref()
+            .is_some_and(|namespaces| namespaces.is_match(&user.name.0))
+        {
+            flags |= UserFlags::IN_APPSERVICE_NAMESPACE;
+        }
+        if is_erased {
+            flags |= UserFlags::IS_ERASED;
+        }
+        flags
+    }
+
+    /// Remember the Synapse user ID of the user added to the lookup table
+    /// with the given localpart, see [`MigrationState::synapse_user_id`].
+    fn record_synapse_user_id(&mut self, localpart: &CompactString, user_id: &FullUserId) {
+        // Checked without building the canonical user ID, as this runs for every user
+        let is_canonical = user_id
+            .0
+            .strip_prefix('@')
+            .and_then(|rest| rest.strip_suffix(self.server_name.as_str()))
+            .and_then(|rest| rest.strip_suffix(':'))
+            == Some(localpart.as_str());
+        if !is_canonical {
+            self.noncanonical_user_ids
+                .insert(localpart.clone(), user_id.clone());
+        }
+    }
+
+    /// The Synapse user ID of the user in the lookup table with the given
+    /// localpart.
+    fn synapse_user_id(&self, localpart: &str) -> FullUserId {
+        self.noncanonical_user_ids
+            .get(localpart)
+            .cloned()
+            .unwrap_or_else(|| FullUserId(format!("@{localpart}:{}", self.server_name)))
+    }
+
+    /// Whether a user is left out of the migration by the user filters.
+    fn filters_out_user(&self, localpart: &str, has_password: bool) -> bool {
+        self.user_filter
+            .as_ref()
+            .is_some_and(|user_filter| !user_filter.contains(localpart))
+            || !self.user_auth_filter.includes(has_password)
+    }
+
+    /// Records how many rows of a Synapse table were skipped, once the phase
+    /// reading it is done.
+    fn record_skipped_rows(&mut self, table: &'static str, progress_counter: &ProgressCounter) {
+        self.skipped_rows
+            .insert(table, progress_counter.skipped().into());
+    }
+
+    /// Handles a row which can't be migrated, according to the failure mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns the given error back when using [`FailureMode::Abort`].
+    fn row_failed(&mut self, table: &'static str, error: Error) -> Result<(), Error> {
+        match self.failure_mode {
+            FailureMode::Abort => Err(error),
+            FailureMode::Collect => {
+                tracing::warn!(
+                    error = &error as &dyn std::error::Error,
+                    "Failed to migrate a row from {table}, skipping"
+                );
+                self.report
+                    .row_errors
+                    .push(MigrationRowError { table, error });
+                Ok(())
+            }
+        }
+    }
+
+    /// Looks up the infos of the user owning a row of a table depending on
+    /// the users table.
+    ///
+    /// Returns `None` if the row should be skipped, either because the user
+    /// failed to migrate or because the lookup failed and the error was
+    /// collected.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lookup failed when using [`FailureMode::Abort`].
+    fn user_infos_for_row(
+        &mut self,
+        synapse_user_id: &FullUserId,
+        table: &'static str,
+    ) -> Result<Option<UserInfo>, Error> {
+        if self.failed_users.contains(synapse_user_id) {
+            return Ok(None);
+        }
+
+        // Extracting the localpart borrows from the user ID without allocating (the
+        // user ID is only cloned on error), so it is done for every row: it takes
+        // about as long as looking the user ID up in a cache would.
+        let username = match synapse_user_id
+            .extract_localpart(&self.server_name)
+            .into_extract_localpart_with(|| synapse_user_id.clone())
+        {
+            Ok(username) => username,
+            Err(error) => {
+                self.row_failed(table, error)?;
+                return Ok(None);
+            }
+        };
+
+        if let Some(user_infos) = self
+            .users
+            .get(username)
+            .into_user_map("looking up a user")?
+        {
+            return Ok(Some(user_infos));
+        }
+
+        match self.missing_user_policy {
+            MissingUserPolicy::Abort => {
+                let error = Error::MissingUserFromDependentTable {
+                    table: table.to_owned(),
+                    user: synapse_user_id.clone(),
+                };
+                self.row_failed(table, error)?;
+            }
+            MissingUserPolicy::SkipAndReport => {
+                let missing_users = self.report.missing_users.entry(table).or_default();
+                if !missing_users.contains(synapse_user_id) {
+                    tracing::warn!(
+                        "User {synapse_user_id} is missing from the users table but has rows in {table}, skipping them"
+                    );
+                    missing_users.insert(synapse_user_id.clone());
+                }
+                self.report.skipped_rows_of_missing_users += 1;
+            }
+        }
+
+        Ok(None)
+    }
 }
 
 /// Performs a migration from Synapse's database to MAS' database.
 ///
+/// Returns a [`MigrationReport`] of what was migrated.
+///
 /// # Panics
 ///
 /// - If there are more than `usize::MAX` users
@@ -140,70 +1546,384 @@ struct MigrationState {
 ///
 /// - An underlying database access error, either to MAS or to Synapse.
 /// - Invalid data in the Synapse database.
-#[expect(clippy::implicit_hasher)]
+#[expect(clippy::implicit_hasher, clippy::too_many_arguments)]
 pub async fn migrate(
     mut synapse: SynapseReader<'_>,
-    mas: MasWriter,
+    mut mas: MasWriter,
     server_name: String,
     clock: &dyn Clock,
     rng: &mut impl RngCore,
-    provider_id_mapping: std::collections::HashMap<String, Uuid>,
+    provider_id_mapping: std::collections::HashMap<String, Uuid>,
+    progress: &Progress,
+    options: &MigrationOptions,
+) -> Result<MigrationReport, Error> {
+    let start = Instant::now();
+
+    check_synapse_server_name(options.synapse_server_name.as_deref(), &server_name)?;
+
+    if options.runs_phase(Phase::Users) == options.existing_users.is_some() {
+        return Err(Error::InvalidUsersSource);
+    }
+
+    if !options.allow_existing_data {
+        mas.verify_empty()
+            .await
+            .into_mas("checking the MAS database is empty")?;
+    }
+
+    if options.count_only {
+        mas.discard_rows();
+    }
+
+    if let Some(batch_size) = options.batch_size {
+        mas.set_batch_size(batch_size);
+    }
+
+    mas.set_write_mode(options.write_mode)
+        .await
+        .into_mas("restoring the primary keys for the write mode")?;
+    mas.set_copy_format(options.copy_format);
+
+    if let Some(max_in_flight_batches) = options.max_in_flight_batches {
+        mas.set_max_in_flight_batches(max_in_flight_batches);
+    }
+
+    if let Some(batch_retries) = options.batch_retries {
+        mas.set_batch_retries(batch_retries);
+    }
+
+    if let Some(export_path) = &options.export_path {
+        mas.export_rows_to(export_path)
+            .into_mas("opening the export file")?;
+    }
+
+    // Check the server name of all users up front, rather than failing on the
+    // first foreign user halfway through the migration
+    for found in synapse
+        .distinct_server_names()
+        .await
+        .into_synapse("listing server names")?
+    {
+        // Users with a `:` in their localpart (which can happen for appservice users)
+        // end up with a longer server name here. They are dealt with when migrating
+        // users.
+        if found != server_name && !found.ends_with(&format!(":{server_name}")) {
+            return Err(Error::UnexpectedServerName {
+                found,
+                expected: server_name,
+            });
+        }
+    }
+
+    let counts = synapse
+        .count_rows(options.count_mode)
+        .await
+        .into_synapse("counting rows")?;
+
+    if counts.ui_auth_sessions > 0 {
+        info!(
+            "Synapse has {} UI auth sessions, which are not migrated and can be cleaned up",
+            counts.ui_auth_sessions
+        );
+    }
+
+    let erased_users = synapse
+        .erased_users()
+        .await
+        .into_synapse("listing erased users")?
+        .into_iter()
+        .collect();
+
+    let mut duplicate_device_names: HashMap<FullUserId, HashSet<String>> = HashMap::default();
+    if options.dedupe_device_names && options.runs_phase(Phase::Devices) {
+        for (user_id, display_name) in synapse
+            .duplicate_device_names(options.include_hidden_devices)
+            .await
+            .into_synapse("listing duplicate device names")?
+        {
+            duplicate_device_names
+                .entry(user_id)
+                .or_default()
+                .insert(display_name);
+        }
+    }
+
+    let mut state = MigrationState {
+        server_name,
+        // We oversize the hashmaps, as the estimates are innaccurate, and we would like to avoid
+        // reallocations.
+        users: UserMap::new(&options.user_map_backing, counts.users * 9 / 8)
+            .into_user_map("creating the table")?,
+        noncanonical_user_ids: HashMap::default(),
+        erased_users,
+        duplicate_device_names,
+        // Sessions created for deviceless access tokens never end up in this map, so sizing it
+        // after the number of devices is enough.
+        devices_to_compat_sessions: HashMap::with_capacity_and_hasher(
+            counts.devices * 9 / 8,
+            RandomState::default(),
+        ),
+        provider_id_mapping,
+        fallback_timestamp_policy: options.fallback_timestamp_policy,
+        failure_mode: options.failure_mode,
+        unknown_provider_policy: options.unknown_provider_policy,
+        duplicate_email_policy: options.duplicate_email_policy,
+        missing_user_policy: options.missing_user_policy,
+        include_hidden_devices: options.include_hidden_devices,
+        invalid_ip_policy: options.invalid_ip_policy,
+        ip_anonymization: options.ip_anonymization,
+        puppet_token_policy: options.puppet_token_policy,
+        fallback_user_creation_time: options.fallback_user_creation_time,
+        user_filter: options.user_filter.clone(),
+        user_auth_filter: options.user_auth_filter,
+        admin_allowlist: options.admin_allowlist.clone(),
+        shadow_banned_policy: options.shadow_banned_policy,
+        unknown_password_scheme_policy: options.unknown_password_scheme_policy,
+        invalid_username_policy: options.invalid_username_policy,
+        appservice_namespaces: options.appservice_namespaces.clone(),
+        ids: IdGenerator {
+            deterministic: options.deterministic_ids,
+        },
+        failed_users: HashSet::default(),
+        skipped_rows: BTreeMap::new(),
+        report: MigrationReport::default(),
+    };
+
+    if let Some(existing_users) = &options.existing_users {
+        load_existing_users(&mut synapse, &mut state, existing_users).await?;
+    }
+
+    for phase in Phase::all().filter(|&phase| options.runs_phase(phase)) {
+        (mas, state) = with_phase_timeout(
+            phase,
+            options.phase_timeouts.get(&phase).copied(),
+            run_phase(
+                phase,
+                &mut synapse,
+                mas,
+                clock,
+                rng,
+                state,
+                &counts,
+                progress,
+            ),
+        )
+        .await?;
+    }
+
+    if options.skip_count_check {
+        tracing::warn!("Skipping the row count check, as asked");
+    } else {
+        // Estimated counts can't be reconciled with the migrated rows
+        let exact_counts;
+        let counts = if options.count_mode == CountMode::Estimate {
+            info!("Counting the Synapse rows exactly for the row count check");
+            exact_counts = synapse
+                .count_rows(CountMode::Exact)
+                .await
+                .into_synapse("counting rows exactly")?;
+            &exact_counts
+        } else {
+            &counts
+        };
+
+        state.report.count_checked_tables =
+            check_row_counts(counts, &state.report, &state.skipped_rows)?;
+    }
+
+    synapse
+        .finish()
+        .await
+        .into_synapse("failed to close Synapse reader")?;
+
+    let orphaned_rows = mas
+        .verify_referential_integrity()
+        .await
+        .into_mas("failed to verify referential integrity")?;
+    if !orphaned_rows.is_empty() {
+        return Err(Error::OrphanedRows(orphaned_rows));
+    }
+
+    mas.finish(progress)
+        .await
+        .into_mas("failed to finalise MAS database")?;
+
+    log_migration_summary(&state, start);
+
+    Ok(state.report)
+}
+
+/// Runs a phase of the migration, failing with [`Error::PhaseTimeout`] if it
+/// takes longer than the given timeout.
+///
+/// The phase is dropped when it times out, which stops its tasks once they
+/// notice the other end of their channel is gone.
+async fn with_phase_timeout<T>(
+    phase: Phase,
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+
+    let start = Instant::now();
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| Error::PhaseTimeout {
+            phase,
+            elapsed: start.elapsed(),
+        })?
+}
+
+/// Runs a single phase of the migration.
+#[expect(clippy::too_many_arguments)]
+async fn run_phase(
+    phase: Phase,
+    synapse: &mut SynapseReader<'_>,
+    mut mas: MasWriter,
+    clock: &dyn Clock,
+    rng: &mut impl RngCore,
+    mut state: MigrationState,
+    counts: &SynapseRowCounts,
     progress: &Progress,
-) -> Result<(), Error> {
-    let counts = synapse.count_rows().await.into_synapse("counting users")?;
+) -> Result<(MasWriter, MigrationState), Error> {
+    match phase {
+        Phase::Users => {
+            let progress_counter = progress.migrating_data(EntityType::Users, counts.users);
+            (mas, state) =
+                migrate_users(synapse, mas, state, rng, progress_counter.clone()).await?;
+            state.record_skipped_rows("users", &progress_counter);
+        }
 
-    let state = MigrationState {
-        server_name,
-        // We oversize the hashmaps, as the estimates are innaccurate, and we would like to avoid
-        // reallocations.
-        users: HashMap::with_capacity_and_hasher(counts.users * 9 / 8, RandomState::default()),
-        devices_to_compat_sessions: HashMap::with_capacity_and_hasher(
-            counts.devices * 9 / 8,
-            RandomState::default(),
-        ),
-        provider_id_mapping,
-    };
+        Phase::Threepids => {
+            let progress_counter = progress.migrating_data(EntityType::ThreePids, counts.threepids);
+            (mas, state) =
+                migrate_threepids(synapse, mas, rng, state, progress_counter.clone()).await?;
+            state.record_skipped_rows("user_threepids", &progress_counter);
+        }
 
-    let progress_counter = progress.migrating_data(EntityType::Users, counts.users);
-    let (mas, state) = migrate_users(&mut synapse, mas, state, rng, progress_counter).await?;
+        Phase::ExternalIds => {
+            let progress_counter =
+                progress.migrating_data(EntityType::ExternalIds, counts.external_ids);
+            (mas, state) =
+                migrate_external_ids(synapse, mas, rng, state, progress_counter.clone()).await?;
+            state.record_skipped_rows("user_external_ids", &progress_counter);
+        }
 
-    let progress_counter = progress.migrating_data(EntityType::ThreePids, counts.threepids);
-    let (mas, state) = migrate_threepids(&mut synapse, mas, rng, state, progress_counter).await?;
+        Phase::AccountData => {
+            let progress_counter =
+                progress.migrating_data(EntityType::AccountData, counts.account_data);
+            (mas, state) =
+                migrate_account_data(synapse, mas, state, progress_counter.clone()).await?;
+            state.record_skipped_rows("account_data", &progress_counter);
+        }
 
-    let progress_counter = progress.migrating_data(EntityType::ExternalIds, counts.external_ids);
-    let (mas, state) =
-        migrate_external_ids(&mut synapse, mas, rng, state, progress_counter).await?;
+        Phase::UserFilters => {
+            let progress_counter =
+                progress.migrating_data(EntityType::UserFilters, counts.user_filters);
+            (mas, state) =
+                migrate_user_filters(synapse, mas, state, progress_counter.clone()).await?;
+            state.record_skipped_rows("user_filters", &progress_counter);
+        }
 
-    let progress_counter = progress.migrating_data(
-        EntityType::NonRefreshableAccessTokens,
-        counts.access_tokens - counts.refresh_tokens,
-    );
-    let (mas, state) =
-        migrate_unrefreshable_access_tokens(&mut synapse, mas, clock, rng, state, progress_counter)
+        // The tokens aren't checked against the Synapse row counts, as the ones superseded by a
+        // refresh or whose device is gone are left out when reading
+        Phase::UnrefreshableTokens => {
+            let progress_counter = progress.migrating_data(
+                EntityType::NonRefreshableAccessTokens,
+                counts.access_tokens - counts.refresh_tokens,
+            );
+            (mas, state) = migrate_unrefreshable_access_tokens(
+                synapse,
+                mas,
+                clock,
+                rng,
+                state,
+                progress_counter,
+            )
             .await?;
+        }
 
-    let progress_counter =
-        progress.migrating_data(EntityType::RefreshableTokens, counts.refresh_tokens);
-    let (mas, state) =
-        migrate_refreshable_token_pairs(&mut synapse, mas, clock, rng, state, progress_counter)
-            .await?;
+        Phase::RefreshableTokens => {
+            let progress_counter =
+                progress.migrating_data(EntityType::RefreshableTokens, counts.refresh_tokens);
+            (mas, state) =
+                migrate_refreshable_token_pairs(synapse, mas, clock, rng, state, progress_counter)
+                    .await?;
+        }
 
-    let progress_counter = progress.migrating_data(EntityType::Devices, counts.devices);
-    let (mas, _state) = migrate_devices(&mut synapse, mas, rng, state, progress_counter).await?;
+        // The devices reuse the compatibility sessions created for their access tokens, so
+        // they come after the token phases
+        Phase::Devices => {
+            let progress_counter = progress.migrating_data(EntityType::Devices, counts.devices);
+            (mas, state) =
+                migrate_devices(synapse, mas, clock, rng, state, progress_counter.clone()).await?;
+            state.record_skipped_rows("devices", &progress_counter);
+        }
+    }
 
-    synapse
-        .finish()
-        .await
-        .into_synapse("failed to close Synapse reader")?;
+    Ok((mas, state))
+}
 
-    mas.finish(progress)
-        .await
-        .into_mas("failed to finalise MAS database")?;
+/// The Synapse tables whose row counts are never checked, as the access tokens
+/// superseded by a refresh or whose device is gone are never read.
+pub const UNCHECKED_COUNT_TABLES: [&str; 2] = ["access_tokens", "refresh_tokens"];
 
-    Ok(())
+/// Checks that every row counted in the Synapse tables which were read in full
+/// was either migrated or skipped on purpose, returning the tables which were
+/// checked.
+///
+/// # Errors
+///
+/// Returns [`Error::CountMismatch`] for the first table whose counts don't
+/// add up.
+fn check_row_counts(
+    counts: &SynapseRowCounts,
+    report: &MigrationReport,
+    skipped_rows: &BTreeMap<&'static str, u64>,
+) -> Result<Vec<&'static str>, Error> {
+    let tables = [
+        ("users", counts.users, report.users),
+        ("user_threepids", counts.threepids, report.threepids),
+        (
+            "user_external_ids",
+            counts.external_ids,
+            report.external_ids,
+        ),
+        ("account_data", counts.account_data, report.account_data),
+        ("user_filters", counts.user_filters, report.user_filters),
+        ("devices", counts.devices, report.devices),
+    ];
+
+    let mut checked = Vec::with_capacity(tables.len());
+    for (table, expected, actual) in tables {
+        // The tables of the phases which didn't run have nothing to check
+        let Some(&skipped) = skipped_rows.get(table) else {
+            continue;
+        };
+
+        let expected = u64::try_from(expected).unwrap_or(u64::MAX);
+        if actual + skipped != expected {
+            return Err(Error::CountMismatch {
+                table: table.to_owned(),
+                expected,
+                actual,
+                skipped,
+            });
+        }
+        checked.push(table);
+    }
+
+    Ok(checked)
 }
 
-#[tracing::instrument(skip_all, level = Level::INFO)]
+#[tracing::instrument(
+    skip_all,
+    level = Level::INFO,
+    err,
+    fields(otel.status_code, rows.migrated, rows.skipped, elapsed_ms),
+)]
 async fn migrate_users(
     synapse: &mut SynapseReader<'_>,
     mut mas: MasWriter,
@@ -213,6 +1933,7 @@ async fn migrate_users(
 ) -> Result<(MasWriter, MigrationState), Error> {
     let start = Instant::now();
     let progress_counter_ = progress_counter.clone();
+    let outcome = PhaseOutcome::new(&progress_counter_, start);
 
     let (tx, mut rx) = tokio::sync::mpsc::channel::<SynapseUser>(100 * 1024);
 
@@ -225,56 +1946,135 @@ async fn migrate_users(
             let mut password_buffer = MasWriteBuffer::new(&mas);
 
             while let Some(user) = rx.recv().await {
-                // Handling an edge case: some AS users may have invalid localparts containing
-                // extra `:` characters. These users are ignored and a warning is logged.
-                if user.appservice_id.is_some()
-                    && user
-                        .name
-                        .0
-                        .strip_suffix(&format!(":{}", state.server_name))
-                        .is_some_and(|localpart| localpart.contains(':'))
-                {
+                if has_invalid_appservice_localpart(&user, &state.server_name) {
                     tracing::warn!("AS user {} has invalid localpart, ignoring!", user.name.0);
+                    progress_counter.increment_skipped();
                     continue;
                 }
 
-                let (mas_user, mas_password_opt) =
-                    transform_user(&user, &state.server_name, &mut rng)?;
+                let is_erased = state.erased_users.contains(&user.name);
+                let (mut mas_user, mas_password_opt) = match transform_user(
+                    &user,
+                    is_erased,
+                    &state.server_name,
+                    state.fallback_user_creation_time,
+                    state.admin_allowlist.as_ref(),
+                    state.ids,
+                    &mut rng,
+                ) {
+                    Ok(transformed) => transformed,
+                    Err(error) => {
+                        state.row_failed("users", error)?;
+                        state.failed_users.insert(user.name);
+                        progress_counter.increment_skipped();
+                        continue;
+                    }
+                };
 
-                let mut flags = UserFlags::empty();
-                if bool::from(user.admin) {
-                    flags |= UserFlags::IS_SYNAPSE_ADMIN;
-                }
-                if bool::from(user.deactivated) {
-                    flags |= UserFlags::IS_DEACTIVATED;
+                // Users registered by an application service aren't written to MAS, so their
+                // localpart doesn't have to follow its rules
+                let invalid_username_reason = user
+                    .appservice_id
+                    .is_none()
+                    .then(|| invalid_localpart_reason(&user.name, &mas_user.username))
+                    .flatten();
+                if let Some(reason) = invalid_username_reason {
+                    let error = Error::InvalidUsername {
+                        user: user.name.clone(),
+                        reason,
+                    };
+                    match state.invalid_username_policy {
+                        InvalidUsernamePolicy::Fail => {
+                            state.row_failed("users", error)?;
+                            state.failed_users.insert(user.name);
+                            progress_counter.increment_skipped();
+                            continue;
+                        }
+                        InvalidUsernamePolicy::Warn => {
+                            tracing::warn!(
+                                error = &error as &dyn std::error::Error,
+                                "Migrating user {} with a username MAS wouldn't accept",
+                                user.name.0
+                            );
+                            state.report.invalid_usernames += 1;
+                        }
+                    }
                 }
-                if bool::from(user.is_guest) {
-                    flags |= UserFlags::IS_GUEST;
+
+                if user.shadow_banned {
+                    apply_shadow_ban(&mut mas_user, state.shadow_banned_policy);
                 }
-                if user.appservice_id.is_some() {
-                    flags |= UserFlags::IS_APPSERVICE;
 
-                    progress_counter.increment_skipped();
+                let flags = state.user_flags(&user, is_erased);
+                let is_appservice = flags.is_appservice();
+                let filtered_out =
+                    state.filters_out_user(&mas_user.username, mas_password_opt.is_some());
 
-                    // Special case for appservice users: we don't insert them into the database
+                // Users which aren't migrated are still recorded, without a MAS user ID, so
+                // that the rows depending on them get skipped
+                let user_infos = UserInfo {
+                    mas_user_id: (!is_appservice && !filtered_out).then_some(mas_user.user_id),
+                    flags,
+                };
+                // Two users mapping to the same localpart would make all the rows of the
+                // dependent tables ambiguous, so refuse to go any further. When collecting
+                // errors, the first user is kept.
+                let localpart = CompactString::new(&mas_user.username);
+                if state
+                    .users
+                    .contains_key(&localpart)
+                    .into_user_map("looking up a user")?
+                {
+                    let error = Error::DuplicateLocalpart {
+                        first_user: state.synapse_user_id(&localpart),
+                        localpart: mas_user.username,
+                        second_user: user.name,
+                    };
+                    state.row_failed("users", error)?;
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+                state.record_synapse_user_id(&localpart, &user.name);
+                state
+                    .users
+                    .insert(localpart, user_infos)
+                    .into_user_map("inserting a user")?;
+
+                if is_appservice || filtered_out {
+                    // Special case for appservice users and users which are filtered out: we
+                    // don't insert them into the database
                     // We just record the user's information in the state and continue
-                    state.users.insert(
-                        CompactString::new(&mas_user.username),
-                        UserInfo {
-                            mas_user_id: None,
-                            flags,
-                        },
-                    );
+                    progress_counter.increment_skipped();
                     continue;
                 }
 
-                state.users.insert(
-                    CompactString::new(&mas_user.username),
-                    UserInfo {
-                        mas_user_id: Some(mas_user.user_id),
-                        flags,
-                    },
-                );
+                // Users in the namespace of an appservice are managed by it, and shouldn't be
+                // able to log in with a password
+                let mut mas_password_opt = mas_password_opt
+                    .filter(|_| !flags.is_in_appservice_namespace() && !flags.is_erased());
+
+                let password_error = mas_password_opt.as_ref().and_then(|mas_password| {
+                    check_password_scheme(&user.name, &mas_password.hashed_password).err()
+                });
+                if let Some(error) = password_error {
+                    match state.unknown_password_scheme_policy {
+                        UnknownPasswordSchemePolicy::Fail => {
+                            state.row_failed("users", error)?;
+                            state.failed_users.insert(user.name);
+                            progress_counter.increment_skipped();
+                            continue;
+                        }
+                        UnknownPasswordSchemePolicy::SkipPassword => {
+                            tracing::warn!(
+                                error = &error as &dyn std::error::Error,
+                                "Migrating user {} without their password",
+                                user.name.0
+                            );
+                            state.report.skipped_passwords += 1;
+                            mas_password_opt = None;
+                        }
+                    }
+                }
 
                 user_buffer
                     .write(&mut mas, mas_user)
@@ -288,6 +2088,19 @@ async fn migrate_users(
                         .into_mas("writing password")?;
                 }
 
+                state.report.users += 1;
+                if flags.is_synapse_admin() {
+                    state.report.synapse_admins += 1;
+                }
+                if flags.is_in_appservice_namespace() {
+                    state.report.appservice_namespace_users += 1;
+                }
+                if flags.is_erased() {
+                    state.report.erased_users += 1;
+                }
+                if user.shadow_banned {
+                    state.report.shadow_banned_users += 1;
+                }
                 progress_counter.increment_migrated();
             }
 
@@ -318,6 +2131,8 @@ async fn migrate_users(
 
     res?;
 
+    outcome.succeeded();
+
     info!(
         "{} users migrated ({} skipped) in {:.1}s",
         progress_counter_.migrated(),
@@ -328,16 +2143,28 @@ async fn migrate_users(
     Ok((mas, state))
 }
 
-#[tracing::instrument(skip_all, level = Level::INFO)]
+/// Normalizes an email address, by trimming the surrounding whitespace and
+/// lowercasing it
+fn normalize_email(address: &str) -> String {
+    address.trim().to_lowercase()
+}
+
+#[tracing::instrument(
+    skip_all,
+    level = Level::INFO,
+    err,
+    fields(otel.status_code, rows.migrated, rows.skipped, elapsed_ms),
+)]
 async fn migrate_threepids(
     synapse: &mut SynapseReader<'_>,
     mut mas: MasWriter,
     rng: &mut impl RngCore,
-    state: MigrationState,
+    mut state: MigrationState,
     progress_counter: ProgressCounter,
 ) -> Result<(MasWriter, MigrationState), Error> {
     let start = Instant::now();
     let progress_counter_ = progress_counter.clone();
+    let outcome = PhaseOutcome::new(&progress_counter_, start);
 
     let (tx, mut rx) = tokio::sync::mpsc::channel::<SynapseThreepid>(100 * 1024);
 
@@ -347,7 +2174,10 @@ async fn migrate_threepids(
     let task = tokio::spawn(
         async move {
             let mut email_buffer = MasWriteBuffer::new(&mas);
+            let mut email_note_buffer = MasWriteBuffer::new(&mas);
             let mut unsupported_buffer = MasWriteBuffer::new(&mas);
+            // The normalized email addresses migrated so far, to detect duplicates
+            let mut migrated_emails: HashSet<(NonNilUuid, String)> = HashSet::default();
 
             while let Some(threepid) = rx.recv().await {
                 let SynapseThreepid {
@@ -358,15 +2188,11 @@ async fn migrate_threepids(
                 } = threepid;
                 let created_at: DateTime<Utc> = added_at.into();
 
-                let username = synapse_user_id
-                    .extract_localpart(&state.server_name)
-                    .into_extract_localpart(synapse_user_id.clone())?
-                    .to_owned();
-                let Some(user_infos) = state.users.get(username.as_str()).copied() else {
-                    return Err(Error::MissingUserFromDependentTable {
-                        table: "user_threepids".to_owned(),
-                        user: synapse_user_id,
-                    });
+                let Some(user_infos) =
+                    state.user_infos_for_row(&synapse_user_id, "user_threepids")?
+                else {
+                    progress_counter.increment_skipped();
+                    continue;
                 };
 
                 let Some(mas_user_id) = user_infos.mas_user_id else {
@@ -374,17 +2200,66 @@ async fn migrate_threepids(
                     continue;
                 };
 
+                // Nothing but the user itself is kept for the users erased from Synapse
+                if user_infos.flags.is_erased() {
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
+                // E-mail addresses are the only kind of third-party ID natively supported
+                // by MAS. Everything else, including phone numbers (`msisdn`), is kept as-is
+                // in the unsupported third-party IDs table, so that it isn't lost.
                 if medium == "email" {
+                    let email = normalize_email(&address);
+
+                    if !migrated_emails.insert((mas_user_id, email.clone())) {
+                        if state.duplicate_email_policy == DuplicateEmailPolicy::SkipWithWarning {
+                            tracing::warn!(
+                                "Email address {address:?} of {synapse_user_id} is a duplicate once normalized, skipping"
+                            );
+                            state.report.skipped_duplicate_emails += 1;
+                            progress_counter.increment_skipped();
+                            continue;
+                        }
+
+                        let error = Error::DuplicateEmail {
+                            user: synapse_user_id,
+                            email,
+                        };
+                        state.row_failed("user_threepids", error)?;
+                        progress_counter.increment_skipped();
+                        continue;
+                    }
+
+                    let user_email_id = Uuid::from(state.ids.ulid(
+                        created_at,
+                        &["user_threepids", &synapse_user_id.0, &medium, &address],
+                        &mut rng,
+                    ));
+
+                    // Keep the address as stored by Synapse if normalizing changed it
+                    if email != address {
+                        email_note_buffer
+                            .write(
+                                &mut mas,
+                                MasNewUserEmailMigrationNote {
+                                    user_email_id,
+                                    field: "email".to_owned(),
+                                    raw_value: address,
+                                },
+                            )
+                            .await
+                            .into_mas("writing email migration note")?;
+                        state.report.normalized_emails += 1;
+                    }
+
                     email_buffer
                         .write(
                             &mut mas,
                             MasNewEmailThreepid {
                                 user_id: mas_user_id,
-                                user_email_id: Uuid::from(Ulid::from_datetime_with_source(
-                                    created_at.into(),
-                                    &mut rng,
-                                )),
-                                email: address,
+                                user_email_id,
+                                email,
                                 created_at,
                             },
                         )
@@ -403,8 +2278,11 @@ async fn migrate_threepids(
                         )
                         .await
                         .into_mas("writing unsupported threepid")?;
+
+                    state.report.unsupported_threepids += 1;
                 }
 
+                state.report.threepids += 1;
                 progress_counter.increment_migrated();
             }
 
@@ -412,6 +2290,10 @@ async fn migrate_threepids(
                 .finish(&mut mas)
                 .await
                 .into_mas("writing email threepids")?;
+            email_note_buffer
+                .finish(&mut mas)
+                .await
+                .into_mas("writing email migration notes")?;
             unsupported_buffer
                 .finish(&mut mas)
                 .await
@@ -435,6 +2317,8 @@ async fn migrate_threepids(
 
     res?;
 
+    outcome.succeeded();
+
     info!(
         "{} third-party IDs migrated ({} skipped) in {:.1}s",
         progress_counter_.migrated(),
@@ -445,16 +2329,22 @@ async fn migrate_threepids(
     Ok((mas, state))
 }
 
-#[tracing::instrument(skip_all, level = Level::INFO)]
+#[tracing::instrument(
+    skip_all,
+    level = Level::INFO,
+    err,
+    fields(otel.status_code, rows.migrated, rows.skipped, elapsed_ms),
+)]
 async fn migrate_external_ids(
     synapse: &mut SynapseReader<'_>,
     mut mas: MasWriter,
     rng: &mut impl RngCore,
-    state: MigrationState,
+    mut state: MigrationState,
     progress_counter: ProgressCounter,
 ) -> Result<(MasWriter, MigrationState), Error> {
     let start = Instant::now();
     let progress_counter_ = progress_counter.clone();
+    let outcome = PhaseOutcome::new(&progress_counter_, start);
 
     let (tx, mut rx) = tokio::sync::mpsc::channel::<SynapseExternalId>(100 * 1024);
 
@@ -464,6 +2354,7 @@ async fn migrate_external_ids(
     let task = tokio::spawn(
         async move {
             let mut write_buffer = MasWriteBuffer::new(&mas);
+            let mut skipped_providers: HashSet<String> = HashSet::default();
 
             while let Some(extid) = rx.recv().await {
                 let SynapseExternalId {
@@ -471,15 +2362,11 @@ async fn migrate_external_ids(
                     auth_provider,
                     external_id: subject,
                 } = extid;
-                let username = synapse_user_id
-                    .extract_localpart(&state.server_name)
-                    .into_extract_localpart(synapse_user_id.clone())?
-                    .to_owned();
-                let Some(user_infos) = state.users.get(username.as_str()).copied() else {
-                    return Err(Error::MissingUserFromDependentTable {
-                        table: "user_external_ids".to_owned(),
-                        user: synapse_user_id,
-                    });
+                let Some(user_infos) =
+                    state.user_infos_for_row(&synapse_user_id, "user_external_ids")?
+                else {
+                    progress_counter.increment_skipped();
+                    continue;
                 };
 
                 let Some(mas_user_id) = user_infos.mas_user_id else {
@@ -487,20 +2374,47 @@ async fn migrate_external_ids(
                     continue;
                 };
 
+                // Nothing but the user itself is kept for the users erased from Synapse
+                if user_infos.flags.is_erased() {
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
                 let Some(&upstream_provider_id) = state.provider_id_mapping.get(&auth_provider)
                 else {
-                    return Err(Error::MissingAuthProviderMapping {
+                    if state.unknown_provider_policy == UnknownProviderPolicy::SkipWithWarning {
+                        if !skipped_providers.contains(&auth_provider) {
+                            tracing::warn!(
+                                "Auth provider {auth_provider:?} has no upstream OAuth 2.0 provider in MAS, skipping its external IDs"
+                            );
+                            skipped_providers.insert(auth_provider);
+                        }
+                        state.report.skipped_external_ids += 1;
+                        progress_counter.increment_skipped();
+                        continue;
+                    }
+
+                    let error = Error::MissingAuthProviderMapping {
                         synapse_id: auth_provider,
                         user: synapse_user_id,
-                    });
+                    };
+                    state.row_failed("user_external_ids", error)?;
+                    progress_counter.increment_skipped();
+                    continue;
                 };
 
                 // To save having to store user creation times, extract it from the ULID
                 // This gives millisecond precision — good enough.
                 let user_created_ts = Ulid::from(mas_user_id.get()).datetime();
 
-                let link_id: Uuid =
-                    Ulid::from_datetime_with_source(user_created_ts, &mut rng).into();
+                let link_id: Uuid = state
+                    .ids
+                    .ulid(
+                        user_created_ts.into(),
+                        &["user_external_ids", &auth_provider, &subject],
+                        &mut rng,
+                    )
+                    .into();
 
                 write_buffer
                     .write(
@@ -516,6 +2430,7 @@ async fn migrate_external_ids(
                     .await
                     .into_mas("failed to write upstream link")?;
 
+                state.report.external_ids += 1;
                 progress_counter.increment_migrated();
             }
 
@@ -542,6 +2457,8 @@ async fn migrate_external_ids(
 
     res?;
 
+    outcome.succeeded();
+
     info!(
         "{} upstream links (external IDs) migrated ({} skipped) in {:.1}s",
         progress_counter_.migrated(),
@@ -552,6 +2469,204 @@ async fn migrate_external_ids(
     Ok((mas, state))
 }
 
+/// Migrates the global account data of users into a side table, which is only
+/// an archive of it, as Synapse keeps serving it.
+#[tracing::instrument(
+    skip_all,
+    level = Level::INFO,
+    err,
+    fields(otel.status_code, rows.migrated, rows.skipped, elapsed_ms),
+)]
+async fn migrate_account_data(
+    synapse: &mut SynapseReader<'_>,
+    mut mas: MasWriter,
+    mut state: MigrationState,
+    progress_counter: ProgressCounter,
+) -> Result<(MasWriter, MigrationState), Error> {
+    let start = Instant::now();
+    let progress_counter_ = progress_counter.clone();
+    let outcome = PhaseOutcome::new(&progress_counter_, start);
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<SynapseAccountData>(100 * 1024);
+
+    let task = tokio::spawn(
+        async move {
+            let mut write_buffer = MasWriteBuffer::new(&mas);
+
+            while let Some(account_data) = rx.recv().await {
+                let SynapseAccountData {
+                    user_id: synapse_user_id,
+                    account_data_type,
+                    content,
+                } = account_data;
+                let Some(user_infos) =
+                    state.user_infos_for_row(&synapse_user_id, "account_data")?
+                else {
+                    progress_counter.increment_skipped();
+                    continue;
+                };
+
+                let Some(mas_user_id) = user_infos.mas_user_id else {
+                    progress_counter.increment_skipped();
+                    continue;
+                };
+
+                // Nothing but the user itself is kept for the users erased from Synapse
+                if user_infos.flags.is_erased() {
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
+                write_buffer
+                    .write(
+                        &mut mas,
+                        MasNewAccountData {
+                            user_id: mas_user_id,
+                            account_data_type,
+                            content,
+                        },
+                    )
+                    .await
+                    .into_mas("writing account data")?;
+
+                state.report.account_data += 1;
+                progress_counter.increment_migrated();
+            }
+
+            write_buffer
+                .finish(&mut mas)
+                .await
+                .into_mas("writing account data")?;
+
+            Ok((mas, state))
+        }
+        .instrument(tracing::info_span!("ingest_task")),
+    );
+
+    // In case this has an error, we still want to join the task, so we look at the
+    // error later
+    let res = synapse
+        .read_account_data()
+        .map_err(|e| e.into_synapse("reading account data"))
+        .forward(PollSender::new(tx).sink_map_err(|_| Error::ChannelClosed))
+        .inspect_err(|e| tracing::error!(error = e as &dyn std::error::Error))
+        .await;
+
+    let (mas, state) = task.await.into_join("account data write task")??;
+
+    res?;
+
+    outcome.succeeded();
+
+    info!(
+        "{} account data entries migrated ({} skipped) in {:.1}s",
+        progress_counter_.migrated(),
+        progress_counter_.skipped(),
+        Instant::now().duration_since(start).as_secs_f64()
+    );
+
+    Ok((mas, state))
+}
+
+/// Migrates the sync filters of users into a side table, which is only an
+/// archive of them, as Synapse keeps serving them.
+#[tracing::instrument(
+    skip_all,
+    level = Level::INFO,
+    err,
+    fields(otel.status_code, rows.migrated, rows.skipped, elapsed_ms),
+)]
+async fn migrate_user_filters(
+    synapse: &mut SynapseReader<'_>,
+    mut mas: MasWriter,
+    mut state: MigrationState,
+    progress_counter: ProgressCounter,
+) -> Result<(MasWriter, MigrationState), Error> {
+    let start = Instant::now();
+    let progress_counter_ = progress_counter.clone();
+    let outcome = PhaseOutcome::new(&progress_counter_, start);
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<SynapseUserFilter>(100 * 1024);
+
+    let task = tokio::spawn(
+        async move {
+            let mut write_buffer = MasWriteBuffer::new(&mas);
+
+            while let Some(user_filter) = rx.recv().await {
+                let SynapseUserFilter {
+                    user_id: synapse_user_id,
+                    filter_id,
+                    filter_json,
+                } = user_filter;
+                let Some(user_infos) =
+                    state.user_infos_for_row(&synapse_user_id, "user_filters")?
+                else {
+                    progress_counter.increment_skipped();
+                    continue;
+                };
+
+                let Some(mas_user_id) = user_infos.mas_user_id else {
+                    progress_counter.increment_skipped();
+                    continue;
+                };
+
+                // Nothing but the user itself is kept for the users erased from Synapse
+                if user_infos.flags.is_erased() {
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
+                write_buffer
+                    .write(
+                        &mut mas,
+                        MasNewUserFilter {
+                            user_id: mas_user_id,
+                            filter_id,
+                            filter_json,
+                        },
+                    )
+                    .await
+                    .into_mas("writing user filters")?;
+
+                state.report.user_filters += 1;
+                progress_counter.increment_migrated();
+            }
+
+            write_buffer
+                .finish(&mut mas)
+                .await
+                .into_mas("writing user filters")?;
+
+            Ok((mas, state))
+        }
+        .instrument(tracing::info_span!("ingest_task")),
+    );
+
+    // In case this has an error, we still want to join the task, so we look at the
+    // error later
+    let res = synapse
+        .read_user_filters()
+        .map_err(|e| e.into_synapse("reading user filters"))
+        .forward(PollSender::new(tx).sink_map_err(|_| Error::ChannelClosed))
+        .inspect_err(|e| tracing::error!(error = e as &dyn std::error::Error))
+        .await;
+
+    let (mas, state) = task.await.into_join("user filters write task")??;
+
+    res?;
+
+    outcome.succeeded();
+
+    info!(
+        "{} user filters migrated ({} skipped) in {:.1}s",
+        progress_counter_.migrated(),
+        progress_counter_.skipped(),
+        Instant::now().duration_since(start).as_secs_f64()
+    );
+
+    Ok((mas, state))
+}
+
 /// Migrate devices from Synapse to MAS (as compat sessions).
 ///
 /// In order to get the right session creation timestamps, the access tokens
@@ -560,25 +2675,34 @@ async fn migrate_external_ids(
 ///
 /// This is because only access tokens store a timestamp that in any way
 /// resembles a creation timestamp.
-#[tracing::instrument(skip_all, level = Level::INFO)]
+#[tracing::instrument(
+    skip_all,
+    level = Level::INFO,
+    err,
+    fields(otel.status_code, rows.migrated, rows.skipped, elapsed_ms),
+)]
 async fn migrate_devices(
     synapse: &mut SynapseReader<'_>,
     mut mas: MasWriter,
+    clock: &dyn Clock,
     rng: &mut impl RngCore,
     mut state: MigrationState,
     progress_counter: ProgressCounter,
 ) -> Result<(MasWriter, MigrationState), Error> {
     let start = Instant::now();
     let progress_counter_ = progress_counter.clone();
+    let outcome = PhaseOutcome::new(&progress_counter_, start);
 
     let (tx, mut rx) = tokio::sync::mpsc::channel(100 * 1024);
 
+    let now = clock.now();
     // create a new RNG seeded from the passed RNG so that we can move it into the
     // spawned task
     let mut rng = rand_chacha::ChaChaRng::from_rng(rng).expect("failed to seed rng");
     let task = tokio::spawn(
         async move {
             let mut write_buffer = MasWriteBuffer::new(&mas);
+            let mut migration_note_write_buffer = MasWriteBuffer::new(&mas);
 
             while let Some(device) = rx.recv().await {
                 let SynapseDevice {
@@ -588,16 +2712,24 @@ async fn migrate_devices(
                     last_seen,
                     ip,
                     user_agent,
+                    hidden,
                 } = device;
-                let username = synapse_user_id
-                    .extract_localpart(&state.server_name)
-                    .into_extract_localpart(synapse_user_id.clone())?
-                    .to_owned();
-                let Some(user_infos) = state.users.get(username.as_str()).copied() else {
-                    return Err(Error::MissingUserFromDependentTable {
-                        table: "devices".to_owned(),
-                        user: synapse_user_id,
-                    });
+
+                if device_id == GUEST_DEVICE_ID {
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
+                if hidden && !state.include_hidden_devices {
+                    state.report.hidden_devices += 1;
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
+                let Some(user_infos) = state.user_infos_for_row(&synapse_user_id, "devices")?
+                else {
+                    progress_counter.increment_skipped();
+                    continue;
                 };
 
                 let Some(mas_user_id) = user_infos.mas_user_id else {
@@ -609,37 +2741,66 @@ async fn migrate_devices(
                     || user_infos.flags.is_guest()
                     || user_infos.flags.is_appservice()
                 {
+                    progress_counter.increment_skipped();
                     continue;
                 }
 
                 let session_id = *state
                     .devices_to_compat_sessions
                     .entry((mas_user_id, CompactString::new(&device_id)))
-                    .or_insert_with(||
-                // We don't have a creation time for this device (as it has no access token),
-                // so use now as a least-evil fallback.
-                Ulid::with_source(&mut rng).into());
+                    .or_insert_with(|| {
+                        // We don't have a creation time for this device (as it has no access
+                        // token), so use the fallback timestamp as a least-evil choice.
+                        let created_at =
+                            state.fallback_timestamp_policy.timestamp(now, mas_user_id);
+                        state
+                            .ids
+                            .ulid(
+                                created_at,
+                                &["devices", &synapse_user_id.0, &device_id],
+                                &mut rng,
+                            )
+                            .into()
+                    });
                 let created_at = Ulid::from(session_id).datetime().into();
 
-                // As we're using a real IP type in the MAS database, it is possible
-                // that we encounter invalid IP addresses in the Synapse database.
-                // In that case, we should ignore them, but still log a warning.
-                // One special case: Synapse will record '-' as IP in some cases, we don't want
-                // to log about those
-                let last_active_ip = ip.filter(|ip| ip != "-").and_then(|ip| {
-                    ip.parse()
-                        .map_err(|e| {
-                            tracing::warn!(
-                                error = &e as &dyn std::error::Error,
-                                mxid = %synapse_user_id,
-                                %device_id,
-                                %ip,
-                                "Failed to parse device IP, ignoring"
-                            );
-                        })
-                        .ok()
+                let last_active_ip = parse_last_active_ip(
+                    &mut mas,
+                    &mut migration_note_write_buffer,
+                    &mut state,
+                    session_id,
+                    &synapse_user_id,
+                    Some(&device_id),
+                    ip,
+                )
+                .await?;
+
+                let user_agent = user_agent.map(|user_agent| {
+                    let (user_agent, sanitized) = sanitize_user_agent(user_agent, session_id);
+                    if sanitized {
+                        state.report.sanitized_user_agents += 1;
+                    }
+                    user_agent
                 });
 
+                let last_active_at = last_seen.map(DateTime::from);
+                let human_name = match display_name {
+                    Some(display_name)
+                        if state
+                            .duplicate_device_names
+                            .get(&synapse_user_id)
+                            .is_some_and(|names| names.contains(&display_name)) =>
+                    {
+                        state.report.renamed_devices += 1;
+                        Some(disambiguate_device_name(
+                            &display_name,
+                            &device_id,
+                            last_active_at,
+                        ))
+                    }
+                    display_name => display_name,
+                };
+
                 write_buffer
                     .write(
                         &mut mas,
@@ -647,10 +2808,13 @@ async fn migrate_devices(
                             session_id,
                             user_id: mas_user_id,
                             device_id: Some(device_id),
-                            human_name: display_name,
+                            // `compat_sessions.human_name` is an unbounded `TEXT` column, so
+                            // display names of any length can be kept as-is
+                            human_name,
                             created_at,
+                            finished_at: None,
                             is_synapse_admin: user_infos.flags.is_synapse_admin(),
-                            last_active_at: last_seen.map(DateTime::from),
+                            last_active_at,
                             last_active_ip,
                             user_agent,
                         },
@@ -658,6 +2822,7 @@ async fn migrate_devices(
                     .await
                     .into_mas("writing compat sessions")?;
 
+                state.report.devices += 1;
                 progress_counter.increment_migrated();
             }
 
@@ -665,6 +2830,10 @@ async fn migrate_devices(
                 .finish(&mut mas)
                 .await
                 .into_mas("writing compat sessions")?;
+            migration_note_write_buffer
+                .finish(&mut mas)
+                .await
+                .into_mas("writing migration notes")?;
 
             Ok((mas, state))
         }
@@ -684,6 +2853,8 @@ async fn migrate_devices(
 
     res?;
 
+    outcome.succeeded();
+
     info!(
         "{} devices migrated ({} skipped) in {:.1}s",
         progress_counter_.migrated(),
@@ -696,7 +2867,12 @@ async fn migrate_devices(
 
 /// Migrates unrefreshable access tokens (those without an associated refresh
 /// token). Some of these may be deviceless.
-#[tracing::instrument(skip_all, level = Level::INFO)]
+#[tracing::instrument(
+    skip_all,
+    level = Level::INFO,
+    err,
+    fields(otel.status_code, rows.migrated, rows.skipped, elapsed_ms),
+)]
 async fn migrate_unrefreshable_access_tokens(
     synapse: &mut SynapseReader<'_>,
     mut mas: MasWriter,
@@ -707,6 +2883,7 @@ async fn migrate_unrefreshable_access_tokens(
 ) -> Result<(MasWriter, MigrationState), Error> {
     let start = Instant::now();
     let progress_counter_ = progress_counter.clone();
+    let outcome = PhaseOutcome::new(&progress_counter_, start);
 
     let (tx, mut rx) = tokio::sync::mpsc::channel(100 * 1024);
 
@@ -718,6 +2895,9 @@ async fn migrate_unrefreshable_access_tokens(
         async move {
             let mut write_buffer = MasWriteBuffer::new(&mas);
             let mut deviceless_session_write_buffer = MasWriteBuffer::new(&mas);
+            let mut migration_note_write_buffer = MasWriteBuffer::new(&mas);
+            let mut skipped_tokens_of_deactivated_users: HashMap<NonNilUuid, usize> =
+                HashMap::default();
 
             while let Some(token) = rx.recv().await {
                 let SynapseAccessToken {
@@ -726,16 +2906,33 @@ async fn migrate_unrefreshable_access_tokens(
                     token,
                     valid_until_ms,
                     last_validated,
+                    puppets_user_id,
+                    last_seen,
+                    ip,
+                    user_agent,
                 } = token;
-                let username = synapse_user_id
-                    .extract_localpart(&state.server_name)
-                    .into_extract_localpart(synapse_user_id.clone())?
-                    .to_owned();
-                let Some(user_infos) = state.users.get(username.as_str()).copied() else {
-                    return Err(Error::MissingUserFromDependentTable {
-                        table: "access_tokens".to_owned(),
-                        user: synapse_user_id,
-                    });
+
+                // Synapse keys puppeting tokens on the admin who created them, while they
+                // log in as `puppets_user_id`: they are migrated as tokens of the latter.
+                let (synapse_user_id, device_id, puppeted_by) = match puppets_user_id {
+                    Some(puppets_user_id) => {
+                        state.report.puppet_tokens += 1;
+                        if state.puppet_token_policy == PuppetTokenPolicy::Skip {
+                            progress_counter.increment_skipped();
+                            continue;
+                        }
+                        // The device, if any, belongs to the admin, so the token gets a
+                        // deviceless session of the puppeted user
+                        (puppets_user_id, None, Some(synapse_user_id))
+                    }
+                    None => (synapse_user_id, device_id, None),
+                };
+
+                let Some(user_infos) =
+                    state.user_infos_for_row(&synapse_user_id, "access_tokens")?
+                else {
+                    progress_counter.increment_skipped();
+                    continue;
                 };
 
                 let Some(mas_user_id) = user_infos.mas_user_id else {
@@ -743,18 +2940,25 @@ async fn migrate_unrefreshable_access_tokens(
                     continue;
                 };
 
-                if user_infos.flags.is_deactivated()
-                    || user_infos.flags.is_guest()
-                    || user_infos.flags.is_appservice()
-                {
+                if user_infos.flags.is_deactivated() {
+                    *skipped_tokens_of_deactivated_users
+                        .entry(mas_user_id)
+                        .or_default() += 1;
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
+                if user_infos.flags.is_guest() || user_infos.flags.is_appservice() {
                     progress_counter.increment_skipped();
                     continue;
                 }
 
                 // It's not always accurate, but last_validated is *often* the creation time of
-                // the device If we don't have one, then use the current time as a
-                // fallback.
-                let created_at = last_validated.map_or_else(|| now, DateTime::from);
+                // the device. If we don't have one, then use the fallback timestamp.
+                let created_at = last_validated.map_or_else(
+                    || state.fallback_timestamp_policy.timestamp(now, mas_user_id),
+                    DateTime::from,
+                );
 
                 let session_id = if let Some(device_id) = device_id {
                     // Use the existing device_id if this is the second token for a device
@@ -762,13 +2966,48 @@ async fn migrate_unrefreshable_access_tokens(
                         .devices_to_compat_sessions
                         .entry((mas_user_id, CompactString::new(&device_id)))
                         .or_insert_with(|| {
-                            Uuid::from(Ulid::from_datetime_with_source(created_at.into(), &mut rng))
+                            Uuid::from(state.ids.ulid(
+                                created_at,
+                                &["devices", &synapse_user_id.0, &device_id],
+                                &mut rng,
+                            ))
                         })
                 } else {
                     // If this is a deviceless access token, create a deviceless compat session
                     // for it (since otherwise we won't create one whilst migrating devices)
-                    let deviceless_session_id =
-                        Uuid::from(Ulid::from_datetime_with_source(created_at.into(), &mut rng));
+                    let deviceless_session_id = Uuid::from(state.ids.ulid(
+                        created_at,
+                        &["deviceless_sessions", &token],
+                        &mut rng,
+                    ));
+
+                    // The session only holds this token, so it is over once the token expired
+                    let finished_at = valid_until_ms
+                        .map(DateTime::from)
+                        .filter(|valid_until| *valid_until < now);
+                    if finished_at.is_some() {
+                        state.report.expired_sessions += 1;
+                    }
+
+                    // Synapse only records the activity of deviceless tokens in `user_ips`
+                    let last_active_ip = parse_last_active_ip(
+                        &mut mas,
+                        &mut migration_note_write_buffer,
+                        &mut state,
+                        deviceless_session_id,
+                        &synapse_user_id,
+                        None,
+                        ip,
+                    )
+                    .await?;
+                    let user_agent = user_agent.map(|user_agent| {
+                        let (user_agent, sanitized) =
+                            sanitize_user_agent(user_agent, deviceless_session_id);
+                        if sanitized {
+                            state.report.sanitized_user_agents += 1;
+                        }
+                        user_agent
+                    });
 
                     deviceless_session_write_buffer
                         .write(
@@ -779,10 +3018,11 @@ async fn migrate_unrefreshable_access_tokens(
                                 device_id: None,
                                 human_name: None,
                                 created_at,
+                                finished_at,
                                 is_synapse_admin: false,
-                                last_active_at: None,
-                                last_active_ip: None,
-                                user_agent: None,
+                                last_active_at: last_seen.map(DateTime::from),
+                                last_active_ip,
+                                user_agent,
                             },
                         )
                         .await
@@ -791,8 +3031,25 @@ async fn migrate_unrefreshable_access_tokens(
                     deviceless_session_id
                 };
 
-                let token_id =
-                    Uuid::from(Ulid::from_datetime_with_source(created_at.into(), &mut rng));
+                if let Some(puppeted_by) = puppeted_by {
+                    migration_note_write_buffer
+                        .write(
+                            &mut mas,
+                            MasNewMigrationNote {
+                                session_id,
+                                field: "puppeted_by".to_owned(),
+                                raw_value: puppeted_by.0,
+                            },
+                        )
+                        .await
+                        .into_mas("writing migration notes")?;
+                }
+
+                let token_id = Uuid::from(state.ids.ulid(
+                    created_at,
+                    &["access_tokens", &token],
+                    &mut rng,
+                ));
 
                 write_buffer
                     .write(
@@ -808,8 +3065,15 @@ async fn migrate_unrefreshable_access_tokens(
                     .await
                     .into_mas("writing compat access tokens")?;
 
+                state.report.access_tokens += 1;
                 progress_counter.increment_migrated();
             }
+
+            log_skipped_tokens_of_deactivated_users(
+                &skipped_tokens_of_deactivated_users,
+                "non-refreshable access tokens",
+            );
+
             write_buffer
                 .finish(&mut mas)
                 .await
@@ -818,6 +3082,10 @@ async fn migrate_unrefreshable_access_tokens(
                 .finish(&mut mas)
                 .await
                 .into_mas("writing deviceless compat sessions")?;
+            migration_note_write_buffer
+                .finish(&mut mas)
+                .await
+                .into_mas("writing migration notes")?;
 
             Ok((mas, state))
         }
@@ -837,6 +3105,8 @@ async fn migrate_unrefreshable_access_tokens(
 
     res?;
 
+    outcome.succeeded();
+
     info!(
         "{} non-refreshable access tokens migrated ({} skipped) in {:.1}s",
         progress_counter_.migrated(),
@@ -849,7 +3119,12 @@ async fn migrate_unrefreshable_access_tokens(
 
 /// Migrates (access token, refresh token) pairs.
 /// Does not migrate non-refreshable access tokens.
-#[tracing::instrument(skip_all, level = Level::INFO)]
+#[tracing::instrument(
+    skip_all,
+    level = Level::INFO,
+    err,
+    fields(otel.status_code, rows.migrated, rows.skipped, elapsed_ms),
+)]
 async fn migrate_refreshable_token_pairs(
     synapse: &mut SynapseReader<'_>,
     mut mas: MasWriter,
@@ -860,6 +3135,7 @@ async fn migrate_refreshable_token_pairs(
 ) -> Result<(MasWriter, MigrationState), Error> {
     let start = Instant::now();
     let progress_counter_ = progress_counter.clone();
+    let outcome = PhaseOutcome::new(&progress_counter_, start);
 
     let (tx, mut rx) = tokio::sync::mpsc::channel::<SynapseRefreshableTokenPair>(100 * 1024);
 
@@ -871,6 +3147,8 @@ async fn migrate_refreshable_token_pairs(
         async move {
             let mut access_token_write_buffer = MasWriteBuffer::new(&mas);
             let mut refresh_token_write_buffer = MasWriteBuffer::new(&mas);
+            let mut skipped_tokens_of_deactivated_users: HashMap<NonNilUuid, usize> =
+                HashMap::default();
 
             while let Some(token) = rx.recv().await {
                 let SynapseRefreshableTokenPair {
@@ -882,15 +3160,11 @@ async fn migrate_refreshable_token_pairs(
                     last_validated,
                 } = token;
 
-                let username = synapse_user_id
-                    .extract_localpart(&state.server_name)
-                    .into_extract_localpart(synapse_user_id.clone())?
-                    .to_owned();
-                let Some(user_infos) = state.users.get(username.as_str()).copied() else {
-                    return Err(Error::MissingUserFromDependentTable {
-                        table: "refresh_tokens".to_owned(),
-                        user: synapse_user_id,
-                    });
+                let Some(user_infos) =
+                    state.user_infos_for_row(&synapse_user_id, "refresh_tokens")?
+                else {
+                    progress_counter.increment_skipped();
+                    continue;
                 };
 
                 let Some(mas_user_id) = user_infos.mas_user_id else {
@@ -898,31 +3172,48 @@ async fn migrate_refreshable_token_pairs(
                     continue;
                 };
 
-                if user_infos.flags.is_deactivated()
-                    || user_infos.flags.is_guest()
-                    || user_infos.flags.is_appservice()
-                {
+                if user_infos.flags.is_deactivated() {
+                    *skipped_tokens_of_deactivated_users
+                        .entry(mas_user_id)
+                        .or_default() += 1;
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
+                if user_infos.flags.is_guest() || user_infos.flags.is_appservice() {
                     progress_counter.increment_skipped();
                     continue;
                 }
 
                 // It's not always accurate, but last_validated is *often* the creation time of
-                // the device If we don't have one, then use the current time as a
-                // fallback.
-                let created_at = last_validated.map_or_else(|| now, DateTime::from);
+                // the device. If we don't have one, then use the fallback timestamp.
+                let created_at = last_validated.map_or_else(
+                    || state.fallback_timestamp_policy.timestamp(now, mas_user_id),
+                    DateTime::from,
+                );
 
                 // Use the existing device_id if this is the second token for a device
                 let session_id = *state
                     .devices_to_compat_sessions
                     .entry((mas_user_id, CompactString::new(&device_id)))
                     .or_insert_with(|| {
-                        Uuid::from(Ulid::from_datetime_with_source(created_at.into(), &mut rng))
+                        Uuid::from(state.ids.ulid(
+                            created_at,
+                            &["devices", &synapse_user_id.0, &device_id],
+                            &mut rng,
+                        ))
                     });
 
-                let access_token_id =
-                    Uuid::from(Ulid::from_datetime_with_source(created_at.into(), &mut rng));
-                let refresh_token_id =
-                    Uuid::from(Ulid::from_datetime_with_source(created_at.into(), &mut rng));
+                let access_token_id = Uuid::from(state.ids.ulid(
+                    created_at,
+                    &["access_tokens", &access_token],
+                    &mut rng,
+                ));
+                let refresh_token_id = Uuid::from(state.ids.ulid(
+                    created_at,
+                    &["refresh_tokens", &refresh_token],
+                    &mut rng,
+                ));
 
                 access_token_write_buffer
                     .write(
@@ -951,9 +3242,16 @@ async fn migrate_refreshable_token_pairs(
                     .await
                     .into_mas("writing compat refresh tokens")?;
 
+                state.report.access_tokens += 1;
+                state.report.refresh_tokens += 1;
                 progress_counter.increment_migrated();
             }
 
+            log_skipped_tokens_of_deactivated_users(
+                &skipped_tokens_of_deactivated_users,
+                "refreshable token pairs",
+            );
+
             access_token_write_buffer
                 .finish(&mut mas)
                 .await
@@ -981,6 +3279,8 @@ async fn migrate_refreshable_token_pairs(
 
     res?;
 
+    outcome.succeeded();
+
     info!(
         "{} refreshable token pairs migrated ({} skipped) in {:.1}s",
         progress_counter_.migrated(),
@@ -991,9 +3291,337 @@ async fn migrate_refreshable_token_pairs(
     Ok((mas, state))
 }
 
+/// Records the outcome of a migration phase on its span once dropped, so that
+/// the throughput of each phase can be derived from the migration's traces.
+///
+/// Unless [`PhaseOutcome::succeeded`] was called, the phase is recorded as
+/// failed, which covers the errors returned early and the phases dropped
+/// because they timed out.
+struct PhaseOutcome {
+    span: tracing::Span,
+    progress_counter: ProgressCounter,
+    start: Instant,
+    succeeded: bool,
+}
+
+impl PhaseOutcome {
+    /// Starts recording the outcome of the phase running in the current span
+    fn new(progress_counter: &ProgressCounter, start: Instant) -> Self {
+        Self {
+            span: tracing::Span::current(),
+            progress_counter: progress_counter.clone(),
+            start,
+            succeeded: false,
+        }
+    }
+
+    /// Records the phase as successful
+    fn succeeded(mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for PhaseOutcome {
+    fn drop(&mut self) {
+        let status = if self.succeeded { "OK" } else { "ERROR" };
+        self.span.record("otel.status_code", status);
+        self.span
+            .record("rows.migrated", self.progress_counter.migrated());
+        self.span
+            .record("rows.skipped", self.progress_counter.skipped());
+        self.span.record(
+            "elapsed_ms",
+            u64::try_from(self.start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        );
+    }
+}
+
+/// Whether a user was registered by an application service with an invalid
+/// localpart, containing extra `:` characters.
+///
+/// These users are ignored, as if they weren't in Synapse.
+fn has_invalid_appservice_localpart(user: &SynapseUser, server_name: &str) -> bool {
+    user.appservice_id.is_some()
+        && user
+            .name
+            .0
+            .strip_suffix(&format!(":{server_name}"))
+            .is_some_and(|localpart| localpart.contains(':'))
+}
+
+/// Fill the lookup table of the users with the users migrated by a prior run,
+/// instead of migrating them again.
+///
+/// The users are read from Synapse and recorded the same way as when migrating
+/// them, with the MAS user IDs of the prior run joined by localpart. This
+/// keeps the flags of the users consistent with the prior run, regardless of
+/// the changes made in MAS since, and records the users the prior run didn't
+/// migrate, like the application service users, so that the rows depending on
+/// them are skipped again.
+///
+/// The other Synapse users missing from the MAS database are left out of the
+/// lookup table, so that the rows depending on them are handled according to
+/// [`MigrationOptions::missing_user_policy`].
+async fn load_existing_users(
+    synapse: &mut SynapseReader<'_>,
+    state: &mut MigrationState,
+    existing_users: &std::collections::HashMap<String, MasExistingUser>,
+) -> Result<(), Error> {
+    let mut loaded = 0;
+    let mut users = std::pin::pin!(synapse.read_users());
+    while let Some(user) = users.try_next().await.into_synapse("reading users")? {
+        if has_invalid_appservice_localpart(&user, &state.server_name) {
+            continue;
+        }
+
+        // The users which failed to migrate in the prior run because of their
+        // localpart have their dependent rows skipped, like in that run
+        let Ok(localpart) = user.name.extract_localpart(&state.server_name) else {
+            state.failed_users.insert(user.name);
+            continue;
+        };
+        if user.appservice_id.is_none()
+            && state.invalid_username_policy == InvalidUsernamePolicy::Fail
+            && invalid_localpart_reason(&user.name, localpart).is_some()
+        {
+            state.failed_users.insert(user.name);
+            continue;
+        }
+        let localpart = CompactString::new(localpart);
+
+        let is_erased = state.erased_users.contains(&user.name);
+        let flags = state.user_flags(&user, is_erased);
+        // Synapse treats an empty hash like a missing one, as no password matches it
+        let has_password = user
+            .password_hash
+            .as_deref()
+            .is_some_and(|password_hash| !password_hash.is_empty());
+
+        let mas_user_id =
+            if flags.is_appservice() || state.filters_out_user(&localpart, has_password) {
+                None
+            } else if let Some(existing_user) = existing_users.get(localpart.as_str()) {
+                Some(existing_user.user_id)
+            } else {
+                continue;
+            };
+
+        if state
+            .users
+            .contains_key(&localpart)
+            .into_user_map("looking up a user")?
+        {
+            let error = Error::DuplicateLocalpart {
+                first_user: state.synapse_user_id(&localpart),
+                localpart: localpart.into(),
+                second_user: user.name,
+            };
+            state.row_failed("users", error)?;
+            continue;
+        }
+
+        state.record_synapse_user_id(&localpart, &user.name);
+        state
+            .users
+            .insert(localpart, UserInfo { mas_user_id, flags })
+            .into_user_map("inserting an existing user")?;
+        if mas_user_id.is_some() {
+            loaded += 1;
+        }
+    }
+
+    info!("{loaded} existing users loaded");
+    Ok(())
+}
+
+/// Emit a single event summarising the whole migration, as a marker of its
+/// completion in the logs.
+fn log_migration_summary(state: &MigrationState, start: Instant) {
+    let report = &state.report;
+    let total_rows = report.total_rows();
+    let elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+    let rows_per_second = total_rows.saturating_mul(1000) / elapsed_ms.max(1);
+
+    info!(
+        total_rows,
+        elapsed_ms,
+        rows_per_second,
+        // The lookup tables are what grows the memory usage over the migration
+        lookup_table.users = state.users.len(),
+        lookup_table.devices = state.devices_to_compat_sessions.len(),
+        warnings.dropped_ips = report.dropped_ips,
+        warnings.preserved_ips = report.preserved_ips,
+        warnings.sanitized_user_agents = report.sanitized_user_agents,
+        warnings.unsupported_threepids = report.unsupported_threepids,
+        warnings.skipped_external_ids = report.skipped_external_ids,
+        warnings.hidden_devices = report.hidden_devices,
+        warnings.shadow_banned_users = report.shadow_banned_users,
+        warnings.skipped_passwords = report.skipped_passwords,
+        warnings.invalid_usernames = report.invalid_usernames,
+        warnings.puppet_tokens = report.puppet_tokens,
+        warnings.skipped_rows_of_missing_users = report.skipped_rows_of_missing_users,
+        warnings.failed_rows = report.row_errors.len(),
+        "Migration complete"
+    );
+}
+
+/// Makes the display name of a device unique among the devices of its user,
+/// by appending the day it was last seen, or its ID if it was never seen.
+fn disambiguate_device_name(
+    display_name: &str,
+    device_id: &str,
+    last_seen: Option<DateTime<Utc>>,
+) -> String {
+    match last_seen {
+        Some(last_seen) => format!(
+            "{display_name} (last seen {})",
+            last_seen.format("%Y-%m-%d")
+        ),
+        None => format!("{display_name} ({device_id})"),
+    }
+}
+
+/// The device ID Synapse gives to the access tokens of guest users, which
+/// isn't a real device.
+const GUEST_DEVICE_ID: &str = "guest_device";
+
+/// The longest device user agent migrated, in bytes.
+///
+/// `compat_sessions.user_agent` is an unbounded `TEXT` column, but no client
+/// sends user agents anywhere near this long, so longer ones are junk which
+/// isn't worth carrying over in full.
+const MAX_USER_AGENT_LENGTH: usize = 1024;
+
+/// Parses the IP address a compat session was last active from, and
+/// anonymizes it according to the [`IpAnonymization`] option.
+///
+/// As we're using a real IP type in the MAS database, it is possible that we
+/// encounter invalid IP addresses in the Synapse database. In that case, we
+/// ignore them (or keep them aside as a migration note, depending on the
+/// policy), but still log a warning.
+///
+/// One special case: Synapse will record '-' as IP in some cases, we don't
+/// want to log about those.
+async fn parse_last_active_ip(
+    mas: &mut MasWriter,
+    migration_note_write_buffer: &mut MasWriteBuffer<MasNewMigrationNote>,
+    state: &mut MigrationState,
+    session_id: Uuid,
+    synapse_user_id: &FullUserId,
+    device_id: Option<&str>,
+    ip: Option<String>,
+) -> Result<Option<IpAddr>, Error> {
+    let Some(ip) = ip.filter(|ip| ip != "-") else {
+        return Ok(None);
+    };
+
+    match ip.parse() {
+        Ok(parsed) => return Ok(Some(state.ip_anonymization.apply(parsed))),
+        Err(e) => match state.invalid_ip_policy {
+            InvalidIpPolicy::Drop => {
+                state.report.dropped_ips += 1;
+                tracing::warn!(
+                    error = &e as &dyn std::error::Error,
+                    mxid = %synapse_user_id,
+                    device_id,
+                    %session_id,
+                    %ip,
+                    "Failed to parse session IP, ignoring"
+                );
+            }
+            InvalidIpPolicy::PreserveRaw => {
+                state.report.preserved_ips += 1;
+                tracing::warn!(
+                    error = &e as &dyn std::error::Error,
+                    mxid = %synapse_user_id,
+                    device_id,
+                    %session_id,
+                    %ip,
+                    "Failed to parse session IP, keeping it as a migration note"
+                );
+                migration_note_write_buffer
+                    .write(
+                        mas,
+                        MasNewMigrationNote {
+                            session_id,
+                            field: "last_active_ip".to_owned(),
+                            raw_value: ip,
+                        },
+                    )
+                    .await
+                    .into_mas("writing migration notes")?;
+            }
+        },
+    }
+
+    Ok(None)
+}
+
+/// Converts the user agent of a device to a string, replacing its invalid
+/// UTF-8 sequences with the replacement character, and truncating it to
+/// [`MAX_USER_AGENT_LENGTH`] bytes.
+///
+/// Returns the user agent, and whether it had to be changed, in which case a
+/// warning is logged.
+fn sanitize_user_agent(RawText(raw): RawText, session_id: Uuid) -> (String, bool) {
+    let mut sanitized = false;
+
+    let mut user_agent = match String::from_utf8(raw) {
+        Ok(user_agent) => user_agent,
+        Err(e) => {
+            tracing::warn!(
+                %session_id,
+                "Device user agent is not valid UTF-8, replacing the invalid sequences"
+            );
+            sanitized = true;
+            String::from_utf8_lossy(e.as_bytes()).into_owned()
+        }
+    };
+
+    if user_agent.len() > MAX_USER_AGENT_LENGTH {
+        tracing::warn!(
+            %session_id,
+            length = user_agent.len(),
+            "Device user agent is too long, truncating it to {MAX_USER_AGENT_LENGTH} bytes"
+        );
+        sanitized = true;
+        let mut length = MAX_USER_AGENT_LENGTH;
+        while !user_agent.is_char_boundary(length) {
+            length -= 1;
+        }
+        user_agent.truncate(length);
+    }
+
+    (user_agent, sanitized)
+}
+
+/// Logs, at the debug level, how many tokens were skipped for each deactivated
+/// user during a token migration phase.
+fn log_skipped_tokens_of_deactivated_users(
+    skipped_tokens: &HashMap<NonNilUuid, usize>,
+    token_kind: &str,
+) {
+    for (user_id, count) in skipped_tokens {
+        tracing::debug!(
+            user_id = %user_id.get(),
+            "Skipped {count} {token_kind} of deactivated user"
+        );
+    }
+}
+
+/// Transforms a Synapse user into a MAS user, and their password if they have
+/// one.
+///
+/// The MAS username is always the localpart of the Synapse user ID: Synapse
+/// keeps on identifying the user by their full Matrix ID (in room memberships,
+/// account data, etc.), so a user can't be renamed as part of the migration.
 fn transform_user(
     user: &SynapseUser,
+    is_erased: bool,
     server_name: &str,
+    fallback_creation_time: DateTime<Utc>,
+    admin_allowlist: Option<&std::collections::HashSet<CompactString>>,
+    ids: IdGenerator,
     rng: &mut impl RngCore,
 ) -> Result<(MasNewUser, Option<MasNewUserPassword>), Error> {
     let username = user
@@ -1002,29 +3630,39 @@ fn transform_user(
         .into_extract_localpart(user.name.clone())?
         .to_owned();
 
-    let user_id = Uuid::from(Ulid::from_datetime_with_source(
-        DateTime::<Utc>::from(user.creation_ts).into(),
-        rng,
-    ))
-    .try_into()
-    .expect("ULID generation lead to a nil UUID, this is a bug!");
+    // The same creation time is used for the user and password IDs, so that they
+    // stay consistent for users with no creation time
+    let created_at = user
+        .creation_ts
+        .map_or(fallback_creation_time, DateTime::from);
+
+    let user_id = Uuid::from(ids.ulid(created_at, &["users", &user.name.0], rng))
+        .try_into()
+        .expect("ULID generation lead to a nil UUID, this is a bug!");
+
+    let can_request_admin = can_request_admin(bool::from(user.admin), &username, admin_allowlist);
 
     let new_user = MasNewUser {
         user_id,
         username,
-        created_at: user.creation_ts.into(),
-        locked_at: user.locked.then_some(user.creation_ts.into()),
-        deactivated_at: bool::from(user.deactivated).then_some(user.creation_ts.into()),
-        can_request_admin: bool::from(user.admin),
+        created_at,
+        // Erased users are locked on top of being deactivated, which is what tells
+        // them apart from the users which were only deactivated
+        locked_at: (user.locked || is_erased).then_some(created_at),
+        deactivated_at: bool::from(user.deactivated).then_some(created_at),
+        can_request_admin,
         is_guest: bool::from(user.is_guest),
     };
 
+    // Synapse treats an empty hash like a missing one, as no password matches it
     let mas_password = user
         .password_hash
         .clone()
+        .filter(|password_hash| !password_hash.is_empty())
         .map(|password_hash| MasNewUserPassword {
-            user_password_id: Uuid::from(Ulid::from_datetime_with_source(
-                DateTime::<Utc>::from(user.creation_ts).into(),
+            user_password_id: Uuid::from(ids.ulid(
+                created_at,
+                &["user_passwords", &user.name.0],
                 rng,
             )),
             user_id: new_user.user_id,
@@ -1034,3 +3672,868 @@ fn transform_user(
 
     Ok((new_user, mas_password))
 }
+
+/// Checks that a Synapse password hash is in the bcrypt format Synapse uses,
+/// like `$2b$12$` followed by the 22 characters of the salt and the 31
+/// characters of the hash.
+///
+/// MAS verifies all the migrated passwords with the bcrypt scheme configured
+/// as version 1, which accepts the `2a`, `2b` and `2y` variants alike. The
+/// `2x` variant comes from a buggy implementation, and can't be verified.
+///
+/// # Errors
+///
+/// Returns [`Error::UnknownPasswordScheme`] if the hash is in another format,
+/// with the scheme identifier of the hash but not the hash itself.
+fn check_password_scheme(user: &FullUserId, hashed_password: &str) -> Result<(), Error> {
+    let is_bcrypt = ["$2a$", "$2b$", "$2y$"]
+        .iter()
+        .find_map(|prefix| hashed_password.strip_prefix(prefix))
+        .and_then(|rest| rest.split_once('$'))
+        .is_some_and(|(cost, payload)| {
+            cost.len() == 2
+                && cost
+                    .parse::<u8>()
+                    .is_ok_and(|cost| (4..=31).contains(&cost))
+                && payload.len() == 53
+                && payload
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'/')
+        });
+
+    if is_bcrypt {
+        return Ok(());
+    }
+
+    // Hashes in the modular crypt format start with `$<scheme>$`
+    let scheme = hashed_password
+        .strip_prefix('$')
+        .and_then(|rest| rest.split_once('$'))
+        .map(|(scheme, _)| scheme)
+        .filter(|scheme| {
+            scheme.len() <= 16
+                && scheme
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+        .unwrap_or("unrecognised");
+
+    Err(Error::UnknownPasswordScheme {
+        user: user.clone(),
+        scheme: scheme.to_owned(),
+    })
+}
+
+/// Applies the [`ShadowBannedPolicy`] to a user Synapse shadow-banned.
+///
+/// MAS can't tell the ban apart from a regular lock, so the user keeps the
+/// time they were locked at if they already were.
+fn apply_shadow_ban(user: &mut MasNewUser, policy: ShadowBannedPolicy) {
+    match policy {
+        ShadowBannedPolicy::Lock => {
+            tracing::warn!(
+                username = user.username,
+                "User was shadow-banned in Synapse, locking them"
+            );
+            user.locked_at.get_or_insert(user.created_at);
+        }
+        ShadowBannedPolicy::Ignore => {
+            tracing::warn!(
+                username = user.username,
+                "User was shadow-banned in Synapse, migrating them as a regular user"
+            );
+        }
+    }
+}
+
+/// Checks a localpart against the rules MAS applies to new usernames,
+/// returning why it isn't allowed if it isn't.
+///
+/// Synapse used to be more lenient, so older databases can have users MAS
+/// wouldn't accept. The rules which only apply to new registrations, like
+/// banning all-numeric localparts, are left out, as Synapse uses those for its
+/// guest users.
+fn invalid_localpart_reason(user_id: &FullUserId, localpart: &str) -> Option<String> {
+    if localpart.is_empty() {
+        return Some("it is empty".to_owned());
+    }
+
+    if user_id.0.len() > 255 {
+        return Some(format!(
+            "the user ID is {} bytes long, longer than 255 bytes",
+            user_id.0.len()
+        ));
+    }
+
+    if let Some(c) = localpart.chars().find(|&c| !valid_localpart_character(c)) {
+        return Some(format!(
+            "it contains the character {c:?}, but only lowercase letters, digits and `=_-./` are allowed"
+        ));
+    }
+
+    None
+}
+
+/// Whether a character is allowed in usernames, as per the
+/// `username-invalid-chars` rule of the registration policy.
+fn valid_localpart_character(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '=' | '_' | '-' | '.' | '/')
+}
+
+/// Whether a user can request admin privileges in MAS.
+///
+/// When an allowlist is given, it replaces the admin flag of Synapse
+/// altogether.
+fn can_request_admin(
+    is_synapse_admin: bool,
+    localpart: &str,
+    admin_allowlist: Option<&std::collections::HashSet<CompactString>>,
+) -> bool {
+    match admin_allowlist {
+        Some(allowlist) => allowlist.contains(localpart),
+        None => is_synapse_admin,
+    }
+}
+
+/// Checks that the server name the migration was asked to use is the one
+/// Synapse is configured with, as a typo in either would otherwise give every
+/// user a wrong localpart.
+fn check_synapse_server_name(
+    synapse_server_name: Option<&str>,
+    server_name: &str,
+) -> Result<(), Error> {
+    match synapse_server_name {
+        Some(synapse) if synapse != server_name => Err(Error::ServerNameMismatch {
+            synapse: synapse.to_owned(),
+            mas: server_name.to_owned(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::IpAddr, time::Duration};
+
+    use chrono::{DateTime, Utc};
+    use mas_storage::clock::MockClock;
+    use rand::SeedableRng;
+    use sqlx::{Connection, PgPool, SqliteConnection};
+    use uuid::{NonNilUuid, Uuid};
+
+    use super::{
+        Error, IdGenerator, IpAnonymization, MAX_USER_AGENT_LENGTH, MigrationOptions,
+        MigrationReport, Phase, PhaseTimeout, PuppetTokenPolicy, ShadowBannedPolicy,
+        UnknownPasswordSchemePolicy, UnknownProviderPolicy, UserAuthFilter, apply_shadow_ban,
+        can_request_admin, check_password_scheme, check_row_counts, check_synapse_server_name,
+        disambiguate_device_name, invalid_localpart_reason, migrate, normalize_email,
+        sanitize_user_agent, valid_localpart_character, with_phase_timeout,
+    };
+    use crate::{LockedMasDatabase, MasWriter, Progress, SynapseReader};
+
+    /// Opens a Synapse database using SQLite, with the rows of the SQLite
+    /// fixture of the Synapse reader and the given extra statements
+    async fn sqlite_synapse(extra_statements: &str) -> SqliteConnection {
+        let mut conn = SqliteConnection::connect("sqlite::memory:")
+            .await
+            .expect("failed to open SQLite database");
+        sqlx::raw_sql(include_str!("synapse_reader/fixtures/synapse_sqlite.sql"))
+            .execute(&mut conn)
+            .await
+            .expect("failed to load the SQLite fixture");
+        sqlx::raw_sql(extra_statements)
+            .execute(&mut conn)
+            .await
+            .expect("failed to add rows to the SQLite fixture");
+        conn
+    }
+
+    async fn make_mas_writer(pool: &PgPool) -> MasWriter {
+        let main_conn = pool.acquire().await.unwrap().detach();
+        let mut writer_conns = Vec::new();
+        for _ in 0..2 {
+            writer_conns.push(
+                pool.acquire()
+                    .await
+                    .expect("failed to acquire MasWriter writer connection")
+                    .detach(),
+            );
+        }
+        let locked_main_conn = LockedMasDatabase::try_new(main_conn)
+            .await
+            .expect("failed to lock MAS database")
+            .expect_left("MAS database is already locked");
+        MasWriter::new(locked_main_conn, writer_conns, false)
+            .await
+            .expect("failed to construct MasWriter")
+    }
+
+    /// Tests that the phases depending on the users can run against the users
+    /// of a prior run, and skip the rows of the users this run didn't migrate.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_rerun_phases_with_existing_users(pool: PgPool) {
+        let mut synapse_conn = sqlite_synapse(
+            "
+            UPDATE users SET admin = 1 WHERE name = '@alice:example.com';
+
+            INSERT INTO users (name, creation_ts, deactivated)
+              VALUES ('@bob:example.com', 1530393962, 1);
+            INSERT INTO users (name, creation_ts, appservice_id)
+              VALUES ('@bridge_carol:example.com', 1530393962, 'bridge');
+
+            INSERT INTO user_threepids (user_id, medium, address, validated_at, added_at)
+              VALUES
+              ('@bob:example.com', 'email', 'bob@example.com', 1554228492026, 1554228549014),
+              ('@bridge_carol:example.com', 'email', 'carol@example.com', 1554228492026, 1554228549014);
+
+            INSERT INTO devices (user_id, device_id, last_seen)
+              VALUES
+              ('@bob:example.com', 'BOBDEVICE', 1623366000000),
+              ('@bridge_carol:example.com', 'CAROLDEVICE', 1623366000000);
+
+            INSERT INTO access_tokens (id, user_id, device_id, token)
+              VALUES
+              (43, '@bob:example.com', 'BOBDEVICE', 'syt_bbbbbbbbbbbbbbbb_bbbb'),
+              (44, '@bridge_carol:example.com', 'CAROLDEVICE', 'syt_cccccccccccccccc_cccc');
+            ",
+        )
+        .await;
+        let clock = MockClock::default();
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+
+        // The first run only migrates the users
+        let report = migrate(
+            SynapseReader::new_sqlite(&mut synapse_conn, false)
+                .await
+                .unwrap(),
+            make_mas_writer(&pool).await,
+            "example.com".to_owned(),
+            &clock,
+            &mut rng,
+            std::collections::HashMap::new(),
+            &Progress::default(),
+            &MigrationOptions {
+                only_phases: Some([Phase::Users].into()),
+                ..MigrationOptions::default()
+            },
+        )
+        .await
+        .expect("failed to migrate the users");
+        assert_eq!(report.users, 2);
+        assert_eq!(report.synapse_admins, 1);
+
+        // Changes made in MAS since the first run don't change how the users are
+        // treated
+        sqlx::query("UPDATE users SET can_request_admin = FALSE")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // The second run migrates everything else against the users of the first one
+        let mut mas = make_mas_writer(&pool).await;
+        let existing_users = mas.load_user_localpart_map().await.unwrap();
+        assert_eq!(existing_users.len(), 2);
+        let report = migrate(
+            SynapseReader::new_sqlite(&mut synapse_conn, false)
+                .await
+                .unwrap(),
+            mas,
+            "example.com".to_owned(),
+            &clock,
+            &mut rng,
+            std::collections::HashMap::new(),
+            &Progress::default(),
+            &MigrationOptions {
+                allow_existing_data: true,
+                existing_users: Some(existing_users),
+                unknown_provider_policy: UnknownProviderPolicy::SkipWithWarning,
+                ..MigrationOptions::default()
+            },
+        )
+        .await
+        .expect("failed to migrate the rows depending on the users");
+        assert!(report.row_errors.is_empty());
+        assert_eq!(report.users, 0);
+        assert_eq!(report.skipped_external_ids, 1);
+        // The threepids of the deactivated user are migrated, but not their device
+        assert_eq!(report.threepids, 3);
+        assert_eq!(report.devices, 1);
+        assert_eq!(report.access_tokens, 1);
+
+        // Alice was an admin in Synapse, so her session is still a Synapse admin one
+        let is_synapse_admin: bool = sqlx::query_scalar(
+            "SELECT is_synapse_admin FROM compat_sessions WHERE device_id = 'ADEVICE'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(is_synapse_admin);
+
+        // Nothing of the application service user was migrated
+        let carol_sessions: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM compat_sessions WHERE device_id = 'CAROLDEVICE'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(carol_sessions, 0);
+    }
+
+    /// Tests that puppeting tokens are migrated as tokens of the user they act
+    /// as, with the admin who created them in a migration note.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_migrate_puppet_token(pool: PgPool) {
+        let mut synapse_conn = sqlite_synapse(
+            "
+            INSERT INTO users (name, creation_ts, admin)
+              VALUES ('@bob:example.com', 1530393962, 1);
+
+            INSERT INTO access_tokens (id, user_id, device_id, token, puppets_user_id)
+              VALUES (43, '@bob:example.com', NULL, 'syt_pupupupupup_eett', '@alice:example.com');
+            ",
+        )
+        .await;
+        let clock = MockClock::default();
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+
+        let report = migrate(
+            SynapseReader::new_sqlite(&mut synapse_conn, false)
+                .await
+                .unwrap(),
+            make_mas_writer(&pool).await,
+            "example.com".to_owned(),
+            &clock,
+            &mut rng,
+            std::collections::HashMap::new(),
+            &Progress::default(),
+            &MigrationOptions {
+                unknown_provider_policy: UnknownProviderPolicy::SkipWithWarning,
+                puppet_token_policy: PuppetTokenPolicy::RecordNote,
+                ..MigrationOptions::default()
+            },
+        )
+        .await
+        .expect("failed to migrate");
+        assert_eq!(report.puppet_tokens, 1);
+
+        let (username, device_id, puppeted_by): (String, Option<String>, String) = sqlx::query_as(
+            "
+                SELECT users.username, compat_sessions.device_id, notes.raw_value
+                FROM compat_access_tokens
+                INNER JOIN compat_sessions USING (compat_session_id)
+                INNER JOIN users USING (user_id)
+                INNER JOIN compat_session_migration_notes notes USING (compat_session_id)
+                WHERE compat_access_tokens.access_token = 'syt_pupupupupup_eett'
+                  AND notes.field = 'puppeted_by'
+                ",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(username, "alice");
+        assert_eq!(device_id, None);
+        assert_eq!(puppeted_by, "@bob:example.com");
+    }
+    use crate::{
+        mas_writer::MasNewUser,
+        synapse_reader::{FullUserId, RawText, SynapseRowCounts},
+    };
+
+    #[test]
+    fn test_error_codes() {
+        let error = Error::MissingUserFromDependentTable {
+            table: "user_threepids".to_owned(),
+            user: FullUserId("@alice:example.com".to_owned()),
+        };
+        assert_eq!(error.code(), "missing_user_from_dependent_table");
+
+        let error = Error::MissingAuthProviderMapping {
+            synapse_id: "oidc-github".to_owned(),
+            user: FullUserId("@alice:example.com".to_owned()),
+        };
+        assert_eq!(error.code(), "missing_auth_provider_mapping");
+
+        // The code doesn't change the message
+        assert_eq!(
+            error.to_string(),
+            "missing a mapping for the auth provider with ID \"oidc-github\" (used by @alice:example.com and maybe other users)"
+        );
+    }
+
+    #[test]
+    fn test_check_row_counts() {
+        let counts = SynapseRowCounts {
+            users: 10,
+            devices: 5,
+            threepids: 3,
+            external_ids: 0,
+            account_data: 7,
+            user_filters: 100,
+            access_tokens: 42,
+            refresh_tokens: 12,
+            ui_auth_sessions: 0,
+        };
+        let report = MigrationReport {
+            users: 8,
+            devices: 4,
+            threepids: 3,
+            account_data: 6,
+            ..MigrationReport::default()
+        };
+
+        // The user filters and the tokens aren't checked
+        let mut skipped_rows = [
+            ("users", 2),
+            ("devices", 1),
+            ("user_threepids", 0),
+            ("user_external_ids", 0),
+            ("account_data", 1),
+        ]
+        .into();
+        assert_eq!(
+            check_row_counts(&counts, &report, &skipped_rows).unwrap(),
+            [
+                "users",
+                "user_threepids",
+                "user_external_ids",
+                "account_data",
+                "devices"
+            ]
+        );
+
+        // A row which was neither migrated nor skipped
+        skipped_rows.insert("account_data", 0);
+        let error = check_row_counts(&counts, &report, &skipped_rows).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::CountMismatch {
+                ref table,
+                expected: 7,
+                actual: 6,
+                skipped: 0,
+            } if table == "account_data"
+        ));
+        assert_eq!(error.code(), "count_mismatch");
+    }
+
+    #[test]
+    fn test_ip_anonymization() {
+        assert_eq!(
+            "none".parse::<IpAnonymization>().unwrap(),
+            IpAnonymization::None
+        );
+        assert_eq!(
+            "v4:24".parse::<IpAnonymization>().unwrap(),
+            IpAnonymization::TruncateV4(24)
+        );
+        assert_eq!(
+            "v6:48".parse::<IpAnonymization>().unwrap(),
+            IpAnonymization::TruncateV6(48)
+        );
+        assert!("v4:33".parse::<IpAnonymization>().is_err());
+        assert!("v6:129".parse::<IpAnonymization>().is_err());
+        assert!("v4".parse::<IpAnonymization>().is_err());
+        assert!("v5:8".parse::<IpAnonymization>().is_err());
+
+        let v4: IpAddr = "203.0.113.42".parse().unwrap();
+        let v6: IpAddr = "2001:db8:1234:5678::1".parse().unwrap();
+
+        assert_eq!(IpAnonymization::None.apply(v4), v4);
+        assert_eq!(
+            IpAnonymization::TruncateV4(24).apply(v4),
+            "203.0.113.0".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            IpAnonymization::TruncateV4(0).apply(v4),
+            "0.0.0.0".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(IpAnonymization::TruncateV4(32).apply(v4), v4);
+        // Out of range prefix lengths can't be parsed, but can be built directly
+        assert_eq!(IpAnonymization::TruncateV4(33).apply(v4), v4);
+        assert_eq!(IpAnonymization::TruncateV6(255).apply(v6), v6);
+        assert_eq!(IpAnonymization::TruncateV4(24).apply(v6), v6);
+        assert_eq!(
+            IpAnonymization::TruncateV6(48).apply(v6),
+            "2001:db8:1234::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(IpAnonymization::TruncateV6(48).apply(v4), v4);
+
+        let mapped: IpAddr = "::ffff:203.0.113.42".parse().unwrap();
+        assert_eq!(IpAnonymization::None.apply(mapped), mapped);
+        assert_eq!(
+            IpAnonymization::TruncateV4(24).apply(mapped),
+            "203.0.113.0".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(IpAnonymization::TruncateV6(48).apply(mapped), mapped);
+    }
+
+    #[test]
+    fn test_user_auth_filter() {
+        assert_eq!(
+            "all".parse::<UserAuthFilter>().unwrap(),
+            UserAuthFilter::All
+        );
+        assert_eq!(
+            "password-only".parse::<UserAuthFilter>().unwrap(),
+            UserAuthFilter::PasswordOnly
+        );
+        assert_eq!(
+            "sso-only".parse::<UserAuthFilter>().unwrap(),
+            UserAuthFilter::SsoOnly
+        );
+        assert!("sso".parse::<UserAuthFilter>().is_err());
+
+        assert!(UserAuthFilter::All.includes(true));
+        assert!(UserAuthFilter::All.includes(false));
+        assert!(UserAuthFilter::PasswordOnly.includes(true));
+        assert!(!UserAuthFilter::PasswordOnly.includes(false));
+        assert!(!UserAuthFilter::SsoOnly.includes(true));
+        assert!(UserAuthFilter::SsoOnly.includes(false));
+    }
+
+    #[test]
+    fn test_check_password_scheme() {
+        let user = FullUserId("@alice:example.com".to_owned());
+
+        for hash in [
+            // The format of the Python bcrypt module Synapse uses
+            "$2b$04$EGdrhbKUv8Oc9vGiXX0HQOxSg445d458Muh7DAHskb6QbtCvdxcie",
+            // Older Synapse versions used py-bcrypt, which produced `2a` hashes
+            "$2a$04$n4Uy0eSnMfvnESYL.bLwuuj0U/ETSsoTpRT9GVk5bektyVVa5xnIi",
+            // Hashes imported from PHP use the `2y` prefix
+            "$2y$12$L6Bc/AlTQHyd9liGgGEZyOFLPHNgyxeEPfgYfBCVxJ7JIlwxyVU3u",
+        ] {
+            check_password_scheme(&user, hash).unwrap();
+        }
+
+        for (hash, expected_scheme) in [
+            (
+                "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$hash",
+                "argon2id",
+            ),
+            (
+                "$2x$12$L6Bc/AlTQHyd9liGgGEZyOFLPHNgyxeEPfgYfBCVxJ7JIlwxyVU3u",
+                "2x",
+            ),
+            // Truncated hash
+            ("$2b$12$L6Bc/AlTQHyd9liGgGEZyOFLPHNgyxeEPfgYfBCVx", "2b"),
+            // Out of range cost
+            (
+                "$2b$99$L6Bc/AlTQHyd9liGgGEZyOFLPHNgyxeEPfgYfBCVxJ7JIlwxyVU3u",
+                "2b",
+            ),
+            ("pbkdf2_sha256:260000:salt:hash", "unrecognised"),
+            ("*", "unrecognised"),
+        ] {
+            let error = check_password_scheme(&user, hash).unwrap_err();
+            assert!(
+                matches!(&error, Error::UnknownPasswordScheme { scheme, .. } if scheme == expected_scheme),
+                "unexpected error for {hash:?}: {error:?}"
+            );
+            // The hash itself isn't leaked in the error
+            assert!(!error.to_string().contains(hash));
+        }
+
+        assert_eq!(
+            "skip-password"
+                .parse::<UnknownPasswordSchemePolicy>()
+                .unwrap(),
+            UnknownPasswordSchemePolicy::SkipPassword
+        );
+        "reject".parse::<UnknownPasswordSchemePolicy>().unwrap_err();
+    }
+
+    #[test]
+    fn test_can_request_admin() {
+        // Without an allowlist, the Synapse admins can request admin
+        assert!(can_request_admin(true, "alice", None));
+        assert!(!can_request_admin(false, "bob", None));
+
+        // With one, only the users in it can, whether they were admins or not
+        let allowlist = ["bob".into()].into_iter().collect();
+        assert!(!can_request_admin(true, "alice", Some(&allowlist)));
+        assert!(can_request_admin(false, "bob", Some(&allowlist)));
+    }
+
+    #[test]
+    fn test_check_synapse_server_name() {
+        assert!(check_synapse_server_name(None, "example.com").is_ok());
+        assert!(check_synapse_server_name(Some("example.com"), "example.com").is_ok());
+
+        let error = check_synapse_server_name(Some("example.com"), "exmaple.com").unwrap_err();
+        assert_eq!(error.code(), "server_name_mismatch");
+        assert!(matches!(
+            error,
+            Error::ServerNameMismatch { synapse, mas }
+                if synapse == "example.com" && mas == "exmaple.com"
+        ));
+    }
+
+    #[test]
+    fn test_migration_phases() {
+        let phases: Vec<Phase> = Phase::all().collect();
+        assert_eq!(
+            phases,
+            [
+                Phase::Users,
+                Phase::Threepids,
+                Phase::ExternalIds,
+                Phase::AccountData,
+                Phase::UserFilters,
+                Phase::UnrefreshableTokens,
+                Phase::RefreshableTokens,
+                Phase::Devices,
+            ]
+        );
+
+        // Every phase round-trips through its name
+        for phase in Phase::all() {
+            assert_eq!(phase.to_string().parse::<Phase>().unwrap(), phase);
+        }
+        assert!("tokens".parse::<Phase>().is_err());
+
+        // The account data and user filters are opt-in
+        let options = MigrationOptions::default();
+        assert!(options.runs_phase(Phase::Users));
+        assert!(!options.runs_phase(Phase::AccountData));
+        assert!(!options.runs_phase(Phase::UserFilters));
+        assert!(options.runs_phase(Phase::RefreshableTokens));
+        assert!(options.runs_phase(Phase::Devices));
+
+        // The users aren't migrated again when reusing the ones of a prior run
+        let options = MigrationOptions {
+            existing_users: Some(std::collections::HashMap::new()),
+            ..MigrationOptions::default()
+        };
+        assert!(!options.runs_phase(Phase::Users));
+        assert!(options.runs_phase(Phase::Threepids));
+
+        let options = MigrationOptions {
+            only_phases: Some([Phase::Users, Phase::UnrefreshableTokens].into()),
+            ..MigrationOptions::default()
+        };
+        assert!(options.runs_phase(Phase::Users));
+        assert!(options.runs_phase(Phase::UnrefreshableTokens));
+        assert!(!options.runs_phase(Phase::RefreshableTokens));
+        assert!(!options.runs_phase(Phase::Devices));
+    }
+
+    #[test]
+    fn test_disambiguate_device_name() {
+        let last_seen = DateTime::<Utc>::from_timestamp(1_623_366_000, 0).unwrap();
+        assert_eq!(
+            disambiguate_device_name("Element", "ADEVICE", Some(last_seen)),
+            "Element (last seen 2021-06-10)"
+        );
+        assert_eq!(
+            disambiguate_device_name("Element", "ADEVICE", None),
+            "Element (ADEVICE)"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_user_agent() {
+        let session_id = Uuid::nil();
+
+        let raw = RawText(b"Browser/5.0 (X12; ComputerOS 64)".to_vec());
+        assert_eq!(
+            sanitize_user_agent(raw, session_id),
+            ("Browser/5.0 (X12; ComputerOS 64)".to_owned(), false)
+        );
+
+        // Invalid UTF-8 sequences are replaced
+        let raw = RawText(b"Browser/5.0 (\xff\xfeX12)".to_vec());
+        assert_eq!(
+            sanitize_user_agent(raw, session_id),
+            ("Browser/5.0 (\u{fffd}\u{fffd}X12)".to_owned(), true)
+        );
+
+        // Long user agents are truncated, without splitting a character
+        let raw = RawText("a".repeat(MAX_USER_AGENT_LENGTH - 1).into_bytes());
+        let mut long = raw.clone();
+        long.0.extend_from_slice("éé".as_bytes());
+        let (user_agent, sanitized) = sanitize_user_agent(long, session_id);
+        assert!(sanitized);
+        assert_eq!(user_agent.len(), MAX_USER_AGENT_LENGTH - 1);
+        assert_eq!(user_agent.as_bytes(), raw.0);
+
+        // Both at once
+        let mut raw = b"\xff".to_vec();
+        raw.extend(std::iter::repeat_n(b'a', MAX_USER_AGENT_LENGTH));
+        let (user_agent, sanitized) = sanitize_user_agent(RawText(raw), session_id);
+        assert!(sanitized);
+        assert_eq!(user_agent.len(), MAX_USER_AGENT_LENGTH);
+        assert!(user_agent.starts_with('\u{fffd}'));
+    }
+
+    #[test]
+    fn test_apply_shadow_ban() {
+        let created_at: DateTime<Utc> = "2025-01-01T00:00:00Z".parse().unwrap();
+        let user = MasNewUser {
+            user_id: NonNilUuid::new(Uuid::from_u128(1)).unwrap(),
+            username: "alice".to_owned(),
+            created_at,
+            locked_at: None,
+            deactivated_at: None,
+            can_request_admin: false,
+            is_guest: false,
+        };
+
+        let mut locked = user.clone();
+        apply_shadow_ban(&mut locked, ShadowBannedPolicy::Lock);
+        assert_eq!(locked.locked_at, Some(created_at));
+
+        let mut ignored = user.clone();
+        apply_shadow_ban(&mut ignored, ShadowBannedPolicy::Ignore);
+        assert_eq!(ignored.locked_at, None);
+
+        // Users which were already locked keep their lock time
+        let locked_at = "2025-02-01T00:00:00Z".parse().unwrap();
+        let mut already_locked = MasNewUser {
+            locked_at: Some(locked_at),
+            ..user
+        };
+        apply_shadow_ban(&mut already_locked, ShadowBannedPolicy::Lock);
+        assert_eq!(already_locked.locked_at, Some(locked_at));
+
+        assert_eq!(
+            "ignore".parse::<ShadowBannedPolicy>().unwrap(),
+            ShadowBannedPolicy::Ignore
+        );
+        assert!("skip".parse::<ShadowBannedPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_parse_phase_timeout() {
+        assert_eq!(
+            "devices=3600".parse::<PhaseTimeout>().unwrap(),
+            PhaseTimeout {
+                phase: Phase::Devices,
+                timeout: Duration::from_secs(3600),
+            }
+        );
+        assert!("devices".parse::<PhaseTimeout>().is_err());
+        assert!("devices=0".parse::<PhaseTimeout>().is_err());
+        assert!("devices=1h".parse::<PhaseTimeout>().is_err());
+        assert!("passwords=60".parse::<PhaseTimeout>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_with_phase_timeout() {
+        // Phases without a timeout, or finishing in time, are left alone
+        let result = with_phase_timeout(Phase::Devices, None, async { Ok(42) }).await;
+        assert_eq!(result.unwrap(), 42);
+        let result = with_phase_timeout(Phase::Devices, Some(Duration::from_secs(60)), async {
+            Ok(42)
+        })
+        .await;
+        assert_eq!(result.unwrap(), 42);
+
+        let error = with_phase_timeout::<()>(
+            Phase::Devices,
+            Some(Duration::from_millis(10)),
+            std::future::pending(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code(), "phase_timeout");
+        assert!(
+            error
+                .to_string()
+                .starts_with("the devices phase didn't finish within")
+        );
+        assert!(matches!(
+            error,
+            Error::PhaseTimeout { phase: Phase::Devices, elapsed }
+                if elapsed >= Duration::from_millis(10)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_localpart_reason() {
+        let valid = ["alice", "1234", "_bridge_alice", "a.b=c-d/e"];
+        for localpart in valid {
+            let user_id = FullUserId(format!("@{localpart}:example.com"));
+            assert_eq!(invalid_localpart_reason(&user_id, localpart), None);
+        }
+
+        let user_id = FullUserId("@Alice:example.com".to_owned());
+        assert_eq!(
+            invalid_localpart_reason(&user_id, "Alice").as_deref(),
+            Some(
+                "it contains the character 'A', but only lowercase letters, digits and `=_-./` are allowed"
+            )
+        );
+
+        let user_id = FullUserId("@alice+bob:example.com".to_owned());
+        assert!(invalid_localpart_reason(&user_id, "alice+bob").is_some());
+
+        let user_id = FullUserId("@:example.com".to_owned());
+        assert_eq!(
+            invalid_localpart_reason(&user_id, "").as_deref(),
+            Some("it is empty")
+        );
+
+        let localpart = "a".repeat(250);
+        let user_id = FullUserId(format!("@{localpart}:example.com"));
+        assert!(invalid_localpart_reason(&user_id, &localpart).is_some());
+    }
+
+    /// Tests that the characters allowed in localparts are the ones the
+    /// registration policy allows in usernames.
+    #[test]
+    fn test_localpart_characters_match_policy() {
+        let policy = include_str!("../../../policies/register/register.rego");
+        let (_, rule) = policy
+            .split_once(r#""code": "username-invalid-chars""#)
+            .expect("the registration policy has no username-invalid-chars rule");
+        let (_, rule) = rule
+            .split_once("regex.match(`")
+            .expect("the username-invalid-chars rule doesn't match a regex");
+        let (pattern, _) = rule.split_once('`').unwrap();
+        let pattern = regex::Regex::new(pattern).unwrap();
+
+        for c in (0..=0x2ff).filter_map(char::from_u32) {
+            assert_eq!(
+                valid_localpart_character(c),
+                pattern.is_match(&c.to_string()),
+                "the policy and the migration disagree on {c:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_deterministic_ids() {
+        let ids = IdGenerator {
+            deterministic: true,
+        };
+        let created_at: DateTime<Utc> = "2025-01-01T00:00:00Z".parse().unwrap();
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+
+        let alice = ids.ulid(created_at, &["users", "@alice:example.com"], &mut rng);
+
+        // The same row always gets the same ID, whatever the state of the RNG
+        let mut other_rng = rand_chacha::ChaChaRng::seed_from_u64(1337);
+        assert_eq!(
+            alice,
+            ids.ulid(created_at, &["users", "@alice:example.com"], &mut other_rng)
+        );
+        assert_eq!(DateTime::<Utc>::from(alice.datetime()), created_at);
+
+        // Different rows get different IDs
+        assert_ne!(
+            alice,
+            ids.ulid(created_at, &["users", "@bob:example.com"], &mut rng)
+        );
+        assert_ne!(
+            ids.ulid(created_at, &["devices", "ab", "c"], &mut rng),
+            ids.ulid(created_at, &["devices", "a", "bc"], &mut rng)
+        );
+    }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(normalize_email("alice@example.com"), "alice@example.com");
+        assert_eq!(normalize_email(" Alice@Example.COM\t"), "alice@example.com");
+    }
+}
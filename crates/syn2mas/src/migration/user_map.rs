@@ -0,0 +1,427 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! The lookup table from the localparts of the Synapse users to their infos,
+//! used by every phase after the users one.
+//!
+//! It is kept either in memory, or in files on disk to cap the memory usage
+//! of the migration of very large homeservers.
+
+use std::{
+    fs::{File, OpenOptions},
+    hash::BuildHasher,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use compact_str::CompactString;
+use uuid::{NonNilUuid, Uuid};
+
+use super::{UserFlags, UserInfo};
+use crate::{HashMap, RandomState};
+
+/// Where to keep the lookup table of the users during the migration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum UserMapBacking {
+    /// Keep the table in memory, which is the fastest
+    #[default]
+    InMemory,
+
+    /// Keep the table in files created in the given directory, which are
+    /// removed once the migration is done
+    ///
+    /// This caps the memory used by the table, whatever the number of users,
+    /// at the cost of reading from the files for every row of the phases after
+    /// the users one, which makes them noticeably slower.
+    OnDisk(Utf8PathBuf),
+}
+
+/// The lookup table from the localparts of the users to their infos
+pub(super) enum UserMap {
+    InMemory(HashMap<CompactString, UserInfo>),
+    OnDisk(DiskUserMap),
+}
+
+impl UserMap {
+    /// Create an empty table, sized for `capacity` users
+    pub(super) fn new(backing: &UserMapBacking, capacity: usize) -> std::io::Result<Self> {
+        match backing {
+            UserMapBacking::InMemory => Ok(Self::InMemory(HashMap::with_capacity_and_hasher(
+                capacity,
+                RandomState::default(),
+            ))),
+            UserMapBacking::OnDisk(directory) => {
+                Ok(Self::OnDisk(DiskUserMap::new(directory, capacity)?))
+            }
+        }
+    }
+
+    /// Get the infos of the user with the given localpart
+    pub(super) fn get(&mut self, localpart: &str) -> std::io::Result<Option<UserInfo>> {
+        match self {
+            Self::InMemory(users) => Ok(users.get(localpart).copied()),
+            Self::OnDisk(users) => users.get(localpart),
+        }
+    }
+
+    /// Whether a user with the given localpart is in the table
+    pub(super) fn contains_key(&mut self, localpart: &str) -> std::io::Result<bool> {
+        Ok(self.get(localpart)?.is_some())
+    }
+
+    /// Insert the infos of a user, replacing the previous ones if the
+    /// localpart was already in the table
+    pub(super) fn insert(
+        &mut self,
+        localpart: CompactString,
+        user_infos: UserInfo,
+    ) -> std::io::Result<()> {
+        match self {
+            Self::InMemory(users) => {
+                users.insert(localpart, user_infos);
+                Ok(())
+            }
+            Self::OnDisk(users) => users.insert(&localpart, user_infos),
+        }
+    }
+
+    /// The number of users in the table
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Self::InMemory(users) => users.len(),
+            Self::OnDisk(users) => users.len,
+        }
+    }
+}
+
+/// Length of a bucket of the [`DiskUserMap`]: the hash of the localpart (8
+/// bytes), the offset of the localpart in the localparts file plus one, zero
+/// meaning the bucket is empty (8 bytes), the length of the localpart (2
+/// bytes), the user flags (1 byte) and the MAS user ID, all zeroes if none (16
+/// bytes)
+const BUCKET_LEN: usize = 35;
+
+/// The minimum number of buckets of the [`DiskUserMap`]
+const MIN_BUCKETS: u64 = 1024;
+
+/// An open addressing hash table, with linear probing, stored in two files:
+/// one with fixed-size buckets, and one to which the localparts are appended.
+///
+/// The files are removed when the table is dropped.
+pub(super) struct DiskUserMap {
+    directory: Utf8PathBuf,
+    hasher: RandomState,
+
+    buckets_path: Utf8PathBuf,
+    buckets: File,
+    /// Number of buckets in the buckets file
+    bucket_count: u64,
+
+    /// Bumped every time the buckets are moved to a larger file
+    generation: u32,
+
+    localparts_path: Utf8PathBuf,
+    localparts: File,
+    /// Length of the localparts file
+    localparts_len: u64,
+
+    /// Number of users in the table
+    len: usize,
+}
+
+/// Create a file at `path` for reading and writing, truncating it if it
+/// already exists, as it can only be left over from a previous migration
+fn create_file(path: &Utf8Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+impl DiskUserMap {
+    fn new(directory: &Utf8Path, capacity: usize) -> std::io::Result<Self> {
+        let localparts_path =
+            directory.join(format!("syn2mas-users-{}.localparts", std::process::id()));
+        let localparts = create_file(&localparts_path)?;
+
+        // Keep the load factor under 3/4
+        let bucket_count = (capacity as u64 * 4 / 3).max(MIN_BUCKETS);
+        let buckets_path = Self::buckets_path(directory, 0);
+        let buckets = create_file(&buckets_path)?;
+        buckets.set_len(bucket_count * BUCKET_LEN as u64)?;
+
+        Ok(Self {
+            directory: directory.to_owned(),
+            hasher: RandomState::default(),
+            buckets_path,
+            buckets,
+            bucket_count,
+            generation: 0,
+            localparts_path,
+            localparts,
+            localparts_len: 0,
+            len: 0,
+        })
+    }
+
+    fn buckets_path(directory: &Utf8Path, generation: u32) -> Utf8PathBuf {
+        directory.join(format!(
+            "syn2mas-users-{}-{generation}.buckets",
+            std::process::id()
+        ))
+    }
+
+    fn read_bucket(&mut self, index: u64) -> std::io::Result<[u8; BUCKET_LEN]> {
+        let mut bucket = [0; BUCKET_LEN];
+        self.buckets
+            .seek(SeekFrom::Start(index * BUCKET_LEN as u64))?;
+        self.buckets.read_exact(&mut bucket)?;
+        Ok(bucket)
+    }
+
+    fn write_bucket(&mut self, index: u64, bucket: &[u8; BUCKET_LEN]) -> std::io::Result<()> {
+        self.buckets
+            .seek(SeekFrom::Start(index * BUCKET_LEN as u64))?;
+        self.buckets.write_all(bucket)
+    }
+
+    /// Find the bucket of the given localpart, or the empty bucket where it
+    /// should be inserted
+    ///
+    /// Returns the index of the bucket, and its content if it is not empty.
+    fn find(
+        &mut self,
+        localpart: &str,
+        hash: u64,
+    ) -> std::io::Result<(u64, Option<[u8; BUCKET_LEN]>)> {
+        let mut index = hash % self.bucket_count;
+        loop {
+            let bucket = self.read_bucket(index)?;
+            let (bucket_hash, localpart_offset, localpart_len) = bucket_key(&bucket);
+            let Some(localpart_offset) = localpart_offset else {
+                return Ok((index, None));
+            };
+
+            // Only read the localpart back when the hashes match, which makes
+            // collisions the only lookups reading it more than once
+            if bucket_hash == hash && usize::from(localpart_len) == localpart.len() {
+                let mut stored = vec![0; localpart.len()];
+                self.localparts.seek(SeekFrom::Start(localpart_offset))?;
+                self.localparts.read_exact(&mut stored)?;
+                if stored == localpart.as_bytes() {
+                    return Ok((index, Some(bucket)));
+                }
+            }
+
+            index = (index + 1) % self.bucket_count;
+        }
+    }
+
+    fn get(&mut self, localpart: &str) -> std::io::Result<Option<UserInfo>> {
+        let hash = self.hasher.hash_one(localpart);
+        let (_, bucket) = self.find(localpart, hash)?;
+        Ok(bucket.as_ref().map(bucket_user_infos))
+    }
+
+    fn insert(&mut self, localpart: &str, user_infos: UserInfo) -> std::io::Result<()> {
+        let localpart_len = u16::try_from(localpart.len()).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("localpart {localpart:?} is too long"),
+            )
+        })?;
+
+        if (self.len as u64 + 1) * 4 > self.bucket_count * 3 {
+            self.grow()?;
+        }
+
+        let hash = self.hasher.hash_one(localpart);
+        let (index, existing) = self.find(localpart, hash)?;
+
+        let localpart_offset = if let Some(existing) = existing {
+            // The localpart is already stored, only the infos are replaced
+            bucket_key(&existing)
+                .1
+                .expect("non-empty buckets have a localpart")
+        } else {
+            let offset = self.localparts_len;
+            self.localparts.seek(SeekFrom::Start(offset))?;
+            self.localparts.write_all(localpart.as_bytes())?;
+            self.localparts_len += u64::from(localpart_len);
+            self.len += 1;
+            offset
+        };
+
+        let bucket = encode_bucket(hash, localpart_offset, localpart_len, user_infos);
+        self.write_bucket(index, &bucket)
+    }
+
+    /// Move the buckets to a file twice as large
+    fn grow(&mut self) -> std::io::Result<()> {
+        let generation = self.generation + 1;
+        let buckets_path = Self::buckets_path(&self.directory, generation);
+        let mut buckets = create_file(&buckets_path)?;
+        let bucket_count = self.bucket_count * 2;
+        buckets.set_len(bucket_count * BUCKET_LEN as u64)?;
+
+        self.buckets.seek(SeekFrom::Start(0))?;
+        let mut old_buckets = BufReader::new(&mut self.buckets);
+        let mut bucket = [0; BUCKET_LEN];
+        for _ in 0..self.bucket_count {
+            old_buckets.read_exact(&mut bucket)?;
+            let (hash, localpart_offset, _) = bucket_key(&bucket);
+            if localpart_offset.is_none() {
+                continue;
+            }
+
+            // The localparts are all different, so there is no need to compare
+            // them: the bucket goes to the first empty one
+            let mut index = hash % bucket_count;
+            loop {
+                let mut other = [0; BUCKET_LEN];
+                buckets.seek(SeekFrom::Start(index * BUCKET_LEN as u64))?;
+                buckets.read_exact(&mut other)?;
+                if bucket_key(&other).1.is_none() {
+                    break;
+                }
+                index = (index + 1) % bucket_count;
+            }
+
+            buckets.seek(SeekFrom::Start(index * BUCKET_LEN as u64))?;
+            buckets.write_all(&bucket)?;
+        }
+
+        let old_path = std::mem::replace(&mut self.buckets_path, buckets_path);
+        self.buckets = buckets;
+        self.bucket_count = bucket_count;
+        self.generation = generation;
+        std::fs::remove_file(old_path)
+    }
+}
+
+impl Drop for DiskUserMap {
+    fn drop(&mut self) {
+        for path in [&self.buckets_path, &self.localparts_path] {
+            if let Err(error) = std::fs::remove_file(path) {
+                tracing::warn!(
+                    error = &error as &dyn std::error::Error,
+                    "Failed to remove {path}"
+                );
+            }
+        }
+    }
+}
+
+/// Decode the hash, the offset and the length of the localpart of a bucket
+///
+/// The offset is `None` if the bucket is empty.
+fn bucket_key(bucket: &[u8; BUCKET_LEN]) -> (u64, Option<u64>, u16) {
+    let hash = u64::from_le_bytes(bucket[0..8].try_into().unwrap());
+    let offset = u64::from_le_bytes(bucket[8..16].try_into().unwrap());
+    let len = u16::from_le_bytes(bucket[16..18].try_into().unwrap());
+    (hash, offset.checked_sub(1), len)
+}
+
+fn bucket_user_infos(bucket: &[u8; BUCKET_LEN]) -> UserInfo {
+    let mas_user_id = Uuid::from_bytes(bucket[19..35].try_into().unwrap());
+    UserInfo {
+        mas_user_id: NonNilUuid::new(mas_user_id),
+        flags: UserFlags::from_bits_retain(bucket[18]),
+    }
+}
+
+fn encode_bucket(
+    hash: u64,
+    localpart_offset: u64,
+    localpart_len: u16,
+    user_infos: UserInfo,
+) -> [u8; BUCKET_LEN] {
+    let mut bucket = [0; BUCKET_LEN];
+    bucket[0..8].copy_from_slice(&hash.to_le_bytes());
+    bucket[8..16].copy_from_slice(&(localpart_offset + 1).to_le_bytes());
+    bucket[16..18].copy_from_slice(&localpart_len.to_le_bytes());
+    bucket[18] = user_infos.flags.bits();
+    if let Some(mas_user_id) = user_infos.mas_user_id {
+        bucket[19..35].copy_from_slice(Uuid::from(mas_user_id).as_bytes());
+    }
+    bucket
+}
+
+#[cfg(test)]
+mod test {
+    use compact_str::CompactString;
+    use uuid::{NonNilUuid, Uuid};
+
+    use super::{UserMap, UserMapBacking};
+    use crate::migration::{UserFlags, UserInfo};
+
+    #[test]
+    fn test_disk_user_map() {
+        let directory = camino::Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        let mut users = UserMap::new(&UserMapBacking::OnDisk(directory), 10).unwrap();
+        let UserMap::OnDisk(disk_users) = &users else {
+            panic!("the users should be on disk");
+        };
+        let files = [
+            disk_users.buckets_path.clone(),
+            disk_users.localparts_path.clone(),
+        ];
+
+        // Insert enough users to grow the buckets a few times
+        for i in 0..5000_u128 {
+            let user_infos = UserInfo {
+                mas_user_id: NonNilUuid::new(Uuid::from_u128(i)),
+                flags: if i % 2 == 0 {
+                    UserFlags::IS_DEACTIVATED
+                } else {
+                    UserFlags::empty()
+                },
+            };
+            users
+                .insert(CompactString::new(format!("user{i}")), user_infos)
+                .unwrap();
+        }
+        assert_eq!(users.len(), 5000);
+
+        // The user with the nil ID has none
+        let nil_id_user = users.get("user0").unwrap().unwrap();
+        assert_eq!(nil_id_user.mas_user_id, None);
+        assert!(nil_id_user.flags.is_deactivated());
+
+        let other_user = users.get("user4321").unwrap().unwrap();
+        assert_eq!(
+            other_user.mas_user_id,
+            NonNilUuid::new(Uuid::from_u128(4321))
+        );
+        assert!(!other_user.flags.is_deactivated());
+
+        assert!(users.get("user5000").unwrap().is_none());
+        assert!(!users.contains_key("user").unwrap());
+
+        // Inserting an existing localpart replaces its infos
+        users
+            .insert(
+                CompactString::new("user1"),
+                UserInfo {
+                    mas_user_id: None,
+                    flags: UserFlags::IS_GUEST,
+                },
+            )
+            .unwrap();
+        assert_eq!(users.len(), 5000);
+        let replaced_user = users.get("user1").unwrap().unwrap();
+        assert_eq!(replaced_user.mas_user_id, None);
+        assert!(replaced_user.flags.contains(UserFlags::IS_GUEST));
+
+        // The files are removed with the table, including the buckets of the
+        // previous generations
+        drop(users);
+        for file in files {
+            assert!(!file.exists(), "{file} was not removed");
+        }
+    }
+}
@@ -55,6 +55,12 @@ pub enum EntityType {
     /// Represents external IDs
     ExternalIds,
 
+    /// Represents account data
+    AccountData,
+
+    /// Represents sync filters
+    UserFilters,
+
     /// Represents non-refreshable access tokens
     NonRefreshableAccessTokens,
 
@@ -69,22 +75,46 @@ impl std::fmt::Display for EntityType {
 }
 
 impl EntityType {
+    #[must_use]
     pub const fn name(self) -> &'static str {
         match self {
             Self::Users => "users",
             Self::Devices => "devices",
             Self::ThreePids => "threepids",
             Self::ExternalIds => "external_ids",
+            Self::AccountData => "account_data",
+            Self::UserFilters => "user_filters",
             Self::NonRefreshableAccessTokens => "nonrefreshable_access_tokens",
             Self::RefreshableTokens => "refreshable_tokens",
         }
     }
 
+    #[must_use]
     pub fn as_kv(self) -> KeyValue {
         KeyValue::new("entity", self.name())
     }
 }
 
+/// Number of processed (migrated or skipped) rows between two calls of the
+/// progress callback.
+const PROGRESS_CALLBACK_INTERVAL: u32 = 1000;
+
+/// Snapshot of the progress of a migration phase, passed to the callback set
+/// with [`Progress::with_callback`].
+#[derive(Debug, Clone, Copy)]
+pub struct MigrationProgress {
+    /// The type of entities being migrated in this phase
+    pub phase: EntityType,
+
+    /// Number of rows processed so far in this phase, migrated or skipped
+    pub processed: u64,
+
+    /// Approximate total number of rows in this phase, if known
+    pub total: Option<u64>,
+}
+
+type ProgressCallback = Arc<dyn Fn(MigrationProgress) + Send + Sync>;
+
 /// Tracker for the progress of the migration
 ///
 /// Cloning this struct intuitively gives a 'handle' to the same counters,
@@ -92,6 +122,7 @@ impl EntityType {
 #[derive(Clone)]
 pub struct Progress {
     current_stage: Arc<ArcSwap<ProgressStage>>,
+    callback: Option<ProgressCallback>,
 }
 
 #[derive(Clone)]
@@ -101,15 +132,21 @@ pub struct ProgressCounter {
 
 struct ProgressCounterInner {
     kv: [KeyValue; 1],
+    entity: EntityType,
+    approx_count: u64,
+    callback: Option<ProgressCallback>,
     migrated: AtomicU32,
     skipped: AtomicU32,
 }
 
 impl ProgressCounter {
-    fn new(entity: EntityType) -> Self {
+    fn new(entity: EntityType, approx_count: u64, callback: Option<ProgressCallback>) -> Self {
         Self {
             inner: Arc::new(ProgressCounterInner {
                 kv: [entity.as_kv()],
+                entity,
+                approx_count,
+                callback,
                 migrated: AtomicU32::new(0),
                 skipped: AtomicU32::new(0),
             }),
@@ -121,6 +158,7 @@ impl ProgressCounter {
         self.inner
             .migrated
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.maybe_call_callback();
     }
 
     pub fn increment_skipped(&self) {
@@ -128,6 +166,24 @@ impl ProgressCounter {
         self.inner
             .skipped
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.maybe_call_callback();
+    }
+
+    /// Calls the progress callback, if any, every
+    /// [`PROGRESS_CALLBACK_INTERVAL`] processed rows.
+    fn maybe_call_callback(&self) {
+        let Some(callback) = &self.inner.callback else {
+            return;
+        };
+
+        let processed = self.migrated() + self.skipped();
+        if processed.is_multiple_of(PROGRESS_CALLBACK_INTERVAL) {
+            callback(MigrationProgress {
+                phase: self.inner.entity,
+                processed: processed.into(),
+                total: Some(self.inner.approx_count),
+            });
+        }
     }
 
     #[must_use]
@@ -146,9 +202,22 @@ impl ProgressCounter {
 }
 
 impl Progress {
+    /// Creates a new progress tracker which calls the given callback every
+    /// 1000 rows processed by each migration phase.
+    ///
+    /// The callback is called from within the migration tasks, so it should
+    /// return quickly.
+    #[must_use]
+    pub fn with_callback(callback: impl Fn(MigrationProgress) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Some(Arc::new(callback)),
+            ..Self::default()
+        }
+    }
+
     #[must_use]
     pub fn migrating_data(&self, entity: EntityType, approx_count: usize) -> ProgressCounter {
-        let counter = ProgressCounter::new(entity);
+        let counter = ProgressCounter::new(entity, approx_count as u64, self.callback.clone());
         APPROX_TOTAL_GAUGE.record(approx_count as u64, &[entity.as_kv()]);
         self.set_current_stage(ProgressStage::MigratingData {
             entity,
@@ -188,6 +257,7 @@ impl Default for Progress {
     fn default() -> Self {
         Self {
             current_stage: Arc::new(ArcSwap::new(Arc::new(ProgressStage::SettingUp))),
+            callback: None,
         }
     }
 }
@@ -13,11 +13,11 @@ use mas_config::{
     BrandingConfig, CaptchaConfig, ConfigurationSection, ConfigurationSectionExt, MatrixConfig,
     PasswordAlgorithm, PasswordsConfig, UpstreamOAuth2Config,
 };
-use sqlx::{PgConnection, prelude::FromRow, query_as, query_scalar};
+use sqlx::{prelude::FromRow, query_as, query_scalar};
 use thiserror::Error;
 
-use super::config::Config;
-use crate::mas_writer::MIGRATED_PASSWORD_VERSION;
+use super::{SynapseConnection, config::Config};
+use crate::{UnknownProviderPolicy, mas_writer::MIGRATED_PASSWORD_VERSION};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -130,6 +130,11 @@ pub enum CheckWarning {
         "Synapse database contains {num_non_email_3pids} non-email 3PIDs (probably phone numbers), which will be migrated but are not supported by MAS."
     )]
     NonEmailThreepidsInDatabase { num_non_email_3pids: i64 },
+
+    #[error(
+        "Synapse database contains {num_users} users associated to the provider '{provider}' which is not configured as an upstream provider in MAS. Their upstream links will be skipped during migration."
+    )]
+    UnknownOAuthProviderSkipped { provider: String, num_users: i64 },
 }
 
 /// Check that the Synapse configuration is sane for migration.
@@ -246,6 +251,11 @@ pub async fn synapse_config_check_against_mas_config(
 /// Check that the Synapse database is sane for migration. Returns a list of
 /// warnings and errors.
 ///
+/// The queries are the same for Postgres and SQLite.
+///
+/// With [`UnknownProviderPolicy::SkipWithWarning`], external IDs of providers
+/// which are not configured in MAS are reported as warnings instead of errors.
+///
 /// # Errors
 ///
 /// - If there is some database connection error, or the given database is not a
@@ -253,9 +263,10 @@ pub async fn synapse_config_check_against_mas_config(
 /// - If the OAuth2 section of the MAS configuration could not be parsed.
 #[tracing::instrument(skip_all)]
 pub async fn synapse_database_check(
-    synapse_connection: &mut PgConnection,
+    synapse_connection: &mut SynapseConnection,
     synapse: &Config,
     mas: &Figment,
+    unknown_provider_policy: UnknownProviderPolicy,
 ) -> Result<(Vec<CheckWarning>, Vec<CheckError>), Error> {
     #[derive(FromRow)]
     struct UpstreamOAuthProvider {
@@ -263,36 +274,57 @@ pub async fn synapse_database_check(
         num_users: i64,
     }
 
+    const NUM_GUESTS_QUERY: &str = "SELECT COUNT(1) FROM users WHERE is_guest <> 0";
+    const NUM_NON_EMAIL_3PIDS_QUERY: &str =
+        "SELECT COUNT(1) FROM user_threepids WHERE medium <> 'email'";
+    const OAUTH_PROVIDER_USER_COUNTS_QUERY: &str = "
+        SELECT auth_provider, COUNT(*) AS num_users
+        FROM user_external_ids
+        GROUP BY auth_provider
+        ORDER BY auth_provider
+    ";
+
     let mut errors = Vec::new();
     let mut warnings = Vec::new();
 
-    let num_guests: i64 = query_scalar("SELECT COUNT(1) FROM users WHERE is_guest <> 0")
-        .fetch_one(&mut *synapse_connection)
-        .await?;
+    let num_guests: i64 = match synapse_connection {
+        SynapseConnection::Postgres(conn) => query_scalar(NUM_GUESTS_QUERY).fetch_one(conn).await?,
+        SynapseConnection::Sqlite(conn) => query_scalar(NUM_GUESTS_QUERY).fetch_one(conn).await?,
+    };
     if num_guests > 0 {
         warnings.push(CheckWarning::GuestsInDatabase { num_guests });
     }
 
-    let num_non_email_3pids: i64 =
-        query_scalar("SELECT COUNT(1) FROM user_threepids WHERE medium <> 'email'")
-            .fetch_one(&mut *synapse_connection)
-            .await?;
+    let num_non_email_3pids: i64 = match synapse_connection {
+        SynapseConnection::Postgres(conn) => {
+            query_scalar(NUM_NON_EMAIL_3PIDS_QUERY)
+                .fetch_one(conn)
+                .await?
+        }
+        SynapseConnection::Sqlite(conn) => {
+            query_scalar(NUM_NON_EMAIL_3PIDS_QUERY)
+                .fetch_one(conn)
+                .await?
+        }
+    };
     if num_non_email_3pids > 0 {
         warnings.push(CheckWarning::NonEmailThreepidsInDatabase {
             num_non_email_3pids,
         });
     }
 
-    let oauth_provider_user_counts = query_as::<_, UpstreamOAuthProvider>(
-        "
-        SELECT auth_provider, COUNT(*) AS num_users
-        FROM user_external_ids
-        GROUP BY auth_provider
-        ORDER BY auth_provider
-        ",
-    )
-    .fetch_all(&mut *synapse_connection)
-    .await?;
+    let oauth_provider_user_counts: Vec<UpstreamOAuthProvider> = match synapse_connection {
+        SynapseConnection::Postgres(conn) => {
+            query_as(OAUTH_PROVIDER_USER_COUNTS_QUERY)
+                .fetch_all(conn)
+                .await?
+        }
+        SynapseConnection::Sqlite(conn) => {
+            query_as(OAUTH_PROVIDER_USER_COUNTS_QUERY)
+                .fetch_all(conn)
+                .await?
+        }
+    };
     if !oauth_provider_user_counts.is_empty() {
         let syn_oauth2 = synapse.all_oidc_providers();
         let mas_oauth2 = UpstreamOAuth2Config::extract_or_default(mas).map_err(Error::MasConfig)?;
@@ -305,6 +337,23 @@ pub async fn synapse_database_check(
                 continue;
             }
 
+            // Matching by `synapse_idp_id` is the same as what we'll do for the migration
+            let matching_mas = mas_oauth2.providers.iter().find(|mas_provider| {
+                mas_provider.synapse_idp_id.as_ref() == Some(&row.auth_provider)
+            });
+
+            // The migration will skip the links of this provider, so there is no need to
+            // check the Synapse side of things
+            if matching_mas.is_none()
+                && unknown_provider_policy == UnknownProviderPolicy::SkipWithWarning
+            {
+                warnings.push(CheckWarning::UnknownOAuthProviderSkipped {
+                    provider: row.auth_provider,
+                    num_users: row.num_users,
+                });
+                continue;
+            }
+
             let matching_syn = syn_oauth2.get(&row.auth_provider);
 
             let Some(matching_syn) = matching_syn else {
@@ -315,11 +364,6 @@ pub async fn synapse_database_check(
                 continue;
             };
 
-            // Matching by `synapse_idp_id` is the same as what we'll do for the migration
-            let matching_mas = mas_oauth2.providers.iter().find(|mas_provider| {
-                mas_provider.synapse_idp_id.as_ref() == Some(&row.auth_provider)
-            });
-
             if matching_mas.is_none() {
                 errors.push(CheckError::MasMissingOAuthProvider {
                     provider: row.auth_provider,
@@ -0,0 +1,26 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+INSERT INTO access_tokens
+  (
+  id,
+  user_id,
+  device_id,
+  token
+  )
+  VALUES
+  (
+  43,
+  '@alice:example.com',
+  NULL,
+  'syt_dddddddddddddd_dddd'
+  );
+
+INSERT INTO user_ips
+  (user_id, access_token, device_id, ip, user_agent, last_seen)
+  VALUES
+  ('@alice:example.com', 'syt_dddddddddddddd_dddd', NULL, '203.0.113.2', 'Bot/1.0', 1623360000000),
+  ('@alice:example.com', 'syt_dddddddddddddd_dddd', NULL, '203.0.113.3', 'Bot/2.0', 1623366000000),
+  ('@alice:example.com', 'syt_aaaaaaaaaaaaaa_aaaa', 'ADEVICE', '203.0.113.1', 'Browser/5.0', 1623370000000);
@@ -3,6 +3,7 @@
 -- SPDX-License-Identifier: AGPL-3.0-only
 -- Please see LICENSE files in the repository root for full details.
 
+-- A token of the admin @bob:example.com, logging in as @alice:example.com
 INSERT INTO access_tokens
   (
   id,
@@ -14,8 +15,8 @@ INSERT INTO access_tokens
   VALUES
   (
   42,
-  '@alice:example.com',
+  '@bob:example.com',
   NULL,
   'syt_pupupupupup_eett',
-  '@bob:example.com'
+  '@alice:example.com'
   );
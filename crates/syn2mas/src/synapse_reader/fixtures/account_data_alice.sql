@@ -0,0 +1,25 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+INSERT INTO account_data
+  (
+    user_id,
+    account_data_type,
+    stream_id,
+    content
+  )
+  VALUES
+  (
+    '@alice:example.com',
+    'im.vector.setting.breadcrumbs',
+    2,
+    '{"recent_rooms":["!room:example.com"]}'
+  ),
+  (
+    '@alice:example.com',
+    'm.push_rules',
+    3,
+    '{"global":{}}'
+  );
@@ -0,0 +1,43 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+INSERT INTO devices
+  (
+    user_id,
+    device_id,
+    display_name,
+    last_seen,
+    ip,
+    user_agent,
+    hidden
+  )
+  VALUES
+  (
+    '@alice:example.com',
+    'ELEMENTWEB',
+    'Element',
+    1623366000000,
+    NULL,
+    NULL,
+    FALSE
+  ),
+  (
+    '@alice:example.com',
+    'ELEMENTDESKTOP',
+    'Element',
+    NULL,
+    NULL,
+    NULL,
+    FALSE
+  ),
+  (
+    '@alice:example.com',
+    'ELEMENTHIDDEN',
+    'Matrix Console',
+    NULL,
+    NULL,
+    NULL,
+    TRUE
+  );
@@ -0,0 +1,13 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+INSERT INTO erased_users
+  (
+    user_id
+  )
+  VALUES
+  (
+    '@alice:example.com'
+  );
@@ -0,0 +1,189 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+-- A Synapse database using SQLite, with the same rows as the `*_alice.sql`
+-- fixtures. Synapse stores booleans as 0 or 1 and binary data as BLOBs in
+-- SQLite.
+
+CREATE TABLE users (
+  name TEXT,
+  password_hash TEXT,
+  creation_ts BIGINT,
+  admin SMALLINT DEFAULT 0 NOT NULL,
+  upgrade_ts BIGINT,
+  is_guest SMALLINT DEFAULT 0 NOT NULL,
+  appservice_id TEXT,
+  consent_version TEXT,
+  consent_server_notice_sent TEXT,
+  user_type TEXT DEFAULT NULL,
+  deactivated SMALLINT DEFAULT 0 NOT NULL,
+  shadow_banned BOOLEAN,
+  consent_ts BIGINT,
+  approved BOOLEAN,
+  locked BOOLEAN NOT NULL DEFAULT FALSE,
+  suspended BOOLEAN NOT NULL DEFAULT FALSE,
+  UNIQUE(name)
+);
+
+CREATE TABLE erased_users (
+  user_id TEXT NOT NULL
+);
+
+CREATE TABLE user_threepids (
+  user_id TEXT NOT NULL,
+  medium TEXT NOT NULL,
+  address TEXT NOT NULL,
+  validated_at BIGINT NOT NULL,
+  added_at BIGINT NOT NULL,
+  CONSTRAINT medium_address UNIQUE (medium, address)
+);
+
+CREATE TABLE user_external_ids (
+  auth_provider TEXT NOT NULL,
+  external_id TEXT NOT NULL,
+  user_id TEXT NOT NULL,
+  UNIQUE (auth_provider, external_id)
+);
+
+CREATE TABLE account_data (
+  user_id TEXT NOT NULL,
+  account_data_type TEXT NOT NULL,
+  stream_id BIGINT NOT NULL,
+  content TEXT NOT NULL,
+  instance_name TEXT,
+  CONSTRAINT account_data_uniqueness UNIQUE (user_id, account_data_type)
+);
+
+CREATE TABLE user_filters (
+  user_id TEXT NOT NULL,
+  filter_id BIGINT NOT NULL,
+  filter_json BYTEA NOT NULL,
+  full_user_id TEXT
+);
+
+CREATE TABLE devices (
+  user_id TEXT NOT NULL,
+  device_id TEXT NOT NULL,
+  display_name TEXT,
+  last_seen BIGINT,
+  ip TEXT,
+  user_agent TEXT,
+  hidden BOOLEAN DEFAULT 0,
+  CONSTRAINT device_uniqueness UNIQUE (user_id, device_id)
+);
+
+CREATE TABLE access_tokens (
+  id BIGINT PRIMARY KEY,
+  user_id TEXT NOT NULL,
+  device_id TEXT,
+  token TEXT NOT NULL,
+  valid_until_ms BIGINT,
+  puppets_user_id TEXT,
+  last_validated BIGINT,
+  refresh_token_id BIGINT,
+  used BOOLEAN,
+  UNIQUE(token)
+);
+
+CREATE TABLE refresh_tokens (
+  id BIGINT PRIMARY KEY,
+  user_id TEXT NOT NULL,
+  device_id TEXT NOT NULL,
+  token TEXT NOT NULL,
+  next_token_id BIGINT,
+  expiry_ts BIGINT DEFAULT NULL,
+  ultimate_session_expiry_ts BIGINT DEFAULT NULL,
+  UNIQUE(token)
+);
+
+CREATE TABLE ui_auth_sessions (
+  session_id TEXT NOT NULL,
+  creation_time BIGINT NOT NULL,
+  serverdict TEXT NOT NULL,
+  clientdict TEXT NOT NULL,
+  uri TEXT NOT NULL,
+  method TEXT NOT NULL,
+  description TEXT NOT NULL,
+  UNIQUE (session_id)
+);
+
+CREATE TABLE user_ips (
+  user_id TEXT NOT NULL,
+  access_token TEXT NOT NULL,
+  device_id TEXT,
+  ip TEXT NOT NULL,
+  user_agent TEXT NOT NULL,
+  last_seen BIGINT NOT NULL
+);
+
+INSERT INTO users
+  (
+    name,
+    password_hash,
+    creation_ts,
+    admin,
+    is_guest,
+    consent_version,
+    consent_server_notice_sent,
+    deactivated,
+    locked,
+    suspended
+  )
+  VALUES
+  (
+    '@alice:example.com',
+    '$2b$12$aaa/aaaaaaaaaa.aaaaaaaaaaaaaaa./aaaaaaaaaaaaaaaaaaa/A',
+    1530393962,
+    0,
+    0,
+    '1.0',
+    '1.0',
+    0,
+    0,
+    0
+  );
+
+INSERT INTO user_threepids
+  (user_id, medium, address, validated_at, added_at)
+  VALUES
+  ('@alice:example.com', 'email', 'alice@example.com', 1554228492026, 1554228549014),
+  ('@alice:example.com', 'msisdn', '441189998819991197253', 1555228492026, 1555228549014);
+
+INSERT INTO user_external_ids
+  (user_id, auth_provider, external_id)
+  VALUES
+  ('@alice:example.com', 'oidc-raasu', '871.syn30');
+
+INSERT INTO account_data
+  (user_id, account_data_type, stream_id, content)
+  VALUES
+  ('@alice:example.com', 'im.vector.setting.breadcrumbs', 2, '{"recent_rooms":["!room:example.com"]}'),
+  ('@alice:example.com', 'm.push_rules', 3, '{"global":{}}');
+
+INSERT INTO user_filters
+  (user_id, filter_id, filter_json, full_user_id)
+  VALUES
+  ('alice', 0, CAST('{"room":{"timeline":{"limit":10}}}' AS BLOB), '@alice:example.com'),
+  ('alice', 1, CAST('{"presence":{"types":[]}}' AS BLOB), '@alice:example.com');
+
+INSERT INTO devices
+  (user_id, device_id, display_name, last_seen, ip, user_agent, hidden)
+  VALUES
+  (
+    '@alice:example.com',
+    'ADEVICE',
+    'Matrix Console',
+    1623366000000,
+    '203.0.113.1',
+    'Browser/5.0 (X12; ComputerOS 64; rv:1024.0)',
+    0
+  ),
+  ('@alice:example.com', 'master signing key', NULL, NULL, NULL, NULL, 1),
+  ('@alice:example.com', 'self_signing signing key', NULL, NULL, NULL, NULL, 1);
+
+INSERT INTO access_tokens
+  (id, user_id, device_id, token)
+  VALUES
+  (42, '@alice:example.com', 'ADEVICE', 'syt_aaaaaaaaaaaaaa_aaaa');
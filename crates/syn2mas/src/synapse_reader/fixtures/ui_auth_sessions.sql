@@ -0,0 +1,34 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+INSERT INTO ui_auth_sessions
+  (
+    session_id,
+    creation_time,
+    serverdict,
+    clientdict,
+    uri,
+    method,
+    description
+  )
+  VALUES
+  (
+    'SESSIONONE',
+    1530000000000,
+    '{}',
+    '{}',
+    '/_matrix/client/v3/delete_devices',
+    'POST',
+    'remove device(s) from your account'
+  ),
+  (
+    'SESSIONTWO',
+    1530000000000,
+    '{}',
+    '{}',
+    '/_matrix/client/v3/account/password',
+    'POST',
+    'modify your account password'
+  );
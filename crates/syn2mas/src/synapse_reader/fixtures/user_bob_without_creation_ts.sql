@@ -0,0 +1,28 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+-- Users created by ancient versions of Synapse have no creation time
+INSERT INTO users
+  (
+    name,
+    password_hash,
+    creation_ts,
+    admin,
+    is_guest,
+    appservice_id,
+    deactivated,
+    locked
+  )
+  VALUES
+  (
+    '@bob:example.com',
+    NULL,
+    NULL,
+    0,
+    0,
+    NULL,
+    0,
+    false
+  );
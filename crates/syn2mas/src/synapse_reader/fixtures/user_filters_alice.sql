@@ -0,0 +1,25 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+INSERT INTO user_filters
+  (
+    user_id,
+    filter_id,
+    filter_json,
+    full_user_id
+  )
+  VALUES
+  (
+    'alice',
+    0,
+    convert_to('{"room":{"timeline":{"limit":10}}}', 'UTF8'),
+    '@alice:example.com'
+  ),
+  (
+    'alice',
+    1,
+    convert_to('{"presence":{"types":[]}}', 'UTF8'),
+    '@alice:example.com'
+  );
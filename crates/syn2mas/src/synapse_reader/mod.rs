@@ -6,15 +6,20 @@
 //! # Synapse Database Reader
 //!
 //! This module provides facilities for streaming relevant types of database
-//! records from a Synapse database.
+//! records from a Synapse database, which can either be a Postgres or a SQLite
+//! database.
 
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use chrono::{DateTime, Utc};
-use futures_util::{Stream, TryStreamExt};
-use sqlx::{Acquire, FromRow, PgConnection, Postgres, Transaction, Type, query};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use sqlx::{
+    Acquire, Database, FromRow, PgConnection, Postgres, Sqlite, SqliteConnection, Transaction,
+    Type, postgres::PgRow, query, sqlite::SqliteRow,
+};
 use thiserror::Error;
 use thiserror_ext::ContextInto;
+use tracing::{debug, info, warn};
 
 pub mod checks;
 pub mod config;
@@ -29,7 +34,7 @@ pub enum Error {
     },
 }
 
-#[derive(Clone, Debug, sqlx::Decode, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, sqlx::Decode, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FullUserId(pub String);
 
 impl Display for FullUserId {
@@ -38,9 +43,16 @@ impl Display for FullUserId {
     }
 }
 
-impl Type<Postgres> for FullUserId {
-    fn type_info() -> <sqlx::Postgres as sqlx::Database>::TypeInfo {
-        <String as Type<Postgres>>::type_info()
+impl<DB: Database> Type<DB> for FullUserId
+where
+    String: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as Type<DB>>::compatible(ty)
     }
 }
 
@@ -94,18 +106,25 @@ impl FullUserId {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SynapseBool(bool);
 
-impl<'r> sqlx::Decode<'r, Postgres> for SynapseBool {
-    fn decode(
-        value: <Postgres as sqlx::Database>::ValueRef<'r>,
-    ) -> Result<Self, sqlx::error::BoxDynError> {
-        <i16 as sqlx::Decode<Postgres>>::decode(value)
-            .map(|boolean_int| SynapseBool(boolean_int != 0))
+impl<'r, DB: Database> sqlx::Decode<'r, DB> for SynapseBool
+where
+    i16: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: DB::ValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        <i16 as sqlx::Decode<DB>>::decode(value).map(|boolean_int| SynapseBool(boolean_int != 0))
     }
 }
 
-impl sqlx::Type<Postgres> for SynapseBool {
-    fn type_info() -> <Postgres as sqlx::Database>::TypeInfo {
-        <i16 as sqlx::Type<Postgres>>::type_info()
+impl<DB: Database> sqlx::Type<DB> for SynapseBool
+where
+    i16: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <i16 as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <i16 as sqlx::Type<DB>>::compatible(ty)
     }
 }
 
@@ -127,11 +146,12 @@ impl From<SecondsTimestamp> for DateTime<Utc> {
     }
 }
 
-impl<'r> sqlx::Decode<'r, Postgres> for SecondsTimestamp {
-    fn decode(
-        value: <Postgres as sqlx::Database>::ValueRef<'r>,
-    ) -> Result<Self, sqlx::error::BoxDynError> {
-        <i64 as sqlx::Decode<Postgres>>::decode(value).map(|seconds_since_epoch| {
+impl<'r, DB: Database> sqlx::Decode<'r, DB> for SecondsTimestamp
+where
+    i64: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: DB::ValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        <i64 as sqlx::Decode<DB>>::decode(value).map(|seconds_since_epoch| {
             SecondsTimestamp(DateTime::from_timestamp_nanos(
                 seconds_since_epoch * 1_000_000_000,
             ))
@@ -139,9 +159,16 @@ impl<'r> sqlx::Decode<'r, Postgres> for SecondsTimestamp {
     }
 }
 
-impl sqlx::Type<Postgres> for SecondsTimestamp {
-    fn type_info() -> <Postgres as sqlx::Database>::TypeInfo {
-        <i64 as sqlx::Type<Postgres>>::type_info()
+impl<DB: Database> sqlx::Type<DB> for SecondsTimestamp
+where
+    i64: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <i64 as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <i64 as sqlx::Type<DB>>::compatible(ty)
     }
 }
 
@@ -156,11 +183,12 @@ impl From<MillisecondsTimestamp> for DateTime<Utc> {
     }
 }
 
-impl<'r> sqlx::Decode<'r, Postgres> for MillisecondsTimestamp {
-    fn decode(
-        value: <Postgres as sqlx::Database>::ValueRef<'r>,
-    ) -> Result<Self, sqlx::error::BoxDynError> {
-        <i64 as sqlx::Decode<Postgres>>::decode(value).map(|milliseconds_since_epoch| {
+impl<'r, DB: Database> sqlx::Decode<'r, DB> for MillisecondsTimestamp
+where
+    i64: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: DB::ValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        <i64 as sqlx::Decode<DB>>::decode(value).map(|milliseconds_since_epoch| {
             MillisecondsTimestamp(DateTime::from_timestamp_nanos(
                 milliseconds_since_epoch * 1_000_000,
             ))
@@ -168,9 +196,53 @@ impl<'r> sqlx::Decode<'r, Postgres> for MillisecondsTimestamp {
     }
 }
 
-impl sqlx::Type<Postgres> for MillisecondsTimestamp {
-    fn type_info() -> <Postgres as sqlx::Database>::TypeInfo {
-        <i64 as sqlx::Type<Postgres>>::type_info()
+impl<DB: Database> sqlx::Type<DB> for MillisecondsTimestamp
+where
+    i64: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <i64 as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <i64 as sqlx::Type<DB>>::compatible(ty)
+    }
+}
+
+/// Text read as-is from the Synapse database, which may not be valid UTF-8.
+///
+/// Databases using the `SQL_ASCII` encoding don't check what they store, so
+/// some columns filled from client input can hold arbitrary bytes.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RawText(pub Vec<u8>);
+
+impl std::fmt::Debug for RawText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&String::from_utf8_lossy(&self.0), f)
+    }
+}
+
+impl<'r, DB: Database> sqlx::Decode<'r, DB> for RawText
+where
+    &'r [u8]: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: DB::ValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        // Decoding the bytes of the value doesn't check that they are UTF-8, unlike
+        // decoding a `String`
+        <&[u8] as sqlx::Decode<DB>>::decode(value).map(|bytes| RawText(bytes.to_owned()))
+    }
+}
+
+impl<DB: Database> sqlx::Type<DB> for RawText
+where
+    String: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as sqlx::Type<DB>>::compatible(ty)
     }
 }
 
@@ -187,8 +259,12 @@ pub struct SynapseUser {
     pub deactivated: SynapseBool,
     /// Whether the user is locked
     pub locked: bool,
-    /// When the user was created
-    pub creation_ts: SecondsTimestamp,
+    /// Whether the user is shadow-banned, which makes Synapse silently drop
+    /// the events they send
+    pub shadow_banned: bool,
+    /// When the user was created. Can be null for users created by ancient
+    /// versions of Synapse.
+    pub creation_ts: Option<SecondsTimestamp>,
     /// Whether the user is a guest.
     /// Note that not all numeric user IDs are guests; guests can upgrade their
     /// account!
@@ -214,6 +290,22 @@ pub struct SynapseExternalId {
     pub external_id: String,
 }
 
+/// Row of the `account_data` table in Synapse.
+#[derive(Clone, Debug, FromRow, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SynapseAccountData {
+    pub user_id: FullUserId,
+    pub account_data_type: String,
+    pub content: String,
+}
+
+/// Row of the `user_filters` table in Synapse.
+#[derive(Clone, Debug, FromRow, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SynapseUserFilter {
+    pub user_id: FullUserId,
+    pub filter_id: i64,
+    pub filter_json: Vec<u8>,
+}
+
 /// Row of the `devices` table in Synapse.
 #[derive(Clone, Debug, FromRow, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SynapseDevice {
@@ -222,7 +314,8 @@ pub struct SynapseDevice {
     pub display_name: Option<String>,
     pub last_seen: Option<MillisecondsTimestamp>,
     pub ip: Option<String>,
-    pub user_agent: Option<String>,
+    pub user_agent: Option<RawText>,
+    pub hidden: bool,
 }
 
 /// Row of the `access_tokens` table in Synapse.
@@ -233,6 +326,17 @@ pub struct SynapseAccessToken {
     pub token: String,
     pub valid_until_ms: Option<MillisecondsTimestamp>,
     pub last_validated: Option<MillisecondsTimestamp>,
+    /// The user this token acts as, if it is a puppeting token. `user_id` is
+    /// then the admin who created it.
+    pub puppets_user_id: Option<FullUserId>,
+    /// When this token was last used, from its most recent row in the
+    /// `user_ips` table. Only read for deviceless tokens, as the activity of
+    /// the other tokens is migrated with their device.
+    pub last_seen: Option<MillisecondsTimestamp>,
+    /// The IP address this token was last used from
+    pub ip: Option<String>,
+    /// The user agent this token was last used with
+    pub user_agent: Option<RawText>,
 }
 
 /// Row of the `refresh_tokens` table in Synapse.
@@ -253,33 +357,101 @@ pub struct SynapseRefreshableTokenPair {
 /// time as the migration.
 const TABLES_TO_LOCK: &[&str] = &[
     "users",
+    "erased_users",
     "user_threepids",
     "user_external_ids",
+    "account_data",
+    "user_filters",
     "devices",
     "access_tokens",
     "refresh_tokens",
+    "user_ips",
 ];
 
+/// How to count the rows of the Synapse tables
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CountMode {
+    /// Count the rows exactly, which needs a full scan of each table
+    Exact,
+
+    /// Use the estimates maintained by Postgres in `pg_class.reltuples`,
+    /// which is nearly instant even on large databases
+    ///
+    /// The tables whose statistics look stale, because they were never
+    /// analysed or had more rows changed since than the estimate, are counted
+    /// exactly instead. SQLite keeps no such estimates, so the rows of a SQLite
+    /// database are always counted exactly.
+    #[default]
+    Estimate,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid count mode {0:?}, expected `exact` or `estimate`")]
+pub struct InvalidCountMode(String);
+
+impl FromStr for CountMode {
+    type Err = InvalidCountMode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(Self::Exact),
+            "estimate" => Ok(Self::Estimate),
+            _ => Err(InvalidCountMode(s.to_owned())),
+        }
+    }
+}
+
 /// Number of migratable rows in various Synapse tables.
-/// Used to estimate progress.
+/// Used to estimate progress, and to check that no row was silently left out
+/// once the migration is done.
 #[derive(Clone, Debug)]
 pub struct SynapseRowCounts {
     pub users: usize,
     pub devices: usize,
     pub threepids: usize,
     pub external_ids: usize,
+    pub account_data: usize,
+    pub user_filters: usize,
     pub access_tokens: usize,
     pub refresh_tokens: usize,
+
+    /// Number of user-interactive authentication sessions.
+    ///
+    /// They are short-lived and never migrated, so they are only counted to
+    /// let operators know that stale ones can be cleaned up in Synapse.
+    pub ui_auth_sessions: usize,
+}
+
+/// A connection to the Synapse database, depending on the database Synapse
+/// uses
+pub enum SynapseConnection {
+    Postgres(PgConnection),
+    Sqlite(SqliteConnection),
+}
+
+/// The transaction in which the Synapse database is read, depending on the
+/// database Synapse uses
+enum SynapseTransaction<'c> {
+    Postgres(Transaction<'c, Postgres>),
+    Sqlite(Transaction<'c, Sqlite>),
 }
 
 pub struct SynapseReader<'c> {
-    txn: Transaction<'c, Postgres>,
+    txn: SynapseTransaction<'c>,
 }
 
 impl<'conn> SynapseReader<'conn> {
     /// Create a new Synapse reader, which entails creating a transaction and
     /// locking Synapse tables.
     ///
+    /// The connection may point at a read replica of the Synapse database, to
+    /// avoid adding load on the primary during large migrations. Tables can't
+    /// be locked on a replica, so nothing then stops Synapse from writing to
+    /// the primary: Synapse must be stopped, and the replica must have
+    /// replayed every write made before Synapse was stopped, otherwise the
+    /// newest data is silently left out of the migration. See
+    /// [`SynapseReader::assert_synapse_stopped`].
+    ///
     /// # Errors
     ///
     /// Errors are returned under the following circumstances:
@@ -291,11 +463,33 @@ impl<'conn> SynapseReader<'conn> {
         synapse_connection: &'conn mut PgConnection,
         dry_run: bool,
     ) -> Result<Self, Error> {
+        let is_replica: bool = sqlx::query_scalar("SELECT pg_is_in_recovery()")
+            .fetch_one(&mut *synapse_connection)
+            .await
+            .into_database("checking whether the Synapse database is a replica")?;
+
         let mut txn = synapse_connection
             .begin()
             .await
             .into_database("begin transaction")?;
 
+        if is_replica {
+            info!(
+                "Reading from a replica of the Synapse database, make sure it caught up with the primary since Synapse was stopped"
+            );
+
+            // Serializable transactions aren't available on replicas, but a repeatable read
+            // one still gives a consistent snapshot of the database
+            query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY;")
+                .execute(&mut *txn)
+                .await
+                .into_database("set transaction")?;
+
+            return Ok(Self {
+                txn: SynapseTransaction::Postgres(txn),
+            });
+        }
+
         query("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE READ ONLY DEFERRABLE;")
             .execute(&mut *txn)
             .await
@@ -315,7 +509,108 @@ impl<'conn> SynapseReader<'conn> {
                 .into_database_with(|| format!("locking Synapse table `{table}`"))?;
         }
 
-        Ok(Self { txn })
+        Ok(Self {
+            txn: SynapseTransaction::Postgres(txn),
+        })
+    }
+
+    /// Create a new Synapse reader for a Synapse database using SQLite, which
+    /// entails creating a transaction.
+    ///
+    /// SQLite has no table locks: unless this is a dry run, the transaction
+    /// takes the write lock of the whole database instead, so that Synapse
+    /// can't write to it during the migration.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned under the following circumstances:
+    ///
+    /// - An underlying database error
+    /// - If we can't take the write lock of the database (pointing to the fact
+    ///   that Synapse may still be running)
+    pub async fn new_sqlite(
+        synapse_connection: &'conn mut SqliteConnection,
+        dry_run: bool,
+    ) -> Result<Self, Error> {
+        // Dry runs are expected to be done alongside Synapse running, so they only
+        // read from a consistent snapshot of the database
+        let statement = if dry_run {
+            "BEGIN DEFERRED"
+        } else {
+            "BEGIN IMMEDIATE"
+        };
+
+        let txn = sqlx::Connection::begin_with(synapse_connection, statement)
+            .await
+            .into_database("begin transaction")?;
+
+        Ok(Self {
+            txn: SynapseTransaction::Sqlite(txn),
+        })
+    }
+
+    /// Create a new Synapse reader for whichever database Synapse uses.
+    ///
+    /// See [`SynapseReader::new`] and [`SynapseReader::new_sqlite`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`SynapseReader::new`] and [`SynapseReader::new_sqlite`].
+    pub async fn from_connection(
+        synapse_connection: &'conn mut SynapseConnection,
+        dry_run: bool,
+    ) -> Result<Self, Error> {
+        match synapse_connection {
+            SynapseConnection::Postgres(conn) => Self::new(conn, dry_run).await,
+            SynapseConnection::Sqlite(conn) => Self::new_sqlite(conn, dry_run).await,
+        }
+    }
+
+    /// Checks that Synapse looks stopped, by looking at the newest device
+    /// activity and access token use it recorded.
+    ///
+    /// If this activity is more recent than `threshold` before `now`, Synapse
+    /// is probably still running, or a replica didn't catch up yet, and a
+    /// warning is logged.
+    ///
+    /// Returns whether Synapse looks stopped.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned under the following circumstances:
+    ///
+    /// - An underlying database error
+    pub async fn assert_synapse_stopped(
+        &mut self,
+        now: DateTime<Utc>,
+        threshold: chrono::Duration,
+    ) -> Result<bool, Error> {
+        let (last_activity,): (Option<MillisecondsTimestamp>,) = self
+            .fetch_one(
+                "
+                SELECT MAX(last_activity) FROM (
+                  SELECT MAX(last_seen) AS last_activity FROM devices
+                  UNION ALL
+                  SELECT MAX(last_validated) AS last_activity FROM access_tokens
+                ) AS activity
+                ",
+                "reading the last activity of Synapse",
+            )
+            .await?;
+
+        let Some(last_activity) = last_activity.map(DateTime::from) else {
+            return Ok(true);
+        };
+
+        if now - last_activity < threshold {
+            warn!(
+                %last_activity,
+                "Synapse recorded activity recently, it may still be running. If reading from a replica, it may not have caught up yet."
+            );
+            return Ok(false);
+        }
+
+        Ok(true)
     }
 
     /// Finishes the Synapse reader, committing the transaction.
@@ -326,11 +621,15 @@ impl<'conn> SynapseReader<'conn> {
     ///
     /// - An underlying database error whilst committing the transaction.
     pub async fn finish(self) -> Result<(), Error> {
-        self.txn.commit().await.into_database("end transaction")?;
+        match self.txn {
+            SynapseTransaction::Postgres(txn) => txn.commit().await,
+            SynapseTransaction::Sqlite(txn) => txn.commit().await,
+        }
+        .into_database("end transaction")?;
         Ok(())
     }
 
-    /// Counts the rows in the Synapse database to get an estimate of how large
+    /// Counts the rows in the Synapse database to get an idea of how large
     /// the migration is going to be.
     ///
     /// # Errors
@@ -338,156 +637,252 @@ impl<'conn> SynapseReader<'conn> {
     /// Errors are returned under the following circumstances:
     ///
     /// - An underlying database error
-    pub async fn count_rows(&mut self) -> Result<SynapseRowCounts, Error> {
-        // We don't get to filter out application service users by using this estimate,
-        // which is a shame, but on a large database this is way faster.
-        // On matrix.org, counting users and devices properly takes around 1m10s,
-        // which is unnecessary extra downtime during the migration, just to
-        // show a more accurate progress bar and size a hash map accurately.
-        let users = sqlx::query_scalar::<_, i64>(
-            "
-            SELECT reltuples::bigint AS estimate FROM pg_class WHERE oid = 'users'::regclass;
-            ",
-        )
-        .fetch_one(&mut *self.txn)
-        .await
-        .into_database("estimating count of users")?
-        .max(0)
-        .try_into()
-        .unwrap_or(usize::MAX);
+    pub async fn count_rows(&mut self, mode: CountMode) -> Result<SynapseRowCounts, Error> {
+        Ok(SynapseRowCounts {
+            users: self.count_table_rows("users", mode).await?,
+            devices: self.count_table_rows("devices", mode).await?,
+            threepids: self.count_table_rows("user_threepids", mode).await?,
+            external_ids: self.count_table_rows("user_external_ids", mode).await?,
+            account_data: self.count_table_rows("account_data", mode).await?,
+            user_filters: self.count_table_rows("user_filters", mode).await?,
+            access_tokens: self.count_table_rows("access_tokens", mode).await?,
+            refresh_tokens: self.count_table_rows("refresh_tokens", mode).await?,
+            ui_auth_sessions: self.count_table_rows("ui_auth_sessions", mode).await?,
+        })
+    }
 
-        let devices = sqlx::query_scalar::<_, i64>(
-            "
-            SELECT reltuples::bigint AS estimate FROM pg_class WHERE oid = 'devices'::regclass;
-            ",
-        )
-        .fetch_one(&mut *self.txn)
-        .await
-        .into_database("estimating count of devices")?
-        .max(0)
-        .try_into()
-        .unwrap_or(usize::MAX);
+    /// Counts the rows of a single Synapse table, with the given [`CountMode`].
+    async fn count_table_rows(&mut self, table: &str, mode: CountMode) -> Result<usize, Error> {
+        if let (CountMode::Estimate, SynapseTransaction::Postgres(txn)) = (mode, &mut self.txn) {
+            // We don't get to filter out application service users by using this estimate,
+            // which is a shame, but on a large database this is way faster.
+            // On matrix.org, counting users and devices properly takes around 1m10s,
+            // which is unnecessary extra downtime during the migration, just to
+            // show a more accurate progress bar and size a hash map accurately.
+            let (estimate, modified_since_analyze): (i64, i64) = sqlx::query_as(&format!(
+                "
+                SELECT c.reltuples::bigint, COALESCE(s.n_mod_since_analyze, 0)
+                FROM pg_class c
+                LEFT JOIN pg_stat_user_tables s ON s.relid = c.oid
+                WHERE c.oid = '{table}'::regclass;
+                "
+            ))
+            .fetch_one(&mut **txn)
+            .await
+            .into_database_with(|| format!("estimating rows of {table}"))?;
 
-        let threepids = sqlx::query_scalar::<_, i64>(
-            "
-            SELECT reltuples::bigint AS estimate FROM pg_class WHERE oid = 'user_threepids'::regclass;
-            "
-        )
-        .fetch_one(&mut *self.txn)
-        .await
-        .into_database("estimating count of threepids")?
-        .max(0)
-        .try_into()
-        .unwrap_or(usize::MAX);
+            // The estimate is negative when the table was never analysed, and can be way
+            // off when more rows changed since than it counted
+            if estimate >= 0 && modified_since_analyze <= estimate {
+                return Ok(estimate.try_into().unwrap_or(usize::MAX));
+            }
 
-        let access_tokens = sqlx::query_scalar::<_, i64>(
-            "
-            SELECT reltuples::bigint AS estimate FROM pg_class WHERE oid = 'access_tokens'::regclass;
-            "
-        )
-        .fetch_one(&mut *self.txn)
-        .await
-        .into_database("estimating count of access tokens")?
-        .max(0)
-        .try_into()
-        .unwrap_or(usize::MAX);
+            debug!("statistics of {table} look stale, counting its rows exactly");
+        }
 
-        let refresh_tokens = sqlx::query_scalar::<_, i64>(
-            "
-            SELECT reltuples::bigint AS estimate FROM pg_class WHERE oid = 'refresh_tokens'::regclass;
-            "
-        )
-        .fetch_one(&mut *self.txn)
-        .await
-        .into_database("estimating count of refresh tokens")?
-        .max(0)
-        .try_into()
-        .unwrap_or(usize::MAX);
+        let (count,): (i64,) = self
+            .fetch_one(
+                &format!("SELECT COUNT(*) FROM {table};"),
+                &format!("counting rows of {table}"),
+            )
+            .await?;
 
-        let external_ids = sqlx::query_scalar::<_, i64>(
-            "
-            SELECT reltuples::bigint AS estimate FROM pg_class WHERE oid = 'user_external_ids'::regclass;
-            "
-        )
-        .fetch_one(&mut *self.txn)
-        .await
-        .into_database("estimating count of external IDs")?
-        .max(0)
-        .try_into()
-        .unwrap_or(usize::MAX);
+        Ok(count.max(0).try_into().unwrap_or(usize::MAX))
+    }
 
-        Ok(SynapseRowCounts {
-            users,
-            devices,
-            threepids,
-            external_ids,
-            access_tokens,
-            refresh_tokens,
-        })
+    /// Lists the distinct server names found in the user IDs of Synapse users.
+    ///
+    /// The server name is taken to be everything after the first `:` of the
+    /// user ID, so users with a `:` in their localpart show up with a longer
+    /// server name.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned under the following circumstances:
+    ///
+    /// - An underlying database error
+    pub async fn distinct_server_names(&mut self) -> Result<Vec<String>, Error> {
+        let sql = match self.txn {
+            SynapseTransaction::Postgres(_) => {
+                "
+                SELECT DISTINCT substring(name FROM position(':' IN name) + 1)
+                FROM users
+                "
+            }
+            SynapseTransaction::Sqlite(_) => {
+                "
+                SELECT DISTINCT substr(name, instr(name, ':') + 1)
+                FROM users
+                "
+            }
+        };
+
+        let server_names: Vec<(String,)> = self
+            .fetch_all(sql, "listing server names of Synapse users")
+            .await?;
+        Ok(server_names
+            .into_iter()
+            .map(|(server_name,)| server_name)
+            .collect())
+    }
+
+    /// Lists the users erased from Synapse, who asked for their personal data
+    /// to be forgotten when they were deactivated.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned under the following circumstances:
+    ///
+    /// - An underlying database error
+    pub async fn erased_users(&mut self) -> Result<Vec<FullUserId>, Error> {
+        let erased_users: Vec<(FullUserId,)> = self
+            .fetch_all(
+                "
+                SELECT user_id
+                FROM erased_users
+                ",
+                "listing erased Synapse users",
+            )
+            .await?;
+        Ok(erased_users.into_iter().map(|(user_id,)| user_id).collect())
+    }
+
+    /// Lists the display names shared by several devices of the same user,
+    /// along with that user.
+    ///
+    /// The hidden devices are only considered if `include_hidden` is set, as
+    /// they are otherwise not migrated.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned under the following circumstances:
+    ///
+    /// - An underlying database error
+    pub async fn duplicate_device_names(
+        &mut self,
+        include_hidden: bool,
+    ) -> Result<Vec<(FullUserId, String)>, Error> {
+        let sql = "
+            SELECT user_id, display_name
+            FROM devices
+            WHERE device_id != 'guest_device'
+              AND display_name IS NOT NULL
+              AND ($1 OR NOT COALESCE(hidden, FALSE))
+            GROUP BY user_id, display_name
+            HAVING COUNT(*) > 1
+            ";
+
+        match &mut self.txn {
+            SynapseTransaction::Postgres(txn) => {
+                sqlx::query_as(sql)
+                    .bind(include_hidden)
+                    .fetch_all(&mut **txn)
+                    .await
+            }
+            SynapseTransaction::Sqlite(txn) => {
+                sqlx::query_as(sql)
+                    .bind(include_hidden)
+                    .fetch_all(&mut **txn)
+                    .await
+            }
+        }
+        .into_database("listing duplicate Synapse device names")
     }
 
     /// Reads Synapse users, excluding application service users (which do not
     /// need to be migrated), from the database.
     pub fn read_users(&mut self) -> impl Stream<Item = Result<SynapseUser, Error>> + '_ {
-        sqlx::query_as(
+        self.fetch(
             "
             SELECT
-              name, password_hash, admin, deactivated, locked, creation_ts, is_guest, appservice_id
+              name, password_hash, admin, deactivated, locked,
+              COALESCE(shadow_banned, FALSE) AS shadow_banned,
+              creation_ts, is_guest, appservice_id
             FROM users
             ",
+            "reading Synapse users",
         )
-        .fetch(&mut *self.txn)
-        .map_err(|err| err.into_database("reading Synapse users"))
     }
 
     /// Reads threepids (such as e-mail and phone number associations) from
     /// Synapse.
     pub fn read_threepids(&mut self) -> impl Stream<Item = Result<SynapseThreepid, Error>> + '_ {
-        sqlx::query_as(
+        self.fetch(
             "
             SELECT
               user_id, medium, address, added_at
             FROM user_threepids
             ",
+            "reading Synapse threepids",
         )
-        .fetch(&mut *self.txn)
-        .map_err(|err| err.into_database("reading Synapse threepids"))
     }
 
     /// Read associations between Synapse users and external identity providers
     pub fn read_user_external_ids(
         &mut self,
     ) -> impl Stream<Item = Result<SynapseExternalId, Error>> + '_ {
-        sqlx::query_as(
+        self.fetch(
             "
             SELECT
               user_id, auth_provider, external_id
             FROM user_external_ids
             ",
+            "reading Synapse user external IDs",
+        )
+    }
+
+    /// Reads the global (not room-specific) account data of users from
+    /// Synapse.
+    pub fn read_account_data(
+        &mut self,
+    ) -> impl Stream<Item = Result<SynapseAccountData, Error>> + '_ {
+        self.fetch(
+            "
+            SELECT
+              user_id, account_data_type, content
+            FROM account_data
+            ",
+            "reading Synapse account data",
+        )
+    }
+
+    /// Reads the sync filters of users from Synapse.
+    pub fn read_user_filters(
+        &mut self,
+    ) -> impl Stream<Item = Result<SynapseUserFilter, Error>> + '_ {
+        self.fetch(
+            "
+            SELECT
+              full_user_id AS user_id, filter_id, filter_json
+            FROM user_filters
+            ",
+            "reading Synapse user filters",
         )
-        .fetch(&mut *self.txn)
-        .map_err(|err| err.into_database("reading Synapse user external IDs"))
     }
 
     /// Reads devices from the Synapse database.
-    /// Does not include so-called 'hidden' devices, which are just a mechanism
-    /// for storing various signing keys shared between the real devices.
+    /// This includes so-called 'hidden' devices, which are just a mechanism
+    /// for storing various signing keys shared between the real devices, and
+    /// are flagged as such.
+    ///
+    /// This also includes the `guest_device` placeholder devices of guest
+    /// users, so that the migration accounts for every row it skips.
     pub fn read_devices(&mut self) -> impl Stream<Item = Result<SynapseDevice, Error>> + '_ {
-        sqlx::query_as(
+        self.fetch(
             "
             SELECT
-              user_id, device_id, display_name, last_seen, ip, user_agent
+              user_id, device_id, display_name, last_seen, ip, user_agent,
+              COALESCE(hidden, FALSE) AS hidden
             FROM devices
-            WHERE NOT hidden AND device_id != 'guest_device'
             ",
+            "reading Synapse devices",
         )
-        .fetch(&mut *self.txn)
-        .map_err(|err| err.into_database("reading Synapse devices"))
     }
 
     /// Reads unrefreshable access tokens from the Synapse database.
-    /// This does not include access tokens used for puppetting users, as those
-    /// are not supported by MAS.
+    /// This includes access tokens used for puppetting users, which have
+    /// `puppets_user_id` set: it is up to the migration to decide what to do
+    /// with them.
     ///
     /// This also excludes access tokens whose referenced device ID does not
     /// exist, except for deviceless access tokens.
@@ -497,24 +892,35 @@ impl<'conn> SynapseReader<'conn> {
     pub fn read_unrefreshable_access_tokens(
         &mut self,
     ) -> impl Stream<Item = Result<SynapseAccessToken, Error>> + '_ {
-        sqlx::query_as(
+        self.fetch(
             "
             SELECT
-              at0.user_id, at0.device_id, at0.token, at0.valid_until_ms, at0.last_validated
+              at0.user_id, at0.device_id, at0.token, at0.valid_until_ms, at0.last_validated, at0.puppets_user_id,
+              NULL AS last_seen, NULL AS ip, NULL AS user_agent
             FROM access_tokens at0
             INNER JOIN devices USING (user_id, device_id)
-            WHERE at0.puppets_user_id IS NULL AND at0.refresh_token_id IS NULL
+            WHERE at0.refresh_token_id IS NULL
 
             UNION ALL
 
             SELECT
-              at0.user_id, at0.device_id, at0.token, at0.valid_until_ms, at0.last_validated
+              at0.user_id, at0.device_id, at0.token, at0.valid_until_ms, at0.last_validated, at0.puppets_user_id,
+              ui.last_seen, ui.ip, ui.user_agent
             FROM access_tokens at0
-            WHERE at0.puppets_user_id IS NULL AND at0.refresh_token_id IS NULL AND at0.device_id IS NULL
+            LEFT JOIN (
+              SELECT
+                access_token, last_seen, ip, user_agent,
+                ROW_NUMBER() OVER (PARTITION BY access_token ORDER BY last_seen DESC) AS recency
+              FROM user_ips
+              WHERE access_token IN (
+                SELECT token FROM access_tokens
+                WHERE refresh_token_id IS NULL AND device_id IS NULL
+              )
+            ) AS ui ON ui.access_token = at0.token AND ui.recency = 1
+            WHERE at0.refresh_token_id IS NULL AND at0.device_id IS NULL
             ",
+            "reading Synapse access tokens",
         )
-        .fetch(&mut *self.txn)
-        .map_err(|err| err.into_database("reading Synapse access tokens"))
     }
 
     /// Reads (access token, refresh token) pairs from the Synapse database.
@@ -522,6 +928,11 @@ impl<'conn> SynapseReader<'conn> {
     /// by using the refresh token and then acknowledging the
     /// successor access token by using it to authenticate a request.
     ///
+    /// The `next_token_id` chain is not read any further than that: a refresh
+    /// token whose successor hasn't been used yet can still be used in
+    /// Synapse, so it is migrated as a token pair of its own, and MAS has no
+    /// notion of a token superseding another one to carry over.
+    ///
     /// The `expiry_ts` and `ultimate_session_expiry_ts` columns are ignored as
     /// they are not implemented in MAS.
     /// Further, they are unused by any real-world deployment to the best of
@@ -529,7 +940,7 @@ impl<'conn> SynapseReader<'conn> {
     pub fn read_refreshable_token_pairs(
         &mut self,
     ) -> impl Stream<Item = Result<SynapseRefreshableTokenPair, Error>> + '_ {
-        sqlx::query_as(
+        self.fetch(
             "
             SELECT
               rt0.user_id, rt0.device_id, at0.token AS access_token, rt0.token AS refresh_token, at0.valid_until_ms, at0.last_validated
@@ -539,9 +950,53 @@ impl<'conn> SynapseReader<'conn> {
             LEFT JOIN access_tokens at1 ON at1.refresh_token_id = rt0.next_token_id
             WHERE NOT at1.used OR at1.used IS NULL
             ",
+            "reading Synapse refresh tokens",
         )
-        .fetch(&mut *self.txn)
-        .map_err(|err| err.into_database("reading Synapse refresh tokens"))
+    }
+
+    /// Streams the rows of a query, which must run as-is on both Postgres and
+    /// SQLite.
+    fn fetch<'a, T>(
+        &'a mut self,
+        sql: &'static str,
+        context: &'static str,
+    ) -> impl Stream<Item = Result<T, Error>> + 'a
+    where
+        T: for<'r> FromRow<'r, PgRow> + for<'r> FromRow<'r, SqliteRow> + Send + Unpin + 'a,
+    {
+        match &mut self.txn {
+            SynapseTransaction::Postgres(txn) => {
+                sqlx::query_as(sql).fetch(&mut **txn).left_stream()
+            }
+            SynapseTransaction::Sqlite(txn) => sqlx::query_as(sql).fetch(&mut **txn).right_stream(),
+        }
+        .map_err(move |err| err.into_database(context))
+    }
+
+    /// Fetches the single row of a query, which must run as-is on both
+    /// Postgres and SQLite.
+    async fn fetch_one<T>(&mut self, sql: &str, context: &str) -> Result<T, Error>
+    where
+        T: for<'r> FromRow<'r, PgRow> + for<'r> FromRow<'r, SqliteRow> + Send + Unpin,
+    {
+        match &mut self.txn {
+            SynapseTransaction::Postgres(txn) => sqlx::query_as(sql).fetch_one(&mut **txn).await,
+            SynapseTransaction::Sqlite(txn) => sqlx::query_as(sql).fetch_one(&mut **txn).await,
+        }
+        .into_database(context)
+    }
+
+    /// Fetches all the rows of a query, which must run as-is on both Postgres
+    /// and SQLite.
+    async fn fetch_all<T>(&mut self, sql: &str, context: &str) -> Result<Vec<T>, Error>
+    where
+        T: for<'r> FromRow<'r, PgRow> + for<'r> FromRow<'r, SqliteRow> + Send + Unpin,
+    {
+        match &mut self.txn {
+            SynapseTransaction::Postgres(txn) => sqlx::query_as(sql).fetch_all(&mut **txn).await,
+            SynapseTransaction::Sqlite(txn) => sqlx::query_as(sql).fetch_all(&mut **txn).await,
+        }
+        .into_database(context)
     }
 }
 
@@ -549,15 +1004,23 @@ impl<'conn> SynapseReader<'conn> {
 mod test {
     use std::collections::BTreeSet;
 
+    use chrono::DateTime;
+    use figment::{
+        Figment,
+        providers::{Format, Yaml},
+    };
     use futures_util::TryStreamExt;
     use insta::assert_debug_snapshot;
-    use sqlx::{PgPool, migrate::Migrator};
+    use sqlx::{Connection, PgPool, SqliteConnection, migrate::Migrator};
 
     use crate::{
-        SynapseReader,
+        SynapseReader, UnknownProviderPolicy,
         synapse_reader::{
-            SynapseAccessToken, SynapseDevice, SynapseExternalId, SynapseRefreshableTokenPair,
-            SynapseThreepid, SynapseUser,
+            CountMode, FullUserId, RawText, SynapseAccessToken, SynapseAccountData,
+            SynapseConnection, SynapseDevice, SynapseExternalId, SynapseRefreshableTokenPair,
+            SynapseThreepid, SynapseUser, SynapseUserFilter, TABLES_TO_LOCK,
+            checks::{CheckWarning, synapse_database_check},
+            config::Config,
         },
     };
 
@@ -579,6 +1042,114 @@ mod test {
         assert_debug_snapshot!(users);
     }
 
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "erased_user_alice"))]
+    async fn test_erased_users(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let erased_users = reader
+            .erased_users()
+            .await
+            .expect("failed to list erased Synapse users");
+
+        assert_eq!(
+            erased_users,
+            vec![FullUserId("@alice:example.com".to_owned())]
+        );
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_bob_without_creation_ts"))]
+    async fn test_read_users_without_creation_ts(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let users: Vec<SynapseUser> = reader
+            .read_users()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse users");
+
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].creation_ts, None);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice"))]
+    async fn test_distinct_server_names(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let server_names = reader
+            .distinct_server_names()
+            .await
+            .expect("failed to list server names");
+
+        assert_eq!(server_names, vec!["example.com".to_owned()]);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "threepids_alice"))]
+    async fn test_count_rows_exact(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let counts = reader
+            .count_rows(CountMode::Exact)
+            .await
+            .expect("failed to count rows");
+
+        assert_eq!(counts.users, 1);
+        assert_eq!(counts.threepids, 2);
+        assert_eq!(counts.devices, 0);
+    }
+
+    /// Tests that the estimates fall back to exact counts for the tables
+    /// which were never analysed.
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "threepids_alice"))]
+    async fn test_count_rows_estimate_never_analysed(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let counts = reader
+            .count_rows(CountMode::Estimate)
+            .await
+            .expect("failed to count rows");
+
+        assert_eq!(counts.users, 1);
+        assert_eq!(counts.threepids, 2);
+        assert_eq!(counts.devices, 0);
+    }
+
+    /// Tests that the UI auth sessions are counted, but neither locked nor
+    /// counted as any migrated data.
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "ui_auth_sessions"))]
+    async fn test_count_rows_ui_auth_sessions(pool: PgPool) {
+        assert!(!TABLES_TO_LOCK.contains(&"ui_auth_sessions"));
+
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let counts = reader
+            .count_rows(CountMode::Exact)
+            .await
+            .expect("failed to count rows");
+
+        assert_eq!(counts.ui_auth_sessions, 2);
+        assert_eq!(counts.users, 1);
+        assert_eq!(counts.devices, 0);
+        assert_eq!(counts.access_tokens, 0);
+    }
+
     #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "threepids_alice"))]
     async fn test_read_threepids(pool: PgPool) {
         let mut conn = pool.acquire().await.expect("failed to get connection");
@@ -611,6 +1182,38 @@ mod test {
         assert_debug_snapshot!(external_ids);
     }
 
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "account_data_alice"))]
+    async fn test_read_account_data(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let account_data: BTreeSet<SynapseAccountData> = reader
+            .read_account_data()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse account data");
+
+        assert_debug_snapshot!(account_data);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "user_filters_alice"))]
+    async fn test_read_user_filters(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let user_filters: BTreeSet<SynapseUserFilter> = reader
+            .read_user_filters()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse user filters");
+
+        assert_debug_snapshot!(user_filters);
+    }
+
     #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "devices_alice"))]
     async fn test_read_devices(pool: PgPool) {
         let mut conn = pool.acquire().await.expect("failed to get connection");
@@ -627,6 +1230,38 @@ mod test {
         assert_debug_snapshot!(devices);
     }
 
+    #[sqlx::test(
+        migrator = "MIGRATOR",
+        fixtures("user_alice", "devices_alice", "devices_alice_duplicate_names")
+    )]
+    async fn test_duplicate_device_names(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let alice = FullUserId("@alice:example.com".to_owned());
+
+        let names = reader
+            .duplicate_device_names(false)
+            .await
+            .expect("failed to list duplicate device names");
+        assert_eq!(names, vec![(alice.clone(), "Element".to_owned())]);
+
+        let mut names = reader
+            .duplicate_device_names(true)
+            .await
+            .expect("failed to list duplicate device names");
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                (alice.clone(), "Element".to_owned()),
+                (alice, "Matrix Console".to_owned()),
+            ]
+        );
+    }
+
     #[sqlx::test(
         migrator = "MIGRATOR",
         fixtures("user_alice", "devices_alice", "access_token_alice")
@@ -646,7 +1281,33 @@ mod test {
         assert_debug_snapshot!(access_tokens);
     }
 
-    /// Tests that puppetting access tokens are ignored.
+    #[sqlx::test(migrator = "MIGRATOR", fixtures("user_alice", "devices_alice"))]
+    async fn test_assert_synapse_stopped(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        // The device of alice was last seen at 2021-06-10T23:00:00Z
+        let last_seen = DateTime::from_timestamp_millis(1_623_366_000_000).unwrap();
+        let threshold = chrono::Duration::minutes(5);
+
+        assert!(
+            !reader
+                .assert_synapse_stopped(last_seen + chrono::Duration::minutes(1), threshold)
+                .await
+                .expect("failed to check Synapse activity")
+        );
+        assert!(
+            reader
+                .assert_synapse_stopped(last_seen + chrono::Duration::hours(1), threshold)
+                .await
+                .expect("failed to check Synapse activity")
+        );
+    }
+
+    /// Tests that puppetting access tokens are read, with the admin who created
+    /// them as the owner and the user they act as in `puppets_user_id`.
     #[sqlx::test(
         migrator = "MIGRATOR",
         fixtures("user_alice", "devices_alice", "access_token_alice_with_puppet")
@@ -663,7 +1324,61 @@ mod test {
             .await
             .expect("failed to read Synapse access tokens");
 
-        assert!(access_tokens.is_empty());
+        assert_eq!(access_tokens.len(), 1);
+        let access_token = access_tokens.first().unwrap();
+        assert_eq!(access_token.user_id.0, "@bob:example.com");
+        assert_eq!(
+            access_token.puppets_user_id,
+            Some(FullUserId("@alice:example.com".to_owned()))
+        );
+    }
+
+    /// Tests that the most recent activity of deviceless access tokens is read
+    /// from `user_ips`.
+    #[sqlx::test(
+        migrator = "MIGRATOR",
+        fixtures(
+            "user_alice",
+            "devices_alice",
+            "access_token_alice",
+            "access_token_alice_deviceless"
+        )
+    )]
+    async fn test_read_access_token_activity(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let access_tokens: Vec<SynapseAccessToken> = reader
+            .read_unrefreshable_access_tokens()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse access tokens");
+        assert_eq!(access_tokens.len(), 2);
+
+        // The activity of tokens with a device is migrated with the device
+        let device_token = access_tokens
+            .iter()
+            .find(|token| token.device_id.is_some())
+            .unwrap();
+        assert_eq!(device_token.last_seen, None);
+        assert_eq!(device_token.ip, None);
+        assert_eq!(device_token.user_agent, None);
+
+        let deviceless_token = access_tokens
+            .iter()
+            .find(|token| token.device_id.is_none())
+            .unwrap();
+        assert_eq!(
+            deviceless_token.last_seen.map(DateTime::from),
+            DateTime::from_timestamp_millis(1_623_366_000_000)
+        );
+        assert_eq!(deviceless_token.ip.as_deref(), Some("203.0.113.3"));
+        assert_eq!(
+            deviceless_token.user_agent,
+            Some(RawText(b"Bot/2.0".to_vec()))
+        );
     }
 
     #[sqlx::test(
@@ -727,4 +1442,170 @@ mod test {
         );
         assert_debug_snapshot!(refresh_tokens);
     }
+
+    /// Opens an in-memory SQLite database holding a Synapse database, from
+    /// the `synapse_sqlite.sql` fixture.
+    async fn sqlite_synapse() -> SqliteConnection {
+        let mut conn = SqliteConnection::connect("sqlite::memory:")
+            .await
+            .expect("failed to open SQLite database");
+        sqlx::raw_sql(include_str!("fixtures/synapse_sqlite.sql"))
+            .execute(&mut conn)
+            .await
+            .expect("failed to load the SQLite fixture");
+        conn
+    }
+
+    /// Tests that the rows read from a SQLite database are the same as the
+    /// ones read from the equivalent Postgres database.
+    #[tokio::test]
+    async fn test_read_sqlite() {
+        let mut conn = sqlite_synapse().await;
+        let mut reader = SynapseReader::new_sqlite(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let users: BTreeSet<SynapseUser> = reader
+            .read_users()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse users");
+        assert_debug_snapshot!("read_users", users);
+
+        let threepids: BTreeSet<SynapseThreepid> = reader
+            .read_threepids()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse threepids");
+        assert_debug_snapshot!("read_threepids", threepids);
+
+        let external_ids: BTreeSet<SynapseExternalId> = reader
+            .read_user_external_ids()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse external user IDs");
+        assert_debug_snapshot!("read_external_ids", external_ids);
+
+        let account_data: BTreeSet<SynapseAccountData> = reader
+            .read_account_data()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse account data");
+        assert_debug_snapshot!("read_account_data", account_data);
+
+        let user_filters: BTreeSet<SynapseUserFilter> = reader
+            .read_user_filters()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse user filters");
+        assert_debug_snapshot!("read_user_filters", user_filters);
+
+        let devices: BTreeSet<SynapseDevice> = reader
+            .read_devices()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse devices");
+        assert_debug_snapshot!("read_devices", devices);
+
+        let access_tokens: BTreeSet<SynapseAccessToken> = reader
+            .read_unrefreshable_access_tokens()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse access tokens");
+        assert_debug_snapshot!("read_access_token", access_tokens);
+
+        let refresh_tokens: BTreeSet<SynapseRefreshableTokenPair> = reader
+            .read_refreshable_token_pairs()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse refresh tokens");
+        assert!(refresh_tokens.is_empty());
+
+        reader.finish().await.expect("failed to finish reading");
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_checks() {
+        let mut conn = sqlite_synapse().await;
+        let mut reader = SynapseReader::new_sqlite(&mut conn, true)
+            .await
+            .expect("failed to make SynapseReader");
+
+        // There are no estimates to use with SQLite
+        let counts = reader
+            .count_rows(CountMode::Estimate)
+            .await
+            .expect("failed to count rows");
+        assert_eq!(counts.users, 1);
+        assert_eq!(counts.threepids, 2);
+        assert_eq!(counts.devices, 3);
+        assert_eq!(counts.access_tokens, 1);
+        assert_eq!(counts.ui_auth_sessions, 0);
+
+        let server_names = reader
+            .distinct_server_names()
+            .await
+            .expect("failed to list server names");
+        assert_eq!(server_names, vec!["example.com".to_owned()]);
+
+        let erased_users = reader
+            .erased_users()
+            .await
+            .expect("failed to list erased Synapse users");
+        assert!(erased_users.is_empty());
+
+        let names = reader
+            .duplicate_device_names(true)
+            .await
+            .expect("failed to list duplicate device names");
+        assert!(names.is_empty());
+
+        // The device of alice was last seen at 2021-06-10T23:00:00Z
+        let last_seen = DateTime::from_timestamp_millis(1_623_366_000_000).unwrap();
+        let threshold = chrono::Duration::minutes(5);
+        assert!(
+            !reader
+                .assert_synapse_stopped(last_seen + chrono::Duration::minutes(1), threshold)
+                .await
+                .expect("failed to check Synapse activity")
+        );
+        assert!(
+            reader
+                .assert_synapse_stopped(last_seen + chrono::Duration::hours(1), threshold)
+                .await
+                .expect("failed to check Synapse activity")
+        );
+    }
+
+    /// Tests that the database checks can run against a SQLite database.
+    #[tokio::test]
+    async fn test_database_check_sqlite() {
+        let synapse_config: Config = Figment::new()
+            .merge(Yaml::string(
+                "server_name: example.com\ndatabase:\n  name: sqlite3\n",
+            ))
+            .extract()
+            .expect("failed to load the Synapse config");
+
+        let mut conn = SynapseConnection::Sqlite(sqlite_synapse().await);
+        let (warnings, errors) = synapse_database_check(
+            &mut conn,
+            &synapse_config,
+            &Figment::new(),
+            UnknownProviderPolicy::SkipWithWarning,
+        )
+        .await
+        .expect("failed to check the Synapse database");
+
+        assert!(errors.is_empty());
+        assert!(matches!(
+            warnings.as_slice(),
+            [
+                CheckWarning::NonEmailThreepidsInDatabase {
+                    num_non_email_3pids: 1
+                },
+                CheckWarning::UnknownOAuthProviderSkipped { provider, num_users: 1 },
+            ] if provider == "oidc-raasu"
+        ));
+    }
 }
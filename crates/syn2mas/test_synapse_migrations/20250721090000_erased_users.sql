@@ -0,0 +1,10 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+-- Brings in the `erased_users` table from Synapse
+
+CREATE TABLE erased_users (
+    user_id text NOT NULL
+);